@@ -0,0 +1,12 @@
+CREATE TABLE
+  IF NOT EXISTS releases (
+    release_id UUID PRIMARY KEY DEFAULT gen_random_uuid (),
+    apps JSONB NOT NULL, -- snapshot of the released app services
+    envs JSONB NOT NULL, -- snapshot of the released envs
+    volumes JSONB NOT NULL, -- snapshot of the released volumes
+    released_at TIMESTAMP NOT NULL,
+    user_id UUID NOT NULL,
+    project_id UUID NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE,
+    FOREIGN KEY (project_id) REFERENCES projects (project_id) ON DELETE CASCADE
+  );
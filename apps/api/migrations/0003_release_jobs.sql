@@ -0,0 +1,15 @@
+CREATE TYPE release_state AS ENUM ('Pending', 'Running', 'Succeeded', 'Failed');
+
+-- releases recorded before the jobs were applied synchronously
+ALTER TABLE releases
+ADD COLUMN state release_state NOT NULL DEFAULT 'Succeeded',
+ADD COLUMN error TEXT;
+
+ALTER TABLE releases
+ALTER COLUMN state
+SET DEFAULT 'Pending';
+
+-- a project runs one release at a time
+CREATE UNIQUE INDEX IF NOT EXISTS unique_running_release_for_project ON releases (project_id)
+WHERE
+  state IN ('Pending', 'Running');
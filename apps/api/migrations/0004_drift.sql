@@ -0,0 +1,3 @@
+-- drift from the last release is re-applied instead of only being reported
+ALTER TABLE projects
+ADD COLUMN reapply_drift BOOLEAN NOT NULL DEFAULT FALSE;
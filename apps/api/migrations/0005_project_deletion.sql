@@ -0,0 +1,3 @@
+-- set while the cluster resources of a deleted project are torn down, the row is removed once they are gone
+ALTER TABLE projects
+ADD COLUMN deleting BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+-- admins can run the maintenance passes across every project
+ALTER TABLE users
+ADD COLUMN admin BOOLEAN NOT NULL DEFAULT FALSE;
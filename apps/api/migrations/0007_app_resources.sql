@@ -0,0 +1,8 @@
+-- cpu is in millicores and memory in MB, requests never exceed limits
+ALTER TABLE app_services
+ADD COLUMN cpu_request INT NOT NULL DEFAULT 100,
+ADD COLUMN cpu_limit INT NOT NULL DEFAULT 500,
+ADD COLUMN memory_request INT NOT NULL DEFAULT 128,
+ADD COLUMN memory_limit INT NOT NULL DEFAULT 256,
+ADD CONSTRAINT cpu_request_within_limit CHECK (cpu_request <= cpu_limit),
+ADD CONSTRAINT memory_request_within_limit CHECK (memory_request <= memory_limit);
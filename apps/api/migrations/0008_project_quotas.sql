@@ -0,0 +1,7 @@
+-- totals a project may reach over its apps and volumes, cpu is in millicores and memory and storage in MB
+ALTER TABLE projects
+ADD COLUMN quota_apps INT NOT NULL DEFAULT 10 CHECK (quota_apps >= 0),
+ADD COLUMN quota_replicas INT NOT NULL DEFAULT 20 CHECK (quota_replicas >= 0),
+ADD COLUMN quota_cpu INT NOT NULL DEFAULT 4000 CHECK (quota_cpu >= 0),
+ADD COLUMN quota_memory INT NOT NULL DEFAULT 8192 CHECK (quota_memory >= 0),
+ADD COLUMN quota_storage INT NOT NULL DEFAULT 20000 CHECK (quota_storage >= 0);
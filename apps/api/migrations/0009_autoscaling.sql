@@ -0,0 +1,12 @@
+-- an app is autoscaled between min_replicas and max_replicas when both are set, targets are in percent of the requests
+ALTER TABLE app_services
+ADD COLUMN min_replicas INT,
+ADD COLUMN max_replicas INT,
+ADD COLUMN target_cpu_utilization INT,
+ADD COLUMN target_memory_utilization INT,
+ADD CONSTRAINT autoscaling_bounds_together CHECK ((min_replicas IS NULL) = (max_replicas IS NULL)),
+ADD CONSTRAINT autoscaling_min_within_max CHECK (min_replicas <= max_replicas),
+ADD CONSTRAINT autoscaling_targets_need_bounds CHECK (
+  min_replicas IS NOT NULL
+  OR (target_cpu_utilization IS NULL AND target_memory_utilization IS NULL)
+);
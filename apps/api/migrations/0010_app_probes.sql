@@ -0,0 +1,3 @@
+-- liveness, readiness and startup probes of an app, as validated by the api
+ALTER TABLE app_services
+ADD COLUMN probes JSONB NOT NULL DEFAULT '{}';
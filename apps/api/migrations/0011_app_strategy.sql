@@ -0,0 +1,8 @@
+-- how a release replaces the replicas of an app, as validated by the api
+ALTER TABLE app_services
+ADD COLUMN strategy JSONB NOT NULL DEFAULT '{"type": "RollingUpdate"}',
+-- the autoscaler drives a single deployment
+ADD CONSTRAINT autoscaling_needs_single_deployment CHECK (
+  min_replicas IS NULL
+  OR strategy ->> 'type' IN ('RollingUpdate', 'Recreate')
+);
@@ -0,0 +1,5 @@
+-- entrypoint overrides of the app container, the image defaults are used when NULL
+ALTER TABLE app_services
+ADD COLUMN command TEXT[],
+ADD COLUMN args TEXT[],
+ADD COLUMN working_dir TEXT;
@@ -0,0 +1,14 @@
+-- workers don't listen on anything, they run without a port, a service, an ingress or domains
+ALTER TABLE app_services
+ADD COLUMN kind TEXT NOT NULL DEFAULT 'Web' CHECK (kind IN ('Web', 'Worker')),
+ALTER COLUMN port DROP NOT NULL,
+ADD CONSTRAINT web_app_needs_port CHECK (kind = 'Worker' OR port IS NOT NULL),
+ADD CONSTRAINT worker_without_endpoint CHECK (
+  kind = 'Web'
+  OR (port IS NULL AND public_domain IS NULL AND private_domain IS NULL)
+),
+-- probes fall back to the port of the app, workers have to name one
+ADD CONSTRAINT worker_probes_need_port CHECK (
+  kind = 'Web'
+  OR NOT jsonb_path_exists(probes, '$.* ? (@.type != "Exec" && !exists(@.port))')
+);
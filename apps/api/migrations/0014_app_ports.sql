@@ -0,0 +1,15 @@
+-- named ports an app listens on besides its main one, and the ones its domains target
+ALTER TABLE app_services
+ADD COLUMN ports JSONB NOT NULL DEFAULT '[]',
+ADD COLUMN public_port TEXT,
+ADD COLUMN private_port TEXT,
+ADD CONSTRAINT worker_without_ports CHECK (kind = 'Web' OR ports = '[]'),
+-- the ingress only forwards http
+ADD CONSTRAINT public_port_is_tcp CHECK (
+  public_port IS NULL
+  OR jsonb_path_exists(ports, '$[*] ? (@.name == $name && @.protocol == "Tcp")', jsonb_build_object('name', public_port))
+),
+ADD CONSTRAINT private_port_exists CHECK (
+  private_port IS NULL
+  OR jsonb_path_exists(ports, '$[*] ? (@.name == $name)', jsonb_build_object('name', private_port))
+);
@@ -0,0 +1,6 @@
+-- a port of the app exposed over raw tcp or udp on a public port allocated to it, freed with the row
+ALTER TABLE app_services
+ADD COLUMN expose TEXT,
+ADD COLUMN exposed_port INT UNIQUE,
+ADD COLUMN exposed_endpoint TEXT,
+ADD CONSTRAINT exposed_endpoint_with_port CHECK ((exposed_port IS NULL) = (exposed_endpoint IS NULL));
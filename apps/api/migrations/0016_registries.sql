@@ -0,0 +1,11 @@
+-- credentials of the private registries the apps of a project pull their images from, the token is encrypted
+CREATE TABLE
+  IF NOT EXISTS registries (
+    registry_id UUID PRIMARY KEY DEFAULT gen_random_uuid (),
+    registry_host TEXT NOT NULL,
+    username TEXT NOT NULL,
+    token TEXT NOT NULL,
+    project_id UUID NOT NULL,
+    FOREIGN KEY (project_id) REFERENCES projects (project_id) ON DELETE CASCADE,
+    CONSTRAINT unique_registry_host_for_project UNIQUE (registry_host, project_id)
+  );
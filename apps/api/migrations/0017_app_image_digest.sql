@@ -0,0 +1,3 @@
+-- digest the tag of the image pointed to at the last release, the deployment pins the image to it
+ALTER TABLE app_services
+ADD COLUMN image_digest TEXT;
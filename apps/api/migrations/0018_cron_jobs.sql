@@ -0,0 +1,34 @@
+CREATE TABLE
+  IF NOT EXISTS cron_jobs (
+    cron_job_id UUID PRIMARY KEY DEFAULT gen_random_uuid (),
+    cron_job_name TEXT NOT NULL,
+    image TEXT NOT NULL,
+    command TEXT[],
+    args TEXT[],
+    schedule TEXT NOT NULL,
+    concurrency_policy TEXT NOT NULL DEFAULT 'Allow' CHECK (concurrency_policy IN ('Allow', 'Forbid', 'Replace')),
+    successful_jobs_history_limit INT NOT NULL DEFAULT 3 CHECK (successful_jobs_history_limit >= 0),
+    failed_jobs_history_limit INT NOT NULL DEFAULT 1 CHECK (failed_jobs_history_limit >= 0),
+    deleted BOOLEAN NOT NULL DEFAULT FALSE,
+    project_id UUID NOT NULL,
+    FOREIGN KEY (project_id) REFERENCES projects (project_id) ON DELETE CASCADE,
+    CONSTRAINT unique_cron_job_name_for_project UNIQUE (cron_job_name, project_id)
+  );
+
+-- envs belong to either an app or a cron job
+ALTER TABLE envs
+ALTER COLUMN app_id DROP NOT NULL,
+ADD COLUMN cron_job_id UUID,
+ADD FOREIGN KEY (cron_job_id) REFERENCES cron_jobs (cron_job_id) ON DELETE CASCADE,
+ADD CONSTRAINT unique_env_name_for_cron_job UNIQUE (env_name, cron_job_id),
+ADD CONSTRAINT env_with_one_owner CHECK (num_nonnulls (app_id, cron_job_id) = 1);
+
+-- a volume is mounted by an app or by a cron job, never by both
+ALTER TABLE volumes
+ADD COLUMN cron_job_id UUID UNIQUE,
+ADD FOREIGN KEY (cron_job_id) REFERENCES cron_jobs (cron_job_id) ON DELETE SET NULL,
+ADD CONSTRAINT volume_with_one_owner CHECK (app_id IS NULL OR cron_job_id IS NULL);
+
+-- releases recorded before the cron jobs had none
+ALTER TABLE releases
+ADD COLUMN cron_jobs JSONB NOT NULL DEFAULT '[]';
@@ -0,0 +1,17 @@
+CREATE TYPE run_state AS ENUM ('Running', 'Succeeded', 'Failed');
+
+CREATE TABLE
+  IF NOT EXISTS runs (
+    run_id UUID PRIMARY KEY DEFAULT gen_random_uuid (),
+    command TEXT[] NOT NULL,
+    state run_state NOT NULL DEFAULT 'Running',
+    exit_code INT,
+    error TEXT,
+    logs TEXT, -- output of the run, stored once it finished
+    started_at TIMESTAMP NOT NULL,
+    finished_at TIMESTAMP,
+    app_id UUID NOT NULL,
+    user_id UUID NOT NULL,
+    FOREIGN KEY (app_id) REFERENCES app_services (app_id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE
+  );
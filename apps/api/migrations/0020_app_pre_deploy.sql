@@ -0,0 +1,7 @@
+-- command run with the new image and envs before the deployment of an app is patched
+ALTER TABLE app_services
+ADD COLUMN pre_deploy TEXT[];
+
+-- outcome and output of the pre-deploy commands a release ran, releases recorded before had none
+ALTER TABLE releases
+ADD COLUMN hooks JSONB NOT NULL DEFAULT '[]';
@@ -0,0 +1,3 @@
+-- init containers and sidecars of the app, in the order they are added to its pods
+ALTER TABLE app_services
+ADD COLUMN containers JSONB NOT NULL DEFAULT '[]';
@@ -0,0 +1,84 @@
+-- a ReadWriteMany volume can be mounted by several apps at once, a ReadWriteOnce one by a single app or cron job
+ALTER TABLE volumes
+ADD COLUMN access_mode TEXT NOT NULL DEFAULT 'ReadWriteOnce' CHECK (access_mode IN ('ReadWriteOnce', 'ReadWriteMany'));
+
+-- the apps mounting a volume, each at the path of the volume
+CREATE TABLE
+  IF NOT EXISTS volume_apps (
+    volume_id UUID NOT NULL,
+    app_id UUID NOT NULL,
+    PRIMARY KEY (volume_id, app_id),
+    FOREIGN KEY (volume_id) REFERENCES volumes (volume_id) ON DELETE CASCADE,
+    FOREIGN KEY (app_id) REFERENCES app_services (app_id) ON DELETE CASCADE
+  );
+
+INSERT INTO volume_apps (volume_id, app_id)
+SELECT volume_id, app_id
+FROM volumes
+WHERE app_id IS NOT NULL;
+
+-- the volume path of a container becomes a mount of the volume its app had
+CREATE FUNCTION pg_temp.container_volumes (containers JSONB, app_volume UUID) RETURNS JSONB AS $$
+  SELECT COALESCE(
+    jsonb_agg(
+      CASE
+        WHEN container ? 'volumePath' AND app_volume IS NOT NULL THEN (container - 'volumePath') || jsonb_build_object(
+          'volumes',
+          jsonb_build_array(jsonb_build_object('id', app_volume, 'path', container -> 'volumePath'))
+        )
+        ELSE container - 'volumePath'
+      END
+      ORDER BY position
+    ),
+    '[]'
+  )
+  FROM jsonb_array_elements(containers) WITH ORDINALITY AS elements (container, position)
+$$ LANGUAGE SQL;
+
+UPDATE app_services
+SET containers = pg_temp.container_volumes (
+    containers,
+    (SELECT volume_id FROM volumes WHERE volumes.app_id = app_services.app_id)
+  )
+WHERE containers @? '$[*].volumePath';
+
+UPDATE releases
+SET apps = (
+    SELECT jsonb_agg(
+        CASE
+          WHEN app ? 'containers' THEN app || jsonb_build_object(
+            'containers',
+            pg_temp.container_volumes (
+              app -> 'containers',
+              (
+                SELECT (volume ->> 'id')::UUID
+                FROM jsonb_array_elements(releases.volumes) AS volume
+                WHERE volume ->> 'appId' = app ->> 'id'
+              )
+            )
+          )
+          ELSE app
+        END
+        ORDER BY position
+      )
+    FROM jsonb_array_elements(apps) WITH ORDINALITY AS elements (app, position)
+  )
+WHERE apps @? '$[*].containers[*].volumePath';
+
+-- released volumes list the apps mounting them
+UPDATE releases
+SET volumes = (
+    SELECT jsonb_agg(
+        CASE
+          WHEN volume ? 'appId' THEN (volume - 'appId') || jsonb_build_object('appIds', jsonb_build_array(volume -> 'appId'))
+          ELSE volume
+        END
+        ORDER BY position
+      )
+    FROM jsonb_array_elements(volumes) WITH ORDINALITY AS elements (volume, position)
+  )
+WHERE volumes @? '$[*].appId';
+
+ALTER TABLE volumes
+DROP CONSTRAINT volume_with_one_owner,
+DROP COLUMN app_id;
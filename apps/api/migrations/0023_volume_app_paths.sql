@@ -0,0 +1,31 @@
+-- each app mounts a volume at a path of its own, the path of the volume stays the one of its cron job
+ALTER TABLE volume_apps
+ADD COLUMN path TEXT;
+
+UPDATE volume_apps
+SET path = volumes.path
+FROM volumes
+WHERE volumes.volume_id = volume_apps.volume_id;
+
+ALTER TABLE volume_apps
+ALTER COLUMN path SET NOT NULL;
+
+-- released volumes list the apps mounting them along with their paths
+UPDATE releases
+SET volumes = (
+    SELECT jsonb_agg(
+        CASE
+          WHEN volume ? 'appIds' THEN (volume - 'appIds') || jsonb_build_object(
+            'apps',
+            (
+              SELECT COALESCE(jsonb_agg(jsonb_build_object('id', app_id, 'path', volume -> 'path')), '[]')
+              FROM jsonb_array_elements(volume -> 'appIds') AS app_ids (app_id)
+            )
+          )
+          ELSE volume
+        END
+        ORDER BY position
+      )
+    FROM jsonb_array_elements(volumes) WITH ORDINALITY AS elements (volume, position)
+  )
+WHERE volumes @? '$[*].appIds';
@@ -12,8 +12,13 @@ use actix_web_validator::JsonConfig;
 use confique::Config;
 
 use crate::{
-  middleware::{project_middleware, session_middleware},
-  routes::{app, auth as auth_routes, env, project, volume},
+  jobs,
+  k8s::{Cluster, KubeBackend},
+  middleware::{admin_middleware, project_middleware, session_middleware},
+  registry::{HttpRegistry, ImageRegistry},
+  routes::{
+    admin, app, auth as auth_routes, cron_job, env, project, registry, release, run, volume,
+  },
   ApiError, DatabaseConfig,
 };
 
@@ -31,6 +36,8 @@ async fn default_route() -> HttpResponse {
 
 pub fn create_app(
   pool: sqlx::PgPool,
+  cluster: Cluster,
+  registry: ImageRegistry,
 ) -> App<
   impl ServiceFactory<
     actix_web::dev::ServiceRequest,
@@ -44,9 +51,17 @@ pub fn create_app(
   App::new()
     .app_data(JsonConfig::default().error_handler(|err, _| ApiError::from(err).into()))
     .app_data(Data::new(pool))
+    .app_data(Data::new(cluster))
+    .app_data(Data::new(registry))
     .wrap(NormalizePath::new(middleware::TrailingSlash::Always))
     .wrap(cors)
     .service(web::scope("/auth").configure(auth_routes::config))
+    .service(
+      web::scope("/admin")
+        .wrap(middleware::from_fn(admin_middleware))
+        .wrap(middleware::from_fn(session_middleware))
+        .configure(admin::config),
+    )
     .service(
       web::scope("/projects")
         .wrap(middleware::from_fn(session_middleware))
@@ -61,10 +76,22 @@ pub fn create_app(
                 .service(
                   web::scope("/{app_id}")
                     .configure(app::config_with_id)
-                    .configure(env::config),
+                    .configure(env::config)
+                    .configure(run::config),
+                ),
+            )
+            .service(
+              web::scope("/cron-jobs")
+                .configure(cron_job::config_without_id)
+                .service(
+                  web::scope("/{cron_job_id}")
+                    .configure(cron_job::config_with_id)
+                    .configure(env::cron_job_config),
                 ),
             )
-            .configure(volume::config),
+            .configure(volume::config)
+            .configure(registry::config)
+            .configure(release::config),
         ),
     )
     .default_service(web::to(default_route))
@@ -77,10 +104,20 @@ impl AppConfig {
 
   pub async fn build(&self) -> anyhow::Result<Server> {
     let pool = DatabaseConfig::from_env()?.create_pool().await?;
+    jobs::fail_interrupted_releases(&pool).await?;
+    let client = kube::Client::try_default().await?;
+    let cluster = Cluster::new(KubeBackend::new(client.clone()));
+    let registry = ImageRegistry::new(HttpRegistry::new());
+
+    jobs::spawn_drift_controller(pool.clone(), cluster.clone(), client);
+    jobs::spawn_teardown_retries(pool.clone(), cluster.clone());
+    jobs::spawn_gc(pool.clone(), cluster.clone());
+    jobs::resume_runs(&pool, &cluster).await?;
 
-    let server = HttpServer::new(move || create_app(pool.clone()))
-      .bind_auto_h2c((self.address, self.port))?
-      .run();
+    let server =
+      HttpServer::new(move || create_app(pool.clone(), cluster.clone(), registry.clone()))
+        .bind_auto_h2c((self.address, self.port))?
+        .run();
 
     Ok(server)
   }
@@ -7,7 +7,7 @@ use serde::Serialize;
 use utoipa::{IntoResponses, ToSchema};
 use validator::ValidationErrors;
 
-use crate::auth;
+use crate::{auth, k8s::ReleaseError, schemas::app_check_message};
 
 pub type ApiResult<T, E = ApiError> = Result<T, E>;
 
@@ -28,6 +28,11 @@ pub enum ApiError {
   AlreadyExists,
   #[display("Resource not found")]
   NotFound,
+  #[display("{message}")]
+  QuotaExceeded {
+    #[serde(skip)]
+    message: String,
+  },
   #[display("User not authorized")]
   Unauthorized,
   #[display("Internal server error occurred")]
@@ -44,11 +49,13 @@ impl ResponseError for ApiError {
       })
   }
 
+  #[allow(clippy::unneeded_struct_pattern)]
   fn status_code(&self) -> StatusCode {
     match self {
       ApiError::BadRequest { .. } => StatusCode::BAD_REQUEST,
       ApiError::Validation { .. } => StatusCode::BAD_REQUEST,
       ApiError::NotFound { .. } => StatusCode::NOT_FOUND,
+      ApiError::QuotaExceeded { .. } => StatusCode::FORBIDDEN,
       ApiError::AlreadyExists { .. } => StatusCode::CONFLICT,
       ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
       ApiError::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
@@ -78,6 +85,15 @@ impl From<sqlx::Error> for ApiError {
       sqlx::Error::Database(err) => match err.kind() {
         sqlx::error::ErrorKind::UniqueViolation => ApiError::AlreadyExists,
         sqlx::error::ErrorKind::ForeignKeyViolation => ApiError::NotFound,
+        sqlx::error::ErrorKind::CheckViolation => {
+          let constraint = err.constraint().unwrap_or_default();
+
+          ApiError::BadRequest {
+            message: app_check_message(constraint)
+              .map(str::to_string)
+              .unwrap_or_else(|| format!("Check {constraint} failed")),
+          }
+        }
         _ => ApiError::InternalError,
       },
       _ => ApiError::InternalError,
@@ -103,6 +119,13 @@ impl From<kube::Error> for ApiError {
   }
 }
 
+impl From<ReleaseError> for ApiError {
+  fn from(err: ReleaseError) -> Self {
+    log::error!("Release error: {}", err.to_string());
+    ApiError::InternalError
+  }
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct ErrorMessage {
   #[serde(flatten)]
@@ -122,6 +145,10 @@ pub struct NotFoundErrorMessage(#[allow(unused)] ErrorMessage);
 #[response(status = CONFLICT)]
 pub struct AlreadyExistsErrorMessage(#[allow(unused)] ErrorMessage);
 
+#[derive(IntoResponses)]
+#[response(status = FORBIDDEN)]
+pub struct QuotaExceededErrorMessage(#[allow(unused)] ErrorMessage);
+
 #[derive(IntoResponses)]
 #[response(status = UNAUTHORIZED)]
 pub struct UnauthorizedErrorMessage(#[allow(unused)] ErrorMessage);
@@ -0,0 +1,68 @@
+use sqlx::PgConnection;
+
+use crate::{k8s, schemas::AppService, ApiError, ApiResult};
+
+/// Give the app a public port when it starts exposing one of its ports, or free the one it had when it stops.
+///
+/// Run it in the transaction that changed the app, after the change: the unique port column makes a
+/// concurrent allocation of the same port fail instead of sharing it.
+pub async fn allocate_exposed_port(
+  conn: &mut PgConnection,
+  app: AppService,
+) -> ApiResult<AppService> {
+  let Some(expose) = app.expose.as_deref() else {
+    if app.exposed_port.is_none() {
+      return Ok(app);
+    }
+
+    let app = sqlx::query_as!(
+      AppService,
+      "UPDATE app_services SET exposed_port = NULL, exposed_endpoint = NULL WHERE app_id = $1 RETURNING *",
+      app.app_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    return Ok(app);
+  };
+
+  if k8s::app_port(&app, expose).is_none() {
+    return Err(ApiError::BadRequest {
+      message: format!("Unable to expose {expose}, the app has no such port"),
+    });
+  }
+
+  if app.exposed_port.is_some() {
+    return Ok(app);
+  }
+
+  let ports = k8s::exposed_ports();
+
+  let app = sqlx::query_as!(
+    AppService,
+    r#"
+    WITH free AS (
+      SELECT candidate
+      FROM generate_series($2::INT, $3::INT) AS candidate
+      WHERE NOT EXISTS (SELECT 1 FROM app_services WHERE exposed_port = candidate)
+      ORDER BY candidate
+      LIMIT 1
+    )
+    UPDATE app_services
+    SET exposed_port = free.candidate, exposed_endpoint = $4 || ':' || free.candidate
+    FROM free
+    WHERE app_id = $1
+    RETURNING app_services.*
+    "#,
+    app.app_id,
+    ports.start(),
+    ports.end(),
+    k8s::exposed_host()
+  )
+  .fetch_optional(&mut *conn)
+  .await?;
+
+  app.ok_or_else(|| ApiError::QuotaExceeded {
+    message: "No port left to expose the app".to_string(),
+  })
+}
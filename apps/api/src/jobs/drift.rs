@@ -0,0 +1,304 @@
+use std::collections::BTreeSet;
+
+use futures::StreamExt;
+use sqlx::{types::Json, PgPool};
+use uuid::Uuid;
+
+use crate::{
+  k8s::{self, Cluster, ManagedObject},
+  routes::release::record_release,
+  schemas::{
+    AppService, CronJob, EnvVar, PlanAction, Project, Registry, ReleaseState, ResourceChange,
+    Volume,
+  },
+  ApiError, ApiResult,
+};
+
+/// Objects handled together, the initial listing touches every managed object at once
+const DRIFT_BATCH: usize = 64;
+
+pub(super) struct ReleasedState {
+  pub release_id: Uuid,
+  pub project: Project,
+  pub apps: Vec<AppService>,
+  pub cron_jobs: Vec<CronJob>,
+  pub envs: Vec<EnvVar>,
+  pub volumes: Vec<Volume>,
+  /// The registries are not part of the snapshot, their current credentials are used
+  pub registries: Vec<Registry>,
+}
+
+/// Rows of the last successful release, `None` when the project was never released
+pub(super) async fn released_state(
+  pool: &PgPool,
+  project_id: Uuid,
+) -> sqlx::Result<Option<ReleasedState>> {
+  let project = sqlx::query_as!(
+    Project,
+    "SELECT * FROM projects WHERE project_id = $1",
+    project_id
+  )
+  .fetch_one(pool)
+  .await?;
+
+  let snapshot = sqlx::query!(
+    r#"
+    SELECT release_id,
+      apps as "apps: Json<Vec<AppService>>",
+      cron_jobs as "cron_jobs: Json<Vec<CronJob>>",
+      envs as "envs: Json<Vec<EnvVar>>",
+      volumes as "volumes: Json<Vec<Volume>>"
+    FROM releases
+    WHERE project_id = $1 AND state = 'Succeeded'
+    ORDER BY released_at DESC
+    LIMIT 1
+    "#,
+    project_id
+  )
+  .fetch_optional(pool)
+  .await?;
+
+  let Some(snapshot) = snapshot else {
+    return Ok(None);
+  };
+
+  let registries = sqlx::query_as!(
+    Registry,
+    "SELECT * FROM registries WHERE project_id = $1",
+    project_id
+  )
+  .fetch_all(pool)
+  .await?;
+
+  Ok(Some(ReleasedState {
+    release_id: snapshot.release_id,
+    project,
+    apps: snapshot.apps.0,
+    cron_jobs: snapshot.cron_jobs.0,
+    envs: snapshot.envs.0,
+    volumes: snapshot.volumes.0,
+    registries,
+  }))
+}
+
+async fn state_drift(cluster: &Cluster, state: &ReleasedState) -> ApiResult<Vec<ResourceChange>> {
+  let changes = k8s::plan(
+    cluster,
+    &state.project,
+    &state.apps,
+    &state.cron_jobs,
+    &state.envs,
+    &state.volumes,
+    &state.registries,
+  )
+  .await?;
+
+  Ok(
+    changes
+      .into_iter()
+      .filter(|change| change.action != PlanAction::Unchanged)
+      .collect(),
+  )
+}
+
+/// Changes needed to bring the cluster back to the last successful release of the project
+pub async fn project_drift(
+  pool: &PgPool,
+  cluster: &Cluster,
+  project_id: Uuid,
+) -> ApiResult<Vec<ResourceChange>> {
+  match released_state(pool, project_id).await? {
+    Some(state) => state_drift(cluster, &state).await,
+    None => Ok(Vec::new()),
+  }
+}
+
+/// Report the drift of a project, re-applying its drifted objects as a new release when the project opted in
+pub async fn check_drift(pool: &PgPool, cluster: &Cluster, project_id: Uuid) -> ApiResult<()> {
+  // a running release is expected to move the cluster away from the previous one
+  let releasing = sqlx::query_scalar!(
+    r#"SELECT EXISTS(SELECT 1 FROM releases WHERE project_id = $1 AND state IN ('Pending', 'Running')) as "releasing!""#,
+    project_id
+  )
+  .fetch_one(pool)
+  .await?;
+
+  if releasing {
+    return Ok(());
+  }
+
+  let Some(state) = released_state(pool, project_id).await? else {
+    return Ok(());
+  };
+
+  // the teardown of a deleted project must not be undone
+  if state.project.deleting {
+    return Ok(());
+  }
+
+  let drift = state_drift(cluster, &state).await?;
+
+  if drift.is_empty() {
+    return Ok(());
+  }
+
+  for change in &drift {
+    log::warn!(
+      "Project {project_id} drifted from its last release: {:?} {} {}",
+      change.action,
+      change.kind,
+      change.name
+    );
+  }
+
+  if !state.project.reapply_drift {
+    return Ok(());
+  }
+
+  let Some(release_id) = record_reapply(pool, &state).await? else {
+    return Ok(());
+  };
+
+  log::info!("Re-applying the last release of project {project_id}");
+
+  let result = k8s::reapply(
+    cluster,
+    &state.project,
+    state.apps,
+    state.cron_jobs,
+    state.envs,
+    state.volumes,
+    state.registries,
+    &|change| log::info!("Re-applied {} {}", change.kind, change.name),
+  )
+  .await;
+
+  let (release_state, error) = match &result {
+    Ok(()) => (ReleaseState::Succeeded, None),
+    Err(err) => (ReleaseState::Failed, Some(err.to_string())),
+  };
+
+  sqlx::query!(
+    "UPDATE releases SET state = $1, error = $2 WHERE release_id = $3",
+    release_state as _,
+    error,
+    release_id
+  )
+  .execute(pool)
+  .await?;
+
+  Ok(result?)
+}
+
+/// Record the re-apply as a running release of the same rows, `None` when another release started or succeeded since
+/// the state was read, the drift is checked again after it
+async fn record_reapply(pool: &PgPool, state: &ReleasedState) -> ApiResult<Option<Uuid>> {
+  let project_id = state.project.project_id;
+  let mut tx = pool.begin().await?;
+
+  // the row lock orders the re-apply with a deletion of the project
+  let deleting = sqlx::query_scalar!(
+    "SELECT deleting FROM projects WHERE project_id = $1 FOR UPDATE",
+    project_id
+  )
+  .fetch_optional(tx.as_mut())
+  .await?;
+
+  if deleting != Some(false) {
+    return Ok(None);
+  }
+
+  // fails with a conflict while another release of the project is running
+  let release = record_release(
+    tx.as_mut(),
+    project_id,
+    state.project.user_id,
+    &state.apps,
+    &state.cron_jobs,
+    &state.envs,
+    &state.volumes,
+  )
+  .await;
+
+  let release = match release.map_err(ApiError::from) {
+    Ok(release) => release,
+    Err(ApiError::AlreadyExists) => return Ok(None),
+    Err(err) => return Err(err),
+  };
+
+  let last_release = sqlx::query_scalar!(
+    "SELECT release_id FROM releases WHERE project_id = $1 AND state = 'Succeeded' ORDER BY released_at DESC LIMIT 1",
+    project_id
+  )
+  .fetch_optional(tx.as_mut())
+  .await?;
+
+  if last_release != Some(state.release_id) {
+    return Ok(None);
+  }
+
+  sqlx::query!(
+    "UPDATE releases SET state = $1 WHERE release_id = $2",
+    ReleaseState::Running as _,
+    release.release_id
+  )
+  .execute(tx.as_mut())
+  .await?;
+
+  tx.commit().await?;
+
+  Ok(Some(release.release_id))
+}
+
+async fn object_project(pool: &PgPool, object: ManagedObject) -> sqlx::Result<Option<Uuid>> {
+  match object {
+    ManagedObject::Project(project_id) => Ok(Some(project_id)),
+    ManagedObject::App(app_id) => {
+      sqlx::query_scalar!(
+        "SELECT project_id FROM app_services WHERE app_id = $1",
+        app_id
+      )
+      .fetch_optional(pool)
+      .await
+    }
+    ManagedObject::CronJob(cron_job_id) => {
+      sqlx::query_scalar!(
+        "SELECT project_id FROM cron_jobs WHERE cron_job_id = $1",
+        cron_job_id
+      )
+      .fetch_optional(pool)
+      .await
+    }
+    ManagedObject::Volume(volume_id) => {
+      sqlx::query_scalar!(
+        "SELECT project_id FROM volumes WHERE volume_id = $1",
+        volume_id
+      )
+      .fetch_optional(pool)
+      .await
+    }
+  }
+}
+
+/// Watch the managed objects and check the drift of the projects they belong to
+pub fn spawn_drift_controller(pool: PgPool, cluster: Cluster, client: kube::Client) {
+  tokio::spawn(async move {
+    let mut batches = k8s::managed_objects(client).ready_chunks(DRIFT_BATCH);
+
+    while let Some(objects) = batches.next().await {
+      let mut projects = BTreeSet::new();
+
+      for object in BTreeSet::from_iter(objects) {
+        match object_project(&pool, object).await {
+          Ok(project_id) => projects.extend(project_id),
+          Err(err) => log::error!("Unable to find the project of {object:?}: {err}"),
+        }
+      }
+
+      for project_id in projects {
+        // errors are logged by their conversion
+        let _ = check_drift(&pool, &cluster, project_id).await;
+      }
+    }
+  });
+}
@@ -0,0 +1,111 @@
+use std::{collections::HashSet, sync::LazyLock, time::Duration};
+
+use confique::Config;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+  k8s::{self, Cluster, OwnedObject},
+  schemas::OrphanObject,
+  ApiResult,
+};
+
+static GC_CONFIG: LazyLock<GcConfig> = LazyLock::new(|| GcConfig::builder().env().load().unwrap());
+
+#[derive(Config)]
+struct GcConfig {
+  /// Seconds between two periodic passes
+  #[config(env = "GC_INTERVAL", default = 3600)]
+  interval: u64,
+  /// Delete the orphans found by the periodic passes instead of only reporting them
+  #[config(env = "GC_DELETE", default = false)]
+  delete: bool,
+}
+
+impl From<&OwnedObject> for OrphanObject {
+  fn from(object: &OwnedObject) -> Self {
+    Self {
+      kind: object.kind.clone(),
+      name: object.name.clone(),
+      namespace: object.namespace.clone(),
+      project_id: object.project_id,
+      app_id: object.app_id,
+      cron_job_id: object.cron_job_id,
+      volume_id: object.volume_id,
+    }
+  }
+}
+
+/// List the gws objects with no matching row or in the wrong namespace, deleting them when `delete` is set
+pub async fn collect_garbage(
+  pool: &PgPool,
+  cluster: &Cluster,
+  delete: bool,
+) -> ApiResult<Vec<OrphanObject>> {
+  // rows are read after the objects, so anything created in between is not an orphan
+  let objects = k8s::owned_objects(cluster).await?;
+
+  let projects: HashSet<Uuid> = sqlx::query_scalar!("SELECT project_id FROM projects")
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+  let apps: HashSet<Uuid> = sqlx::query_scalar!("SELECT app_id FROM app_services")
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+  let cron_jobs: HashSet<Uuid> = sqlx::query_scalar!("SELECT cron_job_id FROM cron_jobs")
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+  let volumes: HashSet<Uuid> = sqlx::query_scalar!("SELECT volume_id FROM volumes")
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+
+  let orphans: Vec<OwnedObject> = objects
+    .into_iter()
+    .filter(|object| {
+      !projects.contains(&object.project_id)
+        || object.misplaced()
+        || object.app_id.is_some_and(|id| !apps.contains(&id))
+        || object
+          .cron_job_id
+          .is_some_and(|id| !cron_jobs.contains(&id))
+        || object.volume_id.is_some_and(|id| !volumes.contains(&id))
+    })
+    .collect();
+
+  if delete {
+    for orphan in &orphans {
+      k8s::delete_owned_object(cluster, orphan).await?;
+
+      log::info!("Deleted orphan {} {}", orphan.kind, orphan.name);
+    }
+  }
+
+  Ok(orphans.iter().map(OrphanObject::from).collect())
+}
+
+/// Run a garbage collection pass every `GC_INTERVAL` seconds
+pub fn spawn_gc(pool: PgPool, cluster: Cluster) {
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(Duration::from_secs(GC_CONFIG.interval));
+
+    loop {
+      interval.tick().await;
+
+      // errors are logged by their conversion, deletions by the pass
+      if let Ok(orphans) = collect_garbage(&pool, &cluster, GC_CONFIG.delete).await {
+        if !GC_CONFIG.delete {
+          for orphan in orphans {
+            log::warn!("Found orphan {} {}", orphan.kind, orphan.name);
+          }
+        }
+      }
+    }
+  });
+}
@@ -0,0 +1,11 @@
+pub use drift::{check_drift, project_drift, spawn_drift_controller};
+pub use gc::{collect_garbage, spawn_gc};
+pub use release::{fail_interrupted_releases, release_events, spawn_release, ReleaseJob};
+pub use run::{resume_runs, spawn_run};
+pub use teardown::{spawn_teardown_retries, teardown_project};
+
+mod drift;
+mod gc;
+mod release;
+mod run;
+mod teardown;
@@ -0,0 +1,337 @@
+use std::{
+  collections::HashMap,
+  sync::{LazyLock, Mutex, MutexGuard},
+};
+
+use derive_more::derive::{Display, From};
+use futures::{stream, FutureExt, Stream, StreamExt};
+use sqlx::{types::Json, PgPool};
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+use crate::{
+  k8s::{self, Cluster, ReleaseError, RolloutError},
+  schemas::{AppService, CronJob, EnvVar, Project, Registry, ReleaseEvent, ReleaseState, Volume},
+};
+
+use super::drift::released_state;
+
+const EVENTS_CAPACITY: usize = 64;
+
+/// Events of the releases running in this process, removed once their outcome is stored
+static RUNNING_RELEASES: LazyLock<Mutex<HashMap<Uuid, ReleaseProgress>>> =
+  LazyLock::new(Default::default);
+
+struct ReleaseProgress {
+  events: Vec<ReleaseEvent>,
+  sender: broadcast::Sender<ReleaseEvent>,
+}
+
+/// Rows reconciled by a release, the deleted ones are purged only once it succeeds
+pub struct ReleaseJob {
+  pub release_id: Uuid,
+  pub project: Project,
+  pub apps: Vec<AppService>,
+  pub cron_jobs: Vec<CronJob>,
+  pub envs: Vec<EnvVar>,
+  pub volumes: Vec<Volume>,
+  /// Credentials of the project registries, read when the release is made rather than snapshotted with it
+  pub registries: Vec<Registry>,
+  /// Wait for the apps to roll out, rolling them back when one does not
+  pub wait_for_healthy: bool,
+}
+
+#[derive(Debug, Display, From)]
+enum JobError {
+  Release(ReleaseError),
+  #[display("{_0}, the apps were rolled back to the last successful release")]
+  Rollout(RolloutError),
+  #[display("Database error: {_0}")]
+  Database(sqlx::Error),
+}
+
+fn running_releases() -> MutexGuard<'static, HashMap<Uuid, ReleaseProgress>> {
+  RUNNING_RELEASES.lock().expect("Running releases poisoned")
+}
+
+fn publish(release_id: Uuid, event: ReleaseEvent) {
+  if let Some(progress) = running_releases().get_mut(&release_id) {
+    // sending fails only when nobody is listening, the history still replays the event
+    let _ = progress.sender.send(event.clone());
+    progress.events.push(event);
+  }
+}
+
+/// Run the release in the background, its progress is available through [`release_events`]
+pub fn spawn_release(pool: PgPool, cluster: Cluster, job: ReleaseJob) {
+  let (sender, _) = broadcast::channel(EVENTS_CAPACITY);
+
+  running_releases().insert(
+    job.release_id,
+    ReleaseProgress {
+      events: Vec::new(),
+      sender,
+    },
+  );
+
+  tokio::spawn(run_release(pool, cluster, job));
+}
+
+async fn run_release(pool: PgPool, cluster: Cluster, job: ReleaseJob) {
+  let release_id = job.release_id;
+
+  let event = match execute_release(&pool, &cluster, job).await {
+    Ok(()) => ReleaseEvent::succeeded(),
+    Err(err) => {
+      let message = err.to_string();
+
+      log::error!("Release {release_id} failed: {message}");
+
+      let stored = sqlx::query!(
+        "UPDATE releases SET state = $1, error = $2 WHERE release_id = $3",
+        ReleaseState::Failed as _,
+        message,
+        release_id
+      )
+      .execute(&pool)
+      .await;
+
+      if let Err(err) = stored {
+        log::error!("Unable to store the outcome of release {release_id}: {err}");
+      }
+
+      match err {
+        JobError::Release(ReleaseError { kind, name, .. }) => {
+          ReleaseEvent::failed(Some(kind), Some(name), message)
+        }
+        JobError::Rollout(RolloutError { name, .. }) => {
+          ReleaseEvent::failed(Some("Deployment".to_string()), Some(name), message)
+        }
+        JobError::Database(_) => ReleaseEvent::failed(None, None, message),
+      }
+    }
+  };
+
+  publish(release_id, event);
+
+  // dropping the sender ends every stream following the release
+  running_releases().remove(&release_id);
+}
+
+async fn execute_release(
+  pool: &PgPool,
+  cluster: &Cluster,
+  job: ReleaseJob,
+) -> Result<(), JobError> {
+  let ReleaseJob {
+    release_id,
+    project,
+    apps,
+    cron_jobs,
+    envs,
+    volumes,
+    registries,
+    wait_for_healthy,
+  } = job;
+
+  sqlx::query!(
+    "UPDATE releases SET state = $1 WHERE release_id = $2",
+    ReleaseState::Running as _,
+    release_id
+  )
+  .execute(pool)
+  .await?;
+
+  let deleted_apps: Vec<Uuid> = apps
+    .iter()
+    .filter(|app| app.deleted)
+    .map(|app| app.app_id)
+    .collect();
+  let deleted_cron_jobs: Vec<Uuid> = cron_jobs
+    .iter()
+    .filter(|cron_job| cron_job.deleted)
+    .map(|cron_job| cron_job.cron_job_id)
+    .collect();
+  let deleted_volumes: Vec<Uuid> = volumes
+    .iter()
+    .filter(|volume| volume.deleted)
+    .map(|volume| volume.volume_id)
+    .collect();
+
+  let released = wait_for_healthy.then(|| apps.clone());
+  let progress: k8s::Progress = &|change| publish(release_id, ReleaseEvent::from(change));
+
+  let hooks = Mutex::new(Vec::new());
+  let report: k8s::HookReport = &|hook| hooks.lock().expect("Release hooks poisoned").push(hook);
+
+  // a project deleted meanwhile is being torn down, the release must not bring its objects back
+  let project_id = project.project_id;
+  let proceed: k8s::Proceed = &|| project_alive(pool, project_id).boxed();
+
+  let result = k8s::release(
+    cluster,
+    &project,
+    apps,
+    cron_jobs,
+    envs,
+    volumes,
+    registries,
+    progress,
+    Some(report),
+    proceed,
+  )
+  .await;
+
+  // the output of a failed pre-deploy command is the first thing to look at
+  let hooks = hooks.into_inner().expect("Release hooks poisoned");
+  if !hooks.is_empty() {
+    sqlx::query!(
+      "UPDATE releases SET hooks = $1 WHERE release_id = $2",
+      Json(hooks) as _,
+      release_id
+    )
+    .execute(pool)
+    .await?;
+  }
+
+  result?;
+
+  if let Some(released) = released {
+    if let Err(err) = k8s::wait_for_rollouts(cluster, &released).await {
+      log::warn!("Release {release_id} is rolled back: {err}");
+
+      roll_back_apps(pool, cluster, &project, &released, progress, proceed).await?;
+
+      return Err(err.into());
+    }
+  }
+
+  let mut tx = pool.begin().await?;
+
+  // envs of the deleted apps and cron jobs are removed by the cascade
+  sqlx::query!(
+    "DELETE FROM app_services WHERE app_id = ANY($1) AND deleted = true",
+    &deleted_apps
+  )
+  .execute(tx.as_mut())
+  .await?;
+
+  sqlx::query!(
+    "DELETE FROM cron_jobs WHERE cron_job_id = ANY($1) AND deleted = true",
+    &deleted_cron_jobs
+  )
+  .execute(tx.as_mut())
+  .await?;
+
+  sqlx::query!(
+    "DELETE FROM volumes WHERE volume_id = ANY($1) AND deleted = true",
+    &deleted_volumes
+  )
+  .execute(tx.as_mut())
+  .await?;
+
+  sqlx::query!(
+    "UPDATE releases SET state = $1 WHERE release_id = $2",
+    ReleaseState::Succeeded as _,
+    release_id
+  )
+  .execute(tx.as_mut())
+  .await?;
+
+  tx.commit().await?;
+
+  Ok(())
+}
+
+/// Bring the apps of a failed release back to the last successful one, the apps it did not have are left as they are
+async fn roll_back_apps(
+  pool: &PgPool,
+  cluster: &Cluster,
+  project: &Project,
+  released: &[AppService],
+  progress: k8s::Progress<'_>,
+  proceed: k8s::Proceed<'_>,
+) -> Result<(), JobError> {
+  let Some(previous) = released_state(pool, project.project_id).await? else {
+    return Ok(());
+  };
+
+  let apps = previous
+    .apps
+    .into_iter()
+    .filter(|app| {
+      released
+        .iter()
+        .any(|released| released.app_id == app.app_id)
+    })
+    .collect();
+
+  k8s::release_apps(
+    cluster,
+    apps,
+    &previous.envs,
+    &previous.volumes,
+    &previous.registries,
+    progress,
+    // the previous version already went through its pre-deploy commands
+    None,
+    proceed,
+  )
+  .await?;
+
+  Ok(())
+}
+
+/// Whether the project is still there and not being deleted, a failed check lets the release go on
+async fn project_alive(pool: &PgPool, project_id: Uuid) -> bool {
+  let deleting = sqlx::query_scalar!(
+    "SELECT deleting FROM projects WHERE project_id = $1",
+    project_id
+  )
+  .fetch_optional(pool)
+  .await;
+
+  match deleting {
+    Ok(deleting) => deleting == Some(false),
+    Err(err) => {
+      log::warn!("Unable to check whether project {project_id} is being deleted: {err}");
+      true
+    }
+  }
+}
+
+/// Events of a release running in this process, the ones already sent are replayed first.
+///
+/// `None` once the release is over, its outcome is then stored in the database.
+pub fn release_events(release_id: &Uuid) -> Option<impl Stream<Item = ReleaseEvent>> {
+  let releases = running_releases();
+  let progress = releases.get(release_id)?;
+
+  let history = stream::iter(progress.events.clone());
+  let live = stream::unfold(progress.sender.subscribe(), |mut receiver| async move {
+    loop {
+      match receiver.recv().await {
+        Ok(event) => return Some((event, receiver)),
+        Err(RecvError::Lagged(_)) => continue,
+        Err(RecvError::Closed) => return None,
+      }
+    }
+  });
+
+  Some(history.chain(live))
+}
+
+/// Mark as failed the releases a previous run of the server left unfinished
+pub async fn fail_interrupted_releases(pool: &PgPool) -> sqlx::Result<()> {
+  sqlx::query!(
+    "UPDATE releases SET state = $1, error = $2 WHERE state = $3 OR state = $4",
+    ReleaseState::Failed as _,
+    "Release interrupted by a server restart",
+    ReleaseState::Pending as _,
+    ReleaseState::Running as _
+  )
+  .execute(pool)
+  .await?;
+
+  Ok(())
+}
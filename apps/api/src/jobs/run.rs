@@ -0,0 +1,72 @@
+use chrono::Utc;
+use derive_more::derive::{Display, From};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+  k8s::{self, Cluster},
+  schemas::RunState,
+};
+
+#[derive(Debug, Display, From)]
+enum JobError {
+  #[display("K8s error: {_0}")]
+  Cluster(kube::Error),
+  #[display("Database error: {_0}")]
+  Database(sqlx::Error),
+}
+
+/// Follow the job of the run in the background, storing its outcome and logs once it finishes
+pub fn spawn_run(pool: PgPool, cluster: Cluster, project_id: Uuid, run_id: Uuid) {
+  tokio::spawn(async move {
+    if let Err(err) = follow_run(&pool, &cluster, &project_id, &run_id).await {
+      log::error!("Unable to store the outcome of run {run_id}: {err}");
+    }
+  });
+}
+
+async fn follow_run(
+  pool: &PgPool,
+  cluster: &Cluster,
+  project_id: &Uuid,
+  run_id: &Uuid,
+) -> Result<(), JobError> {
+  let outcome = k8s::wait_for_run(cluster, project_id, run_id)
+    .await?
+    .unwrap_or_else(k8s::removed_job);
+
+  let state = match outcome.succeeded {
+    true => RunState::Succeeded,
+    false => RunState::Failed,
+  };
+
+  sqlx::query!(
+    "UPDATE runs SET state = $1, exit_code = $2, error = $3, logs = $4, finished_at = $5 WHERE run_id = $6",
+    state as _,
+    outcome.exit_code,
+    outcome.error,
+    outcome.logs,
+    Utc::now().naive_utc(),
+    run_id
+  )
+  .execute(pool)
+  .await?;
+
+  Ok(())
+}
+
+/// Follow again the runs a previous run of the server left unfinished, their jobs kept running
+pub async fn resume_runs(pool: &PgPool, cluster: &Cluster) -> sqlx::Result<()> {
+  let runs = sqlx::query!(
+    "SELECT run_id, project_id FROM runs JOIN app_services USING (app_id) WHERE state = $1",
+    RunState::Running as _
+  )
+  .fetch_all(pool)
+  .await?;
+
+  for run in runs {
+    spawn_run(pool.clone(), cluster.clone(), run.project_id, run.run_id);
+  }
+
+  Ok(())
+}
@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+
+use crate::{
+  k8s::{self, Cluster},
+  schemas::{AppService, CronJob, Project, Volume},
+  ApiResult,
+};
+
+const TEARDOWN_RETRY_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Remove the cluster resources of a project marked as deleting, then its rows.
+///
+/// The project keeps its deleting mark when the teardown fails, so it can be retried.
+pub async fn teardown_project(
+  pool: &PgPool,
+  cluster: &Cluster,
+  project: Project,
+) -> ApiResult<Project> {
+  let apps = sqlx::query_as!(
+    AppService,
+    "SELECT * FROM app_services WHERE project_id = $1",
+    project.project_id
+  )
+  .fetch_all(pool)
+  .await?;
+
+  let cron_jobs = sqlx::query_as!(
+    CronJob,
+    "SELECT * FROM cron_jobs WHERE project_id = $1",
+    project.project_id
+  )
+  .fetch_all(pool)
+  .await?;
+
+  let volumes = sqlx::query_as!(
+    Volume,
+    r#"
+    SELECT volumes.*, (
+      SELECT COALESCE(jsonb_agg(jsonb_build_object('id', volume_apps.app_id, 'path', volume_apps.path)), '[]')
+      FROM volume_apps
+      WHERE volume_apps.volume_id = volumes.volume_id
+    ) AS "apps!"
+    FROM volumes
+    WHERE project_id = $1
+    "#,
+    project.project_id
+  )
+  .fetch_all(pool)
+  .await?;
+
+  k8s::teardown(cluster, &project, apps, cron_jobs, volumes).await?;
+
+  let project = sqlx::query_as!(
+    Project,
+    "DELETE FROM projects WHERE project_id = $1 RETURNING *",
+    project.project_id
+  )
+  .fetch_one(pool)
+  .await?;
+
+  Ok(project)
+}
+
+/// Periodically retry the teardowns that failed, starting right away
+pub fn spawn_teardown_retries(pool: PgPool, cluster: Cluster) {
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(TEARDOWN_RETRY_INTERVAL);
+
+    loop {
+      interval.tick().await;
+
+      let projects = sqlx::query_as!(Project, "SELECT * FROM projects WHERE deleting = true")
+        .fetch_all(&pool)
+        .await;
+
+      let projects = match projects {
+        Ok(projects) => projects,
+        Err(err) => {
+          log::error!("Unable to list the projects being deleted: {err}");
+          continue;
+        }
+      };
+
+      for project in projects {
+        // errors are logged by their conversion
+        let _ = teardown_project(&pool, &cluster, project).await;
+      }
+    }
+  });
+}
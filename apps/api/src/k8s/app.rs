@@ -1,130 +1,756 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, time::Duration};
 
-use futures::{stream::MapOk, Stream, TryStreamExt};
-use k8s_openapi::api::{
-  apps::v1::Deployment,
-  core::v1::{Secret, Service, Volume as PodVolume, VolumeMount},
-  networking::v1::Ingress,
-};
-use kube::{
-  api::{ListParams, Patch, PatchParams, PostParams},
-  runtime::{
-    utils::{EventFlatten, StreamBackoff},
-    watcher::{self, watcher, DefaultBackoff, Event},
-    WatchStreamExt,
+use futures::{future, stream, Stream, StreamExt, TryStreamExt};
+use k8s_openapi::{
+  api::{
+    apps::v1::Deployment,
+    autoscaling::v2::HorizontalPodAutoscaler,
+    batch::v1::Job,
+    core::v1::{Container, Event as CoreEvent, PodSpec, Secret, Service},
+    networking::v1::Ingress,
   },
-  Api, Client, ResourceExt, Result,
+  apimachinery::pkg::apis::meta::v1::ObjectMeta,
 };
-use serde_json::json;
+use kube::{runtime::watcher, Resource, ResourceExt, Result};
+use serde_json::{json, Value};
 use uuid::Uuid;
 
-use crate::schemas::{AppService, AppStatus, EnvVar, Project, Volume};
+use crate::{
+  registry::image_registry,
+  schemas::{
+    AppKind, AppPort, AppReleaseState, AppService, AppStatus, AppStatusEvent, ContainerKind,
+    EnvVar, PlanAction, PortProtocol, Probe, ProbeCheck, ProbeFailure, Registry, ReleaseHook,
+    ResourceChange, StrategyKind, Volume, DEFAULT_PROGRESS_DEADLINE,
+  },
+};
 
-use super::K8S_CONFIG;
+use super::{
+  app_labels, check_proceed, cpu_quantity,
+  exposure::{plan_exposure, reconcile_exposure},
+  memory_quantity, plan,
+  project::registries_secret_name,
+  project_namespace, report,
+  run::run_pre_deploy,
+  volume::{mount_volumes, single_replica, volume_mount},
+  Cluster, HookError, HookReport, Proceed, Progress, ReleaseError, RolloutError, K8S_CONFIG,
+};
 
-pub async fn app_status(
+/// Status of the deployment of an app, along with the probes failing on its replicas
+pub fn app_status(
+  cluster: &Cluster,
+  project_id: &Uuid,
   id: &Uuid,
-) -> Result<
-  MapOk<
-    StreamBackoff<
-      EventFlatten<impl Stream<Item = Result<Event<Deployment>, watcher::Error>> + Send>,
-      DefaultBackoff,
-    >,
-    impl FnMut(Deployment) -> AppStatus,
-  >,
-> {
-  let client = Client::try_default().await?;
-  let api: Api<Deployment> = Api::namespaced(client, &K8S_CONFIG.namespace);
-
-  let stream = watcher(
-    api,
-    watcher::Config::default().fields(&format!("metadata.name=app-{id}")),
+) -> impl Stream<Item = watcher::Result<AppStatusEvent>> + Send {
+  let namespace = project_namespace(project_id);
+
+  // a blue/green or canary app runs several deployments, each of them is watched
+  let name = format!("app-{id}");
+  let deploys = cluster.api::<Deployment>(&namespace);
+  let status = stream::select_all(
+    Track::ALL.map(|track| deploys.watch(&format!("metadata.name={}", track.deploy_name(&name)))),
   )
-  .applied_objects()
-  .default_backoff()
-  .map_ok(|deploy| match deploy.status {
-    Some(status) => AppStatus::from(status),
-    None => AppStatus::default(),
-  });
+  .map_ok(|deploy| AppStatusEvent::Status(AppStatus::from(deploy)));
+
+  // pods of the app are named after its deployment, the kubelet reports failed probes as unhealthy
+  let pod_prefix = format!("app-{id}-");
+  let probe_failures = cluster
+    .api::<CoreEvent>(&namespace)
+    .watch("involvedObject.kind=Pod,reason=Unhealthy")
+    .try_filter(move |event| {
+      let matches = event
+        .involved_object
+        .name
+        .as_ref()
+        .is_some_and(|name| name.starts_with(&pod_prefix));
+      future::ready(matches)
+    })
+    .map_ok(|event| {
+      AppStatusEvent::ProbeFailure(ProbeFailure {
+        replica: event.involved_object.name.unwrap_or_default(),
+        message: event.message.unwrap_or_default(),
+        count: event.count.unwrap_or(1),
+      })
+    });
+
+  stream::select(status, probe_failures)
+}
+
+/// Wait for the deployment of the app to roll out, failing once the cluster gives up on it or after `timeout`
+pub async fn wait_for_rollout(
+  app: &AppService,
+  cluster: &Cluster,
+  timeout: Duration,
+) -> Result<(), RolloutError> {
+  let name = format!("app-{}", app.app_id);
+
+  let track = released_track(&name, app, cluster)
+    .await
+    .map_err(|err| RolloutError {
+      name: name.clone(),
+      reason: err.to_string(),
+    })?;
+
+  wait_for_deploy(
+    &track.deploy_name(&name),
+    &project_namespace(&app.project_id),
+    cluster,
+    timeout,
+  )
+  .await
+}
+
+async fn wait_for_deploy(
+  name: &str,
+  namespace: &str,
+  cluster: &Cluster,
+  timeout: Duration,
+) -> Result<(), RolloutError> {
+  let failed = |reason: String| RolloutError {
+    name: name.to_string(),
+    reason,
+  };
+
+  let mut statuses = cluster
+    .api::<Deployment>(namespace)
+    .watch(&format!("metadata.name={name}"));
+
+  let rollout = async {
+    while let Some(deploy) = statuses.next().await {
+      let deploy = match deploy {
+        Ok(deploy) => deploy,
+        Err(err) => {
+          log::warn!("Unable to watch the rollout of {name}: {err}");
+          continue;
+        }
+      };
+
+      match AppStatus::from(deploy).state {
+        AppReleaseState::Released => return Ok(()),
+        AppReleaseState::Failed => return Err(failed("progress deadline exceeded".to_string())),
+        AppReleaseState::Progressing | AppReleaseState::Unknown => {}
+      }
+    }
+
+    Err(failed("watch ended".to_string()))
+  };
+
+  tokio::time::timeout(timeout, rollout)
+    .await
+    .unwrap_or_else(|_| {
+      Err(failed(format!(
+        "not available after {}s",
+        timeout.as_secs()
+      )))
+    })
+}
+
+/// Deployments of an app, a blue/green or canary release brings one up next to the serving one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Track {
+  Stable,
+  Blue,
+  Green,
+  Canary,
+}
+
+const TRACK_LABEL: &str = "track";
+
+impl Track {
+  const ALL: [Track; 4] = [Track::Stable, Track::Blue, Track::Green, Track::Canary];
+
+  fn deploy_name(self, name: &str) -> String {
+    match self.label() {
+      Some(label) => format!("{name}-{label}"),
+      None => name.to_string(),
+    }
+  }
+
+  /// Tells the pods of the track apart, the stable pods have none
+  fn label(self) -> Option<&'static str> {
+    match self {
+      Track::Stable => None,
+      Track::Blue => Some("blue"),
+      Track::Green => Some("green"),
+      Track::Canary => Some("canary"),
+    }
+  }
+
+  /// Track the service selects once this one is released, the canary pods share the stable selector
+  fn selected(self) -> Track {
+    match self {
+      Track::Canary => Track::Stable,
+      track => track,
+    }
+  }
+
+  /// Labels selecting the pods of the track, the stable deployment also selects the canary
+  /// pods but its replica sets only count the pods they own
+  fn selector(self, name: &str) -> BTreeMap<String, String> {
+    let mut selector = BTreeMap::from([("app".to_string(), name.to_string())]);
+    if let Some(label) = self.label() {
+      selector.insert(TRACK_LABEL.to_string(), label.to_string());
+    }
+    selector
+  }
+}
 
-  Ok(stream)
+/// Apps with a ReadWriteOnce volume roll their single replica, a second deployment could not mount the claim
+fn strategy_kind(app: &AppService, volumes: &[&Volume]) -> StrategyKind {
+  match &app.strategy.kind {
+    StrategyKind::BlueGreen | StrategyKind::Canary { .. } if single_replica(volumes) => {
+      StrategyKind::default()
+    }
+    // without a service there is no traffic to switch
+    StrategyKind::BlueGreen if app.kind == AppKind::Worker => StrategyKind::default(),
+    kind => kind.clone(),
+  }
 }
 
+/// Track the service of the app sends the traffic to, `None` before the service exists
+async fn service_track(name: &str, app: &AppService, cluster: &Cluster) -> Result<Option<Track>> {
+  let service = cluster
+    .api::<Service>(&project_namespace(&app.project_id))
+    .get_opt(name)
+    .await?;
+
+  Ok(service.map(|service| {
+    let selected = service
+      .spec
+      .and_then(|spec| spec.selector)
+      .and_then(|mut selector| selector.remove(TRACK_LABEL));
+
+    Track::ALL
+      .into_iter()
+      .find(|track| track.label() == selected.as_deref())
+      .unwrap_or(Track::Stable)
+  }))
+}
+
+/// Track a release of the app changed last: the canary while there is one, the selected one otherwise
+async fn released_track(name: &str, app: &AppService, cluster: &Cluster) -> Result<Track> {
+  let canary = cluster
+    .api::<Deployment>(&project_namespace(&app.project_id))
+    .get_opt(&Track::Canary.deploy_name(name))
+    .await?;
+
+  if canary.is_some() {
+    return Ok(Track::Canary);
+  }
+
+  Ok(
+    service_track(name, app, cluster)
+      .await?
+      .unwrap_or(Track::Stable),
+  )
+}
+
+/// Deployment running the last released version of the app, `None` before its first release
+pub(super) async fn released_deploy(
+  app: &AppService,
+  cluster: &Cluster,
+) -> Result<Option<Deployment>> {
+  let name = format!("app-{}", app.app_id);
+  let track = released_track(&name, app, cluster).await?;
+
+  cluster
+    .api::<Deployment>(&project_namespace(&app.project_id))
+    .get_opt(&track.deploy_name(&name))
+    .await
+}
+
+/// Track a release of the app brings up, or the one a plan compares with
+async fn target_track(
+  name: &str,
+  app: &AppService,
+  volumes: &[&Volume],
+  cluster: &Cluster,
+) -> Result<Track> {
+  let track = match strategy_kind(app, volumes) {
+    StrategyKind::BlueGreen => match service_track(name, app, cluster).await? {
+      Some(Track::Blue) => Track::Green,
+      _ => Track::Blue,
+    },
+    // the first release of a canary app, or one promoting the canary, goes to the stable track
+    StrategyKind::Canary { weight } if weight < 100 => {
+      let stable = cluster
+        .api::<Deployment>(&project_namespace(&app.project_id))
+        .get_opt(name)
+        .await?;
+
+      match stable {
+        Some(_) => Track::Canary,
+        None => Track::Stable,
+      }
+    }
+    _ => Track::Stable,
+  };
+
+  Ok(track)
+}
+
+/// Secret the containers of the deployment read their envs from
+fn deploy_secret(deploy: &Deployment) -> Option<String> {
+  deploy
+    .spec
+    .as_ref()?
+    .template
+    .spec
+    .as_ref()?
+    .containers
+    .first()?
+    .env_from
+    .as_ref()?
+    .iter()
+    .find_map(|source| source.secret_ref.as_ref()?.name.clone())
+}
+
+/// Reconcile the resources of the app, running its pre-deploy command first unless `hooks` is `None`.
+///
+/// `proceed` is checked again once the command ran, it may have taken a while.
+#[allow(clippy::too_many_arguments)]
 pub async fn reconcile_app(
-  project: &Project,
   app: AppService,
   envs: Vec<&EnvVar>,
-  volume: Option<&Volume>,
-  client: Client,
-) -> Result<()> {
+  volumes: &[&Volume],
+  registries: &[Registry],
+  cluster: &Cluster,
+  progress: Progress<'_>,
+  hooks: Option<HookReport<'_>>,
+  proceed: Option<Proceed<'_>>,
+) -> Result<(), ReleaseError> {
   let name = format!("app-{}", app.app_id);
-  let params = PatchParams::apply(&K8S_CONFIG.manager).force();
 
-  let secret_name = reconcile_secret(&name, (!app.deleted).then_some(envs), client.clone()).await?;
+  // the secrets of the deployments already there are only pruned once the new ones rolled out
+  let secret_name = match app.deleted {
+    true => None,
+    false => Some(
+      release_secret(&name, &app, envs, volumes, cluster, progress)
+        .await
+        .map_err(ReleaseError::of::<Secret>(&name))?,
+    ),
+  };
+
+  let pull_secret = app_pull_secret(&app, registries);
+
+  if let (Some(command), Some(secret_name), Some(hooks)) = (&app.pre_deploy, &secret_name, hooks) {
+    let pod = generate_deploy(
+      &name,
+      &app,
+      secret_name.clone(),
+      pull_secret.clone(),
+      volumes,
+      Track::Stable,
+    )
+    .spec
+    .and_then(|spec| spec.template.spec)
+    .expect("Invalid app deployment");
+
+    let result = match pre_deploy(&app, command, pod, cluster, progress, hooks).await {
+      Ok(()) => match proceed {
+        Some(proceed) => check_proceed(&app.project_id, proceed).await,
+        None => Ok(()),
+      },
+      err => err,
+    };
+
+    // the deployments are left as they were, only a secret created for them is pruned
+    if let Err(err) = result {
+      prune_secrets(&name, &app, cluster, progress).await?;
+
+      return Err(err);
+    }
+  }
 
-  reconcile_deploy(
+  reconcile_deploys(
     &name,
-    project,
     &app,
     secret_name,
-    volume,
-    client.clone(),
-    &params,
+    pull_secret,
+    volumes,
+    cluster,
+    progress,
   )
   .await?;
 
-  reconcile_svc(&name, &app, client.clone(), &params).await?;
+  prune_secrets(&name, &app, cluster, progress).await?;
+
+  report::<HorizontalPodAutoscaler>(
+    &name,
+    reconcile_hpa(&name, &app, volumes, cluster).await,
+    progress,
+  )?;
 
-  reconcile_ingress(&name, &app, client.clone(), &params).await?;
+  report::<Ingress>(
+    &name,
+    reconcile_ingress(&name, &app, cluster).await,
+    progress,
+  )?;
 
   Ok(())
 }
 
-async fn reconcile_deploy(
+/// Run the pre-deploy command with the pods about to roll out, failing the release before they do when it fails
+async fn pre_deploy(
+  app: &AppService,
+  command: &[String],
+  pod: PodSpec,
+  cluster: &Cluster,
+  progress: Progress<'_>,
+  hooks: HookReport<'_>,
+) -> Result<(), ReleaseError> {
+  let (name, outcome) =
+    run_pre_deploy(app, command, pod, cluster)
+      .await
+      .map_err(ReleaseError::of::<Job>(&format!(
+        "app-{}-pre-deploy",
+        app.app_id
+      )))?;
+
+  progress(plan::change::<Job>(&name, PlanAction::Create));
+
+  hooks(ReleaseHook {
+    app_id: app.app_id,
+    name: name.clone(),
+    command: command.to_vec(),
+    succeeded: outcome.succeeded,
+    exit_code: outcome.exit_code,
+    error: outcome.error.clone(),
+    logs: outcome.logs,
+  });
+
+  if outcome.succeeded {
+    return Ok(());
+  }
+
+  let reason = match (outcome.exit_code, outcome.error) {
+    (Some(exit_code), _) => format!("exited with {exit_code}"),
+    (None, Some(error)) => error,
+    (None, None) => "unknown error".to_string(),
+  };
+
+  Err(ReleaseError {
+    kind: Job::kind(&()).to_string(),
+    name,
+    source: Box::new(HookError { reason }.into()),
+  })
+}
+
+/// Track the plan compares the app with, a blue/green app with the track serving it, a canary app with its canary
+async fn plan_track(
+  name: &str,
+  app: &AppService,
+  volumes: &[&Volume],
+  cluster: &Cluster,
+) -> Result<Track> {
+  Ok(match target_track(name, app, volumes, cluster).await? {
+    Track::Blue | Track::Green => service_track(name, app, cluster)
+      .await?
+      .filter(|track| *track != Track::Stable)
+      .unwrap_or(Track::Blue),
+    track => track,
+  })
+}
+
+pub async fn plan_app(
+  app: &AppService,
+  envs: Vec<&EnvVar>,
+  volumes: &[&Volume],
+  registries: &[Registry],
+  cluster: &Cluster,
+) -> Result<Vec<ResourceChange>> {
+  let name = format!("app-{}", app.app_id);
+  let mut changes = Vec::new();
+
+  let track = plan_track(&name, app, volumes, cluster).await?;
+  let deploy_name = track.deploy_name(&name);
+
+  let deploy = cluster
+    .api::<Deployment>(&project_namespace(&app.project_id))
+    .get_opt(&deploy_name)
+    .await?;
+
+  let secrets = cluster
+    .api::<Secret>(&project_namespace(&app.project_id))
+    .list(&format!("app-name={name}"))
+    .await?;
+
+  let secret = deploy
+    .as_ref()
+    .and_then(deploy_secret)
+    .and_then(|secret_name| {
+      secrets
+        .iter()
+        .find(|secret| secret.name_any() == secret_name)
+    });
+
+  // a release creates a new secret only when the values differ
+  let secret_name = match secret {
+    _ if app.deleted => {
+      changes.extend(
+        secrets
+          .iter()
+          .map(|secret| plan::change::<Secret>(&secret.name_any(), PlanAction::Delete)),
+      );
+      None
+    }
+    None => {
+      changes.push(plan::change::<Secret>(&name, PlanAction::Create));
+      None
+    }
+    Some(secret) if secret_values(secret) == env_values(&envs) => {
+      changes.push(plan::change::<Secret>(
+        &secret.name_any(),
+        PlanAction::Unchanged,
+      ));
+      Some(secret.name_any())
+    }
+    Some(secret) => {
+      changes.push(plan::change::<Secret>(
+        &secret.name_any(),
+        PlanAction::Patch,
+      ));
+      None
+    }
+  };
+
+  let deploy_action = match (&deploy, secret_name) {
+    (Some(_), _) if app.deleted => Some(PlanAction::Delete),
+    (None, _) if app.deleted => None,
+    (None, _) => Some(PlanAction::Create),
+    (Some(_), None) => Some(PlanAction::Patch),
+    (Some(deploy), Some(secret_name)) => Some(plan::diff(
+      Some(deploy),
+      &generate_deploy(
+        &name,
+        app,
+        secret_name,
+        app_pull_secret(app, registries),
+        volumes,
+        track,
+      ),
+    )),
+  };
+  changes.extend(deploy_action.map(|action| plan::change::<Deployment>(&deploy_name, action)));
+
+  let hpa = cluster
+    .api::<HorizontalPodAutoscaler>(&project_namespace(&app.project_id))
+    .get_opt(&name)
+    .await?;
+
+  let hpa_action = match (hpa, autoscaled(app, volumes)) {
+    (Some(_), false) => Some(PlanAction::Delete),
+    (None, false) => None,
+    (hpa, true) => Some(plan::diff(hpa.as_ref(), &generate_hpa(&name, app))),
+  };
+  changes.extend(hpa_action.map(|action| plan::change::<HorizontalPodAutoscaler>(&name, action)));
+
+  let service = cluster
+    .api::<Service>(&project_namespace(&app.project_id))
+    .get_opt(&name)
+    .await?;
+
+  let service_action = match (service, app.deleted || app.kind == AppKind::Worker) {
+    (Some(_), true) => Some(PlanAction::Delete),
+    (None, true) => None,
+    (service, false) => Some(plan::diff(
+      service.as_ref(),
+      &generate_svc(&name, app, track.selected()),
+    )),
+  };
+  changes.extend(service_action.map(|action| plan::change::<Service>(&name, action)));
+
+  changes.extend(plan_exposure(&name, app, &track.selected().selector(&name), cluster).await?);
+
+  let ingress = cluster
+    .api::<Ingress>(&project_namespace(&app.project_id))
+    .get_opt(&name)
+    .await?;
+
+  let ingress_action = match ingress {
+    Some(_) if app.deleted || app.public_domain.is_none() => Some(PlanAction::Delete),
+    None if app.deleted || app.public_domain.is_none() => None,
+    None => Some(PlanAction::Create),
+    Some(ingress) if ingress_changed(&ingress, app) => Some(PlanAction::Patch),
+    Some(_) => Some(PlanAction::Unchanged),
+  };
+  changes.extend(ingress_action.map(|action| plan::change::<Ingress>(&name, action)));
+
+  Ok(changes)
+}
+
+async fn reconcile_deploys(
   name: &str,
-  project: &Project,
   app: &AppService,
   secret_name: Option<String>,
-  volume: Option<&Volume>,
-  client: Client,
-  params: &PatchParams,
-) -> Result<()> {
-  let api: Api<Deployment> = Api::namespaced(client, &K8S_CONFIG.namespace);
+  pull_secret: Option<String>,
+  volumes: &[&Volume],
+  cluster: &Cluster,
+  progress: Progress<'_>,
+) -> Result<(), ReleaseError> {
+  let namespace = project_namespace(&app.project_id);
+  let api = cluster.api::<Deployment>(&namespace);
 
-  let current_deploy = api.get_opt(name).await?;
+  let Some(secret_name) = secret_name.filter(|_| !app.deleted) else {
+    for track in Track::ALL {
+      let deploy_name = track.deploy_name(name);
+      report::<Deployment>(
+        &deploy_name,
+        delete_deploy(&deploy_name, &namespace, cluster).await,
+        progress,
+      )?;
+    }
 
-  if current_deploy.is_some() && app.deleted {
-    api.delete(name, &Default::default()).await?;
-    return Ok(());
+    report::<Service>(
+      name,
+      reconcile_svc(name, app, Track::Stable, cluster).await,
+      progress,
+    )?;
+
+    return reconcile_exposure(name, app, &Track::Stable.selector(name), cluster, progress).await;
+  };
+
+  let track = target_track(name, app, volumes, cluster)
+    .await
+    .map_err(ReleaseError::of::<Deployment>(name))?;
+  let deploy_name = track.deploy_name(name);
+
+  let applied = async {
+    let current = api.get_opt(&deploy_name).await?;
+
+    api
+      .apply(
+        &deploy_name,
+        &generate_deploy(name, app, secret_name, pull_secret, volumes, track),
+      )
+      .await?;
+
+    Ok(Some(match current {
+      Some(_) => PlanAction::Patch,
+      None => PlanAction::Create,
+    }))
+  };
+  report::<Deployment>(&deploy_name, applied.await, progress)?;
+
+  if let StrategyKind::Canary { weight } = app.strategy.kind {
+    if track == Track::Canary {
+      let stable_replicas = app.replicas - canary_replicas(app.replicas, weight);
+      report::<Deployment>(
+        name,
+        scale_deploy(name, &namespace, stable_replicas, cluster).await,
+        progress,
+      )?;
+    }
   }
 
-  // TODO patch deploy only when envs change, this can be achieved whit an update timestamp in envs and a release timestamp in projects
-  // secret name is generated by k8s so is always different
-  if let Some(secret_name) = secret_name {
-    let deploy = generate_deploy(name, project, app, secret_name, volume);
+  // the traffic only moves to another track once it is available
+  let selected = service_track(name, app, cluster)
+    .await
+    .map_err(ReleaseError::of::<Service>(name))?;
+
+  if selected.is_some_and(|selected| selected != track.selected()) {
+    let timeout = Duration::from_secs(K8S_CONFIG.rollout_timeout);
 
-    api.patch(name, params, &Patch::Apply(deploy)).await?;
+    wait_for_deploy(&deploy_name, &namespace, cluster, timeout)
+      .await
+      .map_err(|err| ReleaseError {
+        kind: Deployment::kind(&()).to_string(),
+        name: deploy_name.clone(),
+        source: Box::new(err.into()),
+      })?;
   }
-  /*
-  if current_deploy.is_none()
-    || get_replicas(current_deploy.as_ref()).is_some_and(|replicas| replicas != app.replicas)
-    || get_container_by_name(current_deploy.as_ref(), name).is_some_and(|container| {
-      container
-        .image
-        .as_ref()
-        .is_some_and(|image| image != &app.image)
-    })
-    || get_port_by_name(current_deploy.as_ref(), name, "app")
-      .is_some_and(|port| port.container_port != app.port)
-    || secret_name.is_some()
-  {
-    let deploy = generate_deploy(name, app, secret_name);
 
-    api.patch(name, params, &Patch::Apply(deploy)).await?;
-  } */
+  report::<Service>(
+    name,
+    reconcile_svc(name, app, track.selected(), cluster).await,
+    progress,
+  )?;
+
+  reconcile_exposure(
+    name,
+    app,
+    &track.selected().selector(name),
+    cluster,
+    progress,
+  )
+  .await?;
+
+  for leftover in Track::ALL {
+    if leftover == track || (track == Track::Canary && leftover == Track::Stable) {
+      continue;
+    }
+
+    let leftover_name = leftover.deploy_name(name);
+    report::<Deployment>(
+      &leftover_name,
+      delete_deploy(&leftover_name, &namespace, cluster).await,
+      progress,
+    )?;
+  }
 
   Ok(())
 }
+
+async fn delete_deploy(
+  name: &str,
+  namespace: &str,
+  cluster: &Cluster,
+) -> Result<Option<PlanAction>> {
+  let api = cluster.api::<Deployment>(namespace);
+
+  if api.get_opt(name).await?.is_none() {
+    return Ok(None);
+  }
+
+  api.delete(name).await?;
+
+  Ok(Some(PlanAction::Delete))
+}
+
+/// Give the stable replicas of a canary app their share, leaving the version they run as it is
+async fn scale_deploy(
+  name: &str,
+  namespace: &str,
+  replicas: i32,
+  cluster: &Cluster,
+) -> Result<Option<PlanAction>> {
+  let api = cluster.api::<Deployment>(namespace);
+
+  let Some(current) = api.get_opt(name).await? else {
+    return Ok(None);
+  };
+
+  let Some(mut spec) = current.spec else {
+    return Ok(None);
+  };
+
+  if spec.replicas == Some(replicas) {
+    return Ok(Some(PlanAction::Unchanged));
+  }
+
+  spec.replicas = Some(replicas);
+
+  // only the fields gws applies go back, the server owns the rest of the metadata
+  let scaled = Deployment {
+    metadata: ObjectMeta {
+      name: current.metadata.name,
+      namespace: current.metadata.namespace,
+      labels: current.metadata.labels,
+      ..Default::default()
+    },
+    spec: Some(spec),
+    status: None,
+  };
+
+  api.apply(name, &scaled).await?;
+
+  Ok(Some(PlanAction::Patch))
+}
+
+/// Replicas running the canary, at least one as long as the app runs any
+fn canary_replicas(replicas: i32, weight: i32) -> i32 {
+  (replicas * weight + 99) / 100
+}
+
 /*
 fn get_port_by_name<'a>(
   deploy: Option<&'a Deployment>,
@@ -183,54 +809,77 @@ fn get_deploy_spec(deploy: Option<&Deployment>) -> Option<&DeploymentSpec> {
  */
 fn generate_deploy(
   name: &str,
-  project: &Project,
   app: &AppService,
   secret_name: String,
-  volume: Option<&Volume>,
+  pull_secret: Option<String>,
+  volumes: &[&Volume],
+  track: Track,
 ) -> Deployment {
-  let replicas = match volume {
-    Some(_) => 1,
-    None => app.replicas,
+  // an autoscaled deployment leaves its replicas to the autoscaler
+  let replicas = match (single_replica(volumes), track, &app.strategy.kind) {
+    (true, _, _) => Some(1),
+    (false, Track::Canary, StrategyKind::Canary { weight }) => {
+      Some(canary_replicas(app.replicas, *weight))
+    }
+    (false, _, _) if autoscaled(app, volumes) => None,
+    (false, _, _) => Some(app.replicas),
   };
 
+  let strategy = match strategy_kind(app, volumes) {
+    StrategyKind::RollingUpdate {
+      max_surge,
+      max_unavailable,
+    } => json!({
+      "type": "RollingUpdate",
+      "rollingUpdate": {
+        "maxSurge": max_surge,
+        "maxUnavailable": max_unavailable
+      }
+    }),
+    StrategyKind::Recreate => json!({ "type": "Recreate" }),
+    // the tracks of blue/green and canary apps roll with the defaults
+    StrategyKind::BlueGreen | StrategyKind::Canary { .. } => Value::Null,
+  };
+
+  let mut pod_labels = track.selector(name);
+  pod_labels.insert("project".to_string(), app.project_id.to_string());
+
+  let init_containers = extra_containers(app, ContainerKind::Init, &secret_name, volumes);
+
   let mut deploy: Deployment = serde_json::from_value(json!({
     "apiVersion": "apps/v1",
     "kind": "Deployment",
     "metadata": {
-      "name": name,
-      "namespace": K8S_CONFIG.namespace,
+      "name": track.deploy_name(name),
+      "namespace": project_namespace(&app.project_id),
+      "labels": app_labels(app),
     },
     "spec": {
-      "progressDeadlineSeconds": 120,
+      "progressDeadlineSeconds": app
+        .strategy
+        .progress_deadline_seconds
+        .unwrap_or(DEFAULT_PROGRESS_DEADLINE),
       "replicas": replicas,
+      "strategy": strategy,
       "selector": {
-        "matchLabels": {
-          "app": name
-        }
+        "matchLabels": track.selector(name)
       },
       "template": {
         "metadata": {
-          "labels": {
-            "app": name,
-            "project": project.project_id
-          }
+          "labels": pod_labels
         },
         "spec": {
           "enableServiceLinks": false,
+          "imagePullSecrets": pull_secret.map(|name| json!([{ "name": name }])),
+          "initContainers": (!init_containers.is_empty()).then_some(init_containers),
           "containers": [
             {
               "name": name,
-              "image": app.image,
-              "env": [
-                {
-                  "name": "ADDRESS",
-                  "value": "0.0.0.0"
-                },
-                {
-                  "name": "PORT",
-                  "value": app.port.to_string()
-                }
-              ],
+              "image": pinned_image(app),
+              "command": app.command,
+              "args": app.args,
+              "workingDir": app.working_dir,
+              "env": listen_envs(app),
               "envFrom": [
                 {
                   "secretRef": {
@@ -238,12 +887,20 @@ fn generate_deploy(
                   }
                 }
               ],
-              "ports": [
-                {
-                  "name": K8S_CONFIG.port_name,
-                  "containerPort": app.port
+              "ports": container_ports(app),
+              "resources": {
+                "requests": {
+                  "cpu": cpu_quantity(app.cpu_request),
+                  "memory": memory_quantity(app.memory_request)
+                },
+                "limits": {
+                  "cpu": cpu_quantity(app.cpu_limit),
+                  "memory": memory_quantity(app.memory_limit)
                 }
-              ]
+              },
+              "livenessProbe": app.probes.liveness.as_ref().map(generate_probe),
+              "readinessProbe": app.probes.readiness.as_ref().map(generate_probe),
+              "startupProbe": app.probes.startup.as_ref().map(generate_probe)
             }
           ]
         }
@@ -252,82 +909,326 @@ fn generate_deploy(
   }))
   .expect("Invalid app deployment");
 
-  if let Some(volume) = volume {
-    let name = format!("volume-{}", volume.volume_id);
-    let spec = deploy
-      .spec
-      .as_mut()
-      .unwrap()
-      .template
-      .spec
-      .as_mut()
-      .unwrap();
+  let spec = deploy
+    .spec
+    .as_mut()
+    .unwrap()
+    .template
+    .spec
+    .as_mut()
+    .unwrap();
 
-    let pod_volume: PodVolume = serde_json::from_value(json!({
-      "name": name,
-      "persistentVolumeClaim": {
-        "claimName": name,
+  // each app mounts a volume at its own path
+  let mounts: Vec<_> = volumes
+    .iter()
+    .filter_map(|volume| Some((*volume, volume.app_path(&app.app_id)?)))
+    .collect();
+  mount_volumes(spec, &mounts);
+
+  // sidecars follow the main container, which stays the first one
+  spec.containers.extend(extra_containers(
+    app,
+    ContainerKind::Sidecar,
+    &secret_name,
+    volumes,
+  ));
+
+  deploy
+}
+
+/// Image of the app pinned to the digest its tag pointed to at the last release
+fn pinned_image(app: &AppService) -> String {
+  match &app.image_digest {
+    Some(digest) if !app.image.contains('@') => format!("{}@{digest}", app.image),
+    _ => app.image.clone(),
+  }
+}
+
+/// Containers of the app of the given kind, in their order, sharing the envs and volumes of the main one
+fn extra_containers(
+  app: &AppService,
+  kind: ContainerKind,
+  secret_name: &str,
+  volumes: &[&Volume],
+) -> Vec<Container> {
+  app
+    .containers
+    .0
+    .iter()
+    .filter(|container| container.kind == kind)
+    .map(|container| {
+      // a container only mounts the volumes the app mounts, the others are not in the pod
+      let mounts: Vec<_> = container
+        .volumes
+        .iter()
+        .filter_map(|mount| {
+          volumes
+            .iter()
+            .find(|volume| volume.volume_id == mount.id)
+            .map(|volume| volume_mount(volume, &mount.path))
+        })
+        .collect();
+
+      serde_json::from_value(json!({
+        "name": container.name,
+        "image": container.image,
+        "command": container.command,
+        "args": container.args,
+        "env": (!container.env.is_empty()).then_some(&container.env),
+        "envFrom": [
+          {
+            "secretRef": {
+              "name": secret_name
+            }
+          }
+        ],
+        "volumeMounts": (!mounts.is_empty()).then_some(mounts)
+      }))
+      .expect("Invalid app container")
+    })
+    .collect()
+}
+
+/// Pull secret of the app, when its image or the image of one of its containers comes from a project registry
+fn app_pull_secret(app: &AppService, registries: &[Registry]) -> Option<String> {
+  std::iter::once(&app.image)
+    .chain(app.containers.0.iter().map(|container| &container.image))
+    .find_map(|image| pull_secret(image, &app.project_id, registries))
+}
+
+/// Secret with the credentials of the project registries, when `image` comes from one of them
+pub(super) fn pull_secret(
+  image: &str,
+  project_id: &Uuid,
+  registries: &[Registry],
+) -> Option<String> {
+  let host = image_registry(image);
+
+  registries
+    .iter()
+    .any(|registry| registry.registry_host == host)
+    .then(|| registries_secret_name(project_id))
+}
+
+/// The main port of a web app followed by its other ports, workers have none
+fn app_ports(app: &AppService) -> Vec<AppPort> {
+  let main = app.port.map(|port| AppPort {
+    name: K8S_CONFIG.port_name.clone(),
+    port,
+    protocol: PortProtocol::Tcp,
+  });
+
+  main
+    .into_iter()
+    .chain(app.ports.0.iter().cloned())
+    .collect()
+}
+
+/// Port of the app named `name`, its main one included
+pub fn app_port(app: &AppService, name: &str) -> Option<AppPort> {
+  app_ports(app).into_iter().find(|port| port.name == name)
+}
+
+pub(super) fn protocol(port: &AppPort) -> &'static str {
+  match port.protocol {
+    PortProtocol::Tcp => "TCP",
+    PortProtocol::Udp => "UDP",
+  }
+}
+
+fn container_ports(app: &AppService) -> Value {
+  let ports: Vec<Value> = app_ports(app)
+    .iter()
+    .map(|port| {
+      json!({
+        "name": port.name,
+        "containerPort": port.port,
+        "protocol": protocol(port)
+      })
+    })
+    .collect();
+
+  match ports.is_empty() {
+    true => Value::Null,
+    false => Value::Array(ports),
+  }
+}
+
+/// Where a web app listens, workers get nothing to listen on
+fn listen_envs(app: &AppService) -> Value {
+  match app.port {
+    Some(port) => json!([
+      {
+        "name": "ADDRESS",
+        "value": "0.0.0.0"
+      },
+      {
+        "name": "PORT",
+        "value": port.to_string()
       }
-    }))
-    .expect("Invalid Deployment Volume");
+    ]),
+    None => Value::Null,
+  }
+}
 
-    spec.volumes = Some(vec![pod_volume]);
+fn generate_probe(probe: &Probe) -> Value {
+  // without a port the probe checks the one the app listens on
+  let port =
+    |port: Option<i32>| port.map_or_else(|| json!(K8S_CONFIG.port_name), |port| json!(port));
 
-    let volume_mount: VolumeMount = serde_json::from_value(json!({
-      "name": name,
-      "mountPath": volume.path,
-    }))
-    .expect("Invalid Volume Mount");
+  let mut generated = json!({
+    "initialDelaySeconds": probe.initial_delay_seconds,
+    "periodSeconds": probe.period_seconds,
+    "timeoutSeconds": probe.timeout_seconds,
+    "successThreshold": probe.success_threshold,
+    "failureThreshold": probe.failure_threshold
+  });
+
+  let (handler, check) = match &probe.check {
+    ProbeCheck::Http {
+      path,
+      port: http_port,
+    } => ("httpGet", json!({ "path": path, "port": port(*http_port) })),
+    ProbeCheck::Tcp { port: tcp_port } => ("tcpSocket", json!({ "port": port(*tcp_port) })),
+    ProbeCheck::Exec { command } => ("exec", json!({ "command": command })),
+  };
+  generated[handler] = check;
+
+  generated
+}
+
+/// Apps with a ReadWriteOnce volume are kept to a single replica, so they are never autoscaled
+fn autoscaled(app: &AppService, volumes: &[&Volume]) -> bool {
+  app.autoscaled() && !single_replica(volumes) && !app.deleted
+}
+
+async fn reconcile_hpa(
+  name: &str,
+  app: &AppService,
+  volumes: &[&Volume],
+  cluster: &Cluster,
+) -> Result<Option<PlanAction>> {
+  let api = cluster.api::<HorizontalPodAutoscaler>(&project_namespace(&app.project_id));
+
+  let hpa = api.get_opt(name).await?;
+
+  if !autoscaled(app, volumes) {
+    if hpa.is_some() {
+      api.delete(name).await?;
+      return Ok(Some(PlanAction::Delete));
+    }
 
-    spec.containers.get_mut(0).unwrap().volume_mounts = Some(vec![volume_mount]);
+    return Ok(None);
   }
 
-  deploy
+  let desired = generate_hpa(name, app);
+
+  let action = plan::diff(hpa.as_ref(), &desired);
+
+  if action != PlanAction::Unchanged {
+    api.apply(name, &desired).await?;
+  }
+
+  Ok(Some(action))
+}
+
+fn generate_hpa(name: &str, app: &AppService) -> HorizontalPodAutoscaler {
+  let utilization = |resource: &str, target: i32| {
+    json!({
+      "type": "Resource",
+      "resource": {
+        "name": resource,
+        "target": {
+          "type": "Utilization",
+          "averageUtilization": target
+        }
+      }
+    })
+  };
+
+  let metrics: Vec<_> = [
+    app
+      .target_cpu_utilization
+      .map(|target| utilization("cpu", target)),
+    app
+      .target_memory_utilization
+      .map(|target| utilization("memory", target)),
+  ]
+  .into_iter()
+  .flatten()
+  .collect();
+
+  serde_json::from_value(json!({
+    "apiVersion": "autoscaling/v2",
+    "kind": "HorizontalPodAutoscaler",
+    "metadata": {
+      "name": name,
+      "namespace": project_namespace(&app.project_id),
+      "labels": app_labels(app)
+    },
+    "spec": {
+      "scaleTargetRef": {
+        "apiVersion": "apps/v1",
+        "kind": "Deployment",
+        "name": name
+      },
+      "minReplicas": app.min_replicas,
+      "maxReplicas": app.max_replicas,
+      // the cluster defaults to the cpu target when none is given
+      "metrics": (!metrics.is_empty()).then_some(metrics)
+    }
+  }))
+  .expect("Invalid app autoscaler")
 }
 
 async fn reconcile_svc(
   name: &str,
   app: &AppService,
-  client: Client,
-  params: &PatchParams,
-) -> Result<()> {
-  let api: Api<Service> = Api::namespaced(client, &K8S_CONFIG.namespace);
+  track: Track,
+  cluster: &Cluster,
+) -> Result<Option<PlanAction>> {
+  let api = cluster.api::<Service>(&project_namespace(&app.project_id));
 
   let service = api.get_opt(name).await?;
+  let removed = app.deleted || app.kind == AppKind::Worker;
 
-  if service.is_some() && app.deleted {
-    api.delete(name, &Default::default()).await?;
-    return Ok(());
+  if service.is_some() && removed {
+    api.delete(name).await?;
+    return Ok(Some(PlanAction::Delete));
   }
 
-  if service.is_none() {
-    let service = generate_svc(name, app.port);
+  if removed {
+    return Ok(None);
+  }
 
-    api.patch(name, params, &Patch::Apply(service)).await?;
+  let desired = generate_svc(name, app, track);
+
+  let action = plan::diff(service.as_ref(), &desired);
+
+  if action != PlanAction::Unchanged {
+    api.apply(name, &desired).await?;
   }
 
-  Ok(())
+  Ok(Some(action))
 }
 
-fn generate_svc(name: &str, port: i32) -> Service {
+fn generate_svc(name: &str, app: &AppService, track: Track) -> Service {
   serde_json::from_value(json!({
     "apiVersion": "v1",
     "kind": "Service",
     "metadata": {
       "name": name,
-      "namespace": K8S_CONFIG.namespace
+      "namespace": project_namespace(&app.project_id),
+      "labels": app_labels(app)
     },
     "spec": {
-      "selector": {
-        "app": name
-      },
-      "ports": [
-        {
-          "protocol": "TCP",
-          "port": port,
-          "targetPort": K8S_CONFIG.port_name
-        }
-      ]
+      "selector": track.selector(name),
+      "ports": app_ports(app).iter().map(|port| json!({
+        "name": port.name,
+        "protocol": protocol(port),
+        "port": port.port,
+        "targetPort": port.name
+      })).collect::<Vec<_>>()
     }
   }))
   .expect("Invalid app service")
@@ -336,58 +1237,80 @@ fn generate_svc(name: &str, port: i32) -> Service {
 async fn reconcile_ingress(
   name: &str,
   app: &AppService,
-  client: Client,
-  params: &PatchParams,
-) -> Result<()> {
-  let api: Api<Ingress> = Api::namespaced(client, &K8S_CONFIG.namespace);
+  cluster: &Cluster,
+) -> Result<Option<PlanAction>> {
+  let api = cluster.api::<Ingress>(&project_namespace(&app.project_id));
 
   let ingress = api.get_opt(name).await?;
 
   if (ingress.is_some() && app.deleted) || ingress.is_some() && app.public_domain.is_none() {
-    api.delete(name, &Default::default()).await?;
-    return Ok(());
+    api.delete(name).await?;
+    return Ok(Some(PlanAction::Delete));
   }
 
-  if (ingress.is_none() && app.public_domain.is_some())
-    || ingress.is_some_and(|ingress| {
-      ingress.spec.is_some_and(|spec| {
-        spec.rules.is_some_and(|rules| {
-          rules.first().is_some_and(|rule| {
-            rule.host.as_deref().is_some_and(|host| {
-              app
-                .public_domain
-                .as_deref()
-                .is_some_and(|public_domain| public_domain != host)
-            })
-          })
-        })
-      })
-    })
-  {
-    let service = generate_ingress(name, app);
+  let action = match ingress {
+    None if app.public_domain.is_some() && !app.deleted => PlanAction::Create,
+    Some(ingress) if ingress_changed(&ingress, app) => PlanAction::Patch,
+    Some(_) => return Ok(Some(PlanAction::Unchanged)),
+    None => return Ok(None),
+  };
 
-    api.patch(name, params, &Patch::Apply(service)).await?;
-  }
+  let service = generate_ingress(name, app);
 
-  Ok(())
+  api.apply(name, &service).await?;
+
+  Ok(Some(action))
+}
+
+/// Whether the ingress routes another host or service port than the public domain of the app
+fn ingress_changed(ingress: &Ingress, app: &AppService) -> bool {
+  let Some(rule) = ingress
+    .spec
+    .as_ref()
+    .and_then(|spec| spec.rules.as_ref())
+    .and_then(|rules| rules.first())
+  else {
+    return false;
+  };
+
+  let host_changed = rule
+    .host
+    .as_deref()
+    .is_some_and(|host| public_host(app).is_some_and(|public_host| public_host != host));
+
+  let port_changed = rule
+    .http
+    .as_ref()
+    .and_then(|http| http.paths.first())
+    .and_then(|path| path.backend.service.as_ref())
+    .and_then(|service| service.port.as_ref())
+    .is_some_and(|port| port.name.as_deref() != Some(public_port_name(app)));
+
+  host_changed || port_changed
+}
+
+/// Service port the public domain of the app is routed to
+fn public_port_name(app: &AppService) -> &str {
+  app.public_port.as_deref().unwrap_or(&K8S_CONFIG.port_name)
+}
+
+fn public_host(app: &AppService) -> Option<String> {
+  app
+    .public_domain
+    .as_deref()
+    .map(|public_domain| format!("{public_domain}.{}", K8S_CONFIG.host_domain))
 }
 
 fn generate_ingress(name: &str, app: &AppService) -> Ingress {
-  let domain = format!(
-    "{}.{}",
-    app
-      .public_domain
-      .as_deref()
-      .expect("Generate Ingress with no public domain"),
-    K8S_CONFIG.host_domain
-  );
+  let domain = public_host(app).expect("Generate Ingress with no public domain");
 
   serde_json::from_value(json!({
     "apiVersion": "networking.k8s.io/v1",
     "kind": "Ingress",
     "metadata": {
       "name": name,
-      "namespace": K8S_CONFIG.namespace,
+      "namespace": project_namespace(&app.project_id),
+      "labels": app_labels(app),
       "annotations": {
         "traefik.ingress.kubernetes.io/router.middlewares": "gws-redirect@kubernetescrd",
         "traefik.ingress.kubernetes.io/router.entrypoints": "web, websecure"
@@ -414,7 +1337,7 @@ fn generate_ingress(name: &str, app: &AppService) -> Ingress {
                   "service": {
                     "name": name,
                     "port": {
-                      "number": K8S_CONFIG.service_port
+                      "name": public_port_name(app)
                     }
                   }
                 }
@@ -428,56 +1351,132 @@ fn generate_ingress(name: &str, app: &AppService) -> Ingress {
   .expect("Invalid app ingress")
 }
 
-async fn reconcile_secret(
+/// Secret the deployments of the app read their envs from, the one the plan compared with is kept while it holds the same values
+async fn release_secret(
   name: &str,
-  envs: Option<Vec<&EnvVar>>,
-  client: Client,
-) -> Result<Option<String>> {
-  let api: Api<Secret> = Api::namespaced(client, &K8S_CONFIG.namespace);
-
-  api
-    .delete_collection(
-      &Default::default(),
-      &ListParams::default().labels(&format!("app-name={name}")),
-    )
-    .await?;
+  app: &AppService,
+  envs: Vec<&EnvVar>,
+  volumes: &[&Volume],
+  cluster: &Cluster,
+  progress: Progress<'_>,
+) -> Result<String> {
+  let namespace = project_namespace(&app.project_id);
+  let api = cluster.api::<Secret>(&namespace);
 
-  if let Some(envs) = envs {
-    let secret = generate_secret(name, envs);
+  let track = plan_track(name, app, volumes, cluster).await?;
+  let planned = cluster
+    .api::<Deployment>(&namespace)
+    .get_opt(&track.deploy_name(name))
+    .await?
+    .as_ref()
+    .and_then(deploy_secret);
 
-    let pp = PostParams {
-      field_manager: Some(K8S_CONFIG.manager.clone()),
-      ..Default::default()
-    };
+  if let Some(planned) = planned {
+    let secret = api.get_opt(&planned).await?;
 
-    let secret = api.create(&pp, &secret).await?;
+    if secret.is_some_and(|secret| secret_values(&secret) == env_values(&envs)) {
+      progress(plan::change::<Secret>(&planned, PlanAction::Unchanged));
+      return Ok(planned);
+    }
+  }
+
+  let secret = api.create(&generate_secret(name, app, envs)).await?;
+  let secret_name = secret.name_unchecked();
+  progress(plan::change::<Secret>(&secret_name, PlanAction::Create));
 
-    return Ok(Some(secret.name_unchecked()));
+  Ok(secret_name)
+}
+
+/// Delete the secrets of the app no deployment of it reads anymore
+async fn prune_secrets(
+  name: &str,
+  app: &AppService,
+  cluster: &Cluster,
+  progress: Progress<'_>,
+) -> Result<(), ReleaseError> {
+  let namespace = project_namespace(&app.project_id);
+  let deploys = cluster.api::<Deployment>(&namespace);
+
+  let mut used = Vec::new();
+  for track in Track::ALL {
+    let deploy_name = track.deploy_name(name);
+    let deploy = deploys
+      .get_opt(&deploy_name)
+      .await
+      .map_err(ReleaseError::of::<Deployment>(&deploy_name))?;
+
+    used.extend(deploy.as_ref().and_then(deploy_secret));
   }
 
-  Ok(None)
+  let secrets = cluster
+    .api::<Secret>(&namespace)
+    .list(&format!("app-name={name}"))
+    .await
+    .map_err(ReleaseError::of::<Secret>(name))?;
+
+  for secret in secrets {
+    let secret_name = secret.name_any();
+    if used.contains(&secret_name) {
+      continue;
+    }
+
+    report::<Secret>(
+      &secret_name,
+      delete_secret(&secret_name, &namespace, cluster).await,
+      progress,
+    )?;
+  }
+
+  Ok(())
+}
+
+async fn delete_secret(
+  name: &str,
+  namespace: &str,
+  cluster: &Cluster,
+) -> Result<Option<PlanAction>> {
+  cluster.api::<Secret>(namespace).delete(name).await?;
+
+  Ok(Some(PlanAction::Delete))
+}
+
+pub(super) fn env_values(envs: &[&EnvVar]) -> BTreeMap<String, String> {
+  envs
+    .iter()
+    .map(|&var| (var.env_name.clone(), var.env_value.clone()))
+    .collect()
 }
 
-fn generate_secret(name: &str, envs: Vec<&EnvVar>) -> Secret {
+pub(super) fn secret_values(secret: &Secret) -> BTreeMap<String, String> {
+  let mut values: BTreeMap<String, String> = secret
+    .data
+    .iter()
+    .flatten()
+    .map(|(key, value)| (key.clone(), String::from_utf8_lossy(&value.0).into_owned()))
+    .collect();
+
+  values.extend(secret.string_data.clone().unwrap_or_default());
+
+  values
+}
+
+fn generate_secret(name: &str, app: &AppService, envs: Vec<&EnvVar>) -> Secret {
+  let mut labels = app_labels(app);
+  labels.insert("app-name".to_string(), name.to_string());
+
   let mut secret: Secret = serde_json::from_value(json!({
     "apiVersion": "v1",
     "kind": "Secret",
     "metadata": {
       "generateName": name,
-      "labels": {
-        "app-name": name
-      }
+      "labels": labels
     },
     "type": "Opaque",
     "stringData": {}
   }))
   .expect("Invalid app service");
 
-  secret.string_data = Some(BTreeMap::from_iter(
-    envs
-      .iter()
-      .map(|&var| (var.env_name.clone(), var.env_value.clone())),
-  ));
+  secret.string_data = Some(env_values(&envs));
 
   secret
 }
@@ -0,0 +1,167 @@
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
+use k8s_openapi::api::core::v1::Pod;
+use kube::{
+  api::{DeleteParams, ListParams, LogParams, Patch, PatchParams, PostParams},
+  core::DynamicObject,
+  discovery::ApiResource,
+  runtime::{
+    watcher::{self, watcher},
+    WatchStreamExt,
+  },
+  Api, Client, Result,
+};
+
+use crate::k8s::K8S_CONFIG;
+
+use super::ClusterBackend;
+
+/// Bytes of logs read from a pod, the start of a longer output is dropped
+const LOGS_LIMIT: i64 = 1024 * 1024;
+
+/// Backend talking to a real API server
+#[derive(Clone)]
+pub struct KubeBackend {
+  client: Client,
+}
+
+impl KubeBackend {
+  pub fn new(client: Client) -> Self {
+    Self { client }
+  }
+
+  fn api(&self, resource: &ApiResource, namespace: &str) -> Api<DynamicObject> {
+    match namespace {
+      "" => Api::all_with(self.client.clone(), resource),
+      namespace => Api::namespaced_with(self.client.clone(), namespace, resource),
+    }
+  }
+}
+
+impl ClusterBackend for KubeBackend {
+  fn get<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    name: &'a str,
+  ) -> BoxFuture<'a, Result<Option<DynamicObject>>> {
+    async move { self.api(resource, namespace).get_opt(name).await }.boxed()
+  }
+
+  fn list<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    labels: &'a str,
+  ) -> BoxFuture<'a, Result<Vec<DynamicObject>>> {
+    async move {
+      let list = self
+        .api(resource, namespace)
+        .list(&ListParams::default().labels(labels))
+        .await?;
+
+      Ok(list.items)
+    }
+    .boxed()
+  }
+
+  fn apply<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    name: &'a str,
+    object: DynamicObject,
+  ) -> BoxFuture<'a, Result<DynamicObject>> {
+    async move {
+      let params = PatchParams::apply(&K8S_CONFIG.manager).force();
+
+      self
+        .api(resource, namespace)
+        .patch(name, &params, &Patch::Apply(object))
+        .await
+    }
+    .boxed()
+  }
+
+  fn create<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    object: DynamicObject,
+  ) -> BoxFuture<'a, Result<DynamicObject>> {
+    async move {
+      let params = PostParams {
+        field_manager: Some(K8S_CONFIG.manager.clone()),
+        ..Default::default()
+      };
+
+      self.api(resource, namespace).create(&params, &object).await
+    }
+    .boxed()
+  }
+
+  fn delete<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    name: &'a str,
+  ) -> BoxFuture<'a, Result<()>> {
+    async move {
+      self
+        .api(resource, namespace)
+        .delete(name, &DeleteParams::background())
+        .await?;
+
+      Ok(())
+    }
+    .boxed()
+  }
+
+  fn delete_collection<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    labels: &'a str,
+  ) -> BoxFuture<'a, Result<()>> {
+    async move {
+      self
+        .api(resource, namespace)
+        .delete_collection(
+          &DeleteParams::background(),
+          &ListParams::default().labels(labels),
+        )
+        .await?;
+
+      Ok(())
+    }
+    .boxed()
+  }
+
+  fn logs<'a>(&'a self, namespace: &'a str, name: &'a str) -> BoxFuture<'a, Result<String>> {
+    async move {
+      let params = LogParams {
+        limit_bytes: Some(LOGS_LIMIT),
+        ..Default::default()
+      };
+
+      Api::<Pod>::namespaced(self.client.clone(), namespace)
+        .logs(name, &params)
+        .await
+    }
+    .boxed()
+  }
+
+  fn watch(
+    &self,
+    resource: &ApiResource,
+    namespace: &str,
+    fields: &str,
+  ) -> BoxStream<'static, watcher::Result<DynamicObject>> {
+    watcher(
+      self.api(resource, namespace),
+      watcher::Config::default().fields(fields),
+    )
+    .applied_objects()
+    .default_backoff()
+    .boxed()
+  }
+}
@@ -0,0 +1,367 @@
+use std::{
+  collections::BTreeMap,
+  sync::{Arc, Mutex, MutexGuard},
+};
+
+use futures::{
+  future::{self, BoxFuture},
+  stream::{self, BoxStream},
+  FutureExt, StreamExt,
+};
+use k8s_openapi::api::core::v1::{ConfigMap, Pod};
+use kube::{
+  core::{DynamicObject, ErrorResponse, ObjectMeta},
+  discovery::ApiResource,
+  runtime::watcher,
+  Resource, Result,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::sync::watch;
+use uuid::Uuid;
+
+use super::{from_dynamic, not_found, to_dynamic, ClusterBackend};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct ObjectKey {
+  api_version: String,
+  kind: String,
+  namespace: String,
+  name: String,
+}
+
+impl ObjectKey {
+  fn new(resource: &ApiResource, namespace: &str, name: &str) -> Self {
+    Self {
+      api_version: resource.api_version.clone(),
+      kind: resource.kind.clone(),
+      namespace: namespace.to_string(),
+      name: name.to_string(),
+    }
+  }
+
+  fn is_kind(&self, resource: &ApiResource) -> bool {
+    self.api_version == resource.api_version && self.kind == resource.kind
+  }
+}
+
+/// Backend keeping every object in memory, used to exercise the reconcilers without a cluster.
+///
+/// Clones share the same objects so a test can keep a handle and inspect what a release applied.
+#[derive(Clone)]
+pub struct MemoryBackend {
+  objects: Arc<Mutex<BTreeMap<ObjectKey, DynamicObject>>>,
+  /// Bumped on every change, wakes up the watchers
+  revision: Arc<watch::Sender<u64>>,
+  /// Output of the pods, keyed by namespace and name
+  logs: Arc<Mutex<BTreeMap<(String, String), String>>>,
+}
+
+impl Default for MemoryBackend {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl MemoryBackend {
+  /// Create a backend with the `coredns-custom` ConfigMap the private domains are written to
+  pub fn new() -> Self {
+    let backend = Self {
+      objects: Default::default(),
+      revision: Arc::new(watch::Sender::new(0)),
+      logs: Default::default(),
+    };
+
+    let coredns_custom = ConfigMap {
+      metadata: ObjectMeta {
+        name: Some("coredns-custom".to_string()),
+        namespace: Some("kube-system".to_string()),
+        ..Default::default()
+      },
+      data: Some(Default::default()),
+      ..Default::default()
+    };
+
+    backend.insert(
+      ObjectKey::new(
+        &ApiResource::erase::<ConfigMap>(&()),
+        "kube-system",
+        "coredns-custom",
+      ),
+      to_dynamic(&coredns_custom).expect("Invalid coredns custom"),
+    );
+
+    backend
+  }
+
+  pub fn object<K>(&self, namespace: &str, name: &str) -> Option<K>
+  where
+    K: Resource<DynamicType = ()> + DeserializeOwned,
+  {
+    let key = ObjectKey::new(&ApiResource::erase::<K>(&()), namespace, name);
+
+    self
+      .lock()
+      .get(&key)
+      .cloned()
+      .map(|object| from_dynamic(object).expect("Invalid stored object"))
+  }
+
+  /// Every stored object of kind `K`, across all namespaces
+  pub fn objects<K>(&self) -> Vec<K>
+  where
+    K: Resource<DynamicType = ()> + DeserializeOwned,
+  {
+    let resource = ApiResource::erase::<K>(&());
+
+    self
+      .lock()
+      .iter()
+      .filter(|(key, _)| key.is_kind(&resource))
+      .map(|(_, object)| from_dynamic(object.clone()).expect("Invalid stored object"))
+      .collect()
+  }
+
+  /// Set the output the pod `name` reports
+  pub fn set_logs(&self, namespace: &str, name: &str, logs: &str) {
+    self
+      .logs
+      .lock()
+      .expect("Memory backend poisoned")
+      .insert((namespace.to_string(), name.to_string()), logs.to_string());
+  }
+
+  fn lock(&self) -> MutexGuard<'_, BTreeMap<ObjectKey, DynamicObject>> {
+    self.objects.lock().expect("Memory backend poisoned")
+  }
+
+  fn insert(&self, key: ObjectKey, mut object: DynamicObject) -> DynamicObject {
+    let mut objects = self.lock();
+
+    let (uid, version) = match objects.get(&key) {
+      Some(current) => (
+        current.metadata.uid.clone(),
+        current
+          .metadata
+          .resource_version
+          .as_deref()
+          .and_then(|version| version.parse::<u64>().ok())
+          .unwrap_or_default()
+          + 1,
+      ),
+      None => (Some(Uuid::new_v4().to_string()), 1),
+    };
+
+    object.metadata.name = Some(key.name.clone());
+    object.metadata.namespace = (!key.namespace.is_empty()).then(|| key.namespace.clone());
+    object.metadata.uid = uid;
+    object.metadata.resource_version = Some(version.to_string());
+
+    objects.insert(key, object.clone());
+    self.changed();
+
+    object
+  }
+
+  fn changed(&self) {
+    self.revision.send_modify(|revision| *revision += 1);
+  }
+}
+
+/// Equality based field selector, every field is a dotted path into the object
+fn matches_fields(object: &DynamicObject, selector: &str) -> bool {
+  let object = serde_json::to_value(object).expect("Invalid stored object");
+
+  selector
+    .split(',')
+    .filter(|requirement| !requirement.is_empty())
+    .all(|requirement| match requirement.split_once('=') {
+      Some((path, value)) => {
+        let pointer = format!("/{}", path.trim().replace('.', "/"));
+        object.pointer(&pointer).and_then(Value::as_str) == Some(value.trim())
+      }
+      None => false,
+    })
+}
+
+fn matches_labels(object: &DynamicObject, selector: &str) -> bool {
+  let labels = object.metadata.labels.clone().unwrap_or_default();
+
+  selector
+    .split(',')
+    .filter(|requirement| !requirement.is_empty())
+    .all(|requirement| match requirement.split_once('=') {
+      Some((key, value)) => labels.get(key.trim()).map(String::as_str) == Some(value.trim()),
+      None => labels.contains_key(requirement.trim()),
+    })
+}
+
+impl ClusterBackend for MemoryBackend {
+  fn get<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    name: &'a str,
+  ) -> BoxFuture<'a, Result<Option<DynamicObject>>> {
+    let key = ObjectKey::new(resource, namespace, name);
+
+    future::ready(Ok(self.lock().get(&key).cloned())).boxed()
+  }
+
+  fn list<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    labels: &'a str,
+  ) -> BoxFuture<'a, Result<Vec<DynamicObject>>> {
+    let objects = self
+      .lock()
+      .iter()
+      .filter(|(key, object)| {
+        key.is_kind(resource) && key.namespace == namespace && matches_labels(object, labels)
+      })
+      .map(|(_, object)| object.clone())
+      .collect();
+
+    future::ready(Ok(objects)).boxed()
+  }
+
+  fn apply<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    name: &'a str,
+    object: DynamicObject,
+  ) -> BoxFuture<'a, Result<DynamicObject>> {
+    let key = ObjectKey::new(resource, namespace, name);
+
+    future::ready(Ok(self.insert(key, object))).boxed()
+  }
+
+  fn create<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    object: DynamicObject,
+  ) -> BoxFuture<'a, Result<DynamicObject>> {
+    let name = match (&object.metadata.name, &object.metadata.generate_name) {
+      (Some(name), _) => name.clone(),
+      (None, Some(prefix)) => format!("{prefix}{}", &Uuid::new_v4().simple().to_string()[..5]),
+      (None, None) => {
+        return future::ready(Err(kube::Error::Api(ErrorResponse {
+          status: "Failure".to_string(),
+          message: "name or generateName is required".to_string(),
+          reason: "Invalid".to_string(),
+          code: 422,
+        })))
+        .boxed()
+      }
+    };
+
+    let key = ObjectKey::new(resource, namespace, &name);
+
+    future::ready(Ok(self.insert(key, object))).boxed()
+  }
+
+  fn delete<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    name: &'a str,
+  ) -> BoxFuture<'a, Result<()>> {
+    let key = ObjectKey::new(resource, namespace, name);
+
+    let result = match self.lock().remove(&key) {
+      Some(_) => Ok(()),
+      None => Err(not_found(resource, name)),
+    };
+    self.changed();
+
+    future::ready(result).boxed()
+  }
+
+  fn delete_collection<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    labels: &'a str,
+  ) -> BoxFuture<'a, Result<()>> {
+    self.lock().retain(|key, object| {
+      !(key.is_kind(resource) && key.namespace == namespace && matches_labels(object, labels))
+    });
+    self.changed();
+
+    future::ready(Ok(())).boxed()
+  }
+
+  fn logs<'a>(&'a self, namespace: &'a str, name: &'a str) -> BoxFuture<'a, Result<String>> {
+    let key = ObjectKey::new(&ApiResource::erase::<Pod>(&()), namespace, name);
+
+    let result = match self.lock().contains_key(&key) {
+      true => Ok(
+        self
+          .logs
+          .lock()
+          .expect("Memory backend poisoned")
+          .get(&(namespace.to_string(), name.to_string()))
+          .cloned()
+          .unwrap_or_default(),
+      ),
+      false => Err(not_found(&ApiResource::erase::<Pod>(&()), name)),
+    };
+
+    future::ready(result).boxed()
+  }
+
+  fn watch(
+    &self,
+    resource: &ApiResource,
+    namespace: &str,
+    fields: &str,
+  ) -> BoxStream<'static, watcher::Result<DynamicObject>> {
+    let backend = self.clone();
+    let resource = resource.clone();
+    let namespace = namespace.to_string();
+    let fields = fields.to_string();
+
+    let mut revisions = self.revision.subscribe();
+    // the objects already stored are sent first
+    revisions.mark_changed();
+
+    stream::unfold(
+      (revisions, BTreeMap::new()),
+      move |(mut revisions, mut seen)| {
+        let backend = backend.clone();
+        let resource = resource.clone();
+        let namespace = namespace.clone();
+        let fields = fields.clone();
+
+        async move {
+          revisions.changed().await.ok()?;
+
+          let changed: Vec<(ObjectKey, DynamicObject)> = backend
+            .lock()
+            .iter()
+            .filter(|(key, object)| {
+              key.is_kind(&resource)
+                && key.namespace == namespace
+                && matches_fields(object, &fields)
+                && seen.get(*key) != Some(&object.metadata.resource_version)
+            })
+            .map(|(key, object)| (key.clone(), object.clone()))
+            .collect();
+
+          for (key, object) in &changed {
+            seen.insert(key.clone(), object.metadata.resource_version.clone());
+          }
+
+          let changed = stream::iter(changed.into_iter().map(|(_, object)| Ok(object)));
+
+          Some((changed, (revisions, seen)))
+        }
+      },
+    )
+    .flatten()
+    .boxed()
+  }
+}
@@ -0,0 +1,241 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use futures::{future::BoxFuture, stream::BoxStream, StreamExt};
+use k8s_openapi::ClusterResourceScope;
+use kube::{
+  core::{DynamicObject, ErrorResponse},
+  discovery::ApiResource,
+  runtime::watcher,
+  Resource, Result,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+pub use client::KubeBackend;
+pub use memory::MemoryBackend;
+
+mod client;
+mod memory;
+
+/// Operations the reconcilers need from a cluster.
+///
+/// Objects are exchanged as [`DynamicObject`] so the trait stays object safe,
+/// use [`Cluster::api`] to work with typed resources. An empty `namespace`
+/// addresses a cluster scoped resource.
+pub trait ClusterBackend: Send + Sync {
+  fn get<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    name: &'a str,
+  ) -> BoxFuture<'a, Result<Option<DynamicObject>>>;
+
+  /// Every object matching the equality based `labels` selector
+  fn list<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    labels: &'a str,
+  ) -> BoxFuture<'a, Result<Vec<DynamicObject>>>;
+
+  /// Server side apply of `object`, forcing the ownership of conflicting fields
+  fn apply<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    name: &'a str,
+    object: DynamicObject,
+  ) -> BoxFuture<'a, Result<DynamicObject>>;
+
+  /// Create `object`, the name is generated by the cluster when `generateName` is used
+  fn create<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    object: DynamicObject,
+  ) -> BoxFuture<'a, Result<DynamicObject>>;
+
+  /// Delete the object along with its dependents, such as the pods of a Job
+  fn delete<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    name: &'a str,
+  ) -> BoxFuture<'a, Result<()>>;
+
+  /// Delete every object matching the equality based `labels` selector
+  fn delete_collection<'a>(
+    &'a self,
+    resource: &'a ApiResource,
+    namespace: &'a str,
+    labels: &'a str,
+  ) -> BoxFuture<'a, Result<()>>;
+
+  /// Output of the container of a pod, the end of it past a size limit
+  fn logs<'a>(&'a self, namespace: &'a str, name: &'a str) -> BoxFuture<'a, Result<String>>;
+
+  /// Every change of the objects matching the equality based `fields` selector, starting with
+  /// their current state. Failures are retried with a backoff, the stream only ends when dropped
+  fn watch(
+    &self,
+    resource: &ApiResource,
+    namespace: &str,
+    fields: &str,
+  ) -> BoxStream<'static, watcher::Result<DynamicObject>>;
+}
+
+#[derive(Clone)]
+pub struct Cluster(Arc<dyn ClusterBackend>);
+
+impl Cluster {
+  pub fn new(backend: impl ClusterBackend + 'static) -> Self {
+    Self(Arc::new(backend))
+  }
+
+  pub fn api<K>(&self, namespace: &str) -> ClusterApi<'_, K>
+  where
+    K: Resource<DynamicType = ()>,
+  {
+    ClusterApi {
+      backend: self.0.as_ref(),
+      resource: ApiResource::erase::<K>(&()),
+      namespace: namespace.to_string(),
+      _kind: PhantomData,
+    }
+  }
+
+  /// Typed view over a cluster scoped resource such as a Namespace
+  pub fn cluster_api<K>(&self) -> ClusterApi<'_, K>
+  where
+    K: Resource<DynamicType = (), Scope = ClusterResourceScope>,
+  {
+    self.api("")
+  }
+
+  /// Objects of any kind matching the `labels` selector, for the passes that only look at the metadata
+  pub async fn list_dynamic(
+    &self,
+    resource: &ApiResource,
+    namespace: &str,
+    labels: &str,
+  ) -> Result<Vec<DynamicObject>> {
+    self.0.list(resource, namespace, labels).await
+  }
+
+  pub async fn delete_dynamic(
+    &self,
+    resource: &ApiResource,
+    namespace: &str,
+    name: &str,
+  ) -> Result<()> {
+    self.0.delete(resource, namespace, name).await
+  }
+
+  pub async fn pod_logs(&self, namespace: &str, name: &str) -> Result<String> {
+    self.0.logs(namespace, name).await
+  }
+}
+
+/// Typed view over a [`ClusterBackend`], mirrors the subset of [`kube::Api`] used by the reconcilers
+pub struct ClusterApi<'a, K> {
+  backend: &'a dyn ClusterBackend,
+  resource: ApiResource,
+  namespace: String,
+  _kind: PhantomData<K>,
+}
+
+impl<K> ClusterApi<'_, K>
+where
+  K: Resource<DynamicType = ()> + Serialize + DeserializeOwned,
+{
+  pub async fn get_opt(&self, name: &str) -> Result<Option<K>> {
+    self
+      .backend
+      .get(&self.resource, &self.namespace, name)
+      .await?
+      .map(from_dynamic)
+      .transpose()
+  }
+
+  pub async fn get(&self, name: &str) -> Result<K> {
+    match self.get_opt(name).await? {
+      Some(object) => Ok(object),
+      None => Err(not_found(&self.resource, name)),
+    }
+  }
+
+  pub async fn list(&self, labels: &str) -> Result<Vec<K>> {
+    self
+      .backend
+      .list(&self.resource, &self.namespace, labels)
+      .await?
+      .into_iter()
+      .map(from_dynamic)
+      .collect()
+  }
+
+  pub async fn apply(&self, name: &str, object: &K) -> Result<K> {
+    let object = self
+      .backend
+      .apply(&self.resource, &self.namespace, name, to_dynamic(object)?)
+      .await?;
+
+    from_dynamic(object)
+  }
+
+  pub async fn create(&self, object: &K) -> Result<K> {
+    let object = self
+      .backend
+      .create(&self.resource, &self.namespace, to_dynamic(object)?)
+      .await?;
+
+    from_dynamic(object)
+  }
+
+  pub async fn delete(&self, name: &str) -> Result<()> {
+    self
+      .backend
+      .delete(&self.resource, &self.namespace, name)
+      .await
+  }
+
+  pub async fn delete_collection(&self, labels: &str) -> Result<()> {
+    self
+      .backend
+      .delete_collection(&self.resource, &self.namespace, labels)
+      .await
+  }
+
+  pub fn watch(&self, fields: &str) -> BoxStream<'static, watcher::Result<K>>
+  where
+    K: Send + 'static,
+  {
+    self
+      .backend
+      .watch(&self.resource, &self.namespace, fields)
+      .map(|object| {
+        object.and_then(|object| from_dynamic(object).map_err(watcher::Error::WatchFailed))
+      })
+      .boxed()
+  }
+}
+
+fn to_dynamic<K: Serialize>(object: &K) -> Result<DynamicObject> {
+  serde_json::to_value(object)
+    .and_then(serde_json::from_value)
+    .map_err(kube::Error::SerdeError)
+}
+
+fn from_dynamic<K: DeserializeOwned>(object: DynamicObject) -> Result<K> {
+  serde_json::to_value(object)
+    .and_then(serde_json::from_value)
+    .map_err(kube::Error::SerdeError)
+}
+
+fn not_found(resource: &ApiResource, name: &str) -> kube::Error {
+  kube::Error::Api(ErrorResponse {
+    status: "Failure".to_string(),
+    message: format!("{} \"{name}\" not found", resource.plural),
+    reason: "NotFound".to_string(),
+    code: 404,
+  })
+}
@@ -0,0 +1,252 @@
+use k8s_openapi::{
+  api::{batch::v1::CronJob as KubeCronJob, core::v1::Secret},
+  ByteString,
+};
+use kube::Result;
+use serde_json::json;
+
+use crate::schemas::{CronJob, EnvVar, PlanAction, Registry, ResourceChange, Volume};
+
+use super::{
+  app::{env_values, pull_secret, secret_values},
+  cron_job_labels, plan, project_namespace, report,
+  volume::mount_volumes,
+  Cluster, Progress, ReleaseError,
+};
+
+pub async fn reconcile_cron_job(
+  cron_job: &CronJob,
+  envs: Vec<&EnvVar>,
+  volume: Option<&Volume>,
+  registries: &[Registry],
+  cluster: &Cluster,
+  progress: Progress<'_>,
+) -> Result<(), ReleaseError> {
+  let name = format!("cronjob-{}", cron_job.cron_job_id);
+
+  report::<Secret>(
+    &name,
+    reconcile_secret(&name, cron_job, &envs, cluster).await,
+    progress,
+  )?;
+
+  let pull_secret = pull_secret(&cron_job.image, &cron_job.project_id, registries);
+
+  report::<KubeCronJob>(
+    &name,
+    reconcile_kube_cron_job(&name, cron_job, pull_secret, volume, cluster).await,
+    progress,
+  )
+}
+
+pub async fn plan_cron_job(
+  cron_job: &CronJob,
+  envs: Vec<&EnvVar>,
+  volume: Option<&Volume>,
+  registries: &[Registry],
+  cluster: &Cluster,
+) -> Result<Vec<ResourceChange>> {
+  let name = format!("cronjob-{}", cron_job.cron_job_id);
+  let namespace = project_namespace(&cron_job.project_id);
+
+  let secret = cluster.api::<Secret>(&namespace).get_opt(&name).await?;
+  let live = cluster
+    .api::<KubeCronJob>(&namespace)
+    .get_opt(&name)
+    .await?;
+
+  let (secret_action, cron_job_action) = if cron_job.deleted {
+    (
+      secret.map(|_| PlanAction::Delete),
+      live.map(|_| PlanAction::Delete),
+    )
+  } else {
+    let pull_secret = pull_secret(&cron_job.image, &cron_job.project_id, registries);
+    let desired = generate_cron_job(&name, cron_job, pull_secret, volume);
+
+    (
+      Some(secret_action(secret.as_ref(), &envs)),
+      Some(plan::diff(live.as_ref(), &desired)),
+    )
+  };
+
+  Ok(
+    [
+      secret_action.map(|action| plan::change::<Secret>(&name, action)),
+      cron_job_action.map(|action| plan::change::<KubeCronJob>(&name, action)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect(),
+  )
+}
+
+/// The jobs read the envs from a secret named after the cron job, a new value is picked up by the next run
+async fn reconcile_secret(
+  name: &str,
+  cron_job: &CronJob,
+  envs: &[&EnvVar],
+  cluster: &Cluster,
+) -> Result<Option<PlanAction>> {
+  let api = cluster.api::<Secret>(&project_namespace(&cron_job.project_id));
+
+  let secret = api.get_opt(name).await?;
+
+  if cron_job.deleted {
+    if secret.is_some() {
+      api.delete(name).await?;
+      return Ok(Some(PlanAction::Delete));
+    }
+
+    return Ok(None);
+  }
+
+  let action = secret_action(secret.as_ref(), envs);
+
+  if action != PlanAction::Unchanged {
+    api
+      .apply(name, &generate_secret(name, cron_job, envs))
+      .await?;
+  }
+
+  Ok(Some(action))
+}
+
+fn secret_action(secret: Option<&Secret>, envs: &[&EnvVar]) -> PlanAction {
+  match secret {
+    None => PlanAction::Create,
+    Some(secret) if secret_values(secret) == env_values(envs) => PlanAction::Unchanged,
+    Some(_) => PlanAction::Patch,
+  }
+}
+
+async fn reconcile_kube_cron_job(
+  name: &str,
+  cron_job: &CronJob,
+  pull_secret: Option<String>,
+  volume: Option<&Volume>,
+  cluster: &Cluster,
+) -> Result<Option<PlanAction>> {
+  let api = cluster.api::<KubeCronJob>(&project_namespace(&cron_job.project_id));
+
+  let live = api.get_opt(name).await?;
+
+  if cron_job.deleted {
+    if live.is_some() {
+      api.delete(name).await?;
+      return Ok(Some(PlanAction::Delete));
+    }
+
+    return Ok(None);
+  }
+
+  let desired = generate_cron_job(name, cron_job, pull_secret, volume);
+
+  let action = plan::diff(live.as_ref(), &desired);
+
+  if action != PlanAction::Unchanged {
+    api.apply(name, &desired).await?;
+  }
+
+  Ok(Some(action))
+}
+
+fn generate_secret(name: &str, cron_job: &CronJob, envs: &[&EnvVar]) -> Secret {
+  let mut secret: Secret = serde_json::from_value(json!({
+    "apiVersion": "v1",
+    "kind": "Secret",
+    "metadata": {
+      "name": name,
+      "namespace": project_namespace(&cron_job.project_id),
+      "labels": cron_job_labels(cron_job)
+    },
+    "type": "Opaque"
+  }))
+  .expect("Invalid cron job secret");
+
+  // written as data so a removed env is dropped by the apply
+  secret.data = Some(
+    env_values(envs)
+      .into_iter()
+      .map(|(key, value)| (key, ByteString(value.into_bytes())))
+      .collect(),
+  );
+
+  secret
+}
+
+fn generate_cron_job(
+  name: &str,
+  cron_job: &CronJob,
+  pull_secret: Option<String>,
+  volume: Option<&Volume>,
+) -> KubeCronJob {
+  let mut kube_cron_job: KubeCronJob = serde_json::from_value(json!({
+    "apiVersion": "batch/v1",
+    "kind": "CronJob",
+    "metadata": {
+      "name": name,
+      "namespace": project_namespace(&cron_job.project_id),
+      "labels": cron_job_labels(cron_job),
+    },
+    "spec": {
+      "schedule": cron_job.schedule,
+      "concurrencyPolicy": cron_job.concurrency_policy.as_str(),
+      "successfulJobsHistoryLimit": cron_job.successful_jobs_history_limit,
+      "failedJobsHistoryLimit": cron_job.failed_jobs_history_limit,
+      "jobTemplate": {
+        "spec": {
+          "template": {
+            "metadata": {
+              // the network policy of the project selects its pods by this label
+              "labels": {
+                "cronjob": name,
+                "project": cron_job.project_id
+              }
+            },
+            "spec": {
+              "restartPolicy": "OnFailure",
+              "enableServiceLinks": false,
+              "imagePullSecrets": pull_secret.map(|name| json!([{ "name": name }])),
+              "containers": [
+                {
+                  "name": name,
+                  "image": cron_job.image,
+                  "command": cron_job.command,
+                  "args": cron_job.args,
+                  "envFrom": [
+                    {
+                      "secretRef": {
+                        "name": name
+                      }
+                    }
+                  ]
+                }
+              ]
+            }
+          }
+        }
+      }
+    }
+  }))
+  .expect("Invalid cron job");
+
+  if let Some(volume) = volume {
+    let spec = kube_cron_job
+      .spec
+      .as_mut()
+      .unwrap()
+      .job_template
+      .spec
+      .as_mut()
+      .unwrap()
+      .template
+      .spec
+      .as_mut()
+      .unwrap();
+
+    mount_volumes(spec, &[(volume, &volume.path)]);
+  }
+
+  kube_cron_job
+}
@@ -0,0 +1,106 @@
+use std::fmt::Debug;
+
+use futures::{
+  future,
+  stream::{self, BoxStream},
+  Stream, StreamExt,
+};
+use k8s_openapi::{
+  api::{
+    apps::v1::Deployment,
+    autoscaling::v2::HorizontalPodAutoscaler,
+    batch::v1::CronJob,
+    core::v1::{LimitRange, PersistentVolumeClaim, ResourceQuota, Service},
+    networking::v1::{Ingress, NetworkPolicy},
+  },
+  NamespaceResourceScope,
+};
+use kube::{
+  runtime::{watcher, WatchStreamExt},
+  Api, Client, Resource,
+};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+use super::K8S_CONFIG;
+
+/// Row a gws managed object was generated from, recovered from the object name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ManagedObject {
+  Project(Uuid),
+  App(Uuid),
+  CronJob(Uuid),
+  Volume(Uuid),
+}
+
+/// Every gws managed object changed or deleted in the cluster, starting with the existing ones
+pub fn managed_objects(client: Client) -> impl Stream<Item = ManagedObject> + Send {
+  stream::select_all([
+    touched_objects::<Deployment>(client.clone()),
+    touched_objects::<HorizontalPodAutoscaler>(client.clone()),
+    touched_objects::<CronJob>(client.clone()),
+    touched_objects::<Service>(client.clone()),
+    touched_objects::<Ingress>(client.clone()),
+    touched_objects::<PersistentVolumeClaim>(client.clone()),
+    touched_objects::<NetworkPolicy>(client.clone()),
+    touched_objects::<ResourceQuota>(client.clone()),
+    touched_objects::<LimitRange>(client),
+  ])
+}
+
+fn touched_objects<K>(client: Client) -> BoxStream<'static, ManagedObject>
+where
+  K: Resource<DynamicType = (), Scope = NamespaceResourceScope>
+    + Clone
+    + DeserializeOwned
+    + Debug
+    + Send
+    + 'static,
+{
+  // project namespaces come and go with the projects, watch them all
+  let api: Api<K> = if K8S_CONFIG.project_namespaces {
+    Api::all(client)
+  } else {
+    Api::namespaced(client, &K8S_CONFIG.namespace)
+  };
+
+  watcher(api, watcher::Config::default())
+    .touched_objects()
+    .default_backoff()
+    .filter_map(|object| {
+      future::ready(match object {
+        Ok(object) => managed_object(&object),
+        Err(err) => {
+          log::error!("Unable to watch {}: {err}", K::kind(&()));
+          None
+        }
+      })
+    })
+    .boxed()
+}
+
+fn managed_object(object: &impl Resource) -> Option<ManagedObject> {
+  let meta = object.meta();
+
+  let managed = meta
+    .managed_fields
+    .iter()
+    .flatten()
+    .any(|fields| fields.manager.as_deref() == Some(K8S_CONFIG.manager.as_str()));
+
+  if !managed {
+    return None;
+  }
+
+  // blue/green and canary deployments carry their track after the id
+  let (prefix, id) = meta.name.as_deref()?.split_once('-')?;
+  let id = Uuid::parse_str(id.get(..36)?).ok()?;
+
+  match prefix {
+    "project" => Some(ManagedObject::Project(id)),
+    "app" => Some(ManagedObject::App(id)),
+    "cronjob" => Some(ManagedObject::CronJob(id)),
+    "volume" => Some(ManagedObject::Volume(id)),
+    _ => None,
+  }
+}
@@ -0,0 +1,305 @@
+use std::{borrow::Cow, collections::BTreeMap};
+
+use k8s_openapi::{
+  api::core::v1::Service, apimachinery::pkg::apis::meta::v1::ObjectMeta, NamespaceResourceScope,
+};
+use kube::{discovery::ApiResource, Resource, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{
+  app::{app_port, protocol},
+  app_labels, plan, project_namespace, report, Cluster, ExposeMode, Progress, ReleaseError,
+  K8S_CONFIG,
+};
+use crate::schemas::{AppPort, AppService, PlanAction, PortProtocol, ResourceChange};
+
+/// Declares a Traefik route, its CRD comes with Traefik so only the fields gws sets are known
+macro_rules! traefik_route {
+  ($route:ident, $kind:literal, $plural:literal) => {
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub(super) struct $route {
+      #[serde(rename = "apiVersion")]
+      api_version: String,
+      kind: String,
+      metadata: ObjectMeta,
+      spec: Value,
+    }
+
+    impl Resource for $route {
+      type DynamicType = ();
+      type Scope = NamespaceResourceScope;
+
+      fn kind(_: &()) -> Cow<'_, str> {
+        $kind.into()
+      }
+
+      fn group(_: &()) -> Cow<'_, str> {
+        "traefik.io".into()
+      }
+
+      fn version(_: &()) -> Cow<'_, str> {
+        "v1alpha1".into()
+      }
+
+      fn plural(_: &()) -> Cow<'_, str> {
+        $plural.into()
+      }
+
+      fn meta(&self) -> &ObjectMeta {
+        &self.metadata
+      }
+
+      fn meta_mut(&mut self) -> &mut ObjectMeta {
+        &mut self.metadata
+      }
+    }
+  };
+}
+
+traefik_route!(IngressRouteTcp, "IngressRouteTCP", "ingressroutetcps");
+traefik_route!(IngressRouteUdp, "IngressRouteUDP", "ingressrouteudps");
+
+/// Traefik routes owned by the apps, only listed when Traefik exposes the ports since the CRDs may be missing
+pub(super) fn exposure_resources() -> Vec<ApiResource> {
+  match K8S_CONFIG.expose_mode {
+    ExposeMode::Traefik => vec![
+      ApiResource::erase::<IngressRouteTcp>(&()),
+      ApiResource::erase::<IngressRouteUdp>(&()),
+    ],
+    ExposeMode::NodePort | ExposeMode::LoadBalancer => Vec::new(),
+  }
+}
+
+/// Port of the app exposed and the public port allocated to it, `None` once it is not exposed anymore
+fn exposed(app: &AppService) -> Option<(AppPort, i32)> {
+  if app.deleted {
+    return None;
+  }
+
+  let target = app_port(app, app.expose.as_deref()?)?;
+
+  Some((target, app.exposed_port?))
+}
+
+/// Objects the exposure of the app needs, `None` for the kinds the mode does not use
+struct Exposure {
+  service: Option<Service>,
+  tcp_route: Option<IngressRouteTcp>,
+  udp_route: Option<IngressRouteUdp>,
+}
+
+fn generate_exposure(
+  name: &str,
+  app: &AppService,
+  selector: &BTreeMap<String, String>,
+) -> Exposure {
+  let exposed_name = exposed_name(name);
+  let exposed = exposed(app);
+  let target = |protocol: PortProtocol| {
+    exposed
+      .as_ref()
+      .filter(|(port, _)| port.protocol == protocol)
+  };
+
+  match K8S_CONFIG.expose_mode {
+    ExposeMode::NodePort | ExposeMode::LoadBalancer => Exposure {
+      service: exposed
+        .as_ref()
+        .map(|(target, port)| generate_exposed_svc(&exposed_name, app, selector, target, *port)),
+      tcp_route: None,
+      udp_route: None,
+    },
+    ExposeMode::Traefik => Exposure {
+      service: None,
+      tcp_route: target(PortProtocol::Tcp).map(|(target, port)| {
+        generate_route(&exposed_name, name, app, target, *port, "IngressRouteTCP")
+      }),
+      udp_route: target(PortProtocol::Udp).map(|(target, port)| {
+        generate_route(&exposed_name, name, app, target, *port, "IngressRouteUDP")
+      }),
+    },
+  }
+}
+
+fn exposed_name(name: &str) -> String {
+  format!("{name}-exposed")
+}
+
+pub(super) async fn reconcile_exposure(
+  name: &str,
+  app: &AppService,
+  selector: &BTreeMap<String, String>,
+  cluster: &Cluster,
+  progress: Progress<'_>,
+) -> Result<(), ReleaseError> {
+  let exposed_name = exposed_name(name);
+  let namespace = project_namespace(&app.project_id);
+  let exposure = generate_exposure(name, app, selector);
+
+  report::<Service>(
+    &exposed_name,
+    reconcile_object(&exposed_name, &namespace, exposure.service, cluster).await,
+    progress,
+  )?;
+
+  if K8S_CONFIG.expose_mode == ExposeMode::Traefik {
+    report::<IngressRouteTcp>(
+      &exposed_name,
+      reconcile_object(&exposed_name, &namespace, exposure.tcp_route, cluster).await,
+      progress,
+    )?;
+    report::<IngressRouteUdp>(
+      &exposed_name,
+      reconcile_object(&exposed_name, &namespace, exposure.udp_route, cluster).await,
+      progress,
+    )?;
+  }
+
+  Ok(())
+}
+
+pub(super) async fn plan_exposure(
+  name: &str,
+  app: &AppService,
+  selector: &BTreeMap<String, String>,
+  cluster: &Cluster,
+) -> Result<Vec<ResourceChange>> {
+  let exposed_name = exposed_name(name);
+  let namespace = project_namespace(&app.project_id);
+  let exposure = generate_exposure(name, app, selector);
+
+  let mut changes = Vec::new();
+
+  let service = cluster
+    .api::<Service>(&namespace)
+    .get_opt(&exposed_name)
+    .await?;
+  changes.extend(
+    planned(service.as_ref(), exposure.service.as_ref())
+      .map(|action| plan::change::<Service>(&exposed_name, action)),
+  );
+
+  if K8S_CONFIG.expose_mode == ExposeMode::Traefik {
+    let tcp_route = cluster
+      .api::<IngressRouteTcp>(&namespace)
+      .get_opt(&exposed_name)
+      .await?;
+    changes.extend(
+      planned(tcp_route.as_ref(), exposure.tcp_route.as_ref())
+        .map(|action| plan::change::<IngressRouteTcp>(&exposed_name, action)),
+    );
+
+    let udp_route = cluster
+      .api::<IngressRouteUdp>(&namespace)
+      .get_opt(&exposed_name)
+      .await?;
+    changes.extend(
+      planned(udp_route.as_ref(), exposure.udp_route.as_ref())
+        .map(|action| plan::change::<IngressRouteUdp>(&exposed_name, action)),
+    );
+  }
+
+  Ok(changes)
+}
+
+fn planned<K: Serialize>(live: Option<&K>, desired: Option<&K>) -> Option<PlanAction> {
+  match (live, desired) {
+    (None, None) => None,
+    (Some(_), None) => Some(PlanAction::Delete),
+    (live, Some(desired)) => Some(plan::diff(live, desired)),
+  }
+}
+
+async fn reconcile_object<K>(
+  name: &str,
+  namespace: &str,
+  desired: Option<K>,
+  cluster: &Cluster,
+) -> Result<Option<PlanAction>>
+where
+  K: Resource<DynamicType = ()> + Serialize + DeserializeOwned,
+{
+  let api = cluster.api::<K>(namespace);
+  let live = api.get_opt(name).await?;
+
+  let action = planned(live.as_ref(), desired.as_ref());
+
+  match (&action, desired) {
+    (Some(PlanAction::Delete), _) => api.delete(name).await?,
+    (Some(PlanAction::Create | PlanAction::Patch), Some(desired)) => {
+      api.apply(name, &desired).await?;
+    }
+    _ => {}
+  }
+
+  Ok(action)
+}
+
+fn generate_exposed_svc(
+  exposed_name: &str,
+  app: &AppService,
+  selector: &BTreeMap<String, String>,
+  target: &AppPort,
+  port: i32,
+) -> Service {
+  // a node port keeps the number of the app port inside the cluster, a load balancer listens on the allocated one
+  let (service_type, service_port) = match K8S_CONFIG.expose_mode {
+    ExposeMode::LoadBalancer => ("LoadBalancer", json!({ "port": port })),
+    _ => ("NodePort", json!({ "port": target.port, "nodePort": port })),
+  };
+
+  let mut service_port = service_port;
+  service_port["name"] = json!(target.name);
+  service_port["protocol"] = json!(protocol(target));
+  service_port["targetPort"] = json!(target.name);
+
+  serde_json::from_value(json!({
+    "apiVersion": "v1",
+    "kind": "Service",
+    "metadata": {
+      "name": exposed_name,
+      "namespace": project_namespace(&app.project_id),
+      "labels": app_labels(app)
+    },
+    "spec": {
+      "type": service_type,
+      "selector": selector,
+      "ports": [service_port]
+    }
+  }))
+  .expect("Invalid exposed service")
+}
+
+/// Route from the entry point of the allocated port to the service of the app
+fn generate_route<K: DeserializeOwned>(
+  exposed_name: &str,
+  name: &str,
+  app: &AppService,
+  target: &AppPort,
+  port: i32,
+  kind: &str,
+) -> K {
+  let service = json!({ "name": name, "port": target.port });
+
+  // tcp routes need a rule, without tls every connection matches the catch all one
+  let route = match target.protocol {
+    PortProtocol::Tcp => json!({ "match": "HostSNI(`*`)", "services": [service] }),
+    PortProtocol::Udp => json!({ "services": [service] }),
+  };
+
+  serde_json::from_value(json!({
+    "apiVersion": "traefik.io/v1alpha1",
+    "kind": kind,
+    "metadata": {
+      "name": exposed_name,
+      "namespace": project_namespace(&app.project_id),
+      "labels": app_labels(app)
+    },
+    "spec": {
+      "entryPoints": [format!("{}{port}", K8S_CONFIG.expose_entrypoint_prefix)],
+      "routes": [route]
+    }
+  }))
+  .expect("Invalid exposed route")
+}
@@ -0,0 +1,166 @@
+use k8s_openapi::api::{
+  apps::v1::Deployment,
+  autoscaling::v2::HorizontalPodAutoscaler,
+  batch::v1::{CronJob, Job},
+  core::v1::{LimitRange, Namespace, PersistentVolumeClaim, ResourceQuota, Secret, Service},
+  networking::v1::{Ingress, NetworkPolicy},
+};
+use kube::{core::DynamicObject, discovery::ApiResource, ResourceExt, Result};
+use uuid::Uuid;
+
+use super::{
+  exposure,
+  project::{self, COREDNS_OVERRIDE_KIND},
+  project_namespace, Cluster, APP_LABEL, CRON_JOB_LABEL, K8S_CONFIG, PROJECT_LABEL, VOLUME_LABEL,
+};
+
+/// Object generated for a project, with the owners read from its labels
+#[derive(Debug, Clone)]
+pub struct OwnedObject {
+  location: ObjectLocation,
+  pub kind: String,
+  pub name: String,
+  pub namespace: Option<String>,
+  pub project_id: Uuid,
+  pub app_id: Option<Uuid>,
+  pub cron_job_id: Option<Uuid>,
+  pub volume_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone)]
+enum ObjectLocation {
+  Namespaced(ApiResource),
+  Cluster(ApiResource),
+  CorednsOverride,
+}
+
+impl OwnedObject {
+  /// Whether the object lives outside the namespace its project now uses,
+  /// as happens to the objects of the old layout after `K8S_PROJECT_NAMESPACES` is toggled
+  pub fn misplaced(&self) -> bool {
+    let namespace = project_namespace(&self.project_id);
+
+    match &self.location {
+      ObjectLocation::Namespaced(_) => self.namespace.as_ref() != Some(&namespace),
+      ObjectLocation::Cluster(_) => self.name != namespace,
+      ObjectLocation::CorednsOverride => false,
+    }
+  }
+}
+
+fn owned_resources() -> Vec<ApiResource> {
+  let mut resources = vec![
+    ApiResource::erase::<Deployment>(&()),
+    ApiResource::erase::<HorizontalPodAutoscaler>(&()),
+    ApiResource::erase::<CronJob>(&()),
+    ApiResource::erase::<Job>(&()),
+    ApiResource::erase::<Secret>(&()),
+    ApiResource::erase::<Service>(&()),
+    ApiResource::erase::<Ingress>(&()),
+    ApiResource::erase::<PersistentVolumeClaim>(&()),
+    ApiResource::erase::<NetworkPolicy>(&()),
+    ApiResource::erase::<ResourceQuota>(&()),
+    ApiResource::erase::<LimitRange>(&()),
+  ];
+  resources.extend(exposure::exposure_resources());
+  resources
+}
+
+/// Every object carrying the owner labels, plus the private domain overrides of the projects.
+///
+/// Both the shared namespace and the project namespaces are searched, whichever layout is in use.
+/// Namespaces come last so deleting the listed objects in order never targets a removed namespace.
+pub async fn owned_objects(cluster: &Cluster) -> Result<Vec<OwnedObject>> {
+  let namespace_resource = ApiResource::erase::<Namespace>(&());
+
+  let namespaces: Vec<OwnedObject> = cluster
+    .list_dynamic(&namespace_resource, "", PROJECT_LABEL)
+    .await?
+    .iter()
+    .filter_map(|object| owned_object(&namespace_resource, object))
+    .collect();
+
+  let mut searched = vec![K8S_CONFIG.namespace.clone()];
+  searched.extend(namespaces.iter().map(|namespace| namespace.name.clone()));
+
+  let mut owned = Vec::new();
+
+  for namespace in &searched {
+    for resource in owned_resources() {
+      let objects = cluster
+        .list_dynamic(&resource, namespace, PROJECT_LABEL)
+        .await?;
+
+      owned.extend(
+        objects
+          .iter()
+          .filter_map(|object| owned_object(&resource, object)),
+      );
+    }
+  }
+
+  for name in project::project_overrides(cluster).await? {
+    let project_id = name
+      .strip_prefix("project-")
+      .and_then(|name| name.strip_suffix(".override"))
+      .and_then(|id| Uuid::parse_str(id).ok());
+
+    if let Some(project_id) = project_id {
+      owned.push(OwnedObject {
+        location: ObjectLocation::CorednsOverride,
+        kind: COREDNS_OVERRIDE_KIND.to_string(),
+        name,
+        namespace: None,
+        project_id,
+        app_id: None,
+        cron_job_id: None,
+        volume_id: None,
+      });
+    }
+  }
+
+  owned.extend(namespaces);
+
+  Ok(owned)
+}
+
+fn owned_object(resource: &ApiResource, object: &DynamicObject) -> Option<OwnedObject> {
+  let labels = object.labels();
+  let label_id = |key: &str| {
+    labels
+      .get(key)
+      .and_then(|value| Uuid::parse_str(value).ok())
+  };
+
+  let location = match object.metadata.namespace {
+    Some(_) => ObjectLocation::Namespaced(resource.clone()),
+    None => ObjectLocation::Cluster(resource.clone()),
+  };
+
+  Some(OwnedObject {
+    location,
+    kind: resource.kind.clone(),
+    name: object.metadata.name.clone()?,
+    namespace: object.metadata.namespace.clone(),
+    project_id: label_id(PROJECT_LABEL)?,
+    app_id: label_id(APP_LABEL),
+    cron_job_id: label_id(CRON_JOB_LABEL),
+    volume_id: label_id(VOLUME_LABEL),
+  })
+}
+
+pub async fn delete_owned_object(cluster: &Cluster, object: &OwnedObject) -> Result<()> {
+  match &object.location {
+    ObjectLocation::Namespaced(resource) => {
+      let namespace = object.namespace.as_deref().unwrap_or(&K8S_CONFIG.namespace);
+
+      cluster
+        .delete_dynamic(resource, namespace, &object.name)
+        .await
+    }
+    ObjectLocation::Cluster(resource) => cluster.delete_dynamic(resource, "", &object.name).await,
+    ObjectLocation::CorednsOverride => {
+      project::remove_project_private_domains(&object.name, cluster).await
+    }
+  }
+}
@@ -1,52 +1,519 @@
-use std::sync::LazyLock;
+use std::{collections::BTreeMap, sync::LazyLock, time::Duration};
 
 use confique::Config;
-use kube::Client;
+use derive_more::derive::{Display, Error, From};
+use futures::future::{self, BoxFuture};
+use k8s_openapi::api::{
+  apps::v1::Deployment,
+  batch::v1::CronJob as KubeCronJob,
+  core::v1::{Namespace, PersistentVolumeClaim},
+};
+use kube::Resource;
 
-use crate::schemas::{AppService, EnvVar, Project, Volume};
+use crate::schemas::{
+  AppService, CronJob, EnvVar, PlanAction, Project, Registry, ReleaseHook, ResourceChange, Volume,
+};
 
-pub use app::app_status;
+pub use app::{app_port, app_status};
+pub use cluster::{Cluster, ClusterBackend, KubeBackend, MemoryBackend};
+pub use drift::{managed_objects, ManagedObject};
+pub use gc::{delete_owned_object, owned_objects, OwnedObject};
+pub use run::{create_run, removed_job, run_logs, wait_for_run};
 
 mod app;
+mod cluster;
+mod cron_job;
+mod drift;
+mod exposure;
+mod gc;
+mod plan;
 mod project;
+mod run;
 mod volume;
 
+/// Receives every resource a release reconciled
+pub type Progress<'a> = &'a (dyn Fn(ResourceChange) + Send + Sync);
+
+/// Receives the outcome of every pre-deploy command a release ran
+pub type HookReport<'a> = &'a (dyn Fn(ReleaseHook) + Send + Sync);
+
+/// Tells a release whether it may go on, checked before every step so a project deleted meanwhile is left torn down
+pub type Proceed<'a> = &'a (dyn Fn() -> BoxFuture<'a, bool> + Send + Sync);
+
+/// Failure of a release, naming the resource it could not reconcile
+#[derive(Debug, Display, Error)]
+#[display("Unable to reconcile {kind} {name}: {source}")]
+pub struct ReleaseError {
+  pub kind: String,
+  pub name: String,
+  pub source: Box<ReconcileError>,
+}
+
+impl ReleaseError {
+  /// The pre-deploy command of an app failed, the rest of the release can go on
+  fn is_hook(&self) -> bool {
+    matches!(*self.source, ReconcileError::Hook(_))
+  }
+
+  fn of<K: Resource<DynamicType = ()>>(name: &str) -> impl FnOnce(kube::Error) -> Self + '_ {
+    move |source| Self {
+      kind: K::kind(&()).to_string(),
+      name: name.to_string(),
+      source: Box::new(source.into()),
+    }
+  }
+}
+
+#[derive(Debug, Display, Error, From)]
+pub enum ReconcileError {
+  #[display("{_0}")]
+  Kube(kube::Error),
+  /// A deployment the traffic was about to move to did not become available
+  #[display("{_0}")]
+  Rollout(RolloutError),
+  #[display("{_0}")]
+  Hook(HookError),
+  #[display("{_0}")]
+  Cancelled(ReleaseCancelled),
+}
+
+/// Release stopped between two steps because its project is being deleted
+#[derive(Debug, Display, Error)]
+#[display("Release cancelled, the project is being deleted")]
+pub struct ReleaseCancelled;
+
+/// Fail with [`ReleaseCancelled`] unless the release may go on
+pub(super) async fn check_proceed(
+  project_id: &uuid::Uuid,
+  proceed: Proceed<'_>,
+) -> Result<(), ReleaseError> {
+  if proceed().await {
+    return Ok(());
+  }
+
+  Err(ReleaseError {
+    kind: "Project".to_string(),
+    name: format!("project-{project_id}"),
+    source: Box::new(ReleaseCancelled.into()),
+  })
+}
+
+/// Pre-deploy command of an app that did not succeed, its deployment was left as it was
+#[derive(Debug, Display, Error)]
+#[display("Pre-deploy command failed: {reason}")]
+pub struct HookError {
+  pub reason: String,
+}
+
+/// Deployment that did not become available in a release waiting for its apps
+#[derive(Debug, Display, Error)]
+#[display("Deployment {name} failed to roll out: {reason}")]
+pub struct RolloutError {
+  pub name: String,
+  pub reason: String,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn release(
-  project: Project,
+  cluster: &Cluster,
+  project: &Project,
   apps: Vec<AppService>,
+  cron_jobs: Vec<CronJob>,
   envs: Vec<EnvVar>,
   volumes: Vec<Volume>,
-) -> kube::Result<()> {
-  let client = Client::try_default().await?;
-
-  project::reconcile_project(&project, &apps[..], client.clone()).await?;
+  registries: Vec<Registry>,
+  progress: Progress<'_>,
+  hooks: Option<HookReport<'_>>,
+  proceed: Proceed<'_>,
+) -> Result<(), ReleaseError> {
+  check_proceed(&project.project_id, proceed).await?;
+  project::reconcile_project(project, &apps[..], &registries, cluster, progress).await?;
 
   for volume in &volumes {
-    volume::reconcile_volume(volume, client.clone()).await?;
+    check_proceed(&project.project_id, proceed).await?;
+    volume::reconcile_volume(volume, cluster, progress).await?;
   }
 
-  let apps_volumes: Vec<&Volume> = volumes
-    .iter()
-    .filter(|volume| volume.app_id.is_some())
-    .collect();
+  // a failed pre-deploy command fails the release once the rest of the project is reconciled
+  let hook_failure = match release_apps(
+    cluster,
+    apps,
+    &envs,
+    &volumes,
+    &registries,
+    progress,
+    hooks,
+    proceed,
+  )
+  .await
+  {
+    Err(err) if err.is_hook() => Some(err),
+    result => result.map(|_| None)?,
+  };
+
+  for cron_job in &cron_jobs {
+    check_proceed(&project.project_id, proceed).await?;
+    let (envs, volume) = cron_job_resources(cron_job, &envs, &volumes);
+
+    cron_job::reconcile_cron_job(cron_job, envs, volume, &registries, cluster, progress).await?;
+  }
+
+  hook_failure.map_or(Ok(()), Err)
+}
+
+/// Reconcile only the apps, leaving the project and the volumes as they are
+#[allow(clippy::too_many_arguments)]
+pub async fn release_apps(
+  cluster: &Cluster,
+  apps: Vec<AppService>,
+  envs: &[EnvVar],
+  volumes: &[Volume],
+  registries: &[Registry],
+  progress: Progress<'_>,
+  hooks: Option<HookReport<'_>>,
+  proceed: Proceed<'_>,
+) -> Result<(), ReleaseError> {
+  // a failed pre-deploy command only stops the rollout of its own app
+  let mut hook_failure = None;
 
   for app_service in apps {
-    let envs = envs
+    check_proceed(&app_service.project_id, proceed).await?;
+    let (envs, volumes) = app_resources(&app_service, envs, volumes);
+
+    let result = app::reconcile_app(
+      app_service,
+      envs,
+      &volumes,
+      registries,
+      cluster,
+      progress,
+      hooks,
+      Some(proceed),
+    )
+    .await;
+
+    match result {
+      Err(err) if err.is_hook() => {
+        hook_failure.get_or_insert(err);
+      }
+      result => result?,
+    }
+  }
+
+  hook_failure.map_or(Ok(()), Err)
+}
+
+/// Reconcile again only the project, volumes, apps and cron jobs with an object drifted from the plan
+#[allow(clippy::too_many_arguments)]
+pub async fn reapply(
+  cluster: &Cluster,
+  project: &Project,
+  apps: Vec<AppService>,
+  cron_jobs: Vec<CronJob>,
+  envs: Vec<EnvVar>,
+  volumes: Vec<Volume>,
+  registries: Vec<Registry>,
+  progress: Progress<'_>,
+) -> Result<(), ReleaseError> {
+  let drifted = |changes: &[ResourceChange]| {
+    changes
       .iter()
-      .filter(|env| env.app_id == app_service.app_id)
-      .collect();
+      .any(|change| change.action != PlanAction::Unchanged)
+  };
+
+  let namespace = project_namespace(&project.project_id);
+  let changes = project::plan_project(project, &apps, &registries, cluster)
+    .await
+    .map_err(ReleaseError::of::<Namespace>(&namespace))?;
+  if drifted(&changes) {
+    project::reconcile_project(project, &apps, &registries, cluster, progress).await?;
+  }
 
-    let volume = apps_volumes
+  for volume in &volumes {
+    let change = volume::plan_volume(volume, cluster)
+      .await
+      .map_err(ReleaseError::of::<PersistentVolumeClaim>(&format!(
+        "volume-{}",
+        volume.volume_id
+      )))?;
+    if drifted(change.as_slice()) {
+      volume::reconcile_volume(volume, cluster, progress).await?;
+    }
+  }
+
+  for app_service in apps {
+    let (envs, volumes) = app_resources(&app_service, &envs, &volumes);
+
+    let changes = app::plan_app(&app_service, envs.clone(), &volumes, &registries, cluster)
+      .await
+      .map_err(ReleaseError::of::<Deployment>(&format!(
+        "app-{}",
+        app_service.app_id
+      )))?;
+    if drifted(&changes) {
+      // the released version already went through its pre-deploy commands
+      app::reconcile_app(
+        app_service,
+        envs,
+        &volumes,
+        &registries,
+        cluster,
+        progress,
+        None,
+        None,
+      )
+      .await?;
+    }
+  }
+
+  for cron_job in &cron_jobs {
+    let (envs, volume) = cron_job_resources(cron_job, &envs, &volumes);
+
+    let changes = cron_job::plan_cron_job(cron_job, envs.clone(), volume, &registries, cluster)
+      .await
+      .map_err(ReleaseError::of::<KubeCronJob>(&format!(
+        "cronjob-{}",
+        cron_job.cron_job_id
+      )))?;
+    if drifted(&changes) {
+      cron_job::reconcile_cron_job(cron_job, envs, volume, &registries, cluster, progress).await?;
+    }
+  }
+
+  Ok(())
+}
+
+/// Wait for every app still released to roll out, failing with the first one that does not
+pub async fn wait_for_rollouts(cluster: &Cluster, apps: &[AppService]) -> Result<(), RolloutError> {
+  let timeout = Duration::from_secs(K8S_CONFIG.rollout_timeout);
+
+  future::try_join_all(
+    apps
       .iter()
-      .find(|&&volume| volume.app_id.is_some_and(|id| id == app_service.app_id))
-      .copied();
+      .filter(|app| !app.deleted)
+      .map(|app| app::wait_for_rollout(app, cluster, timeout)),
+  )
+  .await?;
+
+  Ok(())
+}
+
+/// Remove every resource of a project from the cluster
+pub async fn teardown(
+  cluster: &Cluster,
+  project: &Project,
+  apps: Vec<AppService>,
+  cron_jobs: Vec<CronJob>,
+  volumes: Vec<Volume>,
+) -> Result<(), ReleaseError> {
+  let progress: Progress = &|change| log::info!("Removed {} {}", change.kind, change.name);
+
+  // apps and cron jobs go first so no pod keeps using the claims
+  for app_service in apps {
+    let app_service = AppService {
+      deleted: true,
+      ..app_service
+    };
+
+    app::reconcile_app(
+      app_service,
+      Vec::new(),
+      &[],
+      &[],
+      cluster,
+      progress,
+      None,
+      None,
+    )
+    .await?;
+  }
+
+  for cron_job in cron_jobs {
+    let cron_job = CronJob {
+      deleted: true,
+      ..cron_job
+    };
 
-    app::reconcile_app(&project, app_service, envs, volume, client.clone()).await?;
+    cron_job::reconcile_cron_job(&cron_job, Vec::new(), None, &[], cluster, progress).await?;
+  }
+
+  for volume in volumes {
+    let volume = Volume {
+      deleted: true,
+      ..volume
+    };
+
+    volume::reconcile_volume(&volume, cluster, progress).await?;
+  }
+
+  project::teardown_project(project, cluster).await
+}
+
+/// Forward the action a reconciler took on `name` to `progress`, tagging a failure with the resource
+fn report<K: Resource<DynamicType = ()>>(
+  name: &str,
+  result: kube::Result<Option<PlanAction>>,
+  progress: Progress<'_>,
+) -> Result<(), ReleaseError> {
+  if let Some(action) = result.map_err(ReleaseError::of::<K>(name))? {
+    progress(plan::change::<K>(name, action));
   }
 
   Ok(())
 }
 
+/// Changes a release of the same rows would apply, without touching the cluster
+pub async fn plan(
+  cluster: &Cluster,
+  project: &Project,
+  apps: &[AppService],
+  cron_jobs: &[CronJob],
+  envs: &[EnvVar],
+  volumes: &[Volume],
+  registries: &[Registry],
+) -> kube::Result<Vec<ResourceChange>> {
+  let mut changes = project::plan_project(project, apps, registries, cluster).await?;
+
+  for volume in volumes {
+    changes.extend(volume::plan_volume(volume, cluster).await?);
+  }
+
+  for app_service in apps {
+    let (envs, volumes) = app_resources(app_service, envs, volumes);
+
+    changes.extend(app::plan_app(app_service, envs, &volumes, registries, cluster).await?);
+  }
+
+  for cron_job in cron_jobs {
+    let (envs, volume) = cron_job_resources(cron_job, envs, volumes);
+
+    changes.extend(cron_job::plan_cron_job(cron_job, envs, volume, registries, cluster).await?);
+  }
+
+  Ok(changes)
+}
+
+fn app_resources<'a>(
+  app_service: &AppService,
+  envs: &'a [EnvVar],
+  volumes: &'a [Volume],
+) -> (Vec<&'a EnvVar>, Vec<&'a Volume>) {
+  let envs = envs
+    .iter()
+    .filter(|env| env.app_id == Some(app_service.app_id))
+    .collect();
+
+  // a deleted volume is unmounted before its claim goes, another volume may take its path
+  let volumes = volumes
+    .iter()
+    .filter(|volume| !volume.deleted && volume.app_path(&app_service.app_id).is_some())
+    .collect();
+
+  (envs, volumes)
+}
+
+fn cron_job_resources<'a>(
+  cron_job: &CronJob,
+  envs: &'a [EnvVar],
+  volumes: &'a [Volume],
+) -> (Vec<&'a EnvVar>, Option<&'a Volume>) {
+  let envs = envs
+    .iter()
+    .filter(|env| env.cron_job_id == Some(cron_job.cron_job_id))
+    .collect();
+
+  let volume = volumes
+    .iter()
+    .find(|volume| volume.cron_job_id == Some(cron_job.cron_job_id));
+
+  (envs, volume)
+}
+
+/// Owner labels tie every generated object to the rows it comes from
+const PROJECT_LABEL: &str = "gws-project";
+const APP_LABEL: &str = "gws-app";
+const CRON_JOB_LABEL: &str = "gws-cron-job";
+const VOLUME_LABEL: &str = "gws-volume";
+
+fn project_labels(project_id: &uuid::Uuid) -> BTreeMap<String, String> {
+  BTreeMap::from([(PROJECT_LABEL.to_string(), project_id.to_string())])
+}
+
+fn app_labels(app: &AppService) -> BTreeMap<String, String> {
+  let mut labels = project_labels(&app.project_id);
+  labels.insert(APP_LABEL.to_string(), app.app_id.to_string());
+  labels
+}
+
+fn cron_job_labels(cron_job: &CronJob) -> BTreeMap<String, String> {
+  let mut labels = project_labels(&cron_job.project_id);
+  labels.insert(CRON_JOB_LABEL.to_string(), cron_job.cron_job_id.to_string());
+  labels
+}
+
+fn volume_labels(volume: &Volume) -> BTreeMap<String, String> {
+  let mut labels = project_labels(&volume.project_id);
+  labels.insert(VOLUME_LABEL.to_string(), volume.volume_id.to_string());
+  labels
+}
+
+/// Namespace the objects of a project are generated in
+fn project_namespace(project_id: &uuid::Uuid) -> String {
+  if K8S_CONFIG.project_namespaces {
+    format!("{}-{project_id}", K8S_CONFIG.namespace)
+  } else {
+    K8S_CONFIG.namespace.clone()
+  }
+}
+
+/// Ports the exposed ports of the apps are allocated from
+pub fn exposed_ports() -> std::ops::RangeInclusive<i32> {
+  K8S_CONFIG.expose_ports_start..=K8S_CONFIG.expose_ports_end
+}
+
+/// Host the exposed ports of the apps are reached on
+pub fn exposed_host() -> &'static str {
+  K8S_CONFIG
+    .expose_host
+    .as_deref()
+    .unwrap_or(&K8S_CONFIG.host_domain)
+}
+
+/// Name of the main port of the apps, their other ports can't reuse it
+pub fn main_port_name() -> &'static str {
+  &K8S_CONFIG.port_name
+}
+
+fn cpu_quantity(millicores: i32) -> String {
+  decimal_quantity(millicores, &["m", "", "k", "M"])
+}
+
+fn memory_quantity(megabytes: i32) -> String {
+  decimal_quantity(megabytes, &["M", "G", "T", "P"])
+}
+
+/// Quantities are written in the canonical form the API server returns, so a plan sees no drift:
+/// `value` in the first suffix is moved to the larger ones while it stays a whole number
+fn decimal_quantity(value: i32, suffixes: &[&str]) -> String {
+  if value == 0 {
+    return "0".to_string();
+  }
+
+  let mut value = value;
+  let mut suffix = suffixes[0];
+
+  for larger in &suffixes[1..] {
+    if value % 1000 != 0 {
+      break;
+    }
+
+    value /= 1000;
+    suffix = larger;
+  }
+
+  format!("{value}{suffix}")
+}
+
 static K8S_CONFIG: LazyLock<K8sConfig> =
   LazyLock::new(|| K8sConfig::builder().env().load().unwrap());
 
@@ -54,12 +521,52 @@ static K8S_CONFIG: LazyLock<K8sConfig> =
 struct K8sConfig {
   #[config(env = "K8S_NAMESPACE", default = "gws")]
   namespace: String,
+  /// Give every project its own `<K8S_NAMESPACE>-<project id>` namespace instead of sharing `K8S_NAMESPACE`
+  #[config(env = "K8S_PROJECT_NAMESPACES", default = false)]
+  project_namespaces: bool,
   #[config(env = "K8S_MANAGER", default = "gws")]
   manager: String,
   #[config(env = "K8S_PORT_NAME", default = "app")]
   port_name: String,
-  #[config(env = "K8S_SERVICE_PORT", default = 80)]
-  service_port: u16,
+  /// Seconds a release waiting for its apps gives every deployment to become available
+  #[config(env = "K8S_ROLLOUT_TIMEOUT", default = 300)]
+  rollout_timeout: u64,
+  /// Seconds a run is given before it is stopped and failed
+  #[config(env = "K8S_RUN_TIMEOUT", default = 3600)]
+  run_timeout: i64,
+  /// Seconds a pre-deploy command is given before its job is deleted and the rollout of the app failed
+  #[config(env = "K8S_PRE_DEPLOY_TIMEOUT", default = 600)]
+  pre_deploy_timeout: i64,
+  /// Seconds the job of a finished run is kept around, its outcome and logs are stored before
+  #[config(env = "K8S_RUN_TTL", default = 3600)]
+  run_ttl: i32,
+  /// Storage class of the ReadWriteMany volumes, the default local-path one only supports ReadWriteOnce
+  #[config(env = "K8S_SHARED_STORAGE_CLASS", default = "nfs-client")]
+  shared_storage_class: String,
   #[config(env = "HOST_DOMAIN", default = "localhost")]
   host_domain: String,
+  /// How the ports exposed over raw tcp and udp reach the apps
+  #[config(env = "K8S_EXPOSE_MODE", default = "NodePort")]
+  expose_mode: ExposeMode,
+  /// First and last port allocated to the exposed ports, the node port range of the cluster by default
+  #[config(env = "K8S_EXPOSE_PORTS_START", default = 30000)]
+  expose_ports_start: i32,
+  #[config(env = "K8S_EXPOSE_PORTS_END", default = 32767)]
+  expose_ports_end: i32,
+  /// Host the exposed ports are reached on, `HOST_DOMAIN` when unset
+  #[config(env = "K8S_EXPOSE_HOST")]
+  expose_host: Option<String>,
+  /// Traefik needs an entry point named with this prefix and the port for every port of the range
+  #[config(env = "K8S_EXPOSE_ENTRYPOINT_PREFIX", default = "expose-")]
+  expose_entrypoint_prefix: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+enum ExposeMode {
+  /// A NodePort service on the allocated port of every node
+  NodePort,
+  /// A LoadBalancer service listening on the allocated port
+  LoadBalancer,
+  /// An IngressRouteTCP or IngressRouteUDP on the entry point of the allocated port
+  Traefik,
 }
@@ -0,0 +1,46 @@
+use kube::Resource;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::schemas::{PlanAction, ResourceChange};
+
+pub fn change<K: Resource<DynamicType = ()>>(name: &str, action: PlanAction) -> ResourceChange {
+  ResourceChange {
+    kind: K::kind(&()).to_string(),
+    name: name.to_string(),
+    action,
+  }
+}
+
+/// Action needed to bring `live` to `desired`, fields filled in by the API server are ignored
+pub fn diff<K: Serialize>(live: Option<&K>, desired: &K) -> PlanAction {
+  let Some(live) = live else {
+    return PlanAction::Create;
+  };
+
+  let desired = serde_json::to_value(desired).expect("Invalid desired object");
+  let live = serde_json::to_value(live).expect("Invalid live object");
+
+  if is_subset(&desired, &live) {
+    PlanAction::Unchanged
+  } else {
+    PlanAction::Patch
+  }
+}
+
+fn is_subset(desired: &Value, live: &Value) -> bool {
+  match (desired, live) {
+    (Value::Null, _) => true,
+    (Value::Object(desired), Value::Object(live)) => desired
+      .iter()
+      .all(|(key, value)| live.get(key).is_some_and(|live| is_subset(value, live))),
+    (Value::Array(desired), Value::Array(live)) => {
+      desired.len() == live.len()
+        && desired
+          .iter()
+          .zip(live)
+          .all(|(desired, live)| is_subset(desired, live))
+    }
+    (desired, live) => desired == live,
+  }
+}
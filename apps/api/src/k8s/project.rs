@@ -1,54 +1,493 @@
-use k8s_openapi::api::{core::v1::ConfigMap, networking::v1::NetworkPolicy};
-use kube::{
-  api::{Patch, PatchParams},
-  Api, Client, Result,
+use std::collections::BTreeMap;
+
+use k8s_openapi::{
+  api::{
+    core::v1::{ConfigMap, LimitRange, Namespace, ResourceQuota, Secret},
+    networking::v1::NetworkPolicy,
+  },
+  ByteString,
+};
+use kube::Result;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{
+  registry::decrypt_token,
+  schemas::{
+    AppService, PlanAction, Project, Registry, ResourceChange, DEFAULT_CPU_LIMIT,
+    DEFAULT_CPU_REQUEST, DEFAULT_MEMORY_LIMIT, DEFAULT_MEMORY_REQUEST,
+  },
 };
-use serde_json::json;
 
-use crate::schemas::{AppService, Project};
+use super::{
+  cpu_quantity, decimal_quantity, memory_quantity, plan, project_labels, project_namespace, report,
+  Cluster, Progress, ReleaseError, K8S_CONFIG,
+};
 
-use super::K8S_CONFIG;
+const COREDNS_CUSTOM_NAME: &str = "coredns-custom";
+pub(super) const COREDNS_OVERRIDE_KIND: &str = "CorednsOverride";
 
 pub async fn reconcile_project(
   project: &Project,
   apps: &[AppService],
-  client: Client,
-) -> Result<()> {
+  registries: &[Registry],
+  cluster: &Cluster,
+  progress: Progress<'_>,
+) -> Result<(), ReleaseError> {
   let name = format!("project-{}", project.project_id);
-  let params = PatchParams::apply(&K8S_CONFIG.manager).force();
 
-  reconcile_network_policy(&name, project, &params, client.clone()).await?;
+  let namespace = project_namespace(&project.project_id);
+
+  // everything else of the project is generated inside the namespace, so it goes first
+  report::<Namespace>(
+    &namespace,
+    reconcile_namespace(&namespace, project, cluster).await,
+    progress,
+  )?;
+
+  report::<NetworkPolicy>(
+    &name,
+    reconcile_network_policy(&name, project, cluster).await,
+    progress,
+  )?;
 
-  reconcile_project_private_domains(&name, apps, &params, client.clone()).await?;
+  report::<ResourceQuota>(
+    &name,
+    reconcile_quota(&name, project, cluster).await,
+    progress,
+  )?;
+
+  report::<LimitRange>(
+    &name,
+    reconcile_limit_range(&name, project, cluster).await,
+    progress,
+  )?;
+
+  let registries_name = registries_secret_name(&project.project_id);
+
+  report::<Secret>(
+    &registries_name,
+    reconcile_registries(&registries_name, project, registries, cluster).await,
+    progress,
+  )?;
+
+  let override_name = format!("{name}.override");
+
+  match reconcile_project_private_domains(&name, apps, cluster).await {
+    Ok(()) => progress(override_change(override_name, PlanAction::Patch)),
+    Err(source) => {
+      return Err(ReleaseError {
+        kind: COREDNS_OVERRIDE_KIND.to_string(),
+        name: override_name,
+        source: Box::new(source.into()),
+      })
+    }
+  }
 
   Ok(())
 }
 
+pub async fn plan_project(
+  project: &Project,
+  apps: &[AppService],
+  registries: &[Registry],
+  cluster: &Cluster,
+) -> Result<Vec<ResourceChange>> {
+  let name = format!("project-{}", project.project_id);
+  let namespace = project_namespace(&project.project_id);
+  let mut changes = Vec::new();
+
+  if K8S_CONFIG.project_namespaces {
+    let current = cluster
+      .cluster_api::<Namespace>()
+      .get_opt(&namespace)
+      .await?;
+
+    let action = plan::diff(current.as_ref(), &generate_namespace(&namespace, project));
+
+    changes.push(plan::change::<Namespace>(&namespace, action));
+  }
+
+  let net = cluster
+    .api::<NetworkPolicy>(&namespace)
+    .get_opt(&name)
+    .await?;
+
+  let net_action = plan::diff(net.as_ref(), &generate_network_policy(&name, project));
+
+  let coredns_custom = cluster
+    .api::<ConfigMap>("kube-system")
+    .get(COREDNS_CUSTOM_NAME)
+    .await?;
+
+  let override_name = format!("{name}.override");
+  let current_override = coredns_custom
+    .data
+    .as_ref()
+    .and_then(|data| data.get(&override_name));
+
+  let override_action = match current_override {
+    None => PlanAction::Create,
+    Some(current) if *current == generate_project_override(&name, apps) => PlanAction::Unchanged,
+    Some(_) => PlanAction::Patch,
+  };
+
+  changes.push(plan::change::<NetworkPolicy>(&name, net_action));
+
+  if K8S_CONFIG.project_namespaces {
+    let quota = cluster
+      .api::<ResourceQuota>(&namespace)
+      .get_opt(&name)
+      .await?;
+    let quota_action = plan::diff(quota.as_ref(), &generate_quota(&name, project));
+    changes.push(plan::change::<ResourceQuota>(&name, quota_action));
+
+    let limit_range = cluster.api::<LimitRange>(&namespace).get_opt(&name).await?;
+    let limit_range_action =
+      plan::diff(limit_range.as_ref(), &generate_limit_range(&name, project));
+    changes.push(plan::change::<LimitRange>(&name, limit_range_action));
+  }
+
+  let registries_name = registries_secret_name(&project.project_id);
+  let registries_secret = cluster
+    .api::<Secret>(&namespace)
+    .get_opt(&registries_name)
+    .await?;
+  let registries_action = match (
+    registries_secret,
+    generate_registries(&registries_name, project, registries),
+  ) {
+    (None, None) => None,
+    (Some(_), None) => Some(PlanAction::Delete),
+    (current, Some(desired)) => Some(plan::diff(current.as_ref(), &desired)),
+  };
+  changes.extend(registries_action.map(|action| plan::change::<Secret>(&registries_name, action)));
+  changes.push(override_change(override_name, override_action));
+
+  Ok(changes)
+}
+
+fn override_change(name: String, action: PlanAction) -> ResourceChange {
+  ResourceChange {
+    kind: COREDNS_OVERRIDE_KIND.to_string(),
+    name,
+    action,
+  }
+}
+
+/// Remove the network policy, the private domains and the namespace of a deleted project
+pub async fn teardown_project(project: &Project, cluster: &Cluster) -> Result<(), ReleaseError> {
+  let name = format!("project-{}", project.project_id);
+  let namespace = project_namespace(&project.project_id);
+
+  let api = cluster.api::<NetworkPolicy>(&namespace);
+
+  let net = api
+    .get_opt(&name)
+    .await
+    .map_err(ReleaseError::of::<NetworkPolicy>(&name))?;
+
+  if net.is_some() {
+    api
+      .delete(&name)
+      .await
+      .map_err(ReleaseError::of::<NetworkPolicy>(&name))?;
+  }
+
+  let registries_name = registries_secret_name(&project.project_id);
+  let api = cluster.api::<Secret>(&namespace);
+
+  let registries = api
+    .get_opt(&registries_name)
+    .await
+    .map_err(ReleaseError::of::<Secret>(&registries_name))?;
+
+  if registries.is_some() {
+    api
+      .delete(&registries_name)
+      .await
+      .map_err(ReleaseError::of::<Secret>(&registries_name))?;
+  }
+
+  let override_name = format!("{name}.override");
+
+  remove_project_private_domains(&override_name, cluster)
+    .await
+    .map_err(|source| ReleaseError {
+      kind: COREDNS_OVERRIDE_KIND.to_string(),
+      name: override_name,
+      source: Box::new(source.into()),
+    })?;
+
+  if !K8S_CONFIG.project_namespaces {
+    return Ok(());
+  }
+
+  let api = cluster.cluster_api::<Namespace>();
+
+  let current = api
+    .get_opt(&namespace)
+    .await
+    .map_err(ReleaseError::of::<Namespace>(&namespace))?;
+
+  if current.is_some() {
+    api
+      .delete(&namespace)
+      .await
+      .map_err(ReleaseError::of::<Namespace>(&namespace))?;
+  }
+
+  Ok(())
+}
+
+async fn reconcile_namespace(
+  namespace: &str,
+  project: &Project,
+  cluster: &Cluster,
+) -> Result<Option<PlanAction>> {
+  if !K8S_CONFIG.project_namespaces {
+    return Ok(None);
+  }
+
+  let api = cluster.cluster_api::<Namespace>();
+
+  let current = api.get_opt(namespace).await?;
+
+  let desired = generate_namespace(namespace, project);
+
+  let action = plan::diff(current.as_ref(), &desired);
+
+  if action != PlanAction::Unchanged {
+    api.apply(namespace, &desired).await?;
+  }
+
+  Ok(Some(action))
+}
+
+fn generate_namespace(namespace: &str, project: &Project) -> Namespace {
+  serde_json::from_value(json!({
+    "apiVersion": "v1",
+    "kind": "Namespace",
+    "metadata": {
+      "name": namespace,
+      "labels": project_labels(&project.project_id)
+    }
+  }))
+  .expect("Invalid Namespace")
+}
+
+/// Mirror of the project quotas, only generated in a namespace of its own since a quota covers a whole namespace
+async fn reconcile_quota(
+  name: &str,
+  project: &Project,
+  cluster: &Cluster,
+) -> Result<Option<PlanAction>> {
+  if !K8S_CONFIG.project_namespaces {
+    return Ok(None);
+  }
+
+  let api = cluster.api::<ResourceQuota>(&project_namespace(&project.project_id));
+
+  let quota = api.get_opt(name).await?;
+
+  let desired = generate_quota(name, project);
+
+  let action = plan::diff(quota.as_ref(), &desired);
+
+  if action != PlanAction::Unchanged {
+    api.apply(name, &desired).await?;
+  }
+
+  Ok(Some(action))
+}
+
+fn generate_quota(name: &str, project: &Project) -> ResourceQuota {
+  // a rolling update runs the old and new pods side by side, a blue/green or canary rollout the
+  // old and new deployments, the API enforces the quotas on the apps while the cluster leaves
+  // room for a full rollout of each of them
+  serde_json::from_value(json!({
+    "apiVersion": "v1",
+    "kind": "ResourceQuota",
+    "metadata": {
+      "name": name,
+      "namespace": project_namespace(&project.project_id),
+      "labels": project_labels(&project.project_id)
+    },
+    "spec": {
+      "hard": {
+        "count/deployments.apps": decimal_quantity(project.quota_apps.saturating_mul(2), &["", "k", "M", "G"]),
+        "pods": decimal_quantity(project.quota_replicas.saturating_mul(2), &["", "k", "M", "G"]),
+        "limits.cpu": cpu_quantity(project.quota_cpu.saturating_mul(2)),
+        "limits.memory": memory_quantity(project.quota_memory.saturating_mul(2)),
+        "requests.storage": memory_quantity(project.quota_storage)
+      }
+    }
+  }))
+  .expect("Invalid Resource Quota")
+}
+
+/// Containers generated without resources get the app defaults, as the quota requires limits on every pod
+async fn reconcile_limit_range(
+  name: &str,
+  project: &Project,
+  cluster: &Cluster,
+) -> Result<Option<PlanAction>> {
+  if !K8S_CONFIG.project_namespaces {
+    return Ok(None);
+  }
+
+  let api = cluster.api::<LimitRange>(&project_namespace(&project.project_id));
+
+  let limit_range = api.get_opt(name).await?;
+
+  let desired = generate_limit_range(name, project);
+
+  let action = plan::diff(limit_range.as_ref(), &desired);
+
+  if action != PlanAction::Unchanged {
+    api.apply(name, &desired).await?;
+  }
+
+  Ok(Some(action))
+}
+
+fn generate_limit_range(name: &str, project: &Project) -> LimitRange {
+  serde_json::from_value(json!({
+    "apiVersion": "v1",
+    "kind": "LimitRange",
+    "metadata": {
+      "name": name,
+      "namespace": project_namespace(&project.project_id),
+      "labels": project_labels(&project.project_id)
+    },
+    "spec": {
+      "limits": [
+        {
+          "type": "Container",
+          "default": {
+            "cpu": cpu_quantity(DEFAULT_CPU_LIMIT),
+            "memory": memory_quantity(DEFAULT_MEMORY_LIMIT)
+          },
+          "defaultRequest": {
+            "cpu": cpu_quantity(DEFAULT_CPU_REQUEST),
+            "memory": memory_quantity(DEFAULT_MEMORY_REQUEST)
+          }
+        }
+      ]
+    }
+  }))
+  .expect("Invalid Limit Range")
+}
+
+/// Name of the secret the apps of a project pull their images from its private registries with
+pub(super) fn registries_secret_name(project_id: &Uuid) -> String {
+  format!("project-{project_id}-registries")
+}
+
+async fn reconcile_registries(
+  name: &str,
+  project: &Project,
+  registries: &[Registry],
+  cluster: &Cluster,
+) -> Result<Option<PlanAction>> {
+  let api = cluster.api::<Secret>(&project_namespace(&project.project_id));
+
+  let current = api.get_opt(name).await?;
+
+  match (current, generate_registries(name, project, registries)) {
+    (None, None) => Ok(None),
+    (Some(_), None) => {
+      api.delete(name).await?;
+      Ok(Some(PlanAction::Delete))
+    }
+    (current, Some(desired)) => {
+      let action = plan::diff(current.as_ref(), &desired);
+
+      if action != PlanAction::Unchanged {
+        api.apply(name, &desired).await?;
+      }
+
+      Ok(Some(action))
+    }
+  }
+}
+
+/// Docker config with the credentials of every registry of the project, `None` when it has none
+fn generate_registries(name: &str, project: &Project, registries: &[Registry]) -> Option<Secret> {
+  if registries.is_empty() {
+    return None;
+  }
+
+  // a token encrypted with a previous key is left out, pulling from its registry fails until it is set again
+  let auths: BTreeMap<&str, Value> = registries
+    .iter()
+    .filter_map(|registry| {
+      let Some(token) = decrypt_token(&registry.token) else {
+        log::error!(
+          "Unable to decrypt the token of registry {}",
+          registry.registry_id
+        );
+        return None;
+      };
+
+      Some((
+        registry.registry_host.as_str(),
+        json!({ "username": registry.username, "password": token }),
+      ))
+    })
+    .collect();
+
+  let mut secret: Secret = serde_json::from_value(json!({
+    "apiVersion": "v1",
+    "kind": "Secret",
+    "metadata": {
+      "name": name,
+      "namespace": project_namespace(&project.project_id),
+      "labels": project_labels(&project.project_id)
+    },
+    "type": "kubernetes.io/dockerconfigjson"
+  }))
+  .expect("Invalid registries secret");
+
+  let config = serde_json::to_vec(&json!({ "auths": auths })).expect("Invalid docker config");
+  secret.data = Some(BTreeMap::from([(
+    ".dockerconfigjson".to_string(),
+    ByteString(config),
+  )]));
+
+  Some(secret)
+}
+
 async fn reconcile_network_policy(
   name: &str,
   project: &Project,
-  params: &PatchParams,
-  client: Client,
-) -> Result<()> {
-  let api: Api<NetworkPolicy> = Api::namespaced(client, &K8S_CONFIG.namespace);
+  cluster: &Cluster,
+) -> Result<Option<PlanAction>> {
+  let api = cluster.api::<NetworkPolicy>(&project_namespace(&project.project_id));
 
   let net = api.get_opt(name).await?;
 
-  if net.is_none() {
-    let net = generate_network_policy(name, project);
-    api.patch(name, params, &Patch::Apply(net)).await?;
+  let desired = generate_network_policy(name, project);
+
+  let action = plan::diff(net.as_ref(), &desired);
+
+  if action != PlanAction::Unchanged {
+    api.apply(name, &desired).await?;
   }
 
-  Ok(())
+  Ok(Some(action))
 }
 
 fn generate_network_policy(name: &str, project: &Project) -> NetworkPolicy {
+  let namespace = project_namespace(&project.project_id);
+
   serde_json::from_value(json!({
     "apiVersion": "networking.k8s.io/v1",
     "kind": "NetworkPolicy",
     "metadata": {
       "name": name,
-      "namespace": &K8S_CONFIG.namespace
+      "namespace": namespace,
+      "labels": project_labels(&project.project_id)
     },
     "spec": {
       "podSelector": {
@@ -69,7 +508,7 @@ fn generate_network_policy(name: &str, project: &Project) -> NetworkPolicy {
                   {
                     "key": "kubernetes.io/metadata.name",
                     "operator": "In",
-                    "values": ["kube-system", &K8S_CONFIG.namespace]
+                    "values": ["kube-system", namespace]
                   }
                 ]
               }
@@ -93,7 +532,7 @@ fn generate_network_policy(name: &str, project: &Project) -> NetworkPolicy {
                   {
                     "key": "kubernetes.io/metadata.name",
                     "operator": "In",
-                    "values": ["kube-system", &K8S_CONFIG.namespace]
+                    "values": ["kube-system", namespace]
                   }
                 ]
               }
@@ -116,33 +555,11 @@ fn generate_network_policy(name: &str, project: &Project) -> NetworkPolicy {
 async fn reconcile_project_private_domains(
   project_name: &str,
   apps: &[AppService],
-  params: &PatchParams,
-  client: Client,
+  cluster: &Cluster,
 ) -> Result<()> {
-  let api: Api<ConfigMap> = Api::namespaced(client, "kube-system");
-  let coredns_custom_name = "coredns-custom";
-
-  let mut coredns_custom = api.get(coredns_custom_name).await?;
+  let api = cluster.api::<ConfigMap>("kube-system");
 
-  let project_override: Vec<String> = apps
-    .iter()
-    .filter(|app| !app.deleted && app.private_domain.is_some())
-    .map(
-      |AppService {
-         app_id,
-         private_domain,
-         ..
-       }| {
-        let domain = private_domain.as_deref().unwrap();
-        format!(
-          r#"template IN ANY {domain}.{project_name}.projects.internal {{
-          match "^{domain}\.{project_name}\.projects\.internal\.$"
-          answer "{{{{ .Name }}}} 60 IN CNAME app-{app_id}.gws.svc.cluster.local"
-        }}"#
-        )
-      },
-    )
-    .collect();
+  let mut coredns_custom = api.get(COREDNS_CUSTOM_NAME).await?;
 
   coredns_custom
     .data
@@ -150,14 +567,92 @@ async fn reconcile_project_private_domains(
     .expect("Coredns custom not initialized")
     .insert(
       format!("{project_name}.override"),
-      project_override.join("\n"),
+      generate_project_override(project_name, apps),
     );
 
   coredns_custom.metadata.managed_fields = None;
 
-  api
-    .patch(coredns_custom_name, params, &Patch::Apply(coredns_custom))
-    .await?;
+  api.apply(COREDNS_CUSTOM_NAME, &coredns_custom).await?;
+
+  Ok(())
+}
+
+pub(super) async fn remove_project_private_domains(
+  override_name: &str,
+  cluster: &Cluster,
+) -> Result<()> {
+  let api = cluster.api::<ConfigMap>("kube-system");
+
+  let mut coredns_custom = api.get(COREDNS_CUSTOM_NAME).await?;
+
+  let removed = coredns_custom
+    .data
+    .as_mut()
+    .and_then(|data| data.remove(override_name));
+
+  if removed.is_some() {
+    coredns_custom.metadata.managed_fields = None;
+
+    api.apply(COREDNS_CUSTOM_NAME, &coredns_custom).await?;
+  }
 
   Ok(())
 }
+
+/// Names of the private domain overrides written to the CoreDNS custom config
+pub(super) async fn project_overrides(cluster: &Cluster) -> Result<Vec<String>> {
+  let coredns_custom = cluster
+    .api::<ConfigMap>("kube-system")
+    .get(COREDNS_CUSTOM_NAME)
+    .await?;
+
+  Ok(
+    coredns_custom
+      .data
+      .unwrap_or_default()
+      .into_keys()
+      .filter(|key| key.starts_with("project-") && key.ends_with(".override"))
+      .collect(),
+  )
+}
+
+/// Number of the port the private domain of the app targets
+fn private_port(app: &AppService) -> i32 {
+  let target = app
+    .private_port
+    .as_deref()
+    .and_then(|name| app.ports.0.iter().find(|port| port.name == name));
+
+  target.map_or(app.port.unwrap_or_default(), |port| port.port)
+}
+
+fn generate_project_override(project_name: &str, apps: &[AppService]) -> String {
+  let project_override: Vec<String> = apps
+    .iter()
+    .filter(|app| !app.deleted && app.private_domain.is_some())
+    .map(|app| {
+      let AppService {
+        app_id,
+        project_id,
+        private_domain,
+        ..
+      } = app;
+      let domain = private_domain.as_deref().unwrap();
+      let namespace = project_namespace(project_id);
+      // the port can't go in the CNAME, clients looking up the SRV record find the targeted one
+      let port = private_port(app);
+      format!(
+        r#"template IN SRV {domain}.{project_name}.projects.internal {{
+          match "^{domain}\.{project_name}\.projects\.internal\.$"
+          answer "{{{{ .Name }}}} 60 IN SRV 0 0 {port} app-{app_id}.{namespace}.svc.cluster.local"
+        }}
+        template IN ANY {domain}.{project_name}.projects.internal {{
+          match "^{domain}\.{project_name}\.projects\.internal\.$"
+          answer "{{{{ .Name }}}} 60 IN CNAME app-{app_id}.{namespace}.svc.cluster.local"
+        }}"#
+      )
+    })
+    .collect();
+
+  project_override.join("\n")
+}
@@ -0,0 +1,289 @@
+use std::time::Duration;
+
+use futures::StreamExt;
+use k8s_openapi::api::{
+  batch::v1::Job,
+  core::v1::{Pod, PodSpec},
+};
+use kube::Result;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::schemas::{AppService, Run};
+
+use super::{app::released_deploy, app_labels, project_namespace, Cluster, K8S_CONFIG};
+
+/// Time the cluster is given past the deadline of a job to fail it itself before it is deleted
+const JOB_DEADLINE_GRACE: u64 = 60;
+
+/// How the command of a run or of a pre-deploy hook ended
+#[derive(Debug)]
+pub struct RunOutcome {
+  pub succeeded: bool,
+  pub exit_code: Option<i32>,
+  pub error: Option<String>,
+  pub logs: String,
+}
+
+/// Start the job of the run from the pods of the released app, `None` when the app was never released
+pub async fn create_run(run: &Run, app: &AppService, cluster: &Cluster) -> Result<Option<Job>> {
+  let pod = released_deploy(app, cluster)
+    .await?
+    .and_then(|deploy| deploy.spec?.template.spec);
+
+  let Some(pod) = pod else {
+    return Ok(None);
+  };
+
+  let job = generate_job(
+    &run_name(&run.run_id),
+    app,
+    &run.command,
+    pod,
+    K8S_CONFIG.run_timeout,
+  );
+
+  cluster
+    .api::<Job>(&project_namespace(&app.project_id))
+    .create(&job)
+    .await
+    .map(Some)
+}
+
+/// Run the pre-deploy command of the app in a pod of the deployment about to roll out, returns the name of its job
+pub(super) async fn run_pre_deploy(
+  app: &AppService,
+  command: &[String],
+  pod: PodSpec,
+  cluster: &Cluster,
+) -> Result<(String, RunOutcome)> {
+  let namespace = project_namespace(&app.project_id);
+  // the template of a job can't change, every release gets its own
+  let name = format!(
+    "app-{}-pre-deploy-{}",
+    app.app_id,
+    &Uuid::new_v4().simple().to_string()[..5]
+  );
+
+  cluster
+    .api::<Job>(&namespace)
+    .create(&generate_job(
+      &name,
+      app,
+      command,
+      pod,
+      K8S_CONFIG.pre_deploy_timeout,
+    ))
+    .await?;
+
+  let outcome = wait_for_job(cluster, &namespace, &name, K8S_CONFIG.pre_deploy_timeout)
+    .await?
+    .unwrap_or_else(removed_job);
+
+  Ok((name, outcome))
+}
+
+/// Wait for the job of the run to finish, `None` when it no longer exists
+pub async fn wait_for_run(
+  cluster: &Cluster,
+  project_id: &Uuid,
+  run_id: &Uuid,
+) -> Result<Option<RunOutcome>> {
+  wait_for_job(
+    cluster,
+    &project_namespace(project_id),
+    &run_name(run_id),
+    K8S_CONFIG.run_timeout,
+  )
+  .await
+}
+
+/// Outcome of a job that was deleted before it finished
+pub fn removed_job() -> RunOutcome {
+  RunOutcome {
+    succeeded: false,
+    exit_code: None,
+    error: Some("The job was removed before it finished".to_string()),
+    logs: String::new(),
+  }
+}
+
+/// Wait for the job to finish, it is deleted and failed when it is still running a while after its `deadline`
+async fn wait_for_job(
+  cluster: &Cluster,
+  namespace: &str,
+  name: &str,
+  deadline: i64,
+) -> Result<Option<RunOutcome>> {
+  let api = cluster.api::<Job>(namespace);
+
+  if api.get_opt(name).await?.is_none() {
+    return Ok(None);
+  }
+
+  let mut jobs = api.watch(&format!("metadata.name={name}"));
+
+  let finished = async {
+    loop {
+      match jobs.next().await {
+        Some(Ok(job)) => {
+          if let Some(result) = job_result(&job) {
+            break Some(result);
+          }
+        }
+        Some(Err(err)) => log::warn!("Unable to watch the job {name}: {err}"),
+        None => break None,
+      }
+    }
+  };
+
+  let timeout = Duration::from_secs(deadline.max(0) as u64 + JOB_DEADLINE_GRACE);
+  let result = match tokio::time::timeout(timeout, finished).await {
+    Ok(Some(result)) => result,
+    Ok(None) => return Ok(None),
+    Err(_) => {
+      let logs = match run_pod(cluster, namespace, name).await? {
+        Some(pod) => pod_logs(cluster, namespace, &pod).await,
+        None => String::new(),
+      };
+      api.delete(name).await?;
+
+      return Ok(Some(RunOutcome {
+        succeeded: false,
+        exit_code: None,
+        error: Some(format!(
+          "The job didn't finish within {}s",
+          timeout.as_secs()
+        )),
+        logs,
+      }));
+    }
+  };
+
+  let pod = run_pod(cluster, namespace, name).await?;
+
+  let exit_code = pod
+    .as_ref()
+    .and_then(|pod| pod.status.as_ref()?.container_statuses.as_ref()?.first())
+    .and_then(|status| status.state.as_ref()?.terminated.as_ref())
+    .map(|terminated| terminated.exit_code);
+
+  let logs = match &pod {
+    Some(pod) => pod_logs(cluster, namespace, pod).await,
+    None => String::new(),
+  };
+
+  Ok(Some(RunOutcome {
+    succeeded: result.is_ok(),
+    exit_code,
+    error: result.err(),
+    logs,
+  }))
+}
+
+/// Output of the run so far, empty until its pod is scheduled
+pub async fn run_logs(cluster: &Cluster, project_id: &Uuid, run_id: &Uuid) -> Result<String> {
+  let namespace = project_namespace(project_id);
+
+  Ok(
+    match run_pod(cluster, &namespace, &run_name(run_id)).await? {
+      Some(pod) => pod_logs(cluster, &namespace, &pod).await,
+      None => String::new(),
+    },
+  )
+}
+
+fn run_name(run_id: &Uuid) -> String {
+  format!("run-{run_id}")
+}
+
+/// Pod the job started, it is never retried
+async fn run_pod(cluster: &Cluster, namespace: &str, name: &str) -> Result<Option<Pod>> {
+  let pods = cluster
+    .api::<Pod>(namespace)
+    .list(&format!("run={name}"))
+    .await?;
+
+  Ok(pods.into_iter().next())
+}
+
+/// A pod still waiting for its container has no logs yet
+async fn pod_logs(cluster: &Cluster, namespace: &str, pod: &Pod) -> String {
+  let name = pod.metadata.name.as_deref().unwrap_or_default();
+
+  cluster
+    .pod_logs(namespace, name)
+    .await
+    .unwrap_or_else(|err| {
+      log::warn!("Unable to read the logs of the pod {name}: {err}");
+      String::new()
+    })
+}
+
+/// `Ok` once the job completed, the reason it failed otherwise, `None` while it runs
+fn job_result(job: &Job) -> Option<Result<(), String>> {
+  job
+    .status
+    .as_ref()?
+    .conditions
+    .as_ref()?
+    .iter()
+    .filter(|condition| condition.status == "True")
+    .find_map(|condition| match condition.type_.as_str() {
+      "Complete" => Some(Ok(())),
+      "Failed" => Some(Err(
+        condition
+          .message
+          .clone()
+          .unwrap_or_else(|| "Job failed".to_string()),
+      )),
+      _ => None,
+    })
+}
+
+fn generate_job(
+  name: &str,
+  app: &AppService,
+  command: &[String],
+  mut pod: PodSpec,
+  deadline: i64,
+) -> Job {
+  // the job keeps the image, envs, pull secret, volume and init containers of the app, but neither its ports nor its probes,
+  // its sidecars would keep the pod running once the command exits
+  pod.containers.truncate(1);
+  for container in &mut pod.containers {
+    container.command = Some(command.to_vec());
+    container.args = None;
+    container.ports = None;
+    container.liveness_probe = None;
+    container.readiness_probe = None;
+    container.startup_probe = None;
+  }
+  pod.restart_policy = Some("Never".to_string());
+
+  serde_json::from_value(json!({
+    "apiVersion": "batch/v1",
+    "kind": "Job",
+    "metadata": {
+      "name": name,
+      "namespace": project_namespace(&app.project_id),
+      "labels": app_labels(app)
+    },
+    "spec": {
+      "backoffLimit": 0,
+      "activeDeadlineSeconds": deadline,
+      "ttlSecondsAfterFinished": K8S_CONFIG.run_ttl,
+      "template": {
+        "metadata": {
+          // out of the selector of the app service, the network policy of the project still selects the pod
+          "labels": {
+            "run": name,
+            "project": app.project_id
+          }
+        },
+        "spec": pod
+      }
+    }
+  }))
+  .expect("Invalid run job")
+}
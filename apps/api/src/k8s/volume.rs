@@ -1,33 +1,62 @@
-use k8s_openapi::api::core::v1::PersistentVolumeClaim;
-use kube::{
-  api::{Patch, PatchParams},
-  Api, Client, Result,
+use k8s_openapi::api::core::v1::{
+  PersistentVolumeClaim, PodSpec, Volume as PodVolume, VolumeMount,
 };
+use kube::Result;
 use serde_json::json;
 
-use crate::schemas::Volume;
+use crate::schemas::{AccessMode, PlanAction, ResourceChange, Volume};
 
-use super::K8S_CONFIG;
+use super::{
+  plan, project_namespace, report, volume_labels, Cluster, Progress, ReleaseError, K8S_CONFIG,
+};
 
-pub async fn reconcile_volume(volume: &Volume, client: Client) -> Result<()> {
+pub async fn reconcile_volume(
+  volume: &Volume,
+  cluster: &Cluster,
+  progress: Progress<'_>,
+) -> Result<(), ReleaseError> {
   let name = format!("volume-{}", volume.volume_id);
-  let params = PatchParams::apply(&K8S_CONFIG.manager).force();
 
-  let api: Api<PersistentVolumeClaim> = Api::namespaced(client, &K8S_CONFIG.namespace);
+  report::<PersistentVolumeClaim>(&name, reconcile_pvc(&name, volume, cluster).await, progress)
+}
+
+async fn reconcile_pvc(
+  name: &str,
+  volume: &Volume,
+  cluster: &Cluster,
+) -> Result<Option<PlanAction>> {
+  let api = cluster.api::<PersistentVolumeClaim>(&project_namespace(&volume.project_id));
 
-  let pvc = api.get_opt(&name).await?;
+  let pvc = api.get_opt(name).await?;
 
   if pvc.is_some() && volume.deleted {
-    api.delete(&name, &Default::default()).await?;
-    return Ok(());
+    api.delete(name).await?;
+    return Ok(Some(PlanAction::Delete));
   }
 
-  if pvc.is_none() {
-    let pvc = generate_pvc(&name, volume);
-    api.patch(&name, &params, &Patch::Apply(pvc)).await?;
+  if pvc.is_none() && !volume.deleted {
+    let pvc = generate_pvc(name, volume);
+    api.apply(name, &pvc).await?;
+    return Ok(Some(PlanAction::Create));
   }
 
-  Ok(())
+  Ok(pvc.map(|_| PlanAction::Unchanged))
+}
+
+pub async fn plan_volume(volume: &Volume, cluster: &Cluster) -> Result<Option<ResourceChange>> {
+  let name = format!("volume-{}", volume.volume_id);
+
+  let api = cluster.api::<PersistentVolumeClaim>(&project_namespace(&volume.project_id));
+
+  let action = match (api.get_opt(&name).await?, volume.deleted) {
+    (Some(_), true) => PlanAction::Delete,
+    (None, true) => return Ok(None),
+    (None, false) => PlanAction::Create,
+    // claims are never patched once created
+    (Some(_), false) => PlanAction::Unchanged,
+  };
+
+  Ok(Some(plan::change::<PersistentVolumeClaim>(&name, action)))
 }
 
 fn generate_pvc(name: &str, volume: &Volume) -> PersistentVolumeClaim {
@@ -36,13 +65,17 @@ fn generate_pvc(name: &str, volume: &Volume) -> PersistentVolumeClaim {
     "kind": "PersistentVolumeClaim",
     "metadata": {
       "name": name,
-      "namespace": K8S_CONFIG.namespace,
+      "namespace": project_namespace(&volume.project_id),
+      "labels": volume_labels(volume),
     },
     "spec": {
       "accessModes": [
-        "ReadWriteOnce"
+        volume.access_mode.as_str()
       ],
-      "storageClassName": "local-path",
+      "storageClassName": match volume.access_mode {
+        AccessMode::ReadWriteOnce => "local-path",
+        AccessMode::ReadWriteMany => &K8S_CONFIG.shared_storage_class,
+      },
       "resources": {
         "requests": {
           "storage": format!("{}M", volume.capacity)
@@ -52,3 +85,50 @@ fn generate_pvc(name: &str, volume: &Volume) -> PersistentVolumeClaim {
   }))
   .expect("Invalid Persistent Volume Claim")
 }
+
+/// Mount the claims of the volumes at their paths in the first container of the pod
+pub(super) fn mount_volumes(spec: &mut PodSpec, mounts: &[(&Volume, &str)]) {
+  if mounts.is_empty() {
+    return;
+  }
+
+  let pod_volumes = mounts
+    .iter()
+    .map(|(volume, _)| {
+      let name = format!("volume-{}", volume.volume_id);
+
+      serde_json::from_value(json!({
+        "name": name,
+        "persistentVolumeClaim": {
+          "claimName": name,
+        }
+      }))
+      .expect("Invalid Pod Volume")
+    })
+    .collect::<Vec<PodVolume>>();
+
+  spec.volumes = Some(pod_volumes);
+
+  spec.containers.get_mut(0).unwrap().volume_mounts = Some(
+    mounts
+      .iter()
+      .map(|(volume, path)| volume_mount(volume, path))
+      .collect(),
+  );
+}
+
+/// Mount of the claim of `volume` at `path`, once mounted in the pod
+pub(super) fn volume_mount(volume: &Volume, path: &str) -> VolumeMount {
+  serde_json::from_value(json!({
+    "name": format!("volume-{}", volume.volume_id),
+    "mountPath": path,
+  }))
+  .expect("Invalid Volume Mount")
+}
+
+/// A ReadWriteOnce claim can only be mounted by the pods of a single node, the app is kept to one replica
+pub(super) fn single_replica(volumes: &[&Volume]) -> bool {
+  volumes
+    .iter()
+    .any(|volume| volume.access_mode == AccessMode::ReadWriteOnce)
+}
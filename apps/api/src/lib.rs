@@ -4,8 +4,12 @@ mod error;
 mod openapi;
 
 pub(crate) mod auth;
+pub(crate) mod exposure;
+pub(crate) mod jobs;
 pub(crate) mod k8s;
 pub(crate) mod middleware;
+pub(crate) mod quota;
+pub(crate) mod registry;
 pub(crate) mod routes;
 pub(crate) mod utils;
 
@@ -15,4 +19,7 @@ pub use app::{create_app, AppConfig};
 pub use auth::{AuthSecurity, Password, Token, API_KEY};
 pub use database::{DatabaseConfig, MIGRATOR};
 pub use error::{ApiError, ApiResult};
+pub use jobs::check_drift;
+pub use k8s::{Cluster, ClusterBackend, KubeBackend, MemoryBackend};
 pub use openapi::OpenApiSpec;
+pub use registry::{HttpRegistry, ImageRegistry, MemoryRegistry, RegistryBackend};
@@ -0,0 +1,29 @@
+use actix_web::{
+  body::MessageBody,
+  dev::{ServiceRequest, ServiceResponse},
+  middleware::Next,
+  web::ReqData,
+};
+
+use crate::{database::Pool, ApiError};
+
+use super::UserId;
+
+pub async fn admin_middleware(
+  mut req: ServiceRequest,
+  next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+  let user_id = req.extract::<ReqData<UserId>>().await?.into_inner();
+  let pool = req.app_data::<Pool>().expect("Pool not found");
+
+  let admin = sqlx::query_scalar!("SELECT admin FROM users WHERE user_id = $1", *user_id)
+    .fetch_one(pool.as_ref())
+    .await
+    .map_err(ApiError::from)?;
+
+  if !admin {
+    Err(ApiError::Unauthorized)?
+  }
+
+  next.call(req).await
+}
@@ -1,7 +1,9 @@
+mod admin;
 mod project;
 mod session;
 mod user;
 
+pub use admin::admin_middleware;
 pub use project::project_middleware;
 pub use session::session_middleware;
 pub use user::UserId;
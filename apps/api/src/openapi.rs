@@ -21,6 +21,8 @@ use utoipa::OpenApi;
     project::update_project,
     project::delete_project,
     project::release_project,
+    project::plan_release,
+    project::get_project_drift,
     app::list_apps,
     app::create_app,
     app::get_app,
@@ -28,17 +30,44 @@ use utoipa::OpenApi;
     app::update_app,
     app::delete_app,
     app::recover_app,
+    cron_job::list_cron_jobs,
+    cron_job::create_cron_job,
+    cron_job::get_cron_job,
+    cron_job::update_cron_job,
+    cron_job::delete_cron_job,
+    cron_job::recover_cron_job,
+    run::list_runs,
+    run::create_run,
+    run::get_run,
+    run::get_run_logs,
     env::list_envs,
     env::create_env,
     env::get_env,
     env::update_env,
     env::delete_env,
+    env::list_cron_job_envs,
+    env::create_cron_job_env,
+    env::get_cron_job_env,
+    env::update_cron_job_env,
+    env::delete_cron_job_env,
     volume::list_volumes,
     volume::create_volume,
     volume::get_volume,
     volume::update_volume,
     volume::delete_volume,
     volume::recover_volume,
+    registry::list_registries,
+    registry::create_registry,
+    registry::get_registry,
+    registry::update_registry,
+    registry::delete_registry,
+    release::list_releases,
+    release::get_release,
+    release::get_release_events,
+    release::rollback_release,
+    admin::list_orphans,
+    admin::delete_orphans,
+    admin::update_project_quota,
   ),
   components(schemas(
     error::ApiError,
@@ -50,19 +79,57 @@ use utoipa::OpenApi;
     schemas::AuthResponse,
     schemas::ProjectSchema,
     schemas::PartialProjectSchema,
+    schemas::Release,
+    schemas::ReleaseState,
+    schemas::ReleaseDetails,
+    schemas::ReleaseHook,
+    schemas::ReleaseEvent,
+    schemas::PlanAction,
+    schemas::ResourceChange,
+    schemas::OrphanObject,
+    schemas::ProjectQuotaSchema,
     schemas::AppService,
+    schemas::AppKind,
+    schemas::AppPort,
+    schemas::PortProtocol,
+    schemas::AppContainer,
+    schemas::ContainerKind,
+    schemas::ContainerEnv,
+    schemas::ContainerVolume,
     schemas::AppReleaseState,
     schemas::AppStatus,
     schemas::DomainName,
+    schemas::Autoscaling,
+    schemas::AppProbes,
+    schemas::Probe,
+    schemas::ProbeCheck,
+    schemas::ProbeFailure,
+    schemas::AppStrategy,
+    schemas::StrategyKind,
+    schemas::RolloutBound,
     schemas::AppServiceSchema,
     schemas::PartialAppServiceSchema,
+    schemas::Run,
+    schemas::RunState,
+    schemas::RunLogs,
+    schemas::RunSchema,
+    schemas::CronJob,
+    schemas::ConcurrencyPolicy,
+    schemas::CronJobSchema,
+    schemas::PartialCronJobSchema,
     schemas::EnvVar,
     schemas::EnvSchema,
     schemas::PartialEnvSchema,
     schemas::Volume,
-    schemas::VolumeAppId,
+    schemas::VolumeApp,
+    schemas::VolumeAppSchema,
+    schemas::AccessMode,
+    schemas::VolumeCronJobId,
     schemas::VolumeSchema,
     schemas::PartialVolumeSchema,
+    schemas::Registry,
+    schemas::RegistrySchema,
+    schemas::PartialRegistrySchema,
   ))
 )]
 pub struct OpenApiSpec;
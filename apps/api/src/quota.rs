@@ -0,0 +1,82 @@
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{
+  schemas::{DEFAULT_CPU_LIMIT, DEFAULT_MEMORY_LIMIT},
+  ApiError, ApiResult,
+};
+
+/// Fail when the apps and volumes of a project, as seen by `conn`, go over the project quotas.
+///
+/// Run it in the transaction that changed them, after the change: the project row stays locked until the
+/// commit so two concurrent changes can not both fit in what is left.
+pub async fn check_quota(conn: &mut PgConnection, project_id: &Uuid) -> ApiResult<()> {
+  let quota = sqlx::query!(
+    r#"
+    SELECT quota_apps, quota_replicas, quota_cpu, quota_memory, quota_storage
+    FROM projects
+    WHERE project_id = $1
+    FOR NO KEY UPDATE
+    "#,
+    project_id
+  )
+  .fetch_one(&mut *conn)
+  .await?;
+
+  // apps with a volume run a single replica and autoscaled ones up to their maximum,
+  // counting the columns keeps the check conservative. Sidecars get the default limits
+  // of the project limit range, init containers never run along the app
+  let apps = sqlx::query!(
+    r#"
+    WITH sized AS (
+      SELECT COALESCE(max_replicas, replicas)::BIGINT AS replicas,
+        cpu_limit + sidecars * $2 AS cpu_limit,
+        memory_limit + sidecars * $3 AS memory_limit
+      FROM app_services,
+        LATERAL (
+          SELECT COUNT(*)::INT AS sidecars
+          FROM jsonb_array_elements(containers) AS container
+          WHERE container->>'kind' = 'Sidecar'
+        ) AS extra
+      WHERE project_id = $1 AND deleted = false
+    )
+    SELECT COUNT(*) AS "apps!",
+      COALESCE(SUM(replicas), 0)::BIGINT AS "replicas!",
+      COALESCE(SUM(replicas * cpu_limit), 0)::BIGINT AS "cpu!",
+      COALESCE(SUM(replicas * memory_limit), 0)::BIGINT AS "memory!"
+    FROM sized
+    "#,
+    project_id,
+    DEFAULT_CPU_LIMIT,
+    DEFAULT_MEMORY_LIMIT
+  )
+  .fetch_one(&mut *conn)
+  .await?;
+
+  let storage = sqlx::query_scalar!(
+    r#"SELECT COALESCE(SUM(capacity), 0) AS "storage!" FROM volumes WHERE project_id = $1 AND deleted = false"#,
+    project_id
+  )
+  .fetch_one(&mut *conn)
+  .await?;
+
+  let usages = [
+    ("apps", "", apps.apps, quota.quota_apps),
+    ("replicas", "", apps.replicas, quota.quota_replicas),
+    ("cpu", "m", apps.cpu, quota.quota_cpu),
+    ("memory", " MB", apps.memory, quota.quota_memory),
+    ("storage", " MB", storage, quota.quota_storage),
+  ];
+
+  for (resource, unit, usage, quota) in usages {
+    if usage > i64::from(quota) {
+      return Err(ApiError::QuotaExceeded {
+        message: format!(
+          "Project {resource} quota of {quota}{unit} exceeded, it would use {usage}{unit}"
+        ),
+      });
+    }
+  }
+
+  Ok(())
+}
@@ -0,0 +1,182 @@
+use std::{collections::HashMap, sync::LazyLock, time::Duration};
+
+use futures::{future::BoxFuture, FutureExt};
+use regex::Regex;
+use reqwest::{
+  header::{ACCEPT, WWW_AUTHENTICATE},
+  Client, RequestBuilder, StatusCode,
+};
+use serde::Deserialize;
+
+use super::{
+  image_registry, image_repository, RegistryBackend, RegistryCredentials, RegistryError,
+  DEFAULT_REGISTRY,
+};
+
+/// Manifests a tag may point to, an index is preferred so the digest covers every platform
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, application/vnd.docker.distribution.manifest.list.v2+json, application/vnd.oci.image.manifest.v1+json, application/vnd.docker.distribution.manifest.v2+json";
+
+const DIGEST_HEADER: &str = "Docker-Content-Digest";
+
+/// Host serving the API of Docker Hub
+const DEFAULT_REGISTRY_API: &str = "registry-1.docker.io";
+
+/// A registry not answering fails the release instead of keeping it waiting
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+static CHALLENGE_PARAM: LazyLock<Regex> =
+  LazyLock::new(|| Regex::new(r#"(\w+)="([^"]*)""#).unwrap());
+
+/// Client of the registry HTTP API v2, a registry on localhost is reached over plain http like Docker does
+#[derive(Clone)]
+pub struct HttpRegistry {
+  client: Client,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+  token: Option<String>,
+  access_token: Option<String>,
+}
+
+/// How the registry asked the client to log in
+enum Authorization {
+  Basic,
+  Bearer(String),
+}
+
+impl Default for HttpRegistry {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl HttpRegistry {
+  pub fn new() -> Self {
+    let client = Client::builder()
+      .connect_timeout(CONNECT_TIMEOUT)
+      .timeout(REQUEST_TIMEOUT)
+      .build()
+      .expect("Unable to build the registry client");
+
+    Self { client }
+  }
+
+  async fn manifest_digest(
+    &self,
+    image: &str,
+    credentials: Option<&RegistryCredentials>,
+  ) -> Result<String, RegistryError> {
+    let host = match image_registry(image) {
+      DEFAULT_REGISTRY => DEFAULT_REGISTRY_API,
+      host => host,
+    };
+    let (repository, tag) = image_repository(image);
+    let repository = match (host, repository.contains('/')) {
+      (DEFAULT_REGISTRY_API, false) => format!("library/{repository}"),
+      _ => repository.to_string(),
+    };
+
+    let scheme = match host.split(':').next() {
+      Some("localhost" | "127.0.0.1") => "http",
+      _ => "https",
+    };
+    let url = format!("{scheme}://{host}/v2/{repository}/manifests/{tag}");
+    let request = || self.client.head(&url).header(ACCEPT, MANIFEST_TYPES);
+
+    let mut response = request().send().await?;
+
+    if response.status() == StatusCode::UNAUTHORIZED {
+      let challenge = response
+        .headers()
+        .get(WWW_AUTHENTICATE)
+        .and_then(|challenge| challenge.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+      let authorization = self.authorize(&challenge, credentials).await?;
+      response = authorized(request(), authorization, credentials)
+        .send()
+        .await?;
+    }
+
+    if !response.status().is_success() {
+      return Err(RegistryError::Status(response.status()));
+    }
+
+    response
+      .headers()
+      .get(DIGEST_HEADER)
+      .and_then(|digest| digest.to_str().ok())
+      .map(str::to_string)
+      .ok_or(RegistryError::MissingDigest)
+  }
+
+  /// Answer the `WWW-Authenticate` challenge, fetching a token from the realm it names when it asks for one
+  async fn authorize(
+    &self,
+    challenge: &str,
+    credentials: Option<&RegistryCredentials>,
+  ) -> Result<Authorization, RegistryError> {
+    let Some(params) = challenge.strip_prefix("Bearer ") else {
+      return Ok(Authorization::Basic);
+    };
+
+    let params: HashMap<&str, &str> = CHALLENGE_PARAM
+      .captures_iter(params)
+      .map(|captures| {
+        let (_, [key, value]) = captures.extract();
+        (key, value)
+      })
+      .collect();
+
+    let realm = params.get("realm").copied().unwrap_or_default();
+    let query: Vec<(&str, &str)> = ["service", "scope"]
+      .into_iter()
+      .filter_map(|key| Some((key, *params.get(key)?)))
+      .collect();
+
+    let request = self.client.get(realm).query(&query);
+    let response = authorized(request, Authorization::Basic, credentials)
+      .send()
+      .await?;
+
+    if !response.status().is_success() {
+      return Err(RegistryError::Status(response.status()));
+    }
+
+    let token: TokenResponse = response.json().await?;
+
+    token
+      .token
+      .or(token.access_token)
+      .map(Authorization::Bearer)
+      .ok_or(RegistryError::Status(StatusCode::UNAUTHORIZED))
+  }
+}
+
+fn authorized(
+  request: RequestBuilder,
+  authorization: Authorization,
+  credentials: Option<&RegistryCredentials>,
+) -> RequestBuilder {
+  match (authorization, credentials) {
+    (Authorization::Bearer(token), _) => request.bearer_auth(token),
+    (Authorization::Basic, Some(credentials)) => {
+      request.basic_auth(&credentials.username, Some(&credentials.token))
+    }
+    // anonymous pulls only need the token of the realm
+    (Authorization::Basic, None) => request,
+  }
+}
+
+impl RegistryBackend for HttpRegistry {
+  fn resolve<'a>(
+    &'a self,
+    image: &'a str,
+    credentials: Option<&'a RegistryCredentials>,
+  ) -> BoxFuture<'a, Result<String, RegistryError>> {
+    self.manifest_digest(image, credentials).boxed()
+  }
+}
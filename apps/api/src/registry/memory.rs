@@ -0,0 +1,52 @@
+use std::{
+  collections::BTreeMap,
+  sync::{Arc, Mutex, MutexGuard},
+};
+
+use futures::future::{self, BoxFuture};
+use sha2::{Digest, Sha256};
+
+use super::{RegistryBackend, RegistryCredentials, RegistryError};
+
+/// Registry keeping its tags in memory, used to exercise the releases without pulling from a real one.
+///
+/// Every image exists, its digest is derived from its name until another one is pushed. Clones share the
+/// same tags so a test can keep a handle and move a tag between two releases.
+#[derive(Clone, Default)]
+pub struct MemoryRegistry {
+  digests: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl MemoryRegistry {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn digests(&self) -> MutexGuard<'_, BTreeMap<String, String>> {
+    self.digests.lock().expect("Memory registry poisoned")
+  }
+
+  /// Point `image` to `digest`, as pushing a new build under the same tag does
+  pub fn push(&self, image: &str, digest: &str) {
+    self.digests().insert(image.to_string(), digest.to_string());
+  }
+
+  /// Digest `image` currently points to
+  pub fn digest(&self, image: &str) -> String {
+    self
+      .digests()
+      .get(image)
+      .cloned()
+      .unwrap_or_else(|| format!("sha256:{}", hex::encode(Sha256::digest(image.as_bytes()))))
+  }
+}
+
+impl RegistryBackend for MemoryRegistry {
+  fn resolve<'a>(
+    &'a self,
+    image: &'a str,
+    _credentials: Option<&'a RegistryCredentials>,
+  ) -> BoxFuture<'a, Result<String, RegistryError>> {
+    Box::pin(future::ready(Ok(self.digest(image))))
+  }
+}
@@ -0,0 +1,211 @@
+use std::{
+  collections::{HashMap, HashSet},
+  sync::{Arc, LazyLock},
+};
+
+use aes_gcm::{
+  aead::{generic_array::typenum::Unsigned, Aead, AeadCore, KeyInit, Nonce, OsRng},
+  Aes256Gcm, Key,
+};
+use confique::Config;
+use derive_more::derive::{Display, Error, From};
+use futures::{future, future::BoxFuture};
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+
+use crate::{
+  schemas::{AppService, Registry},
+  ApiError, ApiResult,
+};
+
+pub use client::HttpRegistry;
+pub use memory::MemoryRegistry;
+
+mod client;
+mod memory;
+
+#[derive(Config)]
+struct RegistryConfig {
+  /// Key the registry tokens are encrypted with, changing it makes the stored tokens unreadable
+  #[config(env = "REGISTRY_SECRET", default = "secret")]
+  secret: String,
+}
+
+static CIPHER: LazyLock<Aes256Gcm> = LazyLock::new(|| {
+  let secret = RegistryConfig::builder().env().load().unwrap().secret;
+  let key = Sha256::digest(secret.as_bytes());
+
+  Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+});
+
+/// Registry Docker Hub images are pulled from when their name does not start with a host
+const DEFAULT_REGISTRY: &str = "docker.io";
+
+/// Credentials a registry client logs in with, the token is decrypted
+pub struct RegistryCredentials {
+  pub username: String,
+  pub token: String,
+}
+
+#[derive(Debug, Display, Error, From)]
+pub enum RegistryError {
+  #[display("{_0}")]
+  Http(reqwest::Error),
+  #[display("the registry answered {_0}")]
+  Status(#[error(not(source))] reqwest::StatusCode),
+  #[display("the registry did not return the digest of the manifest")]
+  MissingDigest,
+}
+
+/// Operations a release needs from the registries the images are pulled from
+pub trait RegistryBackend: Send + Sync {
+  /// Digest of the manifest the tag of `image` points to, `latest` when it has none
+  fn resolve<'a>(
+    &'a self,
+    image: &'a str,
+    credentials: Option<&'a RegistryCredentials>,
+  ) -> BoxFuture<'a, Result<String, RegistryError>>;
+}
+
+#[derive(Clone)]
+pub struct ImageRegistry(Arc<dyn RegistryBackend>);
+
+impl ImageRegistry {
+  pub fn new(backend: impl RegistryBackend + 'static) -> Self {
+    Self(Arc::new(backend))
+  }
+
+  /// Digest `image` points to, logging in with the project registry it is pulled from if there is one
+  pub async fn resolve_digest(
+    &self,
+    image: &str,
+    registries: &[Registry],
+  ) -> Result<String, RegistryError> {
+    // an image already pinned is left as it is
+    if let Some((_, digest)) = image.split_once('@') {
+      return Ok(digest.to_string());
+    }
+
+    let host = image_registry(image);
+    let credentials = registries
+      .iter()
+      .find(|registry| registry.registry_host == host)
+      .and_then(|registry| {
+        Some(RegistryCredentials {
+          username: registry.username.clone(),
+          token: decrypt_token(&registry.token)?,
+        })
+      });
+
+    self.0.resolve(image, credentials.as_ref()).await
+  }
+}
+
+/// Resolve the tag of every app still released to the digest it points to, keyed by image.
+///
+/// Runs outside of any transaction, the registries may take a while to answer.
+pub async fn resolve_images(
+  registry: &ImageRegistry,
+  apps: &[AppService],
+  registries: &[Registry],
+) -> ApiResult<HashMap<String, String>> {
+  let images: HashSet<&str> = apps
+    .iter()
+    .filter(|app| !app.deleted)
+    .map(|app| app.image.as_str())
+    .collect();
+
+  future::try_join_all(images.into_iter().map(|image| async move {
+    let digest = registry
+      .resolve_digest(image, registries)
+      .await
+      .map_err(|err| ApiError::BadRequest {
+        message: format!("Unable to resolve image {image}: {err}"),
+      })?;
+
+    Ok::<_, ApiError>((image.to_string(), digest))
+  }))
+  .await
+  .map(HashMap::from_iter)
+}
+
+/// Record on every app still released the digest resolved for its image, for the release to pin.
+///
+/// Fails without recording anything when the image of an app changed since it was resolved.
+pub async fn pin_images(
+  conn: &mut PgConnection,
+  apps: &mut [AppService],
+  digests: &HashMap<String, String>,
+) -> ApiResult<()> {
+  for app in apps.iter_mut().filter(|app| !app.deleted) {
+    let digest = digests
+      .get(&app.image)
+      .ok_or_else(|| ApiError::BadRequest {
+        message: format!("The image of {} changed during the release", app.app_name),
+      })?
+      .clone();
+
+    sqlx::query!(
+      "UPDATE app_services SET image_digest = $1 WHERE app_id = $2",
+      digest,
+      app.app_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    app.image_digest = Some(digest);
+  }
+
+  Ok(())
+}
+
+/// Encrypt a registry token with a random nonce, stored in hex in front of the ciphertext
+pub fn encrypt_token(token: &str) -> String {
+  let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+  let ciphertext = CIPHER
+    .encrypt(&nonce, token.as_bytes())
+    .expect("Unable to encrypt the registry token");
+
+  hex::encode([nonce.as_slice(), &ciphertext].concat())
+}
+
+/// Token encrypted by [`encrypt_token`], `None` when it was encrypted with another key
+pub fn decrypt_token(encrypted: &str) -> Option<String> {
+  let bytes = hex::decode(encrypted).ok()?;
+  let nonce_size = <Aes256Gcm as AeadCore>::NonceSize::USIZE;
+
+  if bytes.len() < nonce_size {
+    return None;
+  }
+
+  let (nonce, ciphertext) = bytes.split_at(nonce_size);
+  let token = CIPHER
+    .decrypt(Nonce::<Aes256Gcm>::from_slice(nonce), ciphertext)
+    .ok()?;
+
+  String::from_utf8(token).ok()
+}
+
+/// Host of the registry an image is pulled from, the first part of its name when it looks like a host
+pub fn image_registry(image: &str) -> &str {
+  match image.split_once('/') {
+    Some((host, _)) if host.contains(['.', ':']) || host == "localhost" => host,
+    _ => DEFAULT_REGISTRY,
+  }
+}
+
+/// Repository and tag of an image pulled from `image_registry(image)`
+fn image_repository(image: &str) -> (&str, &str) {
+  let host = image_registry(image);
+  let name = image
+    .strip_prefix(host)
+    .and_then(|name| name.strip_prefix('/'))
+    .unwrap_or(image);
+
+  // the tag follows the last colon of the last path segment
+  let tag_start = name.rfind('/').map_or(0, |slash| slash + 1);
+  match name[tag_start..].rfind(':') {
+    Some(colon) => (&name[..tag_start + colon], &name[tag_start + colon + 1..]),
+    None => (name, "latest"),
+  }
+}
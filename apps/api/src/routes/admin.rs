@@ -0,0 +1,99 @@
+use actix_web::{
+  delete, get, patch,
+  web::{Data, Path, ServiceConfig},
+};
+use actix_web_validator::Json;
+
+use crate::{
+  database::Pool,
+  error::{
+    BadRequestErrorMessage, InternalServerErrorMessage, NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+  },
+  jobs,
+  k8s::Cluster,
+  schemas::{AdminProjectPath, OrphansList, Project, ProjectQuotaSchema},
+  ApiResult,
+};
+
+const CONTEXT_PATH: &str = "/admin";
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH,
+  responses(OrphansList, UnauthorizedErrorMessage, InternalServerErrorMessage)
+)]
+#[get("/orphans/")]
+pub async fn list_orphans(pool: Pool, cluster: Data<Cluster>) -> ApiResult<OrphansList> {
+  let orphans = jobs::collect_garbage(&pool, &cluster, false).await?;
+
+  Ok(OrphansList::from(orphans))
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH,
+  responses(OrphansList, UnauthorizedErrorMessage, InternalServerErrorMessage)
+)]
+#[delete("/orphans/")]
+pub async fn delete_orphans(pool: Pool, cluster: Data<Cluster>) -> ApiResult<OrphansList> {
+  let orphans = jobs::collect_garbage(&pool, &cluster, true).await?;
+
+  Ok(OrphansList::from(orphans))
+}
+
+/// Quotas apply to later changes, a project already above a lowered quota keeps running
+#[utoipa::path(
+  context_path = CONTEXT_PATH,
+  params(AdminProjectPath),
+  responses(
+    Project,
+    BadRequestErrorMessage,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[patch("/projects/{project_id}/quota/")]
+pub async fn update_project_quota(
+  path: Path<AdminProjectPath>,
+  Json(quota): Json<ProjectQuotaSchema>,
+  pool: Pool,
+) -> ApiResult<Project> {
+  let ProjectQuotaSchema {
+    apps,
+    replicas,
+    cpu,
+    memory,
+    storage,
+  } = quota;
+
+  let project = sqlx::query_as!(
+    Project,
+    r#"
+    UPDATE projects
+    SET quota_apps = COALESCE($1, quota_apps),
+      quota_replicas = COALESCE($2, quota_replicas),
+      quota_cpu = COALESCE($3, quota_cpu),
+      quota_memory = COALESCE($4, quota_memory),
+      quota_storage = COALESCE($5, quota_storage)
+    WHERE project_id = $6
+    RETURNING *
+    "#,
+    apps,
+    replicas,
+    cpu,
+    memory,
+    storage,
+    path.project_id
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  Ok(project)
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+  cfg
+    .service(list_orphans)
+    .service(delete_orphans)
+    .service(update_project_quota);
+}
@@ -1,6 +1,6 @@
 use actix_web::{
   delete, get, patch, post,
-  web::{Path, ServiceConfig},
+  web::{Data, Path, ServiceConfig},
   Responder,
 };
 use actix_web_lab::sse;
@@ -11,12 +11,15 @@ use crate::{
   database::Pool,
   error::{
     AlreadyExistsErrorMessage, BadRequestErrorMessage, InternalServerErrorMessage,
-    NotFoundErrorMessage, UnauthorizedErrorMessage,
+    NotFoundErrorMessage, QuotaExceededErrorMessage, UnauthorizedErrorMessage,
   },
-  k8s,
+  exposure::allocate_exposed_port,
+  k8s::{self, Cluster},
+  quota::check_quota,
   schemas::{
-    AppPath, AppService, AppServiceSchema, AppServicesList, AppStatus, PartialAppServiceSchema,
-    ProjectPath,
+    AppKind, AppPath, AppService, AppServiceSchema, AppServicesList, AppStatus, AppStatusEvent,
+    PartialAppServiceSchema, ProjectPath, DEFAULT_CPU_LIMIT, DEFAULT_CPU_REQUEST,
+    DEFAULT_MEMORY_LIMIT, DEFAULT_MEMORY_REQUEST,
   },
   ApiResult,
 };
@@ -55,6 +58,7 @@ pub async fn list_apps(path: Path<ProjectPath>, pool: Pool) -> ApiResult<AppServ
     BadRequestErrorMessage,
     NotFoundErrorMessage,
     AlreadyExistsErrorMessage,
+    QuotaExceededErrorMessage,
     UnauthorizedErrorMessage,
     InternalServerErrorMessage
   )
@@ -70,25 +74,78 @@ pub async fn create_app(
     name,
     replicas,
     image,
+    kind,
     port,
     public_domain,
     private_domain,
+    cpu_request,
+    cpu_limit,
+    memory_request,
+    memory_limit,
+    autoscaling,
+    probes,
+    strategy,
+    command,
+    args,
+    working_dir,
+    pre_deploy,
+    ports,
+    containers,
+    expose,
   } = app;
 
+  let cpu_limit = cpu_limit.unwrap_or(DEFAULT_CPU_LIMIT);
+  // a port only targets a domain the app has
+  let public_port = public_domain.subdomain.as_ref().and(public_domain.port);
+  let private_port = private_domain.subdomain.as_ref().and(private_domain.port);
+  let memory_limit = memory_limit.unwrap_or(DEFAULT_MEMORY_LIMIT);
+
+  let mut tx = pool.begin().await?;
+
   let app = sqlx::query_as!(
     AppService,
-    "INSERT INTO app_services(app_name, replicas, image, port, public_domain, private_domain, project_id) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *",
+    r#"
+    INSERT INTO app_services(app_name, replicas, image, port, public_domain, private_domain, project_id, cpu_request, cpu_limit, memory_request, memory_limit, min_replicas, max_replicas, target_cpu_utilization, target_memory_utilization, probes, strategy, command, args, working_dir, kind, ports, public_port, private_port, expose, pre_deploy, containers)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, NULLIF($18::TEXT[], '{}'), NULLIF($19::TEXT[], '{}'), NULLIF($20, ''), $21, $22, $23, $24, NULLIF($25, ''), NULLIF($26::TEXT[], '{}'), $27)
+    RETURNING *
+    "#,
     name,
     replicas,
     image,
     port,
     public_domain.subdomain,
     private_domain.subdomain,
-    project_id
+    project_id,
+    cpu_request.unwrap_or(DEFAULT_CPU_REQUEST.min(cpu_limit)),
+    cpu_limit,
+    memory_request.unwrap_or(DEFAULT_MEMORY_REQUEST.min(memory_limit)),
+    memory_limit,
+    autoscaling.min_replicas,
+    autoscaling.max_replicas,
+    autoscaling.target_cpu_utilization,
+    autoscaling.target_memory_utilization,
+    sqlx::types::Json(probes) as _,
+    sqlx::types::Json(strategy) as _,
+    command.as_deref(),
+    args.as_deref(),
+    working_dir,
+    kind.as_str(),
+    sqlx::types::Json(ports) as _,
+    public_port,
+    private_port,
+    expose,
+    pre_deploy.as_deref(),
+    sqlx::types::Json(containers) as _
   )
-  .fetch_one(pool.as_ref())
+  .fetch_one(&mut *tx)
   .await?;
 
+  let app = allocate_exposed_port(&mut tx, app).await?;
+
+  check_quota(&mut tx, &project_id).await?;
+
+  tx.commit().await?;
+
   Ok(app)
 }
 
@@ -135,18 +192,21 @@ pub async fn get_app(path: Path<AppPath>, pool: Pool) -> ApiResult<AppService> {
   )
 )]
 #[get("/status/")]
-pub async fn get_app_status(path: Path<AppPath>) -> ApiResult<impl Responder> {
-  let AppPath {
-    project_id: _,
-    app_id,
-  } = *path;
+pub async fn get_app_status(
+  path: Path<AppPath>,
+  cluster: Data<Cluster>,
+) -> ApiResult<impl Responder> {
+  let AppPath { project_id, app_id } = *path;
 
-  let stream = k8s::app_status(&app_id).await?.map_ok(|status| {
-    sse::Event::Data(
-      sse::Data::new_json(status)
+  let stream = k8s::app_status(&cluster, &project_id, &app_id).map_ok(|event| {
+    sse::Event::Data(match event {
+      AppStatusEvent::Status(status) => sse::Data::new_json(status)
         .expect("Invalid app status json")
         .event("message"),
-    )
+      AppStatusEvent::ProbeFailure(failure) => sse::Data::new_json(failure)
+        .expect("Invalid probe failure json")
+        .event("probe"),
+    })
   });
 
   Ok(sse::Sse::from_stream(stream))
@@ -160,6 +220,7 @@ pub async fn get_app_status(path: Path<AppPath>) -> ApiResult<impl Responder> {
     BadRequestErrorMessage,
     NotFoundErrorMessage,
     AlreadyExistsErrorMessage,
+    QuotaExceededErrorMessage,
     UnauthorizedErrorMessage,
     InternalServerErrorMessage
   )
@@ -175,11 +236,28 @@ pub async fn update_app(
     name,
     replicas,
     image,
+    kind,
     port,
     public_domain,
     private_domain,
+    cpu_request,
+    cpu_limit,
+    memory_request,
+    memory_limit,
+    autoscaling,
+    probes,
+    strategy,
+    command,
+    args,
+    working_dir,
+    pre_deploy,
+    ports,
+    containers,
+    expose,
   } = app;
 
+  let mut tx = pool.begin().await?;
+
   let app = sqlx::query_as!(
     AppService,
     r#"
@@ -187,28 +265,90 @@ pub async fn update_app(
     SET app_name = COALESCE($1, app_name),
       replicas = COALESCE($2, replicas),
       image = COALESCE($3, image),
-      port = COALESCE($4, port),
+      image_digest = (CASE WHEN COALESCE($3, image) = image THEN image_digest END),
+      port = (CASE WHEN $25 = 'Worker' THEN $4 ELSE COALESCE($4, port) END),
       public_domain = (CASE WHEN $5 = true THEN $6 ELSE public_domain END),
-      private_domain = (CASE WHEN $7 = true THEN $8 ELSE private_domain END)
-    WHERE project_id = $9 AND app_id = $10
+      public_port = (CASE WHEN $5 = true THEN $26 ELSE public_port END),
+      private_domain = (CASE WHEN $7 = true THEN $8 ELSE private_domain END),
+      private_port = (CASE WHEN $7 = true THEN $27 ELSE private_port END),
+      cpu_request = COALESCE($9, cpu_request),
+      cpu_limit = COALESCE($10, cpu_limit),
+      memory_request = COALESCE($11, memory_request),
+      memory_limit = COALESCE($12, memory_limit),
+      min_replicas = (CASE WHEN $13 = true THEN $14 ELSE min_replicas END),
+      max_replicas = (CASE WHEN $13 = true THEN $15 ELSE max_replicas END),
+      target_cpu_utilization = (CASE WHEN $13 = true THEN $16 ELSE target_cpu_utilization END),
+      target_memory_utilization = (CASE WHEN $13 = true THEN $17 ELSE target_memory_utilization END),
+      probes = COALESCE($18, probes),
+      strategy = COALESCE($19, strategy),
+      command = NULLIF(COALESCE($20, command), '{}'),
+      args = NULLIF(COALESCE($21, args), '{}'),
+      working_dir = NULLIF(COALESCE($22, working_dir), ''),
+      kind = COALESCE($25, kind),
+      ports = COALESCE($28, ports),
+      expose = NULLIF(COALESCE($29, expose), ''),
+      pre_deploy = NULLIF(COALESCE($30, pre_deploy), '{}'),
+      containers = COALESCE($31, containers)
+    WHERE project_id = $23 AND app_id = $24
     RETURNING *
     "#,
     name,
     replicas,
     image,
-    port,
+    port.flatten(),
     public_domain.is_some(),
-    public_domain.map(|domain| domain.subdomain).unwrap_or(None),
+    public_domain
+      .as_ref()
+      .and_then(|domain| domain.subdomain.as_ref()),
     private_domain.is_some(),
     private_domain
-      .map(|domain| domain.subdomain)
-      .unwrap_or(None),
+      .as_ref()
+      .and_then(|domain| domain.subdomain.as_ref()),
+    cpu_request.flatten(),
+    cpu_limit.flatten(),
+    memory_request.flatten(),
+    memory_limit.flatten(),
+    autoscaling.is_some(),
+    autoscaling
+      .as_ref()
+      .and_then(|autoscaling| autoscaling.min_replicas),
+    autoscaling
+      .as_ref()
+      .and_then(|autoscaling| autoscaling.max_replicas),
+    autoscaling
+      .as_ref()
+      .and_then(|autoscaling| autoscaling.target_cpu_utilization),
+    autoscaling
+      .as_ref()
+      .and_then(|autoscaling| autoscaling.target_memory_utilization),
+    probes.map(sqlx::types::Json) as _,
+    strategy.map(sqlx::types::Json) as _,
+    command.as_ref().and_then(Option::as_deref),
+    args.as_ref().and_then(Option::as_deref),
+    working_dir.flatten(),
     project_id,
-    app_id
+    app_id,
+    kind.as_ref().map(AppKind::as_str),
+    public_domain
+      .as_ref()
+      .and_then(|domain| domain.subdomain.as_ref().and(domain.port.as_ref())),
+    private_domain
+      .as_ref()
+      .and_then(|domain| domain.subdomain.as_ref().and(domain.port.as_ref())),
+    ports.map(sqlx::types::Json) as _,
+    expose.flatten(),
+    pre_deploy.as_ref().and_then(Option::as_deref),
+    containers.map(sqlx::types::Json) as _
   )
-  .fetch_one(pool.as_ref())
+  .fetch_one(&mut *tx)
   .await?;
 
+  let app = allocate_exposed_port(&mut tx, app).await?;
+
+  check_quota(&mut tx, &project_id).await?;
+
+  tx.commit().await?;
+
   Ok(app)
 }
 
@@ -244,6 +384,7 @@ pub async fn delete_app(path: Path<AppPath>, pool: Pool) -> ApiResult<AppService
   responses(
     AppService,
     NotFoundErrorMessage,
+    QuotaExceededErrorMessage,
     UnauthorizedErrorMessage,
     InternalServerErrorMessage
   )
@@ -252,15 +393,21 @@ pub async fn delete_app(path: Path<AppPath>, pool: Pool) -> ApiResult<AppService
 pub async fn recover_app(path: Path<AppPath>, pool: Pool) -> ApiResult<AppService> {
   let AppPath { project_id, app_id } = *path;
 
+  let mut tx = pool.begin().await?;
+
   let app = sqlx::query_as!(
     AppService,
     "UPDATE app_services SET deleted = false WHERE project_id = $1 AND app_id = $2 RETURNING *",
     project_id,
     app_id,
   )
-  .fetch_one(pool.as_ref())
+  .fetch_one(&mut *tx)
   .await?;
 
+  check_quota(&mut tx, &project_id).await?;
+
+  tx.commit().await?;
+
   Ok(app)
 }
 
@@ -0,0 +1,242 @@
+use actix_web::{
+  delete, get, patch, post,
+  web::{Path, ServiceConfig},
+};
+use actix_web_validator::Json;
+
+use crate::{
+  database::Pool,
+  error::{
+    AlreadyExistsErrorMessage, BadRequestErrorMessage, InternalServerErrorMessage,
+    NotFoundErrorMessage, UnauthorizedErrorMessage,
+  },
+  schemas::{
+    ConcurrencyPolicy, CronJob, CronJobPath, CronJobSchema, CronJobsList, PartialCronJobSchema,
+    ProjectPath,
+  },
+  ApiResult,
+};
+
+const CONTEXT_PATH_WITHOUT_ID: &str = "/projects/{project_id}/cron-jobs";
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH_WITHOUT_ID,
+  params(ProjectPath),
+  responses(
+    CronJobsList,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[get("/")]
+pub async fn list_cron_jobs(path: Path<ProjectPath>, pool: Pool) -> ApiResult<CronJobsList> {
+  let cron_jobs = sqlx::query_as!(
+    CronJob,
+    "SELECT * FROM cron_jobs WHERE project_id = $1",
+    path.project_id
+  )
+  .fetch_all(pool.as_ref())
+  .await?;
+
+  Ok(CronJobsList::from(cron_jobs))
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH_WITHOUT_ID,
+  params(ProjectPath),
+  responses(
+    CronJob,
+    BadRequestErrorMessage,
+    NotFoundErrorMessage,
+    AlreadyExistsErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[post("/")]
+pub async fn create_cron_job(
+  path: Path<ProjectPath>,
+  Json(cron_job): Json<CronJobSchema>,
+  pool: Pool,
+) -> ApiResult<CronJob> {
+  let CronJobSchema {
+    name,
+    image,
+    schedule,
+    concurrency_policy,
+    successful_jobs_history_limit,
+    failed_jobs_history_limit,
+    command,
+    args,
+  } = cron_job;
+
+  let cron_job = sqlx::query_as!(
+    CronJob,
+    r#"
+    INSERT INTO cron_jobs(cron_job_name, image, schedule, concurrency_policy, successful_jobs_history_limit, failed_jobs_history_limit, command, args, project_id)
+    VALUES ($1, $2, $3, $4, COALESCE($5, 3), COALESCE($6, 1), NULLIF($7::TEXT[], '{}'), NULLIF($8::TEXT[], '{}'), $9)
+    RETURNING *
+    "#,
+    name,
+    image,
+    schedule,
+    concurrency_policy.as_str(),
+    successful_jobs_history_limit,
+    failed_jobs_history_limit,
+    command.as_deref(),
+    args.as_deref(),
+    path.project_id
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  Ok(cron_job)
+}
+
+pub fn config_without_id(cfg: &mut ServiceConfig) {
+  cfg.service(list_cron_jobs).service(create_cron_job);
+}
+
+const CONTEXT_PATH_WITH_ID: &str = "/projects/{project_id}/cron-jobs/{cron_job_id}";
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH_WITH_ID,
+  params(CronJobPath),
+  responses(
+    CronJob,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[get("/")]
+pub async fn get_cron_job(path: Path<CronJobPath>, pool: Pool) -> ApiResult<CronJob> {
+  let cron_job = sqlx::query_as!(
+    CronJob,
+    "SELECT * FROM cron_jobs WHERE project_id = $1 AND cron_job_id = $2",
+    path.project_id,
+    path.cron_job_id
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  Ok(cron_job)
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH_WITH_ID,
+  params(CronJobPath),
+  responses(
+    CronJob,
+    BadRequestErrorMessage,
+    NotFoundErrorMessage,
+    AlreadyExistsErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[patch("/")]
+pub async fn update_cron_job(
+  path: Path<CronJobPath>,
+  Json(cron_job): Json<PartialCronJobSchema>,
+  pool: Pool,
+) -> ApiResult<CronJob> {
+  let PartialCronJobSchema {
+    name,
+    image,
+    schedule,
+    concurrency_policy,
+    successful_jobs_history_limit,
+    failed_jobs_history_limit,
+    command,
+    args,
+  } = cron_job;
+
+  let cron_job = sqlx::query_as!(
+    CronJob,
+    r#"
+    UPDATE cron_jobs
+    SET cron_job_name = COALESCE($1, cron_job_name),
+      image = COALESCE($2, image),
+      schedule = COALESCE($3, schedule),
+      concurrency_policy = COALESCE($4, concurrency_policy),
+      successful_jobs_history_limit = COALESCE($5, successful_jobs_history_limit),
+      failed_jobs_history_limit = COALESCE($6, failed_jobs_history_limit),
+      command = NULLIF(COALESCE($7, command), '{}'),
+      args = NULLIF(COALESCE($8, args), '{}')
+    WHERE project_id = $9 AND cron_job_id = $10
+    RETURNING *
+    "#,
+    name,
+    image,
+    schedule,
+    concurrency_policy.as_ref().map(ConcurrencyPolicy::as_str),
+    successful_jobs_history_limit.flatten(),
+    failed_jobs_history_limit.flatten(),
+    command.as_ref().and_then(Option::as_deref),
+    args.as_ref().and_then(Option::as_deref),
+    path.project_id,
+    path.cron_job_id
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  Ok(cron_job)
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH_WITH_ID,
+  params(CronJobPath),
+  responses(
+    CronJob,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[delete("/")]
+pub async fn delete_cron_job(path: Path<CronJobPath>, pool: Pool) -> ApiResult<CronJob> {
+  let cron_job = sqlx::query_as!(
+    CronJob,
+    "UPDATE cron_jobs SET deleted = true WHERE project_id = $1 AND cron_job_id = $2 RETURNING *",
+    path.project_id,
+    path.cron_job_id,
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  Ok(cron_job)
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH_WITH_ID,
+  params(CronJobPath),
+  responses(
+    CronJob,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[post("/recover/")]
+pub async fn recover_cron_job(path: Path<CronJobPath>, pool: Pool) -> ApiResult<CronJob> {
+  let cron_job = sqlx::query_as!(
+    CronJob,
+    "UPDATE cron_jobs SET deleted = false WHERE project_id = $1 AND cron_job_id = $2 RETURNING *",
+    path.project_id,
+    path.cron_job_id,
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  Ok(cron_job)
+}
+
+pub fn config_with_id(cfg: &mut ServiceConfig) {
+  cfg
+    .service(get_cron_job)
+    .service(update_cron_job)
+    .service(delete_cron_job)
+    .service(recover_cron_job);
+}
@@ -10,7 +10,9 @@ use crate::{
     AlreadyExistsErrorMessage, BadRequestErrorMessage, InternalServerErrorMessage,
     NotFoundErrorMessage, UnauthorizedErrorMessage,
   },
-  schemas::{AppPath, EnvList, EnvPath, EnvSchema, EnvVar, PartialEnvSchema},
+  schemas::{
+    AppPath, CronJobEnvPath, CronJobPath, EnvList, EnvPath, EnvSchema, EnvVar, PartialEnvSchema,
+  },
   ApiResult,
 };
 
@@ -160,3 +162,154 @@ pub fn config(cfg: &mut ServiceConfig) {
     .service(update_env)
     .service(delete_env);
 }
+
+const CRON_JOB_CONTEXT_PATH: &str = "/projects/{project_id}/cron-jobs/{cron_job_id}";
+
+#[utoipa::path(
+  context_path = CRON_JOB_CONTEXT_PATH,
+  params(CronJobPath),
+  responses(
+    EnvList,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[get("/envs/")]
+pub async fn list_cron_job_envs(path: Path<CronJobPath>, pool: Pool) -> ApiResult<EnvList> {
+  let envs = sqlx::query_as!(
+    EnvVar,
+    "SELECT * FROM envs WHERE cron_job_id = $1",
+    path.cron_job_id
+  )
+  .fetch_all(pool.as_ref())
+  .await?;
+
+  Ok(EnvList::from(envs))
+}
+
+#[utoipa::path(
+  context_path = CRON_JOB_CONTEXT_PATH,
+  params(CronJobPath),
+  responses(
+    EnvVar,
+    BadRequestErrorMessage,
+    NotFoundErrorMessage,
+    AlreadyExistsErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[post("/envs/")]
+pub async fn create_cron_job_env(
+  path: Path<CronJobPath>,
+  Json(env): Json<EnvSchema>,
+  pool: Pool,
+) -> ApiResult<EnvVar> {
+  let env = sqlx::query_as!(
+    EnvVar,
+    "INSERT INTO envs(env_name, env_value, cron_job_id) VALUES ($1, $2, $3) RETURNING *",
+    env.name,
+    env.value,
+    path.cron_job_id
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  Ok(env)
+}
+
+#[utoipa::path(
+  context_path = CRON_JOB_CONTEXT_PATH,
+  params(CronJobEnvPath),
+  responses(
+    EnvVar,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[get("/envs/{env_id}/")]
+pub async fn get_cron_job_env(path: Path<CronJobEnvPath>, pool: Pool) -> ApiResult<EnvVar> {
+  let env = sqlx::query_as!(
+    EnvVar,
+    "SELECT * FROM envs WHERE cron_job_id = $1 AND env_id = $2",
+    path.cron_job_id,
+    path.env_id
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  Ok(env)
+}
+
+#[utoipa::path(
+  context_path = CRON_JOB_CONTEXT_PATH,
+  params(CronJobEnvPath),
+  responses(
+    EnvVar,
+    BadRequestErrorMessage,
+    NotFoundErrorMessage,
+    AlreadyExistsErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[patch("/envs/{env_id}/")]
+pub async fn update_cron_job_env(
+  path: Path<CronJobEnvPath>,
+  Json(env): Json<PartialEnvSchema>,
+  pool: Pool,
+) -> ApiResult<EnvVar> {
+  let env = sqlx::query_as!(
+    EnvVar,
+    r#"
+    UPDATE envs
+    SET env_name = COALESCE($1, env_name),
+      env_value = COALESCE($2, env_value)
+    WHERE cron_job_id = $3 AND env_id = $4
+    RETURNING *
+    "#,
+    env.name,
+    env.value,
+    path.cron_job_id,
+    path.env_id
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  Ok(env)
+}
+
+#[utoipa::path(
+  context_path = CRON_JOB_CONTEXT_PATH,
+  params(CronJobEnvPath),
+  responses(
+    EnvVar,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[delete("/envs/{env_id}/")]
+pub async fn delete_cron_job_env(path: Path<CronJobEnvPath>, pool: Pool) -> ApiResult<EnvVar> {
+  let env = sqlx::query_as!(
+    EnvVar,
+    "DELETE FROM envs WHERE cron_job_id = $1 AND env_id = $2 RETURNING *",
+    path.cron_job_id,
+    path.env_id,
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  Ok(env)
+}
+
+pub fn cron_job_config(cfg: &mut ServiceConfig) {
+  cfg
+    .service(list_cron_job_envs)
+    .service(create_cron_job_env)
+    .service(get_cron_job_env)
+    .service(update_cron_job_env)
+    .service(delete_cron_job_env);
+}
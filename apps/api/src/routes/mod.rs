@@ -1,5 +1,10 @@
+pub mod admin;
 pub mod app;
 pub mod auth;
+pub mod cron_job;
 pub mod env;
 pub mod project;
+pub mod registry;
+pub mod release;
+pub mod run;
 pub mod volume;
@@ -1,7 +1,6 @@
 use actix_web::{
   delete, get, patch, post,
-  web::{Path, ServiceConfig},
-  HttpResponse,
+  web::{Data, Path, Query, ServiceConfig},
 };
 use actix_web_validator::Json;
 use uuid::Uuid;
@@ -12,13 +11,16 @@ use crate::{
     AlreadyExistsErrorMessage, BadRequestErrorMessage, InternalServerErrorMessage,
     NotFoundErrorMessage, UnauthorizedErrorMessage,
   },
-  k8s,
+  jobs::{self, ReleaseJob},
+  k8s::{self, Cluster},
   middleware::UserId,
+  registry::{pin_images, resolve_images, ImageRegistry},
+  routes::release::record_release,
   schemas::{
-    AppService, EnvVar, PartialProjectSchema, Project, ProjectPath, ProjectSchema, ProjectsList,
-    Volume,
+    AppService, CronJob, EnvVar, PartialProjectSchema, Project, ProjectDrift, ProjectPath,
+    ProjectSchema, ProjectsList, Registry, Release, ReleaseOptions, ReleasePlan, Volume,
   },
-  ApiResult,
+  ApiError, ApiResult,
 };
 
 const CONTEXT_PATH_WITHOUT_ID: &str = "/projects";
@@ -57,8 +59,9 @@ pub async fn create_project(
 ) -> ApiResult<Project> {
   let new_project = sqlx::query_as!(
     Project,
-    "INSERT INTO projects(project_name, user_id) VALUES ($1, $2) RETURNING *",
+    "INSERT INTO projects(project_name, reapply_drift, user_id) VALUES ($1, $2, $3) RETURNING *",
     project.name,
+    project.reapply_drift,
     *user_id
   )
   .fetch_one(pool.as_ref())
@@ -126,8 +129,9 @@ pub async fn update_project(
 
   let project = sqlx::query_as!(
     Project,
-    "UPDATE projects SET project_name = COALESCE($1, project_name) WHERE user_id = $2 AND project_id = $3 RETURNING *",
+    "UPDATE projects SET project_name = COALESCE($1, project_name), reapply_drift = COALESCE($2, reapply_drift) WHERE user_id = $3 AND project_id = $4 RETURNING *",
     project.name,
+    project.reapply_drift,
     *user_id,
     project_id
   )
@@ -143,6 +147,7 @@ pub async fn update_project(
   responses(
     Project,
     NotFoundErrorMessage,
+    AlreadyExistsErrorMessage,
     UnauthorizedErrorMessage,
     InternalServerErrorMessage
   )
@@ -151,27 +156,55 @@ pub async fn update_project(
 pub async fn delete_project(
   path: Path<ProjectPath>,
   pool: Pool,
+  cluster: Data<Cluster>,
   user_id: UserId,
 ) -> ApiResult<Project> {
   let ProjectPath { project_id } = *path;
 
+  let mut tx = pool.begin().await?;
+
+  // the row lock orders the deletion with the releases being recorded
+  sqlx::query!(
+    "SELECT project_id FROM projects WHERE user_id = $1 AND project_id = $2 FOR UPDATE",
+    *user_id,
+    project_id
+  )
+  .fetch_one(tx.as_mut())
+  .await?;
+
+  // a running release would bring back what the teardown removes
+  let releasing = sqlx::query_scalar!(
+    r#"SELECT EXISTS(SELECT 1 FROM releases WHERE project_id = $1 AND state IN ('Pending', 'Running')) as "releasing!""#,
+    project_id
+  )
+  .fetch_one(tx.as_mut())
+  .await?;
+
+  if releasing {
+    return Err(ApiError::AlreadyExists);
+  }
+
+  // the mark survives a failed teardown, which is then retried in the background
   let project = sqlx::query_as!(
     Project,
-    "DELETE FROM projects WHERE user_id = $1 AND project_id = $2 RETURNING *",
-    *user_id,
+    "UPDATE projects SET deleting = true WHERE project_id = $1 RETURNING *",
     project_id
   )
-  .fetch_one(pool.as_ref())
+  .fetch_one(tx.as_mut())
   .await?;
 
+  tx.commit().await?;
+
+  let project = jobs::teardown_project(&pool, &cluster, project).await?;
+
   Ok(project)
 }
 
 #[utoipa::path(
   context_path = CONTEXT_PATH_WITH_ID,
-  params(ProjectPath),
+  params(ProjectPath, ReleaseOptions),
   responses(
-    (status = NO_CONTENT),
+    Release,
     BadRequestErrorMessage,
     NotFoundErrorMessage,
     AlreadyExistsErrorMessage,
@@ -180,20 +213,44 @@ pub async fn delete_project(
   )
 )]
 #[post("/")]
-pub async fn release_project(path: Path<ProjectPath>, pool: Pool) -> ApiResult<HttpResponse> {
+pub async fn release_project(
+  path: Path<ProjectPath>,
+  options: Query<ReleaseOptions>,
+  pool: Pool,
+  cluster: Data<Cluster>,
+  registry: Data<ImageRegistry>,
+  user_id: UserId,
+) -> ApiResult<Release> {
   let ProjectPath { project_id } = *path;
 
+  // the registries are asked before the transaction, it isn't held open on the network
+  let released_apps = sqlx::query_as!(
+    AppService,
+    "SELECT * FROM app_services WHERE project_id = $1",
+    project_id
+  )
+  .fetch_all(pool.as_ref())
+  .await?;
+  let released_registries = sqlx::query_as!(
+    Registry,
+    "SELECT * FROM registries WHERE project_id = $1",
+    project_id
+  )
+  .fetch_all(pool.as_ref())
+  .await?;
+  let digests = resolve_images(&registry, &released_apps, &released_registries).await?;
+
   let mut tx = pool.begin().await?;
 
   let project = sqlx::query_as!(
     Project,
-    "SELECT * FROM projects WHERE project_id = $1",
+    "SELECT * FROM projects WHERE project_id = $1 AND deleting = false FOR UPDATE",
     project_id
   )
   .fetch_one(tx.as_mut())
   .await?;
 
-  let apps = sqlx::query_as!(
+  let mut apps = sqlx::query_as!(
     AppService,
     "SELECT * FROM app_services WHERE project_id = $1",
     project_id
@@ -201,52 +258,202 @@ pub async fn release_project(path: Path<ProjectPath>, pool: Pool) -> ApiResult<H
   .fetch_all(tx.as_mut())
   .await?;
 
-  sqlx::query!(
-    "DELETE FROM app_services WHERE project_id = $1 AND deleted = true",
-    project_id,
+  let cron_jobs = sqlx::query_as!(
+    CronJob,
+    "SELECT * FROM cron_jobs WHERE project_id = $1",
+    project_id
   )
-  .execute(tx.as_mut())
+  .fetch_all(tx.as_mut())
   .await?;
 
   let apps_id: Vec<Uuid> = apps.iter().map(|app| app.app_id).collect();
+  let cron_jobs_id: Vec<Uuid> = cron_jobs
+    .iter()
+    .map(|cron_job| cron_job.cron_job_id)
+    .collect();
 
   let envs = sqlx::query_as!(
     EnvVar,
-    "SELECT * FROM envs WHERE app_id = ANY($1)",
-    &apps_id
+    "SELECT * FROM envs WHERE app_id = ANY($1) OR cron_job_id = ANY($2)",
+    &apps_id,
+    &cron_jobs_id
   )
   .fetch_all(tx.as_mut())
   .await?;
 
-  sqlx::query!(
-    "DELETE FROM envs USING app_services WHERE envs.app_id = app_services.app_id AND app_services.deleted = true"
+  let volumes = sqlx::query_as!(
+    Volume,
+    r#"
+    SELECT volumes.*, (
+      SELECT COALESCE(jsonb_agg(jsonb_build_object('id', volume_apps.app_id, 'path', volume_apps.path)), '[]')
+      FROM volume_apps
+      WHERE volume_apps.volume_id = volumes.volume_id
+    ) AS "apps!"
+    FROM volumes
+    WHERE project_id = $1
+    "#,
+    path.project_id
   )
-  .execute(tx.as_mut())
+  .fetch_all(tx.as_mut())
+  .await?;
+
+  let registries = sqlx::query_as!(
+    Registry,
+    "SELECT * FROM registries WHERE project_id = $1",
+    project_id
+  )
+  .fetch_all(tx.as_mut())
+  .await?;
+
+  // the tags are pinned before the snapshot, a rollback runs the same images again
+  pin_images(tx.as_mut(), &mut apps, &digests).await?;
+
+  // fails with a conflict while another release of the project is running
+  let release = record_release(
+    tx.as_mut(),
+    project_id,
+    *user_id,
+    &apps,
+    &cron_jobs,
+    &envs,
+    &volumes,
+  )
+  .await?;
+
+  tx.commit().await?;
+
+  jobs::spawn_release(
+    pool.get_ref().clone(),
+    cluster.get_ref().clone(),
+    ReleaseJob {
+      release_id: release.release_id,
+      project,
+      apps,
+      cron_jobs,
+      envs,
+      volumes,
+      registries,
+      wait_for_healthy: options.wait_for_healthy,
+    },
+  );
+
+  Ok(release)
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH_WITH_ID,
+  params(ProjectPath),
+  responses(
+    ReleasePlan,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[get("/release/plan/")]
+pub async fn plan_release(
+  path: Path<ProjectPath>,
+  pool: Pool,
+  cluster: Data<Cluster>,
+) -> ApiResult<ReleasePlan> {
+  let ProjectPath { project_id } = *path;
+
+  let project = sqlx::query_as!(
+    Project,
+    "SELECT * FROM projects WHERE project_id = $1",
+    project_id
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  let apps = sqlx::query_as!(
+    AppService,
+    "SELECT * FROM app_services WHERE project_id = $1",
+    project_id
+  )
+  .fetch_all(pool.as_ref())
+  .await?;
+
+  let cron_jobs = sqlx::query_as!(
+    CronJob,
+    "SELECT * FROM cron_jobs WHERE project_id = $1",
+    project_id
+  )
+  .fetch_all(pool.as_ref())
+  .await?;
+
+  let apps_id: Vec<Uuid> = apps.iter().map(|app| app.app_id).collect();
+  let cron_jobs_id: Vec<Uuid> = cron_jobs
+    .iter()
+    .map(|cron_job| cron_job.cron_job_id)
+    .collect();
+
+  let envs = sqlx::query_as!(
+    EnvVar,
+    "SELECT * FROM envs WHERE app_id = ANY($1) OR cron_job_id = ANY($2)",
+    &apps_id,
+    &cron_jobs_id
+  )
+  .fetch_all(pool.as_ref())
   .await?;
 
   let volumes = sqlx::query_as!(
     Volume,
-    "SELECT * FROM volumes WHERE project_id = $1",
-    path.project_id
+    r#"
+    SELECT volumes.*, (
+      SELECT COALESCE(jsonb_agg(jsonb_build_object('id', volume_apps.app_id, 'path', volume_apps.path)), '[]')
+      FROM volume_apps
+      WHERE volume_apps.volume_id = volumes.volume_id
+    ) AS "apps!"
+    FROM volumes
+    WHERE project_id = $1
+    "#,
+    project_id
   )
-  .fetch_all(tx.as_mut())
+  .fetch_all(pool.as_ref())
   .await?;
 
-  sqlx::query!(
-    "DELETE FROM volumes WHERE project_id = $1 AND deleted = true",
-    path.project_id
+  let registries = sqlx::query_as!(
+    Registry,
+    "SELECT * FROM registries WHERE project_id = $1",
+    project_id
   )
-  .execute(tx.as_mut())
+  .fetch_all(pool.as_ref())
   .await?;
 
-  if let Err(err) = k8s::release(project, apps, envs, volumes).await {
-    tx.rollback().await?;
-    return Err(err.into());
-  } else {
-    tx.commit().await?;
-  }
+  let changes = k8s::plan(
+    &cluster,
+    &project,
+    &apps,
+    &cron_jobs,
+    &envs,
+    &volumes,
+    &registries,
+  )
+  .await?;
+
+  Ok(ReleasePlan::from(changes))
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH_WITH_ID,
+  params(ProjectPath),
+  responses(
+    ProjectDrift,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[get("/drift/")]
+pub async fn get_project_drift(
+  path: Path<ProjectPath>,
+  pool: Pool,
+  cluster: Data<Cluster>,
+) -> ApiResult<ProjectDrift> {
+  let changes = jobs::project_drift(&pool, &cluster, path.project_id).await?;
 
-  Ok(HttpResponse::NoContent().finish())
+  Ok(ProjectDrift::from(changes))
 }
 
 pub fn config_with_id(cfg: &mut ServiceConfig) {
@@ -254,5 +461,7 @@ pub fn config_with_id(cfg: &mut ServiceConfig) {
     .service(get_project)
     .service(update_project)
     .service(delete_project)
-    .service(release_project);
+    .service(release_project)
+    .service(plan_release)
+    .service(get_project_drift);
 }
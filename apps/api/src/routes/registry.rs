@@ -0,0 +1,172 @@
+use actix_web::{
+  delete, get, patch, post,
+  web::{Path, ServiceConfig},
+};
+use actix_web_validator::Json;
+
+use crate::{
+  database::Pool,
+  error::{
+    AlreadyExistsErrorMessage, BadRequestErrorMessage, InternalServerErrorMessage,
+    NotFoundErrorMessage, UnauthorizedErrorMessage,
+  },
+  registry::encrypt_token,
+  schemas::{
+    PartialRegistrySchema, ProjectPath, RegistriesList, Registry, RegistryPath, RegistrySchema,
+  },
+  ApiResult,
+};
+
+const CONTEXT_PATH: &str = "/projects/{project_id}";
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH,
+  params(ProjectPath),
+  responses(
+    RegistriesList,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[get("/registries/")]
+pub async fn list_registries(path: Path<ProjectPath>, pool: Pool) -> ApiResult<RegistriesList> {
+  let registries = sqlx::query_as!(
+    Registry,
+    "SELECT * FROM registries WHERE project_id = $1",
+    path.project_id
+  )
+  .fetch_all(pool.as_ref())
+  .await?;
+
+  Ok(RegistriesList::from(registries))
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH,
+  params(ProjectPath),
+  responses(
+    Registry,
+    BadRequestErrorMessage,
+    NotFoundErrorMessage,
+    AlreadyExistsErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[post("/registries/")]
+pub async fn create_registry(
+  path: Path<ProjectPath>,
+  Json(registry): Json<RegistrySchema>,
+  pool: Pool,
+) -> ApiResult<Registry> {
+  let registry = sqlx::query_as!(
+    Registry,
+    "INSERT INTO registries(registry_host, username, token, project_id) VALUES ($1, $2, $3, $4) RETURNING *",
+    registry.host,
+    registry.username,
+    encrypt_token(&registry.token),
+    path.project_id
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  Ok(registry)
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH,
+  params(RegistryPath),
+  responses(
+    Registry,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[get("/registries/{registry_id}/")]
+pub async fn get_registry(path: Path<RegistryPath>, pool: Pool) -> ApiResult<Registry> {
+  let registry = sqlx::query_as!(
+    Registry,
+    "SELECT * FROM registries WHERE project_id = $1 AND registry_id = $2",
+    path.project_id,
+    path.registry_id
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  Ok(registry)
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH,
+  params(RegistryPath),
+  responses(
+    Registry,
+    BadRequestErrorMessage,
+    NotFoundErrorMessage,
+    AlreadyExistsErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[patch("/registries/{registry_id}/")]
+pub async fn update_registry(
+  path: Path<RegistryPath>,
+  Json(registry): Json<PartialRegistrySchema>,
+  pool: Pool,
+) -> ApiResult<Registry> {
+  let registry = sqlx::query_as!(
+    Registry,
+    r#"
+    UPDATE registries
+    SET registry_host = COALESCE($1, registry_host),
+      username = COALESCE($2, username),
+      token = COALESCE($3, token)
+    WHERE project_id = $4 AND registry_id = $5
+    RETURNING *
+    "#,
+    registry.host,
+    registry.username,
+    registry.token.as_deref().map(encrypt_token),
+    path.project_id,
+    path.registry_id
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  Ok(registry)
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH,
+  params(RegistryPath),
+  responses(
+    Registry,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[delete("/registries/{registry_id}/")]
+pub async fn delete_registry(path: Path<RegistryPath>, pool: Pool) -> ApiResult<Registry> {
+  let registry = sqlx::query_as!(
+    Registry,
+    "DELETE FROM registries WHERE project_id = $1 AND registry_id = $2 RETURNING *",
+    path.project_id,
+    path.registry_id,
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  Ok(registry)
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+  cfg
+    .service(list_registries)
+    .service(create_registry)
+    .service(get_registry)
+    .service(update_registry)
+    .service(delete_registry);
+}
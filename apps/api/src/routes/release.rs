@@ -0,0 +1,523 @@
+use std::convert::Infallible;
+
+use actix_web::{
+  get, post,
+  web::{Data, Path, ServiceConfig},
+  Responder,
+};
+use actix_web_lab::sse;
+use chrono::Utc;
+use futures::{stream, StreamExt};
+use sqlx::{types::Json, PgConnection};
+use uuid::Uuid;
+
+use crate::{
+  database::Pool,
+  error::{
+    AlreadyExistsErrorMessage, InternalServerErrorMessage, NotFoundErrorMessage,
+    QuotaExceededErrorMessage, UnauthorizedErrorMessage,
+  },
+  jobs::{self, ReleaseJob},
+  k8s::Cluster,
+  middleware::UserId,
+  quota::check_quota,
+  schemas::{
+    AppService, CronJob, EnvVar, Project, ProjectPath, Registry, Release, ReleaseDetails,
+    ReleaseEvent, ReleaseHook, ReleasePath, ReleaseState, ReleasesList, Volume,
+  },
+  ApiResult,
+};
+
+const CONTEXT_PATH: &str = "/projects/{project_id}";
+
+/// Store a pending release with a snapshot of the rows that are still alive after it
+pub async fn record_release(
+  conn: &mut PgConnection,
+  project_id: Uuid,
+  user_id: Uuid,
+  apps: &[AppService],
+  cron_jobs: &[CronJob],
+  envs: &[EnvVar],
+  volumes: &[Volume],
+) -> sqlx::Result<Release> {
+  let apps: Vec<&AppService> = apps.iter().filter(|app| !app.deleted).collect();
+  let cron_jobs: Vec<&CronJob> = cron_jobs
+    .iter()
+    .filter(|cron_job| !cron_job.deleted)
+    .collect();
+  let envs: Vec<&EnvVar> = envs
+    .iter()
+    .filter(|env| {
+      apps.iter().any(|app| env.app_id == Some(app.app_id))
+        || cron_jobs
+          .iter()
+          .any(|cron_job| env.cron_job_id == Some(cron_job.cron_job_id))
+    })
+    .collect();
+  let volumes: Vec<&Volume> = volumes.iter().filter(|volume| !volume.deleted).collect();
+
+  sqlx::query_as!(
+    Release,
+    r#"INSERT INTO releases(apps, cron_jobs, envs, volumes, released_at, user_id, project_id) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING release_id, state as "state: ReleaseState", error, released_at, user_id, project_id"#,
+    Json(apps) as _,
+    Json(cron_jobs) as _,
+    Json(envs) as _,
+    Json(volumes) as _,
+    Utc::now().naive_utc(),
+    user_id,
+    project_id
+  )
+  .fetch_one(conn)
+  .await
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH,
+  params(ProjectPath),
+  responses(
+    ReleasesList,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[get("/releases/")]
+pub async fn list_releases(path: Path<ProjectPath>, pool: Pool) -> ApiResult<ReleasesList> {
+  let releases = sqlx::query_as!(
+    Release,
+    r#"SELECT release_id, state as "state: ReleaseState", error, released_at, user_id, project_id FROM releases WHERE project_id = $1 ORDER BY released_at DESC"#,
+    path.project_id
+  )
+  .fetch_all(pool.as_ref())
+  .await?;
+
+  Ok(ReleasesList::from(releases))
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH,
+  params(ReleasePath),
+  responses(
+    ReleaseDetails,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[get("/releases/{release_id}/")]
+pub async fn get_release(path: Path<ReleasePath>, pool: Pool) -> ApiResult<ReleaseDetails> {
+  let release = sqlx::query_as!(
+    ReleaseDetails,
+    r#"
+    SELECT release_id,
+      state as "state: ReleaseState",
+      error,
+      apps as "apps: Json<Vec<AppService>>",
+      cron_jobs as "cron_jobs: Json<Vec<CronJob>>",
+      envs as "envs: Json<Vec<EnvVar>>",
+      volumes as "volumes: Json<Vec<Volume>>",
+      hooks as "hooks: Json<Vec<ReleaseHook>>",
+      released_at,
+      user_id,
+      project_id
+    FROM releases
+    WHERE project_id = $1 AND release_id = $2
+    "#,
+    path.project_id,
+    path.release_id
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  Ok(release)
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH,
+  params(ReleasePath),
+  responses(
+    Release,
+    NotFoundErrorMessage,
+    AlreadyExistsErrorMessage,
+    QuotaExceededErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[post("/releases/{release_id}/rollback/")]
+pub async fn rollback_release(
+  path: Path<ReleasePath>,
+  pool: Pool,
+  cluster: Data<Cluster>,
+  user_id: UserId,
+) -> ApiResult<Release> {
+  let ReleasePath {
+    project_id,
+    release_id,
+  } = *path;
+
+  let mut tx = pool.begin().await?;
+
+  let project = sqlx::query_as!(
+    Project,
+    "SELECT * FROM projects WHERE project_id = $1 AND deleting = false FOR UPDATE",
+    project_id
+  )
+  .fetch_one(tx.as_mut())
+  .await?;
+
+  let snapshot = sqlx::query!(
+    r#"
+    SELECT apps as "apps: Json<Vec<AppService>>",
+      cron_jobs as "cron_jobs: Json<Vec<CronJob>>",
+      envs as "envs: Json<Vec<EnvVar>>",
+      volumes as "volumes: Json<Vec<Volume>>"
+    FROM releases
+    WHERE project_id = $1 AND release_id = $2
+    "#,
+    project_id,
+    release_id
+  )
+  .fetch_one(tx.as_mut())
+  .await?;
+
+  let Json(apps) = snapshot.apps;
+  let Json(cron_jobs) = snapshot.cron_jobs;
+  let Json(envs) = snapshot.envs;
+  let Json(volumes) = snapshot.volumes;
+
+  let apps_id: Vec<Uuid> = apps.iter().map(|app| app.app_id).collect();
+  let cron_jobs_id: Vec<Uuid> = cron_jobs
+    .iter()
+    .map(|cron_job| cron_job.cron_job_id)
+    .collect();
+  let volumes_id: Vec<Uuid> = volumes.iter().map(|volume| volume.volume_id).collect();
+
+  // rows created after the release are dropped, the release below removes them from the cluster
+  let removed_apps = sqlx::query_as!(
+    AppService,
+    "DELETE FROM app_services WHERE project_id = $1 AND NOT (app_id = ANY($2)) RETURNING *",
+    project_id,
+    &apps_id
+  )
+  .fetch_all(tx.as_mut())
+  .await?;
+
+  let removed_cron_jobs = sqlx::query_as!(
+    CronJob,
+    "DELETE FROM cron_jobs WHERE project_id = $1 AND NOT (cron_job_id = ANY($2)) RETURNING *",
+    project_id,
+    &cron_jobs_id
+  )
+  .fetch_all(tx.as_mut())
+  .await?;
+
+  let removed_volumes = sqlx::query_as!(
+    Volume,
+    r#"
+    DELETE FROM volumes
+    WHERE project_id = $1 AND NOT (volume_id = ANY($2))
+    RETURNING *, '[]'::JSONB AS "apps!"
+    "#,
+    project_id,
+    &volumes_id
+  )
+  .fetch_all(tx.as_mut())
+  .await?;
+
+  for app in &apps {
+    sqlx::query!(
+      r#"
+      INSERT INTO app_services(app_id, app_name, replicas, image, port, public_domain, private_domain, project_id, cpu_request, cpu_limit, memory_request, memory_limit, min_replicas, max_replicas, target_cpu_utilization, target_memory_utilization, probes, strategy, command, args, working_dir, kind, ports, public_port, private_port, expose, exposed_port, exposed_endpoint, image_digest, pre_deploy, containers)
+      VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31)
+      ON CONFLICT (app_id) DO UPDATE
+      SET app_name = EXCLUDED.app_name,
+        replicas = EXCLUDED.replicas,
+        image = EXCLUDED.image,
+        port = EXCLUDED.port,
+        public_domain = EXCLUDED.public_domain,
+        private_domain = EXCLUDED.private_domain,
+        cpu_request = EXCLUDED.cpu_request,
+        cpu_limit = EXCLUDED.cpu_limit,
+        memory_request = EXCLUDED.memory_request,
+        memory_limit = EXCLUDED.memory_limit,
+        min_replicas = EXCLUDED.min_replicas,
+        max_replicas = EXCLUDED.max_replicas,
+        target_cpu_utilization = EXCLUDED.target_cpu_utilization,
+        target_memory_utilization = EXCLUDED.target_memory_utilization,
+        probes = EXCLUDED.probes,
+        strategy = EXCLUDED.strategy,
+        command = EXCLUDED.command,
+        args = EXCLUDED.args,
+        working_dir = EXCLUDED.working_dir,
+        kind = EXCLUDED.kind,
+        ports = EXCLUDED.ports,
+        public_port = EXCLUDED.public_port,
+        private_port = EXCLUDED.private_port,
+        expose = EXCLUDED.expose,
+        exposed_port = EXCLUDED.exposed_port,
+        exposed_endpoint = EXCLUDED.exposed_endpoint,
+        image_digest = EXCLUDED.image_digest,
+        pre_deploy = EXCLUDED.pre_deploy,
+        containers = EXCLUDED.containers,
+        deleted = false
+      "#,
+      app.app_id,
+      app.app_name,
+      app.replicas,
+      app.image,
+      app.port,
+      app.public_domain,
+      app.private_domain,
+      project_id,
+      app.cpu_request,
+      app.cpu_limit,
+      app.memory_request,
+      app.memory_limit,
+      app.min_replicas,
+      app.max_replicas,
+      app.target_cpu_utilization,
+      app.target_memory_utilization,
+      Json(&app.probes) as _,
+      Json(&app.strategy) as _,
+      app.command.as_deref(),
+      app.args.as_deref(),
+      app.working_dir,
+      app.kind.as_str(),
+      Json(&app.ports) as _,
+      app.public_port,
+      app.private_port,
+      app.expose,
+      app.exposed_port,
+      app.exposed_endpoint,
+      app.image_digest,
+      app.pre_deploy.as_deref(),
+      Json(&app.containers) as _
+    )
+    .execute(tx.as_mut())
+    .await?;
+  }
+
+  for cron_job in &cron_jobs {
+    sqlx::query!(
+      r#"
+      INSERT INTO cron_jobs(cron_job_id, cron_job_name, image, command, args, schedule, concurrency_policy, successful_jobs_history_limit, failed_jobs_history_limit, project_id)
+      VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+      ON CONFLICT (cron_job_id) DO UPDATE
+      SET cron_job_name = EXCLUDED.cron_job_name,
+        image = EXCLUDED.image,
+        command = EXCLUDED.command,
+        args = EXCLUDED.args,
+        schedule = EXCLUDED.schedule,
+        concurrency_policy = EXCLUDED.concurrency_policy,
+        successful_jobs_history_limit = EXCLUDED.successful_jobs_history_limit,
+        failed_jobs_history_limit = EXCLUDED.failed_jobs_history_limit,
+        deleted = false
+      "#,
+      cron_job.cron_job_id,
+      cron_job.cron_job_name,
+      cron_job.image,
+      cron_job.command.as_deref(),
+      cron_job.args.as_deref(),
+      cron_job.schedule,
+      cron_job.concurrency_policy.as_str(),
+      cron_job.successful_jobs_history_limit,
+      cron_job.failed_jobs_history_limit,
+      project_id
+    )
+    .execute(tx.as_mut())
+    .await?;
+  }
+
+  sqlx::query!(
+    "DELETE FROM envs WHERE app_id = ANY($1) OR cron_job_id = ANY($2)",
+    &apps_id,
+    &cron_jobs_id
+  )
+  .execute(tx.as_mut())
+  .await?;
+
+  for env in &envs {
+    sqlx::query!(
+      "INSERT INTO envs(env_id, env_name, env_value, app_id, cron_job_id) VALUES ($1, $2, $3, $4, $5)",
+      env.env_id,
+      env.env_name,
+      env.env_value,
+      env.app_id,
+      env.cron_job_id
+    )
+    .execute(tx.as_mut())
+    .await?;
+  }
+
+  for volume in &volumes {
+    sqlx::query!(
+      r#"
+      INSERT INTO volumes(volume_id, volume_name, capacity, path, access_mode, cron_job_id, project_id)
+      VALUES ($1, $2, $3, $4, $5, $6, $7)
+      ON CONFLICT (volume_id) DO UPDATE
+      SET volume_name = EXCLUDED.volume_name,
+        capacity = EXCLUDED.capacity,
+        path = EXCLUDED.path,
+        access_mode = EXCLUDED.access_mode,
+        cron_job_id = EXCLUDED.cron_job_id,
+        deleted = false
+      "#,
+      volume.volume_id,
+      volume.volume_name,
+      volume.capacity,
+      volume.path,
+      volume.access_mode.as_str(),
+      volume.cron_job_id,
+      project_id
+    )
+    .execute(tx.as_mut())
+    .await?;
+
+    sqlx::query!(
+      "DELETE FROM volume_apps WHERE volume_id = $1",
+      volume.volume_id
+    )
+    .execute(tx.as_mut())
+    .await?;
+
+    // apps of the release deleted since then are gone with their rows
+    let (app_ids, paths): (Vec<Uuid>, Vec<String>) = volume
+      .apps
+      .0
+      .iter()
+      .map(|app| (app.id, app.path.clone()))
+      .unzip();
+    sqlx::query!(
+      r#"
+      INSERT INTO volume_apps(volume_id, app_id, path)
+      SELECT $1, mounts.app_id, mounts.path
+      FROM UNNEST($2::UUID[], $3::TEXT[]) AS mounts (app_id, path)
+      JOIN app_services USING (app_id)
+      "#,
+      volume.volume_id,
+      &app_ids,
+      &paths
+    )
+    .execute(tx.as_mut())
+    .await?;
+  }
+
+  // quotas may have been lowered since the release was made
+  check_quota(tx.as_mut(), &project_id).await?;
+
+  let registries = sqlx::query_as!(
+    Registry,
+    "SELECT * FROM registries WHERE project_id = $1",
+    project_id
+  )
+  .fetch_all(tx.as_mut())
+  .await?;
+
+  let release = record_release(
+    tx.as_mut(),
+    project_id,
+    *user_id,
+    &apps,
+    &cron_jobs,
+    &envs,
+    &volumes,
+  )
+  .await?;
+
+  tx.commit().await?;
+
+  let apps = apps
+    .into_iter()
+    .chain(removed_apps.into_iter().map(|app| AppService {
+      deleted: true,
+      ..app
+    }))
+    .collect();
+
+  let cron_jobs = cron_jobs
+    .into_iter()
+    .chain(removed_cron_jobs.into_iter().map(|cron_job| CronJob {
+      deleted: true,
+      ..cron_job
+    }))
+    .collect();
+
+  let volumes = volumes
+    .into_iter()
+    .chain(removed_volumes.into_iter().map(|volume| Volume {
+      deleted: true,
+      ..volume
+    }))
+    .collect();
+
+  jobs::spawn_release(
+    pool.get_ref().clone(),
+    cluster.get_ref().clone(),
+    ReleaseJob {
+      release_id: release.release_id,
+      project,
+      apps,
+      cron_jobs,
+      envs,
+      volumes,
+      registries,
+      wait_for_healthy: false,
+    },
+  );
+
+  Ok(release)
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH,
+  params(ReleasePath),
+  responses(
+    ReleaseEvent,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[get("/releases/{release_id}/events/")]
+pub async fn get_release_events(path: Path<ReleasePath>, pool: Pool) -> ApiResult<impl Responder> {
+  let ReleasePath {
+    project_id,
+    release_id,
+  } = *path;
+
+  // subscribe before reading the state, a job stores its outcome before its events go away
+  let events = jobs::release_events(&release_id);
+
+  let release = sqlx::query_as!(
+    Release,
+    r#"SELECT release_id, state as "state: ReleaseState", error, released_at, user_id, project_id FROM releases WHERE project_id = $1 AND release_id = $2"#,
+    project_id,
+    release_id
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  let events = match events {
+    Some(events) => events.left_stream(),
+    None => stream::iter(ReleaseEvent::outcome(&release)).right_stream(),
+  };
+
+  let stream = events.map(|event| {
+    Ok::<_, Infallible>(sse::Event::Data(
+      sse::Data::new_json(event)
+        .expect("Invalid release event json")
+        .event("message"),
+    ))
+  });
+
+  Ok(sse::Sse::from_stream(stream))
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+  cfg
+    .service(list_releases)
+    .service(get_release)
+    .service(get_release_events)
+    .service(rollback_release);
+}
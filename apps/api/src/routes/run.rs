@@ -0,0 +1,197 @@
+use actix_web::{
+  get, post,
+  web::{Data, Path, ServiceConfig},
+};
+use actix_web_validator::Json;
+use chrono::Utc;
+
+use crate::{
+  database::Pool,
+  error::{
+    ApiError, BadRequestErrorMessage, InternalServerErrorMessage, NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+  },
+  jobs,
+  k8s::{self, Cluster},
+  middleware::UserId,
+  schemas::{AppPath, AppService, Run, RunLogs, RunPath, RunSchema, RunState, RunsList},
+  ApiResult,
+};
+
+const CONTEXT_PATH: &str = "/projects/{project_id}/apps/{app_id}";
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH,
+  params(AppPath),
+  responses(
+    RunsList,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[get("/runs/")]
+pub async fn list_runs(path: Path<AppPath>, pool: Pool) -> ApiResult<RunsList> {
+  let runs = sqlx::query_as!(
+    Run,
+    r#"
+    SELECT run_id, command, state as "state: RunState", exit_code, error, started_at, finished_at, app_id, user_id
+    FROM runs
+    WHERE app_id = $2 AND app_id IN (SELECT app_id FROM app_services WHERE project_id = $1)
+    ORDER BY started_at DESC
+    "#,
+    path.project_id,
+    path.app_id
+  )
+  .fetch_all(pool.as_ref())
+  .await?;
+
+  Ok(RunsList::from(runs))
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH,
+  params(AppPath),
+  responses(
+    Run,
+    BadRequestErrorMessage,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[post("/runs/")]
+pub async fn create_run(
+  path: Path<AppPath>,
+  Json(run): Json<RunSchema>,
+  pool: Pool,
+  cluster: Data<Cluster>,
+  user_id: UserId,
+) -> ApiResult<Run> {
+  let AppPath { project_id, app_id } = *path;
+
+  let app = sqlx::query_as!(
+    AppService,
+    "SELECT * FROM app_services WHERE project_id = $1 AND app_id = $2",
+    project_id,
+    app_id
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  let mut tx = pool.begin().await?;
+
+  let run = sqlx::query_as!(
+    Run,
+    r#"
+    INSERT INTO runs(command, started_at, app_id, user_id)
+    VALUES ($1, $2, $3, $4)
+    RETURNING run_id, command, state as "state: RunState", exit_code, error, started_at, finished_at, app_id, user_id
+    "#,
+    &run.command,
+    Utc::now().naive_utc(),
+    app_id,
+    *user_id
+  )
+  .fetch_one(tx.as_mut())
+  .await?;
+
+  // the row is only kept once the job exists
+  if k8s::create_run(&run, &app, &cluster).await?.is_none() {
+    return Err(ApiError::BadRequest {
+      message: format!("App {} has not been released", app.app_name),
+    });
+  }
+
+  tx.commit().await?;
+
+  jobs::spawn_run(
+    pool.get_ref().clone(),
+    cluster.get_ref().clone(),
+    project_id,
+    run.run_id,
+  );
+
+  Ok(run)
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH,
+  params(RunPath),
+  responses(
+    Run,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[get("/runs/{run_id}/")]
+pub async fn get_run(path: Path<RunPath>, pool: Pool) -> ApiResult<Run> {
+  let run = sqlx::query_as!(
+    Run,
+    r#"
+    SELECT run_id, command, state as "state: RunState", exit_code, error, started_at, finished_at, app_id, user_id
+    FROM runs
+    WHERE run_id = $3 AND app_id = $2 AND app_id IN (SELECT app_id FROM app_services WHERE project_id = $1)
+    "#,
+    path.project_id,
+    path.app_id,
+    path.run_id
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  Ok(run)
+}
+
+#[utoipa::path(
+  context_path = CONTEXT_PATH,
+  params(RunPath),
+  responses(
+    RunLogs,
+    NotFoundErrorMessage,
+    UnauthorizedErrorMessage,
+    InternalServerErrorMessage
+  )
+)]
+#[get("/runs/{run_id}/logs/")]
+pub async fn get_run_logs(
+  path: Path<RunPath>,
+  pool: Pool,
+  cluster: Data<Cluster>,
+) -> ApiResult<RunLogs> {
+  let RunPath {
+    project_id,
+    app_id,
+    run_id,
+  } = *path;
+
+  let run = sqlx::query!(
+    r#"
+    SELECT state as "state: RunState", logs
+    FROM runs
+    WHERE run_id = $3 AND app_id = $2 AND app_id IN (SELECT app_id FROM app_services WHERE project_id = $1)
+    "#,
+    project_id,
+    app_id,
+    run_id
+  )
+  .fetch_one(pool.as_ref())
+  .await?;
+
+  // the logs of a finished run are stored, its job does not outlive it for long
+  let logs = match run.state {
+    RunState::Running => k8s::run_logs(&cluster, &project_id, &run_id).await?,
+    _ => run.logs.unwrap_or_default(),
+  };
+
+  Ok(RunLogs { logs })
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+  cfg
+    .service(list_runs)
+    .service(create_run)
+    .service(get_run)
+    .service(get_run_logs);
+}
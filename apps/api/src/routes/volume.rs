@@ -3,14 +3,20 @@ use actix_web::{
   web::{Path, ServiceConfig},
 };
 use actix_web_validator::Json;
+use sqlx::PgConnection;
+use uuid::Uuid;
 
 use crate::{
   database::Pool,
   error::{
     AlreadyExistsErrorMessage, BadRequestErrorMessage, InternalServerErrorMessage,
-    NotFoundErrorMessage, UnauthorizedErrorMessage,
+    NotFoundErrorMessage, QuotaExceededErrorMessage, UnauthorizedErrorMessage,
+  },
+  quota::check_quota,
+  schemas::{
+    AccessMode, PartialVolumeSchema, ProjectPath, Volume, VolumeAppSchema, VolumePath,
+    VolumeSchema, VolumesList,
   },
-  schemas::{PartialVolumeSchema, ProjectPath, Volume, VolumePath, VolumeSchema, VolumesList},
   ApiError, ApiResult,
 };
 
@@ -30,7 +36,15 @@ const CONTEXT_PATH: &str = "/projects/{project_id}";
 pub async fn list_volumes(path: Path<ProjectPath>, pool: Pool) -> ApiResult<VolumesList> {
   let volumes = sqlx::query_as!(
     Volume,
-    "SELECT * FROM volumes WHERE project_id = $1",
+    r#"
+    SELECT volumes.*, (
+      SELECT COALESCE(jsonb_agg(jsonb_build_object('id', volume_apps.app_id, 'path', volume_apps.path)), '[]')
+      FROM volume_apps
+      WHERE volume_apps.volume_id = volumes.volume_id
+    ) AS "apps!"
+    FROM volumes
+    WHERE project_id = $1
+    "#,
     path.project_id
   )
   .fetch_all(pool.as_ref())
@@ -47,6 +61,7 @@ pub async fn list_volumes(path: Path<ProjectPath>, pool: Pool) -> ApiResult<Volu
     BadRequestErrorMessage,
     NotFoundErrorMessage,
     AlreadyExistsErrorMessage,
+    QuotaExceededErrorMessage,
     UnauthorizedErrorMessage,
     InternalServerErrorMessage
   )
@@ -57,12 +72,13 @@ pub async fn create_volume(
   Json(volume): Json<VolumeSchema>,
   pool: Pool,
 ) -> ApiResult<Volume> {
-  // check if the app is in the same project of
-  if let Some(app_id) = &volume.app.id {
+  let apps = project_apps(pool.as_ref(), &path.project_id, &volume.apps).await?;
+
+  if let Some(cron_job_id) = &volume.cron_job.id {
     let is_same_project = sqlx::query!(
-      "SELECT 1 as ok FROM app_services WHERE project_id = $1 AND app_id = $2",
+      "SELECT 1 as ok FROM cron_jobs WHERE project_id = $1 AND cron_job_id = $2",
       path.project_id,
-      app_id
+      cron_job_id
     )
     .fetch_optional(pool.as_ref())
     .await?;
@@ -72,18 +88,28 @@ pub async fn create_volume(
     }
   }
 
-  let volume = sqlx::query_as!(
-    Volume,
-    "INSERT INTO volumes(volume_name, capacity, path, app_id, project_id) VALUES ($1, $2, $3, $4, $5) RETURNING *",
+  let mut tx = pool.begin().await?;
+
+  let volume_id = sqlx::query_scalar!(
+    "INSERT INTO volumes(volume_name, capacity, path, access_mode, cron_job_id, project_id) VALUES ($1, $2, $3, $4, $5, $6) RETURNING volume_id",
     volume.name,
     volume.capacity,
     volume.path,
-    volume.app.id,
+    volume.access_mode.as_str(),
+    volume.cron_job.id,
     path.project_id
   )
-  .fetch_one(pool.as_ref())
+  .fetch_one(&mut *tx)
   .await?;
 
+  attach_apps(&mut tx, &volume_id, &apps).await?;
+
+  check_quota(&mut tx, &path.project_id).await?;
+
+  let volume = fetch_volume(&mut tx, &path.project_id, &volume_id).await?;
+
+  tx.commit().await?;
+
   Ok(volume)
 }
 
@@ -99,14 +125,9 @@ pub async fn create_volume(
 )]
 #[get("/volumes/{volume_id}/")]
 pub async fn get_volume(path: Path<VolumePath>, pool: Pool) -> ApiResult<Volume> {
-  let volume = sqlx::query_as!(
-    Volume,
-    "SELECT * FROM volumes WHERE project_id = $1 AND volume_id = $2",
-    path.project_id,
-    path.volume_id
-  )
-  .fetch_one(pool.as_ref())
-  .await?;
+  let mut conn = pool.acquire().await?;
+
+  let volume = fetch_volume(&mut conn, &path.project_id, &path.volume_id).await?;
 
   Ok(volume)
 }
@@ -119,6 +140,7 @@ pub async fn get_volume(path: Path<VolumePath>, pool: Pool) -> ApiResult<Volume>
     BadRequestErrorMessage,
     NotFoundErrorMessage,
     AlreadyExistsErrorMessage,
+    QuotaExceededErrorMessage,
     UnauthorizedErrorMessage,
     InternalServerErrorMessage
   )
@@ -129,13 +151,17 @@ pub async fn update_volume(
   Json(volume): Json<PartialVolumeSchema>,
   pool: Pool,
 ) -> ApiResult<Volume> {
-  let app_id = volume.app.as_ref().map(|app| app.id).unwrap_or(None);
-  // check if the app is in the same project of
-  if let Some(app_id) = &app_id {
+  let apps = match &volume.apps {
+    Some(apps) => Some(project_apps(pool.as_ref(), &path.project_id, apps).await?),
+    None => None,
+  };
+
+  let cron_job_id = volume.cron_job.as_ref().and_then(|cron_job| cron_job.id);
+  if let Some(cron_job_id) = &cron_job_id {
     let is_same_project = sqlx::query!(
-      "SELECT 1 as ok FROM app_services WHERE project_id = $1 AND app_id = $2",
+      "SELECT 1 as ok FROM cron_jobs WHERE project_id = $1 AND cron_job_id = $2",
       path.project_id,
-      app_id
+      cron_job_id
     )
     .fetch_optional(pool.as_ref())
     .await?;
@@ -145,28 +171,74 @@ pub async fn update_volume(
     }
   }
 
-  let volume = sqlx::query_as!(
-    Volume,
+  let mut tx = pool.begin().await?;
+
+  let current = fetch_volume(&mut tx, &path.project_id, &path.volume_id).await?;
+
+  // the claim keeps the access mode it was created with
+  if volume
+    .access_mode
+    .is_some_and(|access_mode| access_mode != current.access_mode)
+  {
+    return Err(ApiError::BadRequest {
+      message: format!(
+        "Access mode of volume {} can't change once created",
+        current.volume_name
+      ),
+    });
+  }
+
+  // the apps mounting the volume at its path follow it
+  if let Some(volume_path) = &volume.path {
+    sqlx::query!(
+      "UPDATE volume_apps SET path = $1 WHERE volume_id = $2 AND path = $3",
+      volume_path,
+      path.volume_id,
+      current.path
+    )
+    .execute(&mut *tx)
+    .await?;
+  }
+
+  sqlx::query!(
     r#"
     UPDATE volumes
     SET volume_name = COALESCE($1, volume_name),
       capacity = COALESCE($2, capacity),
       path = COALESCE($3, path),
-      app_id = (CASE WHEN $4 = true THEN $5 ELSE app_id END)
-    WHERE project_id = $6 AND volume_id = $7
-    RETURNING *
+      cron_job_id = (CASE WHEN $6 = true THEN $7 ELSE cron_job_id END)
+    WHERE project_id = $4 AND volume_id = $5
     "#,
     volume.name,
     volume.capacity,
     volume.path,
-    volume.app.is_some(),
-    app_id,
     path.project_id,
-    path.volume_id
+    path.volume_id,
+    volume.cron_job.is_some(),
+    cron_job_id
   )
-  .fetch_one(pool.as_ref())
+  .execute(&mut *tx)
   .await?;
 
+  if let Some(apps) = &apps {
+    sqlx::query!(
+      "DELETE FROM volume_apps WHERE volume_id = $1",
+      path.volume_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    attach_apps(&mut tx, &path.volume_id, apps).await?;
+  } else {
+    check_mounts(&mut tx, &path.volume_id).await?;
+  }
+
+  check_quota(&mut tx, &path.project_id).await?;
+
+  let volume = fetch_volume(&mut tx, &path.project_id, &path.volume_id).await?;
+
+  tx.commit().await?;
+
   Ok(volume)
 }
 
@@ -182,16 +254,21 @@ pub async fn update_volume(
 )]
 #[delete("/volumes/{volume_id}/")]
 pub async fn delete_volume(path: Path<VolumePath>, pool: Pool) -> ApiResult<Volume> {
-  let app = sqlx::query_as!(
-    Volume,
-    "UPDATE volumes SET deleted = true WHERE project_id = $1 AND volume_id = $2 RETURNING *",
+  let mut tx = pool.begin().await?;
+
+  sqlx::query!(
+    "UPDATE volumes SET deleted = true WHERE project_id = $1 AND volume_id = $2",
     path.project_id,
     path.volume_id,
   )
-  .fetch_one(pool.as_ref())
+  .execute(&mut *tx)
   .await?;
 
-  Ok(app)
+  let volume = fetch_volume(&mut tx, &path.project_id, &path.volume_id).await?;
+
+  tx.commit().await?;
+
+  Ok(volume)
 }
 
 #[utoipa::path(
@@ -200,6 +277,7 @@ pub async fn delete_volume(path: Path<VolumePath>, pool: Pool) -> ApiResult<Volu
   responses(
     Volume,
     NotFoundErrorMessage,
+    QuotaExceededErrorMessage,
     UnauthorizedErrorMessage,
     InternalServerErrorMessage
   )
@@ -207,16 +285,155 @@ pub async fn delete_volume(path: Path<VolumePath>, pool: Pool) -> ApiResult<Volu
 #[delete("/volumes/{volume_id}/recover/")]
 pub async fn recover_volume(path: Path<VolumePath>, pool: Pool) -> ApiResult<Volume> {
   log::debug!("Found {path:?}");
-  let app = sqlx::query_as!(
-    Volume,
-    "UPDATE volumes SET deleted = false WHERE project_id = $1 AND volume_id = $2 RETURNING *",
+  let mut tx = pool.begin().await?;
+
+  sqlx::query!(
+    "UPDATE volumes SET deleted = false WHERE project_id = $1 AND volume_id = $2",
     path.project_id,
     path.volume_id,
   )
-  .fetch_one(pool.as_ref())
+  .execute(&mut *tx)
   .await?;
 
-  Ok(app)
+  // the apps may have mounted another volume at its path in the meantime
+  check_mounts(&mut tx, &path.volume_id).await?;
+  check_quota(&mut tx, &path.project_id).await?;
+
+  let volume = fetch_volume(&mut tx, &path.project_id, &path.volume_id).await?;
+
+  tx.commit().await?;
+
+  Ok(volume)
+}
+
+async fn fetch_volume(
+  conn: &mut PgConnection,
+  project_id: &Uuid,
+  volume_id: &Uuid,
+) -> ApiResult<Volume> {
+  let volume = sqlx::query_as!(
+    Volume,
+    r#"
+    SELECT volumes.*, (
+      SELECT COALESCE(jsonb_agg(jsonb_build_object('id', volume_apps.app_id, 'path', volume_apps.path)), '[]')
+      FROM volume_apps
+      WHERE volume_apps.volume_id = volumes.volume_id
+    ) AS "apps!"
+    FROM volumes
+    WHERE project_id = $1 AND volume_id = $2
+    "#,
+    project_id,
+    volume_id
+  )
+  .fetch_one(conn)
+  .await?;
+
+  Ok(volume)
+}
+
+/// Apps along with their paths, once checked they all belong to the project
+async fn project_apps<'a>(
+  pool: &sqlx::PgPool,
+  project_id: &Uuid,
+  apps: &'a [VolumeAppSchema],
+) -> ApiResult<Vec<&'a VolumeAppSchema>> {
+  let mut apps: Vec<&VolumeAppSchema> = apps.iter().collect();
+  apps.sort_by_key(|app| app.id);
+  apps.dedup_by_key(|app| app.id);
+  let app_ids: Vec<Uuid> = apps.iter().map(|app| app.id).collect();
+
+  let found = sqlx::query_scalar!(
+    r#"SELECT COUNT(*) AS "count!" FROM app_services WHERE project_id = $1 AND app_id = ANY($2)"#,
+    project_id,
+    &app_ids
+  )
+  .fetch_one(pool)
+  .await?;
+
+  if found != app_ids.len() as i64 {
+    return Err(ApiError::NotFound);
+  }
+
+  Ok(apps)
+}
+
+/// Mount the volume in the apps, at the path of the volume unless they have their own
+async fn attach_apps(
+  conn: &mut PgConnection,
+  volume_id: &Uuid,
+  apps: &[&VolumeAppSchema],
+) -> ApiResult<()> {
+  let app_ids: Vec<Uuid> = apps.iter().map(|app| app.id).collect();
+  let paths: Vec<Option<String>> = apps.iter().map(|app| app.path.clone()).collect();
+
+  sqlx::query!(
+    r#"
+    INSERT INTO volume_apps(volume_id, app_id, path)
+    SELECT $1, mounts.app_id, COALESCE(mounts.path, volumes.path)
+    FROM UNNEST($2::UUID[], $3::TEXT[]) AS mounts (app_id, path)
+    JOIN volumes ON volumes.volume_id = $1
+    "#,
+    volume_id,
+    &app_ids,
+    &paths as &[Option<String>]
+  )
+  .execute(&mut *conn)
+  .await?;
+
+  check_mounts(conn, volume_id).await
+}
+
+/// Fail when the volume, as seen by `conn`, can't be mounted by all its apps and cron job
+async fn check_mounts(conn: &mut PgConnection, volume_id: &Uuid) -> ApiResult<()> {
+  let volume = sqlx::query!(
+    r#"
+    SELECT volume_name,
+      access_mode,
+      (SELECT COUNT(*) FROM volume_apps WHERE volume_id = $1) + (cron_job_id IS NOT NULL)::INT AS "mounts!"
+    FROM volumes
+    WHERE volume_id = $1
+    "#,
+    volume_id
+  )
+  .fetch_one(&mut *conn)
+  .await?;
+
+  if AccessMode::from(volume.access_mode) == AccessMode::ReadWriteOnce && volume.mounts > 1 {
+    return Err(ApiError::BadRequest {
+      message: format!(
+        "Volume {} is ReadWriteOnce, a single app or cron job can mount it",
+        volume.volume_name
+      ),
+    });
+  }
+
+  // two volumes of an app can't share a path
+  let conflict = sqlx::query!(
+    r#"
+    SELECT app_name, volume_apps.path
+    FROM volume_apps
+    JOIN volumes USING (volume_id)
+    JOIN app_services USING (app_id)
+    WHERE app_id IN (SELECT app_id FROM volume_apps WHERE volume_id = $1) AND volumes.deleted = false
+    GROUP BY app_name, volume_apps.path
+    HAVING COUNT(*) > 1
+    LIMIT 1
+    "#,
+    volume_id
+  )
+  .fetch_optional(&mut *conn)
+  .await?;
+
+  if let Some(conflict) = conflict {
+    return Err(ApiError::BadRequest {
+      message: format!(
+        "App {} already mounts a volume at {}",
+        conflict.app_name, conflict.path
+      ),
+    });
+  }
+
+  Ok(())
 }
 
 pub fn config(cfg: &mut ServiceConfig) {
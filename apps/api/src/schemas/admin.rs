@@ -0,0 +1,59 @@
+use derive_more::derive::From;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, IntoResponses, ToSchema};
+use uuid::Uuid;
+
+use validator::Validate;
+
+use crate::impl_json_response;
+
+/// Cluster object whose owner rows no longer exist, or left outside the namespace of its project
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanObject {
+  pub kind: String,
+  pub name: String,
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub namespace: Option<String>,
+  pub project_id: Uuid,
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub app_id: Option<Uuid>,
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub cron_job_id: Option<Uuid>,
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub volume_id: Option<Uuid>,
+}
+
+#[derive(Debug, From, Serialize, IntoResponses)]
+#[response(status = OK)]
+pub struct OrphansList(#[to_schema] Vec<OrphanObject>);
+impl_json_response!(OrphansList);
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct AdminProjectPath {
+  pub project_id: Uuid,
+}
+
+/// New quotas of a project, the omitted ones are left as they are
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectQuotaSchema {
+  #[schema(minimum = 0)]
+  #[validate(range(min = 0))]
+  pub apps: Option<i32>,
+  #[schema(minimum = 0)]
+  #[validate(range(min = 0))]
+  pub replicas: Option<i32>,
+  /// Millicores
+  #[schema(minimum = 0)]
+  #[validate(range(min = 0))]
+  pub cpu: Option<i32>,
+  /// MB
+  #[schema(minimum = 0)]
+  #[validate(range(min = 0))]
+  pub memory: Option<i32>,
+  /// MB
+  #[schema(minimum = 0)]
+  #[validate(range(min = 0))]
+  pub storage: Option<i32>,
+}
@@ -1,14 +1,14 @@
 use derive_more::derive::From;
-use k8s_openapi::api::apps::v1::DeploymentStatus;
+use k8s_openapi::api::apps::v1::Deployment;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, IntoResponses, ToSchema};
 use uuid::Uuid;
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
 use crate::{impl_json_response, partial_schema};
 
-#[derive(Debug, Default, Serialize, ToSchema, IntoResponses)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, ToSchema, IntoResponses)]
 #[response(status = OK)]
 #[serde(rename_all = "camelCase")]
 pub struct AppService {
@@ -18,16 +18,124 @@ pub struct AppService {
   pub app_name: String,
   pub replicas: i32,
   pub image: String,
-  pub port: i32,
+  #[serde(default)]
+  pub kind: AppKind,
+  /// Port web apps listen on, workers have none
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub port: Option<i32>,
   #[serde(skip_serializing_if = "Option::is_none", default)]
   pub public_domain: Option<String>,
   #[serde(skip_serializing_if = "Option::is_none", default)]
   pub private_domain: Option<String>,
   pub deleted: bool,
   pub project_id: Uuid,
+  /// Millicores reserved for every replica
+  #[serde(default = "resource_default::<DEFAULT_CPU_REQUEST>")]
+  pub cpu_request: i32,
+  /// Millicores every replica may use
+  #[serde(default = "resource_default::<DEFAULT_CPU_LIMIT>")]
+  pub cpu_limit: i32,
+  /// MB reserved for every replica
+  #[serde(default = "resource_default::<DEFAULT_MEMORY_REQUEST>")]
+  pub memory_request: i32,
+  /// MB every replica may use
+  #[serde(default = "resource_default::<DEFAULT_MEMORY_LIMIT>")]
+  pub memory_limit: i32,
+  /// Set with `max_replicas` when the app is autoscaled, `replicas` is ignored then
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub min_replicas: Option<i32>,
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub max_replicas: Option<i32>,
+  /// Percent of the cpu request the replicas are scaled to keep on average
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub target_cpu_utilization: Option<i32>,
+  /// Percent of the memory request the replicas are scaled to keep on average
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub target_memory_utilization: Option<i32>,
+  #[serde(default)]
+  pub probes: AppProbes,
+  #[serde(default)]
+  pub strategy: AppStrategy,
+  /// Replaces the entrypoint of the image
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub command: Option<Vec<String>>,
+  /// Replaces the cmd of the image
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub args: Option<Vec<String>>,
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub working_dir: Option<String>,
+  /// Run with the new image and envs before the replicas of a release roll out, the release stops when it fails
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub pre_deploy: Option<Vec<String>>,
+  #[serde(default)]
+  #[schema(value_type = Vec<AppPort>)]
+  pub ports: AppPorts,
+  #[serde(default)]
+  #[schema(value_type = Vec<AppContainer>)]
+  pub containers: AppContainers,
+  /// Port the public domain targets, the main port when missing
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub public_port: Option<String>,
+  /// Port the private domain targets, the main port when missing
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub private_port: Option<String>,
+  /// Port exposed over raw tcp or udp
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub expose: Option<String>,
+  /// Public port allocated to the exposed port, kept until the app is deleted or stops exposing it
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub exposed_port: Option<i32>,
+  /// Host and port the exposed port is reached on
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub exposed_endpoint: Option<String>,
+  /// Digest the tag of the image pointed to at the last release, the one the replicas run
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub image_digest: Option<String>,
+}
+
+impl AppService {
+  pub fn autoscaled(&self) -> bool {
+    self.min_replicas.is_some() && self.max_replicas.is_some()
+  }
 }
 impl_json_response!(AppService);
 
+/// Whether the app serves requests on its port or runs in the background
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum AppKind {
+  /// Listens on its port, reached through a service and its domains
+  #[default]
+  Web,
+  /// Runs without a port, a service or domains
+  Worker,
+}
+
+impl AppKind {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      AppKind::Web => "Web",
+      AppKind::Worker => "Worker",
+    }
+  }
+}
+
+/// The `kind` column only ever holds the kinds of [`AppKind`]
+impl From<String> for AppKind {
+  fn from(value: String) -> Self {
+    serde_json::from_value(serde_json::Value::String(value)).expect("Invalid app kind")
+  }
+}
+
+/// Sizing of the apps created without one, also filled in the snapshots of older releases
+pub const DEFAULT_CPU_REQUEST: i32 = 100;
+pub const DEFAULT_CPU_LIMIT: i32 = 500;
+pub const DEFAULT_MEMORY_REQUEST: i32 = 128;
+pub const DEFAULT_MEMORY_LIMIT: i32 = 256;
+
+const fn resource_default<const VALUE: i32>() -> i32 {
+  VALUE
+}
+
 #[derive(Debug, From, Serialize, IntoResponses)]
 #[response(status = OK)]
 pub struct AppServicesList(#[to_schema] Vec<AppService>);
@@ -48,12 +156,431 @@ pub struct DomainName {
   )]
   #[validate(regex(path = Regex::new(r"(^[a-zA-Z0-9]$)|(^[a-zA-Z0-9][a-zA-Z0-9-]{0,61}[a-zA-Z0-9]$)").unwrap()))]
   pub subdomain: Option<String>,
+  /// One of the `ports` of the app the domain targets, the main port when missing.
+  ///
+  /// A public domain needs a Tcp port, a private one resolves to it in its SRV record.
+  pub port: Option<String>,
+}
+
+/// A port an app listens on besides its main one
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct AppPort {
+  /// Name of the port in the container and in the service
+  #[schema(
+    min_length = 1,
+    max_length = 15,
+    pattern = "^[a-z]([-a-z0-9]*[a-z0-9])?$"
+  )]
+  #[validate(
+    length(min = 1, max = 15),
+    regex(path = Regex::new(r"^[a-z]([-a-z0-9]*[a-z0-9])?$").unwrap())
+  )]
+  pub name: String,
+  #[schema(minimum = 1, maximum = 65535)]
+  #[validate(range(min = 1, max = 65535))]
+  pub port: i32,
+  #[serde(default)]
+  pub protocol: PortProtocol,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum PortProtocol {
+  #[default]
+  Tcp,
+  Udp,
+}
+
+/// The extra ports of an app, stored as a json array
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AppPorts(pub Vec<AppPort>);
+
+/// The `ports` column only ever holds ports validated by the api
+impl From<serde_json::Value> for AppPorts {
+  fn from(value: serde_json::Value) -> Self {
+    serde_json::from_value(value).expect("Invalid app ports")
+  }
+}
+
+fn unique_ports(ports: &[AppPort]) -> Result<(), ValidationError> {
+  for (index, port) in ports.iter().enumerate() {
+    if port.name == crate::k8s::main_port_name() {
+      return Err(
+        ValidationError::new("reserved_name")
+          .with_message(format!("Port name {} is used by the main port", port.name).into()),
+      );
+    }
+
+    let others = &ports[index + 1..];
+    if others.iter().any(|other| other.name == port.name) {
+      return Err(
+        ValidationError::new("duplicate_name")
+          .with_message(format!("Port name {} is used twice", port.name).into()),
+      );
+    }
+    if others
+      .iter()
+      .any(|other| (other.port, other.protocol) == (port.port, port.protocol))
+    {
+      return Err(
+        ValidationError::new("duplicate_port")
+          .with_message(format!("Port {} is declared twice", port.port).into()),
+      );
+    }
+  }
+
+  Ok(())
+}
+
+/// A container added to the pods of an app next to its main one
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AppContainer {
+  /// Name of the container in the pods
+  #[schema(
+    min_length = 1,
+    max_length = 63,
+    pattern = "^[a-z0-9]([-a-z0-9]*[a-z0-9])?$"
+  )]
+  #[validate(
+    length(min = 1, max = 63),
+    regex(path = Regex::new(r"^[a-z0-9]([-a-z0-9]*[a-z0-9])?$").unwrap())
+  )]
+  pub name: String,
+  #[serde(default)]
+  pub kind: ContainerKind,
+  #[schema(min_length = 1)]
+  #[validate(length(min = 1))]
+  pub image: String,
+  /// Replaces the entrypoint of the image
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  #[schema(max_items = 64)]
+  #[validate(length(max = 64))]
+  pub command: Option<Vec<String>>,
+  /// Replaces the cmd of the image
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  #[schema(max_items = 64)]
+  #[validate(length(max = 64))]
+  pub args: Option<Vec<String>>,
+  /// Envs of this container only, the envs of the app are set as well
+  #[serde(default)]
+  #[schema(max_items = 64)]
+  #[validate(nested, length(max = 64))]
+  pub env: Vec<ContainerEnv>,
+  /// Volumes of the app this container mounts as well, the others are ignored
+  #[serde(default)]
+  #[schema(max_items = 16)]
+  #[validate(nested, length(max = 16))]
+  pub volumes: Vec<ContainerVolume>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum ContainerKind {
+  /// Runs to completion before the main container starts, one after the other
+  Init,
+  /// Runs along the main container for as long as the replica
+  #[default]
+  Sidecar,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct ContainerEnv {
+  #[schema(min_length = 1)]
+  #[validate(length(min = 1))]
+  pub name: String,
+  pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+pub struct ContainerVolume {
+  pub id: Uuid,
+  /// Absolute path the volume is mounted at in this container
+  #[schema(pattern = "^/.*$")]
+  #[validate(regex(path = Regex::new(r"^/.*$").unwrap(), message = "Volume path must be an absolute path"))]
+  pub path: String,
+}
+
+/// The extra containers of an app, stored as a json array
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AppContainers(pub Vec<AppContainer>);
+
+/// The `containers` column only ever holds containers validated by the api
+impl From<serde_json::Value> for AppContainers {
+  fn from(value: serde_json::Value) -> Self {
+    serde_json::from_value(value).expect("Invalid app containers")
+  }
+}
+
+fn unique_containers(containers: &[AppContainer]) -> Result<(), ValidationError> {
+  for (index, container) in containers.iter().enumerate() {
+    if containers[index + 1..]
+      .iter()
+      .any(|other| other.name == container.name)
+    {
+      return Err(
+        ValidationError::new("duplicate_name")
+          .with_message(format!("Container name {} is used twice", container.name).into()),
+      );
+    }
+  }
+
+  Ok(())
+}
+
+/// Autoscaling of an app, an empty object turns it off.
+///
+/// Without targets the cluster scales on 80% of the cpu request.
+#[derive(Debug, Default, Serialize, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Autoscaling {
+  #[schema(minimum = 1)]
+  #[validate(range(min = 1))]
+  pub min_replicas: Option<i32>,
+  #[schema(minimum = 1)]
+  #[validate(range(min = 1))]
+  pub max_replicas: Option<i32>,
+  #[schema(minimum = 1, maximum = 100)]
+  #[validate(range(min = 1, max = 100))]
+  pub target_cpu_utilization: Option<i32>,
+  #[schema(minimum = 1, maximum = 100)]
+  #[validate(range(min = 1, max = 100))]
+  pub target_memory_utilization: Option<i32>,
+}
+
+/// Probes run by the cluster on every replica of an app, a missing probe is not run
+#[derive(Debug, Default, Clone, Serialize, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AppProbes {
+  /// Restarts the replica when failing
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  #[validate(nested, custom(function = "single_success"))]
+  pub liveness: Option<Probe>,
+  /// Takes the replica out of the service when failing, a release waits for it to succeed
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  #[validate(nested)]
+  pub readiness: Option<Probe>,
+  /// Holds the other probes back until it succeeds once, for slow starting apps
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  #[validate(nested, custom(function = "single_success"))]
+  pub startup: Option<Probe>,
+}
+
+/// The `probes` column only ever holds probes validated by the api
+impl From<serde_json::Value> for AppProbes {
+  fn from(value: serde_json::Value) -> Self {
+    serde_json::from_value(value).expect("Invalid app probes")
+  }
+}
+
+/// A check of a replica, the timings left out take the cluster defaults
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Probe {
+  #[serde(flatten)]
+  #[validate(custom(function = "valid_check"))]
+  pub check: ProbeCheck,
+  #[schema(minimum = 0, maximum = 3600)]
+  #[validate(range(min = 0, max = 3600))]
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub initial_delay_seconds: Option<i32>,
+  #[schema(minimum = 1, maximum = 3600)]
+  #[validate(range(min = 1, max = 3600))]
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub period_seconds: Option<i32>,
+  #[schema(minimum = 1, maximum = 3600)]
+  #[validate(range(min = 1, max = 3600))]
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub timeout_seconds: Option<i32>,
+  /// Successes in a row for a failing replica to pass again, only readiness probes may need more than one
+  #[schema(minimum = 1, maximum = 100)]
+  #[validate(range(min = 1, max = 100))]
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub success_threshold: Option<i32>,
+  /// Failures in a row for the probe to fail
+  #[schema(minimum = 1, maximum = 100)]
+  #[validate(range(min = 1, max = 100))]
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub failure_threshold: Option<i32>,
+}
+
+/// How a probe checks a replica, the port defaults to the one of the app
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type")]
+pub enum ProbeCheck {
+  /// Succeeds when a GET on the path answers a 2xx or 3xx status
+  Http {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    port: Option<i32>,
+  },
+  /// Succeeds when a connection to the port opens
+  Tcp {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    port: Option<i32>,
+  },
+  /// Succeeds when the command exits with 0 inside the container
+  Exec { command: Vec<String> },
+}
+
+fn valid_check(check: &ProbeCheck) -> Result<(), ValidationError> {
+  let port = match check {
+    ProbeCheck::Http { path, .. } if !path.starts_with('/') => {
+      return Err(ValidationError::new("path").with_message("Path must start with /".into()));
+    }
+    ProbeCheck::Exec { command } if command.is_empty() => {
+      return Err(ValidationError::new("command").with_message("Command is empty".into()));
+    }
+    ProbeCheck::Http { port, .. } | ProbeCheck::Tcp { port } => *port,
+    ProbeCheck::Exec { .. } => None,
+  };
+
+  match port {
+    Some(port) if !(1..=65535).contains(&port) => {
+      Err(ValidationError::new("port").with_message("Port must be within 1 and 65535".into()))
+    }
+    _ => Ok(()),
+  }
+}
+
+/// The cluster only runs liveness and startup probes with a success threshold of 1
+fn single_success(probe: &Probe) -> Result<(), ValidationError> {
+  match probe.success_threshold {
+    Some(threshold) if threshold != 1 => Err(
+      ValidationError::new("success_threshold").with_message("Success threshold must be 1".into()),
+    ),
+    _ => Ok(()),
+  }
+}
+
+/// How a release replaces the replicas of an app.
+///
+/// Apps with a ReadWriteOnce volume always roll their single replica, a second deployment could not mount the claim.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AppStrategy {
+  #[serde(flatten)]
+  #[validate(custom(function = "valid_strategy"))]
+  pub kind: StrategyKind,
+  /// Seconds a rollout may go without progress before it fails, defaults to 120
+  #[schema(minimum = 10, maximum = 3600)]
+  #[validate(range(min = 10, max = 3600))]
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub progress_deadline_seconds: Option<i32>,
+}
+
+/// The `strategy` column only ever holds strategies validated by the api
+impl From<serde_json::Value> for AppStrategy {
+  fn from(value: serde_json::Value) -> Self {
+    serde_json::from_value(value).expect("Invalid app strategy")
+  }
+}
+
+pub const DEFAULT_PROGRESS_DEADLINE: i32 = 120;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type")]
+pub enum StrategyKind {
+  /// Replaces the replicas a few at a time, the cluster defaults both bounds to 25%
+  RollingUpdate {
+    #[serde(rename = "maxSurge", skip_serializing_if = "Option::is_none", default)]
+    max_surge: Option<RolloutBound>,
+    #[serde(
+      rename = "maxUnavailable",
+      skip_serializing_if = "Option::is_none",
+      default
+    )]
+    max_unavailable: Option<RolloutBound>,
+  },
+  /// Stops every replica before starting the new ones, for apps that cannot run two versions at once
+  Recreate,
+  /// Brings the new version up in a second deployment and moves the traffic once it is available
+  BlueGreen,
+  /// Runs the new version next to the current one on `weight` percent of the replicas,
+  /// a release with a weight of 100 promotes it
+  Canary { weight: i32 },
+}
+
+impl Default for StrategyKind {
+  fn default() -> Self {
+    StrategyKind::RollingUpdate {
+      max_surge: None,
+      max_unavailable: None,
+    }
+  }
+}
+
+/// Count of replicas, or percent of them like `25%`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum RolloutBound {
+  Count(i32),
+  Percent(String),
+}
+
+impl RolloutBound {
+  fn is_zero(&self) -> bool {
+    match self {
+      RolloutBound::Count(count) => *count == 0,
+      RolloutBound::Percent(percent) => percent.trim_end_matches('%') == "0",
+    }
+  }
+
+  fn is_valid(&self) -> bool {
+    match self {
+      RolloutBound::Count(count) => *count >= 0,
+      RolloutBound::Percent(percent) => percent
+        .strip_suffix('%')
+        .and_then(|percent| percent.parse::<u8>().ok())
+        .is_some_and(|percent| percent <= 100),
+    }
+  }
+}
+
+fn valid_strategy(kind: &StrategyKind) -> Result<(), ValidationError> {
+  match kind {
+    StrategyKind::RollingUpdate {
+      max_surge,
+      max_unavailable,
+    } => {
+      let bounds = [max_surge, max_unavailable];
+
+      if bounds
+        .iter()
+        .flat_map(|bound| bound.as_ref())
+        .any(|bound| !bound.is_valid())
+      {
+        return Err(
+          ValidationError::new("bound")
+            .with_message("Bounds are a positive count or a percent like 25%".into()),
+        );
+      }
+
+      // the cluster defaults a missing bound to 25%, it only rejects two explicit zeros
+      if bounds
+        .iter()
+        .all(|bound| bound.as_ref().is_some_and(RolloutBound::is_zero))
+      {
+        return Err(
+          ValidationError::new("bound")
+            .with_message("Max surge and max unavailable cannot both be 0".into()),
+        );
+      }
+
+      Ok(())
+    }
+    StrategyKind::Canary { weight } if !(1..=100).contains(weight) => {
+      Err(ValidationError::new("weight").with_message("Weight must be within 1 and 100".into()))
+    }
+    _ => Ok(()),
+  }
 }
 
 partial_schema! {
   PartialAppServiceSchema,
   #[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
   #[serde(rename_all = "camelCase")]
+  #[validate(schema(function = "Self::check_rules"))]
   pub struct AppServiceSchema {
     #[schema(min_length = 1)]
     #[validate(length(min = 1))]
@@ -64,17 +591,232 @@ partial_schema! {
     #[schema(min_length = 1)]
     #[validate(length(min = 1))]
     pub image: String,
+    /// Workers can't have a port or domains, their Http and Tcp probes need a port
+    #[serde(default)]
+    pub kind: AppKind,
+    /// Required by web apps, changing the kind to a worker removes it
     #[schema(minimum = 1, maximum = 65535)]
     #[validate(range(min = 1, max = 65535))]
-    pub port: i32,
+    pub port: Option<i32>,
     #[validate(nested)]
     pub public_domain: DomainName,
     #[validate(nested)]
     pub private_domain: DomainName,
+    /// Millicores reserved for every replica, defaults to 100 or the limit when lower
+    #[schema(minimum = 10, maximum = 8000)]
+    #[validate(range(min = 10, max = 8000))]
+    pub cpu_request: Option<i32>,
+    /// Millicores every replica may use, defaults to 500
+    #[schema(minimum = 10, maximum = 8000)]
+    #[validate(range(min = 10, max = 8000))]
+    pub cpu_limit: Option<i32>,
+    /// MB reserved for every replica, defaults to 128 or the limit when lower
+    #[schema(minimum = 16, maximum = 32768)]
+    #[validate(range(min = 16, max = 32768))]
+    pub memory_request: Option<i32>,
+    /// MB every replica may use, defaults to 256
+    #[schema(minimum = 16, maximum = 32768)]
+    #[validate(range(min = 16, max = 32768))]
+    pub memory_limit: Option<i32>,
+    /// Apps with a ReadWriteOnce volume are never autoscaled
+    #[serde(default)]
+    #[validate(nested)]
+    pub autoscaling: Autoscaling,
+    /// Replaces all the probes of the app, an empty object removes them
+    #[serde(default)]
+    #[validate(nested)]
+    pub probes: AppProbes,
+    /// Autoscaled apps are limited to the rolling update and recreate strategies
+    #[serde(default)]
+    #[validate(nested)]
+    pub strategy: AppStrategy,
+    /// Replaces the entrypoint of the image, an empty list restores it
+    #[schema(max_items = 64)]
+    #[validate(length(max = 64))]
+    pub command: Option<Vec<String>>,
+    /// Replaces the cmd of the image, an empty list restores it
+    #[schema(max_items = 64)]
+    #[validate(length(max = 64))]
+    pub args: Option<Vec<String>>,
+    /// Absolute path the container starts in, an empty string restores the one of the image
+    #[schema(pattern = "^(/.*)?$")]
+    #[validate(regex(path = Regex::new(r"^(/.*)?$").unwrap(), message = "Working directory must be an absolute path"))]
+    pub working_dir: Option<String>,
+    /// Command that must succeed before the replicas of a release roll out, an empty list removes it
+    #[schema(max_items = 64)]
+    #[validate(length(max = 64))]
+    pub pre_deploy: Option<Vec<String>>,
+    /// Replaces the ports the app listens on besides its main one, workers can't have any
+    #[serde(default)]
+    #[schema(max_items = 16)]
+    #[validate(nested, length(max = 16), custom(function = "unique_ports"))]
+    pub ports: Vec<AppPort>,
+    /// Replaces the init containers and sidecars of the app, added to its pods in this order
+    #[serde(default)]
+    #[schema(max_items = 16)]
+    #[validate(nested, length(max = 16), custom(function = "unique_containers"))]
+    pub containers: Vec<AppContainer>,
+    /// Name of the port exposed over raw tcp or udp on an allocated public port, an empty string stops exposing it
+    #[schema(max_length = 15)]
+    #[validate(length(max = 15))]
+    pub expose: Option<String>,
+  }
+}
+
+impl AppServiceSchema {
+  fn check_rules(&self) -> Result<(), ValidationError> {
+    AppRules {
+      kind: Some(self.kind),
+      port: Some(self.port),
+      public_domain: Some(&self.public_domain),
+      private_domain: Some(&self.private_domain),
+      probes: Some(&self.probes),
+      ports: Some(&self.ports),
+      autoscaled: Some(self.autoscaling.min_replicas.is_some()),
+      strategy: Some(&self.strategy.kind),
+    }
+    .check()
+  }
+}
+
+impl PartialAppServiceSchema {
+  /// Only the fields sent are checked, the database checks them along with the current ones
+  fn check_rules(&self) -> Result<(), ValidationError> {
+    AppRules {
+      kind: self.kind,
+      port: self.port,
+      public_domain: self.public_domain.as_ref(),
+      private_domain: self.private_domain.as_ref(),
+      probes: self.probes.as_ref(),
+      ports: self.ports.as_deref(),
+      autoscaled: self
+        .autoscaling
+        .as_ref()
+        .map(|autoscaling| autoscaling.min_replicas.is_some()),
+      strategy: self.strategy.as_ref().map(|strategy| &strategy.kind),
+    }
+    .check()
+  }
+}
+
+const WEB_APP_NEEDS_PORT: &str = "A web app needs a port";
+const WORKER_WITHOUT_ENDPOINT: &str = "A worker can't have a port or domains";
+const WORKER_WITHOUT_PORTS: &str = "A worker can't have ports";
+const WORKER_PROBES_NEED_PORT: &str = "The Http and Tcp probes of a worker need a port";
+const AUTOSCALING_NEEDS_SINGLE_DEPLOYMENT: &str =
+  "An autoscaled app is limited to the RollingUpdate and Recreate strategies";
+const PUBLIC_PORT_IS_TCP: &str = "The port of the public domain must be one of the Tcp ports";
+const PRIVATE_PORT_EXISTS: &str = "The port of the private domain must be one of the ports";
+
+/// Rules across the fields of an app, a missing field is left to the database checks
+struct AppRules<'a> {
+  kind: Option<AppKind>,
+  port: Option<Option<i32>>,
+  public_domain: Option<&'a DomainName>,
+  private_domain: Option<&'a DomainName>,
+  probes: Option<&'a AppProbes>,
+  ports: Option<&'a [AppPort]>,
+  autoscaled: Option<bool>,
+  strategy: Option<&'a StrategyKind>,
+}
+
+impl<'a> AppRules<'a> {
+  fn check(&self) -> Result<(), ValidationError> {
+    let rule = |code: &'static str, message: &'static str| {
+      Err(ValidationError::new(code).with_message(message.into()))
+    };
+
+    match self.kind {
+      Some(AppKind::Web) if self.port == Some(None) => return rule("port", WEB_APP_NEEDS_PORT),
+      Some(AppKind::Worker) => {
+        let has_domain = [self.public_domain, self.private_domain]
+          .into_iter()
+          .flatten()
+          .any(|domain| domain.subdomain.is_some());
+        if matches!(self.port, Some(Some(_))) || has_domain {
+          return rule("endpoint", WORKER_WITHOUT_ENDPOINT);
+        }
+
+        if self.ports.is_some_and(|ports| !ports.is_empty()) {
+          return rule("ports", WORKER_WITHOUT_PORTS);
+        }
+
+        let probe_without_port = self.probes.is_some_and(|probes| {
+          [&probes.liveness, &probes.readiness, &probes.startup]
+            .into_iter()
+            .flatten()
+            .any(|probe| {
+              matches!(
+                probe.check,
+                ProbeCheck::Http { port: None, .. } | ProbeCheck::Tcp { port: None }
+              )
+            })
+        });
+        if probe_without_port {
+          return rule("probe_port", WORKER_PROBES_NEED_PORT);
+        }
+      }
+      _ => {}
+    }
+
+    if self.autoscaled == Some(true)
+      && matches!(
+        self.strategy,
+        Some(StrategyKind::BlueGreen | StrategyKind::Canary { .. })
+      )
+    {
+      return rule("strategy", AUTOSCALING_NEEDS_SINGLE_DEPLOYMENT);
+    }
+
+    // a domain only targets a port it has, the public one goes through the ingress which only forwards tcp
+    let Some(ports) = self.ports else {
+      return Ok(());
+    };
+    let target = |domain: Option<&'a DomainName>| {
+      domain
+        .filter(|domain| domain.subdomain.is_some())
+        .and_then(|domain| domain.port.as_ref())
+        .map(|name| ports.iter().find(|port| port.name == *name))
+    };
+
+    match target(self.public_domain) {
+      Some(Some(port)) if port.protocol != PortProtocol::Tcp => {
+        return rule("public_port", PUBLIC_PORT_IS_TCP)
+      }
+      Some(None) => return rule("public_port", PUBLIC_PORT_IS_TCP),
+      _ => {}
+    }
+
+    if let Some(None) = target(self.private_domain) {
+      return rule("private_port", PRIVATE_PORT_EXISTS);
+    }
+
+    Ok(())
   }
 }
 
-#[derive(Debug, Default, Serialize, ToSchema)]
+/// Message of a failed database check of an app, the schemas check the same rules
+pub fn app_check_message(constraint: &str) -> Option<&'static str> {
+  let message = match constraint {
+    "web_app_needs_port" => WEB_APP_NEEDS_PORT,
+    "worker_without_endpoint" => WORKER_WITHOUT_ENDPOINT,
+    "worker_without_ports" => WORKER_WITHOUT_PORTS,
+    "worker_probes_need_port" => WORKER_PROBES_NEED_PORT,
+    "autoscaling_needs_single_deployment" => AUTOSCALING_NEEDS_SINGLE_DEPLOYMENT,
+    "public_port_is_tcp" => PUBLIC_PORT_IS_TCP,
+    "private_port_exists" => PRIVATE_PORT_EXISTS,
+    "cpu_request_within_limit" => "The cpu request can't exceed the cpu limit",
+    "memory_request_within_limit" => "The memory request can't exceed the memory limit",
+    "autoscaling_bounds_together" => "Autoscaling needs both min and max replicas",
+    "autoscaling_min_within_max" => "Autoscaling min replicas can't exceed max replicas",
+    "autoscaling_targets_need_bounds" => "Autoscaling targets need min and max replicas",
+    _ => return None,
+  };
+
+  Some(message)
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
 pub enum AppReleaseState {
   #[default]
   Unknown,
@@ -83,20 +825,91 @@ pub enum AppReleaseState {
   Released,
 }
 
+/// Deployment of an app, a blue/green app runs on the blue or green one, a canary app adds the canary one
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+pub enum AppTrack {
+  #[default]
+  Stable,
+  Blue,
+  Green,
+  Canary,
+}
+
+impl From<Option<&str>> for AppTrack {
+  fn from(label: Option<&str>) -> Self {
+    match label {
+      Some("blue") => AppTrack::Blue,
+      Some("green") => AppTrack::Green,
+      Some("canary") => AppTrack::Canary,
+      _ => AppTrack::Stable,
+    }
+  }
+}
+
 #[derive(Debug, Default, Serialize, ToSchema, IntoResponses)]
 #[response(status = 200, content_type = "text/event-stream")]
 #[serde(rename_all = "camelCase")]
 pub struct AppStatus {
+  /// Deployment the status is of, every deployment of the app sends its own
+  pub track: AppTrack,
   available: bool,
-  state: AppReleaseState,
+  pub state: AppReleaseState,
+  /// Replicas currently running, old and new ones during a rollout
+  current_replicas: i32,
+  /// Replicas wanted by the app or by its autoscaler
+  desired_replicas: i32,
+}
+
+/// A failed probe of a replica, sent as a `probe` event in the status stream of the app
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeFailure {
+  /// Pod of the replica
+  pub replica: String,
+  /// What the cluster reported, like `Readiness probe failed: HTTP probe failed with statuscode: 500`
+  pub message: String,
+  /// Failures reported for this replica and message so far
+  pub count: i32,
+}
+
+/// An item of the status stream of an app
+#[derive(Debug)]
+pub enum AppStatusEvent {
+  Status(AppStatus),
+  ProbeFailure(ProbeFailure),
 }
 
-impl From<DeploymentStatus> for AppStatus {
-  fn from(value: DeploymentStatus) -> Self {
+impl From<Deployment> for AppStatus {
+  fn from(deploy: Deployment) -> Self {
+    let generation = deploy.metadata.generation;
+    let track = deploy
+      .spec
+      .as_ref()
+      .and_then(|spec| {
+        spec
+          .template
+          .metadata
+          .as_ref()?
+          .labels
+          .as_ref()?
+          .get("track")
+      })
+      .map(String::as_str)
+      .into();
     let mut status = AppStatus {
+      track,
       available: false,
       state: AppReleaseState::Unknown,
+      current_replicas: 0,
+      desired_replicas: deploy
+        .spec
+        .and_then(|spec| spec.replicas)
+        .unwrap_or_default(),
     };
+    let Some(value) = deploy.status else {
+      return status;
+    };
+    status.current_replicas = value.replicas.unwrap_or_default();
     if let Some(conditions) = value.conditions {
       if let Some(available) = conditions.iter().find(|&con| con.type_ == "Available") {
         status.available = available.status == "True";
@@ -113,6 +926,11 @@ impl From<DeploymentStatus> for AppStatus {
       }
     }
 
+    // the conditions still describe the previous rollout until the controller sees the new spec
+    if value.observed_generation < generation {
+      status.state = AppReleaseState::Progressing;
+    }
+
     status
   }
 }
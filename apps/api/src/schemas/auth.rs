@@ -1,14 +1,11 @@
-use chrono::{naive::serde::ts_milliseconds_option, NaiveDateTime};
-use derive_more::derive::{Constructor, Debug};
+use chrono::NaiveDateTime;
+use derive_more::derive::Debug;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use uuid::Uuid;
 use validator::Validate;
 
-use crate::{
-  auth::{Password, Token},
-  impl_json_response, utils,
-};
+use crate::{auth::Password, utils};
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct User {
@@ -17,6 +14,8 @@ pub struct User {
   pub username: String,
   #[serde(skip)]
   pub password: String,
+  #[serde(default)]
+  pub admin: bool,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -31,20 +30,34 @@ pub struct Session {
   pub user_id: Uuid,
 }
 
-#[derive(Debug, Serialize, Deserialize, Constructor, ToSchema)]
-pub struct AuthResponse {
-  pub user: User,
-  #[debug(skip)]
-  pub token: Token,
-  #[serde(
-    with = "ts_milliseconds_option",
-    skip_serializing_if = "Option::is_none",
-    default
-  )]
-  #[schema(value_type = Option<i64>)]
-  pub expires: Option<NaiveDateTime>,
+pub use auth_response::AuthResponse;
+
+// the `Constructor` derive spells out every field, the lint has to be allowed around its output
+#[allow(clippy::redundant_field_names)]
+mod auth_response {
+  use chrono::{naive::serde::ts_milliseconds_option, NaiveDateTime};
+  use derive_more::derive::{Constructor, Debug};
+  use serde::{Deserialize, Serialize};
+  use utoipa::ToSchema;
+
+  use super::User;
+  use crate::{auth::Token, impl_json_response};
+
+  #[derive(Debug, Serialize, Deserialize, Constructor, ToSchema)]
+  pub struct AuthResponse {
+    pub user: User,
+    #[debug(skip)]
+    pub token: Token,
+    #[serde(
+      with = "ts_milliseconds_option",
+      skip_serializing_if = "Option::is_none",
+      default
+    )]
+    #[schema(value_type = Option<i64>)]
+    pub expires: Option<NaiveDateTime>,
+  }
+  impl_json_response!(AuthResponse);
 }
-impl_json_response!(AuthResponse);
 
 #[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct AuthData {
@@ -0,0 +1,115 @@
+use derive_more::derive::From;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, IntoResponses, ToSchema};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{impl_json_response, partial_schema};
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, ToSchema, IntoResponses)]
+#[response(status = OK)]
+#[serde(rename_all = "camelCase")]
+pub struct CronJob {
+  #[serde(rename = "id")]
+  pub cron_job_id: Uuid,
+  #[serde(rename = "name")]
+  pub cron_job_name: String,
+  pub image: String,
+  /// Replaces the entrypoint of the image
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub command: Option<Vec<String>>,
+  /// Replaces the cmd of the image
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub args: Option<Vec<String>>,
+  /// Cron expression the jobs are started on
+  pub schedule: String,
+  pub concurrency_policy: ConcurrencyPolicy,
+  /// Finished jobs kept around with their pods and logs
+  pub successful_jobs_history_limit: i32,
+  pub failed_jobs_history_limit: i32,
+  pub deleted: bool,
+  pub project_id: Uuid,
+}
+impl_json_response!(CronJob);
+
+/// What happens when a job is due while the previous one is still running
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum ConcurrencyPolicy {
+  /// Both jobs run
+  #[default]
+  Allow,
+  /// The new job is skipped
+  Forbid,
+  /// The running job is stopped for the new one
+  Replace,
+}
+
+impl ConcurrencyPolicy {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ConcurrencyPolicy::Allow => "Allow",
+      ConcurrencyPolicy::Forbid => "Forbid",
+      ConcurrencyPolicy::Replace => "Replace",
+    }
+  }
+}
+
+/// The `concurrency_policy` column only ever holds the policies of [`ConcurrencyPolicy`]
+impl From<String> for ConcurrencyPolicy {
+  fn from(value: String) -> Self {
+    serde_json::from_value(serde_json::Value::String(value)).expect("Invalid concurrency policy")
+  }
+}
+
+#[derive(Debug, From, Serialize, IntoResponses)]
+#[response(status = OK)]
+pub struct CronJobsList(#[to_schema] Vec<CronJob>);
+impl_json_response!(CronJobsList);
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CronJobPath {
+  pub project_id: Uuid,
+  pub cron_job_id: Uuid,
+}
+
+partial_schema! {
+  PartialCronJobSchema,
+  #[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+  #[serde(rename_all = "camelCase")]
+  pub struct CronJobSchema {
+    #[schema(min_length = 1)]
+    #[validate(length(min = 1))]
+    pub name: String,
+    #[schema(min_length = 1)]
+    #[validate(length(min = 1))]
+    pub image: String,
+    /// Five fields cron expression, or one of the `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` macros
+    #[schema(
+      pattern = r"^(@(annually|yearly|monthly|weekly|daily|midnight|hourly)|[0-9A-Za-z*?/,-]+( [0-9A-Za-z*?/,-]+){4})$"
+    )]
+    #[validate(regex(
+      path = Regex::new(r"^(@(annually|yearly|monthly|weekly|daily|midnight|hourly)|[0-9A-Za-z*?/,-]+( [0-9A-Za-z*?/,-]+){4})$").unwrap(),
+      message = "Schedule must be a cron expression"
+    ))]
+    pub schedule: String,
+    #[serde(default)]
+    pub concurrency_policy: ConcurrencyPolicy,
+    /// Finished jobs kept, defaults to 3
+    #[schema(minimum = 0, maximum = 100)]
+    #[validate(range(min = 0, max = 100))]
+    pub successful_jobs_history_limit: Option<i32>,
+    /// Failed jobs kept, defaults to 1
+    #[schema(minimum = 0, maximum = 100)]
+    #[validate(range(min = 0, max = 100))]
+    pub failed_jobs_history_limit: Option<i32>,
+    /// Replaces the entrypoint of the image, an empty list restores it
+    #[schema(max_items = 64)]
+    #[validate(length(max = 64))]
+    pub command: Option<Vec<String>>,
+    /// Replaces the cmd of the image, an empty list restores it
+    #[schema(max_items = 64)]
+    #[validate(length(max = 64))]
+    pub args: Option<Vec<String>>,
+  }
+}
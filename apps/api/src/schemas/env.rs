@@ -6,7 +6,7 @@ use validator::Validate;
 
 use crate::{impl_json_response, partial_schema};
 
-#[derive(Debug, Serialize, ToSchema, IntoResponses)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, IntoResponses)]
 #[response(status = OK)]
 #[serde(rename_all = "camelCase")]
 pub struct EnvVar {
@@ -16,7 +16,10 @@ pub struct EnvVar {
   pub env_name: String,
   #[serde(rename = "value")]
   pub env_value: String,
-  pub app_id: Uuid,
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub app_id: Option<Uuid>,
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub cron_job_id: Option<Uuid>,
 }
 impl_json_response!(EnvVar);
 
@@ -32,6 +35,13 @@ pub struct EnvPath {
   pub env_id: Uuid,
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CronJobEnvPath {
+  pub project_id: Uuid,
+  pub cron_job_id: Uuid,
+  pub env_id: Uuid,
+}
+
 partial_schema! {
   PartialEnvSchema,
   #[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
@@ -1,11 +1,21 @@
+mod admin;
 mod app;
 mod auth;
+mod cron_job;
 mod env;
 mod project;
+mod registry;
+mod release;
+mod run;
 mod volume;
 
+pub use admin::*;
 pub use app::*;
 pub use auth::*;
+pub use cron_job::*;
 pub use env::*;
 pub use project::*;
+pub use registry::*;
+pub use release::*;
+pub use run::*;
 pub use volume::*;
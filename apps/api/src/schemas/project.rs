@@ -6,6 +6,8 @@ use validator::Validate;
 
 use crate::{impl_json_response, partial_schema};
 
+use super::ResourceChange;
+
 #[derive(Debug, Serialize, ToSchema, IntoResponses)]
 #[response(status = OK)]
 #[serde(rename_all = "camelCase")]
@@ -15,6 +17,20 @@ pub struct Project {
   #[serde(rename = "name")]
   pub project_name: String,
   pub user_id: Uuid,
+  /// Re-apply the last release when the cluster drifts from it, otherwise drift is only reported
+  pub reapply_drift: bool,
+  /// Set while the cluster resources are torn down after a deletion
+  pub deleting: bool,
+  /// Apps not marked as deleted the project may have
+  pub quota_apps: i32,
+  /// Replicas summed over the apps
+  pub quota_replicas: i32,
+  /// Millicores summed over the cpu limits of every replica
+  pub quota_cpu: i32,
+  /// MB summed over the memory limits of every replica
+  pub quota_memory: i32,
+  /// MB summed over the capacity of the volumes
+  pub quota_storage: i32,
 }
 impl_json_response!(Project);
 
@@ -31,9 +47,17 @@ pub struct ProjectPath {
 partial_schema! {
   PartialProjectSchema,
   #[derive(Debug, Deserialize, Validate, ToSchema)]
+  #[serde(rename_all = "camelCase")]
   pub struct ProjectSchema {
     #[schema(min_length = 1)]
     #[validate(length(min = 1))]
     pub name: String,
+    #[serde(default)]
+    pub reapply_drift: bool,
   }
 }
+
+#[derive(Debug, From, Serialize, IntoResponses)]
+#[response(status = OK)]
+pub struct ProjectDrift(#[to_schema] Vec<ResourceChange>);
+impl_json_response!(ProjectDrift);
@@ -0,0 +1,54 @@
+use derive_more::derive::From;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, IntoResponses, ToSchema};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{impl_json_response, partial_schema};
+
+#[derive(Debug, Clone, Serialize, ToSchema, IntoResponses)]
+#[response(status = OK)]
+#[serde(rename_all = "camelCase")]
+pub struct Registry {
+  #[serde(rename = "id")]
+  pub registry_id: Uuid,
+  /// Host the images are pulled from, with its port when it is not the default one
+  #[serde(rename = "host")]
+  pub registry_host: String,
+  pub username: String,
+  /// Encrypted token, never returned
+  #[serde(skip)]
+  pub token: String,
+  pub project_id: Uuid,
+}
+impl_json_response!(Registry);
+
+#[derive(Debug, From, Serialize, IntoResponses)]
+#[response(status = OK)]
+pub struct RegistriesList(#[to_schema] Vec<Registry>);
+impl_json_response!(RegistriesList);
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct RegistryPath {
+  pub project_id: Uuid,
+  pub registry_id: Uuid,
+}
+
+partial_schema! {
+  PartialRegistrySchema,
+  #[derive(Debug, Deserialize, Validate, ToSchema)]
+  #[serde(rename_all = "camelCase")]
+  pub struct RegistrySchema {
+    #[schema(pattern = r"^[a-z0-9]([a-z0-9.-]*[a-z0-9])?(:[0-9]{1,5})?$")]
+    #[validate(regex(path = Regex::new(r"^[a-z0-9]([a-z0-9.-]*[a-z0-9])?(:[0-9]{1,5})?$").unwrap()))]
+    pub host: String,
+    #[schema(min_length = 1)]
+    #[validate(length(min = 1))]
+    pub username: String,
+    /// Password or access token of the user, only ever stored encrypted
+    #[schema(min_length = 1, write_only)]
+    #[validate(length(min = 1))]
+    pub token: String,
+  }
+}
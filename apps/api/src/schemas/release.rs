@@ -0,0 +1,181 @@
+use chrono::{naive::serde::ts_milliseconds, NaiveDateTime};
+use derive_more::derive::From;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use utoipa::{IntoParams, IntoResponses, ToSchema};
+use uuid::Uuid;
+
+use crate::impl_json_response;
+
+use super::{AppService, CronJob, EnvVar, Volume};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema, sqlx::Type)]
+#[sqlx(type_name = "release_state")]
+pub enum ReleaseState {
+  Pending,
+  Running,
+  Succeeded,
+  Failed,
+}
+
+#[derive(Debug, Serialize, ToSchema, IntoResponses)]
+#[response(status = ACCEPTED)]
+#[serde(rename_all = "camelCase")]
+pub struct Release {
+  #[serde(rename = "id")]
+  pub release_id: Uuid,
+  pub state: ReleaseState,
+  pub error: Option<String>,
+  #[serde(with = "ts_milliseconds")]
+  #[schema(value_type = i64)]
+  pub released_at: NaiveDateTime,
+  pub user_id: Uuid,
+  pub project_id: Uuid,
+}
+impl_json_response!(Release, actix_web::http::StatusCode::ACCEPTED);
+
+#[derive(Debug, From, Serialize, IntoResponses)]
+#[response(status = OK)]
+pub struct ReleasesList(#[to_schema] Vec<Release>);
+impl_json_response!(ReleasesList);
+
+#[derive(Debug, Serialize, ToSchema, IntoResponses)]
+#[response(status = OK)]
+#[serde(rename_all = "camelCase")]
+pub struct ReleaseDetails {
+  #[serde(rename = "id")]
+  pub release_id: Uuid,
+  pub state: ReleaseState,
+  pub error: Option<String>,
+  #[schema(value_type = Vec<AppService>)]
+  pub apps: Json<Vec<AppService>>,
+  #[schema(value_type = Vec<CronJob>)]
+  pub cron_jobs: Json<Vec<CronJob>>,
+  #[schema(value_type = Vec<EnvVar>)]
+  pub envs: Json<Vec<EnvVar>>,
+  #[schema(value_type = Vec<Volume>)]
+  pub volumes: Json<Vec<Volume>>,
+  #[schema(value_type = Vec<ReleaseHook>)]
+  pub hooks: Json<Vec<ReleaseHook>>,
+  #[serde(with = "ts_milliseconds")]
+  #[schema(value_type = i64)]
+  pub released_at: NaiveDateTime,
+  pub user_id: Uuid,
+  pub project_id: Uuid,
+}
+impl_json_response!(ReleaseDetails);
+
+/// Outcome of the pre-deploy command of an app, the release stopped at the first one failing
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReleaseHook {
+  pub app_id: Uuid,
+  /// Job the command ran in
+  pub name: String,
+  pub command: Vec<String>,
+  pub succeeded: bool,
+  pub exit_code: Option<i32>,
+  pub error: Option<String>,
+  pub logs: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ReleasePath {
+  pub project_id: Uuid,
+  pub release_id: Uuid,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub struct ReleaseOptions {
+  /// Wait for every app to become available before the release succeeds, the apps are rolled
+  /// back to the last successful release when one does not
+  #[serde(default)]
+  pub wait_for_healthy: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+pub enum PlanAction {
+  Create,
+  Patch,
+  Delete,
+  Unchanged,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceChange {
+  pub kind: String,
+  pub name: String,
+  pub action: PlanAction,
+}
+
+#[derive(Debug, From, Serialize, IntoResponses)]
+#[response(status = OK)]
+pub struct ReleasePlan(#[to_schema] Vec<ResourceChange>);
+impl_json_response!(ReleasePlan);
+
+/// Progress of a release job, sent for every reconciled resource and once more with the outcome
+#[derive(Debug, Clone, Serialize, ToSchema, IntoResponses)]
+#[response(status = 200, content_type = "text/event-stream")]
+#[serde(rename_all = "camelCase")]
+pub struct ReleaseEvent {
+  pub state: ReleaseState,
+  /// Kind of the reconciled resource, or of the one the release failed on
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub kind: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub action: Option<PlanAction>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+}
+
+impl ReleaseEvent {
+  pub fn succeeded() -> Self {
+    Self {
+      state: ReleaseState::Succeeded,
+      kind: None,
+      name: None,
+      action: None,
+      error: None,
+    }
+  }
+
+  pub fn failed(kind: Option<String>, name: Option<String>, error: String) -> Self {
+    Self {
+      state: ReleaseState::Failed,
+      kind,
+      name,
+      action: None,
+      error: Some(error),
+    }
+  }
+
+  /// Outcome of a finished release, `None` while it is still running
+  pub fn outcome(release: &Release) -> Option<Self> {
+    match release.state {
+      ReleaseState::Succeeded => Some(Self::succeeded()),
+      ReleaseState::Failed => Some(Self::failed(
+        None,
+        None,
+        release.error.clone().unwrap_or_default(),
+      )),
+      ReleaseState::Pending | ReleaseState::Running => None,
+    }
+  }
+}
+
+impl From<ResourceChange> for ReleaseEvent {
+  fn from(change: ResourceChange) -> Self {
+    Self {
+      state: ReleaseState::Running,
+      kind: Some(change.kind),
+      name: Some(change.name),
+      action: Some(change.action),
+      error: None,
+    }
+  }
+}
@@ -0,0 +1,70 @@
+use chrono::{
+  naive::serde::{ts_milliseconds, ts_milliseconds_option},
+  NaiveDateTime,
+};
+use derive_more::derive::From;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, IntoResponses, ToSchema};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::impl_json_response;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema, sqlx::Type)]
+#[sqlx(type_name = "run_state")]
+pub enum RunState {
+  Running,
+  Succeeded,
+  Failed,
+}
+
+/// One-off run of a command with the released image, envs and volume of an app
+#[derive(Debug, Clone, Serialize, ToSchema, IntoResponses)]
+#[response(status = OK)]
+#[serde(rename_all = "camelCase")]
+pub struct Run {
+  #[serde(rename = "id")]
+  pub run_id: Uuid,
+  pub command: Vec<String>,
+  pub state: RunState,
+  /// Exit code of the command, missing while it runs or when it never started
+  pub exit_code: Option<i32>,
+  pub error: Option<String>,
+  #[serde(with = "ts_milliseconds")]
+  #[schema(value_type = i64)]
+  pub started_at: NaiveDateTime,
+  #[serde(with = "ts_milliseconds_option")]
+  #[schema(value_type = Option<i64>)]
+  pub finished_at: Option<NaiveDateTime>,
+  pub app_id: Uuid,
+  pub user_id: Uuid,
+}
+impl_json_response!(Run);
+
+#[derive(Debug, From, Serialize, IntoResponses)]
+#[response(status = OK)]
+pub struct RunsList(#[to_schema] Vec<Run>);
+impl_json_response!(RunsList);
+
+#[derive(Debug, Serialize, ToSchema, IntoResponses)]
+#[response(status = OK)]
+pub struct RunLogs {
+  /// Output of the command so far, the whole output once the run finished
+  pub logs: String,
+}
+impl_json_response!(RunLogs);
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct RunPath {
+  pub project_id: Uuid,
+  pub app_id: Uuid,
+  pub run_id: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct RunSchema {
+  /// Replaces the entrypoint of the image
+  #[schema(min_items = 1, max_items = 64)]
+  #[validate(length(min = 1, max = 64))]
+  pub command: Vec<String>,
+}
@@ -7,7 +7,7 @@ use validator::Validate;
 
 use crate::{impl_json_response, partial_schema};
 
-#[derive(Debug, Default, Serialize, ToSchema, IntoResponses)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, ToSchema, IntoResponses)]
 #[response(status = OK)]
 #[serde(rename_all = "camelCase")]
 pub struct Volume {
@@ -16,14 +16,77 @@ pub struct Volume {
   #[serde(rename = "name")]
   pub volume_name: String,
   pub capacity: i32,
+  /// Path the cron job mounts the volume at, and the default one of the apps
   pub path: String,
   pub deleted: bool,
+  #[serde(default)]
+  pub access_mode: AccessMode,
+  /// Apps mounting the volume, each at its own path
+  #[serde(default)]
+  #[schema(value_type = Vec<VolumeApp>)]
+  pub apps: VolumeApps,
   #[serde(skip_serializing_if = "Option::is_none", default)]
-  pub app_id: Option<Uuid>,
+  pub cron_job_id: Option<Uuid>,
   pub project_id: Uuid,
 }
 impl_json_response!(Volume);
 
+impl Volume {
+  /// Path the app mounts the volume at, when it mounts it
+  pub fn app_path(&self, app_id: &Uuid) -> Option<&str> {
+    self
+      .apps
+      .0
+      .iter()
+      .find(|app| app.id == *app_id)
+      .map(|app| app.path.as_str())
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct VolumeApp {
+  pub id: Uuid,
+  pub path: String,
+}
+
+/// The apps mounting a volume, aggregated from its `volume_apps` rows as a json array
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct VolumeApps(pub Vec<VolumeApp>);
+
+/// The aggregated `volume_apps` rows only ever hold apps and paths validated by the api
+impl From<serde_json::Value> for VolumeApps {
+  fn from(value: serde_json::Value) -> Self {
+    serde_json::from_value(value).expect("Invalid volume apps")
+  }
+}
+
+/// How many pods can mount the claim of a volume at once
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum AccessMode {
+  /// Mounted by the single replica of a single app, or by a single cron job
+  #[default]
+  ReadWriteOnce,
+  /// Mounted by any number of apps and replicas, it needs a storage class that supports it
+  ReadWriteMany,
+}
+
+impl AccessMode {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      AccessMode::ReadWriteOnce => "ReadWriteOnce",
+      AccessMode::ReadWriteMany => "ReadWriteMany",
+    }
+  }
+}
+
+/// The `access_mode` column only ever holds the modes of [`AccessMode`]
+impl From<String> for AccessMode {
+  fn from(value: String) -> Self {
+    serde_json::from_value(serde_json::Value::String(value)).expect("Invalid access mode")
+  }
+}
+
 #[derive(Debug, From, Serialize, IntoResponses)]
 #[response(status = OK)]
 pub struct VolumesList(#[to_schema] Vec<Volume>);
@@ -35,8 +98,18 @@ pub struct VolumePath {
   pub volume_id: Uuid,
 }
 
-#[derive(Debug, Serialize, Deserialize, ToSchema)]
-pub struct VolumeAppId {
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct VolumeAppSchema {
+  pub id: Uuid,
+  /// Path the app mounts the volume at, the path of the volume when missing
+  #[schema(min_length = 1, pattern = r"^/([a-zA-Z0-9.\-_/])*")]
+  #[validate(regex(path = Regex::new(r"^/([a-zA-Z0-9.\-_/])*").unwrap()))]
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub path: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct VolumeCronJobId {
   pub id: Option<Uuid>,
 }
 
@@ -57,6 +130,16 @@ partial_schema! {
     )]
     #[validate(regex(path = Regex::new(r"^/([a-zA-Z0-9.\-_/])*").unwrap()))]
     pub path: String,
-    pub app: VolumeAppId,
+    /// Fixed once the volume is created
+    #[serde(default)]
+    pub access_mode: AccessMode,
+    /// Apps mounting the volume, a single one unless it is ReadWriteMany
+    #[serde(default)]
+    #[schema(max_items = 64)]
+    #[validate(nested, length(max = 64))]
+    pub apps: Vec<VolumeAppSchema>,
+    /// Cron job mounting the volume, a ReadWriteOnce volume mounted by an app can't be mounted by a cron job
+    #[serde(default)]
+    pub cron_job: VolumeCronJobId,
   }
 }
@@ -0,0 +1,156 @@
+use actix_web::{
+  http::StatusCode,
+  test::{self, TestRequest},
+};
+use api::{
+  create_app, schemas::User, Cluster, ImageRegistry, MemoryBackend, MemoryRegistry, API_KEY,
+};
+use fake::{uuid::UUIDv4, Fake};
+use k8s_openapi::api::{
+  apps::v1::Deployment,
+  core::v1::{ConfigMap, Secret, Service},
+  networking::v1::{Ingress, NetworkPolicy},
+};
+use serde_json::Value;
+
+mod utils;
+
+use utils::{database, release::release_project};
+
+const NAMESPACE: &str = "gws";
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn orphans_require_admin(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+
+  let app = test::init_service(utils::create_app(pool)).await;
+
+  let req = TestRequest::get()
+    .uri("/admin/orphans")
+    .append_header((API_KEY, session.token))
+    .to_request();
+  let res = test::try_call_service(&app, req).await;
+
+  assert_eq!(
+    res.err().unwrap().error_response().status(),
+    StatusCode::UNAUTHORIZED
+  );
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn orphans_are_reported_and_deleted(pool: sqlx::PgPool) {
+  let admin = User {
+    user_id: UUIDv4.fake(),
+    username: "admin".to_string(),
+    password: "admin".to_string(),
+    admin: true,
+  };
+  database::insert_user(&pool, &admin).await;
+  let admin_session = database::insert_valid_session(&pool, &admin.user_id).await;
+
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let kept_app = database::insert_app(&pool, &project.project_id).await;
+  let orphan_app = database::insert_app(&pool, &project.project_id).await;
+  let other_project = database::insert_project(&pool, &user.user_id).await;
+  database::insert_app(&pool, &other_project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool.clone(),
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  release_project(&app, &project.project_id, &session.token).await;
+  release_project(&app, &other_project.project_id, &session.token).await;
+
+  // rows removed without going through a release leave their objects behind
+  sqlx::query!(
+    "DELETE FROM app_services WHERE app_id = $1",
+    orphan_app.app_id
+  )
+  .execute(&pool)
+  .await
+  .unwrap();
+  sqlx::query!(
+    "DELETE FROM projects WHERE project_id = $1",
+    other_project.project_id
+  )
+  .execute(&pool)
+  .await
+  .unwrap();
+
+  let orphans = |req: TestRequest| {
+    req
+      .uri("/admin/orphans")
+      .append_header((API_KEY, admin_session.token.clone()))
+      .to_request()
+  };
+
+  let body: Value = test::call_and_read_body_json(&app, orphans(TestRequest::get())).await;
+  let mut reported: Vec<(String, String)> = body
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|orphan| {
+      (
+        orphan["kind"].as_str().unwrap().to_string(),
+        orphan["projectId"].as_str().unwrap().to_string(),
+      )
+    })
+    .collect();
+  reported.sort();
+
+  let project_id = project.project_id.to_string();
+  let other_project_id = other_project.project_id.to_string();
+  let mut expected = vec![
+    ("CorednsOverride".to_string(), other_project_id.clone()),
+    ("Deployment".to_string(), project_id.clone()),
+    ("Deployment".to_string(), other_project_id.clone()),
+    ("Ingress".to_string(), project_id.clone()),
+    ("Ingress".to_string(), other_project_id.clone()),
+    ("NetworkPolicy".to_string(), other_project_id.clone()),
+    ("Secret".to_string(), project_id.clone()),
+    ("Secret".to_string(), other_project_id.clone()),
+    ("Service".to_string(), project_id.clone()),
+    ("Service".to_string(), other_project_id.clone()),
+  ];
+  expected.sort();
+  assert_eq!(reported, expected);
+
+  // reporting leaves the cluster untouched
+  assert_eq!(memory.objects::<Deployment>().len(), 3);
+
+  let res = test::call_service(&app, orphans(TestRequest::delete())).await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  let deploys = memory.objects::<Deployment>();
+  assert_eq!(deploys.len(), 1);
+  assert_eq!(
+    deploys[0].metadata.name,
+    Some(format!("app-{}", kept_app.app_id))
+  );
+  assert_eq!(memory.objects::<Secret>().len(), 1);
+  assert_eq!(memory.objects::<Service>().len(), 1);
+  assert_eq!(memory.objects::<Ingress>().len(), 1);
+  assert!(memory
+    .object::<NetworkPolicy>(NAMESPACE, &format!("project-{}", project.project_id))
+    .is_some());
+  assert_eq!(memory.objects::<NetworkPolicy>().len(), 1);
+
+  let coredns_custom = memory
+    .object::<ConfigMap>("kube-system", "coredns-custom")
+    .unwrap();
+  let overrides: Vec<String> = coredns_custom.data.unwrap().into_keys().collect();
+  assert_eq!(
+    overrides,
+    vec![format!("project-{}.override", project.project_id)]
+  );
+
+  let body: Value = test::call_and_read_body_json(&app, orphans(TestRequest::get())).await;
+  assert_eq!(body, Value::Array(vec![]));
+}
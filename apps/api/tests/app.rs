@@ -0,0 +1,833 @@
+use actix_web::{
+  body::MessageBody,
+  dev::ServiceResponse,
+  http::StatusCode,
+  test::{self, TestRequest},
+};
+use api::{create_app, Cluster, ImageRegistry, MemoryBackend, MemoryRegistry, API_KEY};
+use k8s_openapi::api::{
+  apps::v1::Deployment,
+  autoscaling::v2::HorizontalPodAutoscaler,
+  core::v1::{ConfigMap, Service},
+  networking::v1::Ingress,
+};
+use serde_json::{json, Value};
+
+mod utils;
+
+use utils::{database, release::release_project};
+
+const NAMESPACE: &str = "gws";
+
+/// The request failed with a message naming the rule it broke
+async fn assert_rejected<B: MessageBody>(res: ServiceResponse<B>, message: &str) {
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+  let body: Value = test::read_body_json(res).await;
+  assert!(
+    body["message"].as_str().unwrap().contains(message),
+    "{body}"
+  );
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn app_resources_default(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+
+  let app = test::init_service(utils::create_app(pool)).await;
+
+  let req = TestRequest::post()
+    .uri(&format!("/projects/{}/apps", project.project_id))
+    .append_header((API_KEY, session.token))
+    .set_json(json!({
+      "name": "web",
+      "replicas": 1,
+      "image": "nginx:latest",
+      "port": 80,
+      "publicDomain": {},
+      "privateDomain": {},
+      // the default request would be above this limit
+      "memoryLimit": 64
+    }))
+    .to_request();
+  let body: Value = test::call_and_read_body_json(&app, req).await;
+
+  assert_eq!(body["cpuRequest"], 100);
+  assert_eq!(body["cpuLimit"], 500);
+  assert_eq!(body["memoryRequest"], 64);
+  assert_eq!(body["memoryLimit"], 64);
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn app_resources_are_validated(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let app = test::init_service(utils::create_app(pool)).await;
+
+  let update = |body: Value| {
+    TestRequest::patch()
+      .uri(&format!(
+        "/projects/{}/apps/{}",
+        project.project_id, app_service.app_id
+      ))
+      .append_header((API_KEY, session.token.clone()))
+      .set_json(body)
+      .to_request()
+  };
+
+  let res = test::call_service(&app, update(json!({ "cpuLimit": 100000 }))).await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  // the request is checked against the stored limit
+  let res = test::call_service(&app, update(json!({ "cpuRequest": 1000 }))).await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  let body: Value = test::call_and_read_body_json(
+    &app,
+    update(json!({ "cpuRequest": 1000, "cpuLimit": 2000 })),
+  )
+  .await;
+  assert_eq!(body["cpuRequest"], 1000);
+  assert_eq!(body["cpuLimit"], 2000);
+  assert_eq!(body["memoryLimit"], 256);
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn app_resources_are_released(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+  sqlx::query!(
+    "UPDATE app_services SET cpu_limit = 2000, memory_limit = 1500 WHERE app_id = $1",
+    app_service.app_id
+  )
+  .execute(&pool)
+  .await
+  .unwrap();
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let deploy = memory
+    .object::<Deployment>(NAMESPACE, &format!("app-{}", app_service.app_id))
+    .unwrap();
+  let container = &deploy.spec.unwrap().template.spec.unwrap().containers[0];
+
+  assert_eq!(
+    serde_json::to_value(&container.resources).unwrap(),
+    json!({
+      "requests": { "cpu": "100m", "memory": "128M" },
+      "limits": { "cpu": "2", "memory": "1500M" }
+    })
+  );
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn autoscaling_is_reconciled(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let update = |body: Value| {
+    TestRequest::patch()
+      .uri(&format!(
+        "/projects/{}/apps/{}",
+        project.project_id, app_service.app_id
+      ))
+      .append_header((API_KEY, session.token.clone()))
+      .set_json(body)
+      .to_request()
+  };
+
+  let res = test::call_service(
+    &app,
+    update(json!({ "autoscaling": { "minReplicas": 5, "maxReplicas": 2 } })),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  let body: Value = test::call_and_read_body_json(
+    &app,
+    update(json!({
+      "autoscaling": { "minReplicas": 2, "maxReplicas": 6, "targetCpuUtilization": 70 }
+    })),
+  )
+  .await;
+  assert_eq!(body["maxReplicas"], 6);
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let name = format!("app-{}", app_service.app_id);
+
+  let hpa = memory
+    .object::<HorizontalPodAutoscaler>(NAMESPACE, &name)
+    .expect("Autoscaler not applied");
+  let spec = hpa.spec.unwrap();
+  assert_eq!(spec.scale_target_ref.name, name);
+  assert_eq!((spec.min_replicas, spec.max_replicas), (Some(2), 6));
+  assert_eq!(
+    serde_json::to_value(spec.metrics).unwrap(),
+    json!([{
+      "type": "Resource",
+      "resource": {
+        "name": "cpu",
+        "target": { "type": "Utilization", "averageUtilization": 70 }
+      }
+    }])
+  );
+
+  let deploy = memory.object::<Deployment>(NAMESPACE, &name).unwrap();
+  assert_eq!(deploy.spec.unwrap().replicas, None);
+
+  // an empty block turns autoscaling off
+  let body: Value = test::call_and_read_body_json(&app, update(json!({ "autoscaling": {} }))).await;
+  assert!(body.get("maxReplicas").is_none());
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  assert!(memory
+    .object::<HorizontalPodAutoscaler>(NAMESPACE, &name)
+    .is_none());
+  let deploy = memory.object::<Deployment>(NAMESPACE, &name).unwrap();
+  assert_eq!(deploy.spec.unwrap().replicas, Some(2));
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn probes_are_released(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let update = |body: Value| {
+    TestRequest::patch()
+      .uri(&format!(
+        "/projects/{}/apps/{}",
+        project.project_id, app_service.app_id
+      ))
+      .append_header((API_KEY, session.token.clone()))
+      .set_json(body)
+      .to_request()
+  };
+
+  // only readiness probes may need more than one success
+  let res = test::call_service(
+    &app,
+    update(json!({
+      "probes": { "liveness": { "type": "Tcp", "successThreshold": 2 } }
+    })),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  let res = test::call_service(
+    &app,
+    update(json!({ "probes": { "readiness": { "type": "Http", "path": "health" } } })),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  let probes = json!({
+    "liveness": { "type": "Exec", "command": ["cat", "/tmp/alive"], "periodSeconds": 5 },
+    "readiness": { "type": "Http", "path": "/health", "successThreshold": 2 },
+    "startup": { "type": "Tcp", "port": 9000, "failureThreshold": 30 }
+  });
+  let body: Value = test::call_and_read_body_json(&app, update(json!({ "probes": probes }))).await;
+  assert_eq!(body["probes"], probes);
+
+  // other changes keep the probes
+  let body: Value = test::call_and_read_body_json(&app, update(json!({ "replicas": 3 }))).await;
+  assert_eq!(body["probes"], probes);
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let deploy = memory
+    .object::<Deployment>(NAMESPACE, &format!("app-{}", app_service.app_id))
+    .unwrap();
+  let container =
+    serde_json::to_value(&deploy.spec.unwrap().template.spec.unwrap().containers[0]).unwrap();
+
+  assert_eq!(
+    container["livenessProbe"],
+    json!({ "exec": { "command": ["cat", "/tmp/alive"] }, "periodSeconds": 5 })
+  );
+  assert_eq!(
+    container["readinessProbe"],
+    json!({ "httpGet": { "path": "/health", "port": "app" }, "successThreshold": 2 })
+  );
+  assert_eq!(
+    container["startupProbe"],
+    json!({ "tcpSocket": { "port": 9000 }, "failureThreshold": 30 })
+  );
+
+  // an empty object removes them
+  let body: Value = test::call_and_read_body_json(&app, update(json!({ "probes": {} }))).await;
+  assert_eq!(body["probes"], json!({}));
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn strategy_is_released(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let update = |body: Value| {
+    TestRequest::patch()
+      .uri(&format!(
+        "/projects/{}/apps/{}",
+        project.project_id, app_service.app_id
+      ))
+      .append_header((API_KEY, session.token.clone()))
+      .set_json(body)
+      .to_request()
+  };
+
+  // a rollout needs room to either surge or take replicas down
+  let res = test::call_service(
+    &app,
+    update(json!({
+      "strategy": { "type": "RollingUpdate", "maxSurge": 0, "maxUnavailable": "0%" }
+    })),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  let res = test::call_service(
+    &app,
+    update(json!({ "strategy": { "type": "Canary", "weight": 0 } })),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  let strategy = json!({
+    "type": "RollingUpdate",
+    "maxSurge": "50%",
+    "maxUnavailable": 0,
+    "progressDeadlineSeconds": 600
+  });
+  let body: Value =
+    test::call_and_read_body_json(&app, update(json!({ "strategy": strategy }))).await;
+  assert_eq!(body["strategy"], strategy);
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let name = format!("app-{}", app_service.app_id);
+
+  let spec = memory
+    .object::<Deployment>(NAMESPACE, &name)
+    .unwrap()
+    .spec
+    .unwrap();
+  assert_eq!(spec.progress_deadline_seconds, Some(600));
+  assert_eq!(
+    serde_json::to_value(spec.strategy).unwrap(),
+    json!({ "type": "RollingUpdate", "rollingUpdate": { "maxSurge": "50%", "maxUnavailable": 0 } })
+  );
+
+  let body: Value =
+    test::call_and_read_body_json(&app, update(json!({ "strategy": { "type": "Recreate" } })))
+      .await;
+  assert_eq!(body["strategy"], json!({ "type": "Recreate" }));
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let spec = memory
+    .object::<Deployment>(NAMESPACE, &name)
+    .unwrap()
+    .spec
+    .unwrap();
+  assert_eq!(spec.progress_deadline_seconds, Some(120));
+  assert_eq!(
+    serde_json::to_value(spec.strategy).unwrap(),
+    json!({ "type": "Recreate" })
+  );
+
+  // blue/green and canary releases need a single deployment to scale
+  let res = test::call_service(
+    &app,
+    update(json!({ "autoscaling": { "minReplicas": 1, "maxReplicas": 3 } })),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  let res = test::call_service(&app, update(json!({ "strategy": { "type": "BlueGreen" } }))).await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+  let body: Value = test::read_body_json(res).await;
+  assert_eq!(
+    body["message"],
+    "An autoscaled app is limited to the RollingUpdate and Recreate strategies"
+  );
+
+  let res = test::call_service(
+    &app,
+    update(json!({
+      "autoscaling": { "minReplicas": 1, "maxReplicas": 3 },
+      "strategy": { "type": "Canary", "weight": 20 }
+    })),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+  let body: Value = test::read_body_json(res).await;
+  assert_eq!(body["kind"], "Validation");
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn command_is_released(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let update = |body: Value| {
+    TestRequest::patch()
+      .uri(&format!(
+        "/projects/{}/apps/{}",
+        project.project_id, app_service.app_id
+      ))
+      .append_header((API_KEY, session.token.clone()))
+      .set_json(body)
+      .to_request()
+  };
+
+  let res = test::call_service(&app, update(json!({ "workingDir": "app" }))).await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  let body: Value = test::call_and_read_body_json(
+    &app,
+    update(json!({
+      "command": ["python", "-m"],
+      "args": ["worker", "--queue", "emails"],
+      "workingDir": "/srv/app"
+    })),
+  )
+  .await;
+  assert_eq!(body["command"], json!(["python", "-m"]));
+
+  // other changes keep them
+  let body: Value = test::call_and_read_body_json(&app, update(json!({ "replicas": 1 }))).await;
+  assert_eq!(body["args"], json!(["worker", "--queue", "emails"]));
+  assert_eq!(body["workingDir"], "/srv/app");
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let name = format!("app-{}", app_service.app_id);
+  let container = |memory: &MemoryBackend| {
+    let deploy = memory.object::<Deployment>(NAMESPACE, &name).unwrap();
+    deploy
+      .spec
+      .unwrap()
+      .template
+      .spec
+      .unwrap()
+      .containers
+      .remove(0)
+  };
+
+  let released = container(&memory);
+  assert_eq!(
+    released.command,
+    Some(vec!["python".to_string(), "-m".to_string()])
+  );
+  assert_eq!(released.args.unwrap().len(), 3);
+  assert_eq!(released.working_dir.as_deref(), Some("/srv/app"));
+
+  // empty values restore the defaults of the image
+  let body: Value = test::call_and_read_body_json(
+    &app,
+    update(json!({ "command": [], "args": [], "workingDir": "" })),
+  )
+  .await;
+  assert!(body.get("command").is_none());
+  assert!(body.get("workingDir").is_none());
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let released = container(&memory);
+  assert_eq!(
+    (released.command, released.args, released.working_dir),
+    (None, None, None)
+  );
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn workers_run_without_endpoint(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let create = |body: Value| {
+    TestRequest::post()
+      .uri(&format!("/projects/{}/apps", project.project_id))
+      .append_header((API_KEY, session.token.clone()))
+      .set_json(body)
+      .to_request()
+  };
+  let update = |body: Value| {
+    TestRequest::patch()
+      .uri(&format!(
+        "/projects/{}/apps/{}",
+        project.project_id, app_service.app_id
+      ))
+      .append_header((API_KEY, session.token.clone()))
+      .set_json(body)
+      .to_request()
+  };
+  let worker = |extra: Value| {
+    let mut body = json!({
+      "name": "queue",
+      "replicas": 1,
+      "image": "worker:latest",
+      "kind": "Worker",
+      "publicDomain": {},
+      "privateDomain": {}
+    });
+    body
+      .as_object_mut()
+      .unwrap()
+      .extend(extra.as_object().unwrap().clone());
+    body
+  };
+
+  // web apps need a port, workers can't be reached
+  let res = test::call_service(&app, create(worker(json!({ "kind": "Web" })))).await;
+  assert_rejected(res, "A web app needs a port").await;
+
+  let res = test::call_service(&app, create(worker(json!({ "port": 8080 })))).await;
+  assert_rejected(res, "A worker can't have a port or domains").await;
+
+  let res = test::call_service(
+    &app,
+    create(worker(json!({ "privateDomain": { "subdomain": "queue" } }))),
+  )
+  .await;
+  assert_rejected(res, "A worker can't have a port or domains").await;
+
+  let res = test::call_service(
+    &app,
+    create(worker(
+      json!({ "probes": { "liveness": { "type": "Tcp" } } }),
+    )),
+  )
+  .await;
+  assert_rejected(res, "The Http and Tcp probes of a worker need a port").await;
+
+  let body: Value = test::call_and_read_body_json(&app, create(worker(json!({})))).await;
+  assert_eq!(body["kind"], "Worker");
+  assert!(body.get("port").is_none());
+
+  // a web app keeps its domains until they are removed with the port
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let name = format!("app-{}", app_service.app_id);
+  assert!(memory.object::<Service>(NAMESPACE, &name).is_some());
+
+  // the database checks the fields left out against the current ones
+  let res = test::call_service(&app, update(json!({ "kind": "Worker" }))).await;
+  assert_rejected(res, "A worker can't have a port or domains").await;
+
+  let body: Value = test::call_and_read_body_json(
+    &app,
+    update(json!({ "kind": "Worker", "publicDomain": {}, "privateDomain": {} })),
+  )
+  .await;
+  assert_eq!(body["kind"], "Worker");
+  assert!(body.get("port").is_none());
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  assert!(memory.object::<Service>(NAMESPACE, &name).is_none());
+  assert!(memory.object::<Ingress>(NAMESPACE, &name).is_none());
+
+  let container = memory
+    .object::<Deployment>(NAMESPACE, &name)
+    .unwrap()
+    .spec
+    .unwrap()
+    .template
+    .spec
+    .unwrap()
+    .containers
+    .remove(0);
+  assert_eq!((container.env, container.ports), (None, None));
+
+  // going back to a web app needs a port again
+  let res = test::call_service(&app, update(json!({ "kind": "Web" }))).await;
+  assert_rejected(res, "A web app needs a port").await;
+
+  let res = test::call_service(&app, update(json!({ "kind": "Web", "port": 3000 }))).await;
+  assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn ports_are_released(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let update = |body: Value| {
+    TestRequest::patch()
+      .uri(&format!(
+        "/projects/{}/apps/{}",
+        project.project_id, app_service.app_id
+      ))
+      .append_header((API_KEY, session.token.clone()))
+      .set_json(body)
+      .to_request()
+  };
+
+  let ports = json!([
+    { "name": "grpc", "port": 50051, "protocol": "Tcp" },
+    { "name": "dns", "port": 53, "protocol": "Udp" }
+  ]);
+
+  // names are unique and the main one is taken
+  let res = test::call_service(
+    &app,
+    update(json!({ "ports": [{ "name": "app", "port": 9000 }] })),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  let res = test::call_service(
+    &app,
+    update(
+      json!({ "ports": [{ "name": "grpc", "port": 9000 }, { "name": "grpc", "port": 9001 }] }),
+    ),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  // the ingress only forwards tcp to a declared port
+  let res = test::call_service(
+    &app,
+    update(json!({ "ports": ports, "publicDomain": { "subdomain": "public", "port": "dns" } })),
+  )
+  .await;
+  assert_rejected(
+    res,
+    "The port of the public domain must be one of the Tcp ports",
+  )
+  .await;
+
+  let res = test::call_service(
+    &app,
+    update(json!({ "privateDomain": { "subdomain": "private", "port": "metrics" } })),
+  )
+  .await;
+  assert_rejected(
+    res,
+    "The port of the private domain must be one of the ports",
+  )
+  .await;
+
+  let body: Value = test::call_and_read_body_json(
+    &app,
+    update(json!({
+      "ports": ports,
+      "publicDomain": { "subdomain": "public", "port": "grpc" },
+      "privateDomain": { "subdomain": "private", "port": "dns" }
+    })),
+  )
+  .await;
+  assert_eq!(body["ports"], ports);
+  assert_eq!(
+    (&body["publicPort"], &body["privatePort"]),
+    (&json!("grpc"), &json!("dns"))
+  );
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let name = format!("app-{}", app_service.app_id);
+
+  let container = memory
+    .object::<Deployment>(NAMESPACE, &name)
+    .unwrap()
+    .spec
+    .unwrap()
+    .template
+    .spec
+    .unwrap()
+    .containers
+    .remove(0);
+  assert_eq!(
+    serde_json::to_value(container.ports).unwrap(),
+    json!([
+      { "name": "app", "containerPort": 8080, "protocol": "TCP" },
+      { "name": "grpc", "containerPort": 50051, "protocol": "TCP" },
+      { "name": "dns", "containerPort": 53, "protocol": "UDP" }
+    ])
+  );
+
+  let service = memory.object::<Service>(NAMESPACE, &name).unwrap();
+  assert_eq!(
+    serde_json::to_value(service.spec.unwrap().ports).unwrap(),
+    json!([
+      { "name": "app", "port": 8080, "protocol": "TCP", "targetPort": "app" },
+      { "name": "grpc", "port": 50051, "protocol": "TCP", "targetPort": "grpc" },
+      { "name": "dns", "port": 53, "protocol": "UDP", "targetPort": "dns" }
+    ])
+  );
+
+  let ingress = serde_json::to_value(memory.object::<Ingress>(NAMESPACE, &name).unwrap()).unwrap();
+  assert_eq!(
+    ingress["spec"]["rules"][0]["http"]["paths"][0]["backend"]["service"]["port"],
+    json!({ "name": "grpc" })
+  );
+
+  let project_override = memory
+    .object::<ConfigMap>("kube-system", "coredns-custom")
+    .unwrap()
+    .data
+    .unwrap()
+    .remove(&format!("project-{}.override", project.project_id))
+    .unwrap();
+  assert!(project_override.contains(&format!("IN SRV 0 0 53 {name}.")));
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn exposed_port_is_allocated(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+  let other_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let update = |app_id: &uuid::Uuid, body: Value| {
+    TestRequest::patch()
+      .uri(&format!("/projects/{}/apps/{app_id}", project.project_id))
+      .append_header((API_KEY, session.token.clone()))
+      .set_json(body)
+      .to_request()
+  };
+
+  // only a declared port can be exposed
+  let res = test::call_service(
+    &app,
+    update(&app_service.app_id, json!({ "expose": "dns" })),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  let body: Value = test::call_and_read_body_json(
+    &app,
+    update(
+      &app_service.app_id,
+      json!({
+        "ports": [{ "name": "dns", "port": 53, "protocol": "Udp" }],
+        "expose": "dns"
+      }),
+    ),
+  )
+  .await;
+  assert_eq!(body["expose"], "dns");
+  assert_eq!(body["exposedPort"], 30000);
+  assert_eq!(body["exposedEndpoint"], "localhost:30000");
+
+  // the next app gets the next free port
+  let body: Value = test::call_and_read_body_json(
+    &app,
+    update(&other_service.app_id, json!({ "expose": "app" })),
+  )
+  .await;
+  assert_eq!(body["exposedPort"], 30001);
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let exposed_name = format!("app-{}-exposed", app_service.app_id);
+  let service = memory.object::<Service>(NAMESPACE, &exposed_name).unwrap();
+  let spec = serde_json::to_value(service.spec.unwrap()).unwrap();
+  assert_eq!(spec["type"], "NodePort");
+  assert_eq!(
+    spec["ports"],
+    json!([{ "name": "dns", "port": 53, "nodePort": 30000, "protocol": "UDP", "targetPort": "dns" }])
+  );
+
+  // stopping the exposure frees the port for the next app asking
+  let body: Value =
+    test::call_and_read_body_json(&app, update(&app_service.app_id, json!({ "expose": "" }))).await;
+  assert_eq!(body["expose"], Value::Null);
+  assert_eq!(body["exposedPort"], Value::Null);
+  assert_eq!(body["exposedEndpoint"], Value::Null);
+
+  release_project(&app, &project.project_id, &session.token).await;
+  assert!(memory.object::<Service>(NAMESPACE, &exposed_name).is_none());
+
+  let body: Value = test::call_and_read_body_json(
+    &app,
+    update(&app_service.app_id, json!({ "expose": "dns" })),
+  )
+  .await;
+  assert_eq!(body["exposedPort"], 30000);
+}
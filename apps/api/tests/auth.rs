@@ -5,13 +5,12 @@ use actix_web::{
   test::{self, TestRequest},
 };
 use api::{
-  create_app,
   schemas::{AuthResponse, User},
   AuthSecurity, Password,
 };
 use fake::{uuid::UUIDv4, Fake};
 use serde_json::json;
-use utils::database;
+use utils::{create_app, database};
 
 #[sqlx::test(migrator = "api::MIGRATOR")]
 async fn username_already_used(pool: sqlx::PgPool) {
@@ -135,6 +134,7 @@ async fn login_wrong_password(pool: sqlx::PgPool) {
       user_id: UUIDv4.fake(),
       username: "foo".to_string(),
       password: "bar".to_string(),
+      admin: false,
     },
   )
   .await;
@@ -163,6 +163,7 @@ async fn login_wrong_username(pool: sqlx::PgPool) {
       user_id: UUIDv4.fake(),
       username: "foo".to_string(),
       password: "bar".to_string(),
+      admin: false,
     },
   )
   .await;
@@ -0,0 +1,179 @@
+use actix_web::{
+  http::StatusCode,
+  test::{self, TestRequest},
+};
+use api::{create_app, Cluster, ImageRegistry, MemoryBackend, MemoryRegistry, API_KEY};
+use k8s_openapi::api::{batch::v1::CronJob, core::v1::Secret};
+use serde_json::{json, Value};
+
+mod utils;
+
+use utils::{database, release::release_project};
+
+const NAMESPACE: &str = "gws";
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn cron_job_is_released(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool.clone(),
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let request = |req: TestRequest, path: &str| {
+    req
+      .uri(&format!("/projects/{}/{path}", project.project_id))
+      .append_header((API_KEY, session.token.clone()))
+  };
+
+  let res = test::call_service(
+    &app,
+    request(TestRequest::post(), "cron-jobs/")
+      .set_json(json!({ "name": "backup", "image": "busybox", "schedule": "every hour" }))
+      .to_request(),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  let cron_job: Value = test::call_and_read_body_json(
+    &app,
+    request(TestRequest::post(), "cron-jobs/")
+      .set_json(json!({
+        "name": "backup",
+        "image": "busybox",
+        "schedule": "*/5 * * * *",
+        "concurrencyPolicy": "Forbid",
+        "command": ["sh", "-c", "tar czf /data/backup.tgz /srv"]
+      }))
+      .to_request(),
+  )
+  .await;
+  assert_eq!(cron_job["successfulJobsHistoryLimit"], 3);
+  assert_eq!(cron_job["failedJobsHistoryLimit"], 1);
+  let cron_job_id = cron_job["id"].as_str().unwrap();
+
+  let env: Value = test::call_and_read_body_json(
+    &app,
+    request(
+      TestRequest::post(),
+      &format!("cron-jobs/{cron_job_id}/envs/"),
+    )
+    .set_json(json!({ "name": "TARGET", "value": "s3://backups" }))
+    .to_request(),
+  )
+  .await;
+  assert_eq!(env["cronJobId"], cron_job_id);
+  assert!(env.get("appId").is_none());
+
+  // a ReadWriteOnce volume is mounted by an app or a cron job, not both
+  let res = test::call_service(
+    &app,
+    request(TestRequest::post(), "volumes/")
+      .set_json(json!({
+        "name": "shared",
+        "capacity": 100,
+        "path": "/data",
+        "apps": [{ "id": app_service.app_id }],
+        "cronJob": { "id": cron_job_id }
+      }))
+      .to_request(),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  let volume: Value = test::call_and_read_body_json(
+    &app,
+    request(TestRequest::post(), "volumes/")
+      .set_json(json!({
+        "name": "data",
+        "capacity": 100,
+        "path": "/data",
+        "cronJob": { "id": cron_job_id }
+      }))
+      .to_request(),
+  )
+  .await;
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let name = format!("cronjob-{cron_job_id}");
+  let released = memory
+    .object::<CronJob>(NAMESPACE, &name)
+    .expect("CronJob not applied");
+  let spec = released.spec.unwrap();
+  assert_eq!(spec.schedule, "*/5 * * * *");
+  assert_eq!(spec.concurrency_policy.as_deref(), Some("Forbid"));
+  assert_eq!(spec.successful_jobs_history_limit, Some(3));
+
+  let template = spec.job_template.spec.unwrap().template;
+  // the pods are selected by the network policy of the project
+  assert_eq!(
+    template.metadata.unwrap().labels.unwrap()["project"],
+    project.project_id.to_string()
+  );
+
+  let pod = template.spec.unwrap();
+  let container = &pod.containers[0];
+  assert_eq!(container.image.as_deref(), Some("busybox"));
+  assert_eq!(
+    container.command,
+    Some(vec![
+      "sh".to_string(),
+      "-c".to_string(),
+      "tar czf /data/backup.tgz /srv".to_string()
+    ])
+  );
+  assert_eq!(
+    container.env_from.as_ref().unwrap()[0]
+      .secret_ref
+      .as_ref()
+      .unwrap()
+      .name,
+    Some(name.clone())
+  );
+  assert_eq!(
+    container.volume_mounts.as_ref().unwrap()[0].mount_path,
+    "/data"
+  );
+  assert_eq!(
+    pod.volumes.unwrap()[0]
+      .persistent_volume_claim
+      .as_ref()
+      .unwrap()
+      .claim_name,
+    format!("volume-{}", volume["id"].as_str().unwrap())
+  );
+
+  let secret = memory
+    .object::<Secret>(NAMESPACE, &name)
+    .expect("Secret not applied");
+  assert_eq!(secret.data.unwrap()["TARGET"].0, b"s3://backups");
+
+  let res = test::call_service(
+    &app,
+    request(TestRequest::delete(), &format!("cron-jobs/{cron_job_id}/")).to_request(),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  assert!(memory.object::<CronJob>(NAMESPACE, &name).is_none());
+  assert!(memory.object::<Secret>(NAMESPACE, &name).is_none());
+
+  let cron_jobs = sqlx::query_scalar!(
+    "SELECT COUNT(*) FROM cron_jobs WHERE project_id = $1",
+    project.project_id
+  )
+  .fetch_one(&pool)
+  .await
+  .unwrap();
+  assert_eq!(cron_jobs, Some(0));
+}
@@ -2,11 +2,11 @@ use actix_web::{
   http::StatusCode,
   test::{self, TestRequest},
 };
-use api::{create_app, API_KEY};
+use api::API_KEY;
 
 mod utils;
 
-use utils::database;
+use utils::{create_app, database};
 
 #[sqlx::test(migrator = "api::MIGRATOR")]
 async fn user_is_not_the_owner(pool: sqlx::PgPool) {
@@ -0,0 +1,309 @@
+use std::time::Duration;
+
+use actix_web::{
+  http::StatusCode,
+  test::{self, TestRequest},
+};
+use api::{
+  create_app, schemas::User, Cluster, ClusterBackend, ImageRegistry, MemoryBackend, MemoryRegistry,
+  API_KEY,
+};
+use fake::{uuid::UUIDv4, Fake};
+use k8s_openapi::api::{
+  apps::v1::Deployment,
+  core::v1::{
+    ConfigMap, LimitRange, Namespace, PersistentVolumeClaim, ResourceQuota, Secret, Service,
+  },
+  networking::v1::{Ingress, NetworkPolicy},
+};
+use kube::discovery::ApiResource;
+use serde_json::{json, Value};
+
+mod utils;
+
+use utils::{
+  database,
+  release::{release_events, release_project},
+};
+
+const SHARED_NAMESPACE: &str = "gws";
+
+/// Every test of this binary runs with the per-project namespaces, set before the config is first read
+fn enable_project_namespaces() {
+  std::env::set_var("K8S_PROJECT_NAMESPACES", "true");
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn release_applies_into_project_namespace(pool: sqlx::PgPool) {
+  enable_project_namespaces();
+
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+  let volume = database::insert_volume(&pool, &project.project_id, Some(&app_service.app_id)).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let events = release_project(&app, &project.project_id, &session.token).await;
+  assert_eq!(events.last().unwrap()["state"], "Succeeded");
+  // the namespace is reconciled before anything is generated in it
+  assert_eq!(events[0]["kind"], "Namespace");
+
+  let namespace = format!("gws-{}", project.project_id);
+  let name = format!("app-{}", app_service.app_id);
+  let project_name = format!("project-{}", project.project_id);
+
+  let created = memory
+    .object::<Namespace>("", &namespace)
+    .expect("Namespace not applied");
+  assert_eq!(
+    created.metadata.labels.unwrap().get("gws-project"),
+    Some(&project.project_id.to_string())
+  );
+
+  assert!(memory.object::<Deployment>(&namespace, &name).is_some());
+  assert!(memory.object::<Service>(&namespace, &name).is_some());
+  assert!(memory.object::<Ingress>(&namespace, &name).is_some());
+  assert_eq!(
+    memory.objects::<Secret>()[0].metadata.namespace,
+    Some(namespace.clone())
+  );
+  assert!(memory
+    .object::<PersistentVolumeClaim>(&namespace, &format!("volume-{}", volume.volume_id))
+    .is_some());
+  assert!(memory
+    .object::<Deployment>(SHARED_NAMESPACE, &name)
+    .is_none());
+
+  let net = memory
+    .object::<NetworkPolicy>(&namespace, &project_name)
+    .expect("Network policy not applied");
+  let allowed = serde_json::to_value(net.spec.unwrap().ingress.unwrap()).unwrap();
+  assert_eq!(
+    allowed[0]["from"][0]["namespaceSelector"]["matchExpressions"][0]["values"],
+    serde_json::json!(["kube-system", namespace])
+  );
+
+  // the quotas of the project, with room for the rollouts
+  let quota = memory
+    .object::<ResourceQuota>(&namespace, &project_name)
+    .expect("Resource quota not applied");
+  assert_eq!(
+    serde_json::to_value(quota.spec.unwrap().hard).unwrap(),
+    serde_json::json!({
+      "count/deployments.apps": "20",
+      "pods": "40",
+      "limits.cpu": "8",
+      "limits.memory": "16384M",
+      "requests.storage": "20G"
+    })
+  );
+  assert!(memory
+    .object::<LimitRange>(&namespace, &project_name)
+    .is_some());
+
+  let coredns_custom = memory
+    .object::<ConfigMap>("kube-system", "coredns-custom")
+    .unwrap();
+  let project_override = coredns_custom
+    .data
+    .unwrap()
+    .remove(&format!("{project_name}.override"))
+    .unwrap();
+  assert!(project_override.contains(&format!("{name}.{namespace}.svc.cluster.local")));
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn blue_green_release_fits_app_quota(pool: sqlx::PgPool) {
+  enable_project_namespaces();
+
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+  sqlx::query("UPDATE projects SET quota_apps = 1 WHERE project_id = $1")
+    .bind(project.project_id)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let update = |body: Value| {
+    TestRequest::patch()
+      .uri(&format!(
+        "/projects/{}/apps/{}",
+        project.project_id, app_service.app_id
+      ))
+      .append_header((API_KEY, session.token.clone()))
+      .set_json(body)
+      .to_request()
+  };
+
+  let res = test::call_service(&app, update(json!({ "strategy": { "type": "BlueGreen" } }))).await;
+  assert_eq!(res.status(), StatusCode::OK);
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let res = test::call_service(&app, update(json!({ "image": "nginx:next" }))).await;
+  assert_eq!(res.status(), StatusCode::OK);
+  let req = TestRequest::post()
+    .uri(&format!("/projects/{}", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let release: Value = test::call_and_read_body_json(&app, req).await;
+
+  let namespace = format!("gws-{}", project.project_id);
+  let green = format!("app-{}-green", app_service.app_id);
+  let mut deploy = loop {
+    if let Some(deploy) = memory.object::<Deployment>(&namespace, &green) {
+      break deploy;
+    }
+    tokio::time::sleep(Duration::from_millis(10)).await;
+  };
+
+  // the blue and green deployments run side by side until the switch
+  let quota = memory
+    .object::<ResourceQuota>(&namespace, &format!("project-{}", project.project_id))
+    .unwrap();
+  let deployments = quota.spec.unwrap().hard.unwrap()["count/deployments.apps"].clone();
+  assert_eq!(memory.objects::<Deployment>().len(), 2);
+  assert!(deployments.0.parse::<usize>().unwrap() >= 2);
+
+  deploy.status = serde_json::from_value(json!({
+    "conditions": [{ "type": "Progressing", "status": "True", "reason": "NewReplicaSetAvailable" }]
+  }))
+  .unwrap();
+  memory
+    .apply(
+      &ApiResource::erase::<Deployment>(&()),
+      &namespace,
+      &green,
+      serde_json::from_value(serde_json::to_value(deploy).unwrap()).unwrap(),
+    )
+    .await
+    .unwrap();
+
+  let events = release_events(
+    &app,
+    &project.project_id,
+    release["id"].as_str().unwrap(),
+    &session.token,
+  )
+  .await;
+  assert_eq!(events.last().unwrap()["state"], "Succeeded");
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn project_deletion_removes_namespace(pool: sqlx::PgPool) {
+  enable_project_namespaces();
+
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  release_project(&app, &project.project_id, &session.token).await;
+  assert_eq!(memory.objects::<Namespace>().len(), 1);
+
+  let req = TestRequest::delete()
+    .uri(&format!("/projects/{}", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let res = test::call_service(&app, req).await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  assert!(memory.objects::<Namespace>().is_empty());
+  assert!(memory.objects::<Deployment>().is_empty());
+  assert!(memory.objects::<NetworkPolicy>().is_empty());
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn objects_left_in_shared_namespace_are_orphans(pool: sqlx::PgPool) {
+  enable_project_namespaces();
+
+  let admin = User {
+    user_id: UUIDv4.fake(),
+    username: "admin".to_string(),
+    password: "admin".to_string(),
+    admin: true,
+  };
+  database::insert_user(&pool, &admin).await;
+  let admin_session = database::insert_valid_session(&pool, &admin.user_id).await;
+
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  // a deployment released before the project namespaces were enabled
+  let name = format!("app-{}", app_service.app_id);
+  let namespace = format!("gws-{}", project.project_id);
+  let deploy = memory.object::<Deployment>(&namespace, &name).unwrap();
+  memory
+    .apply(
+      &ApiResource::erase::<Deployment>(&()),
+      SHARED_NAMESPACE,
+      &name,
+      serde_json::from_value(serde_json::to_value(deploy).unwrap()).unwrap(),
+    )
+    .await
+    .unwrap();
+
+  let orphans = |req: TestRequest| {
+    req
+      .uri("/admin/orphans")
+      .append_header((API_KEY, admin_session.token.clone()))
+      .to_request()
+  };
+
+  let body: Value = test::call_and_read_body_json(&app, orphans(TestRequest::get())).await;
+  assert_eq!(
+    body,
+    serde_json::json!([{
+      "kind": "Deployment",
+      "name": name,
+      "namespace": SHARED_NAMESPACE,
+      "projectId": project.project_id,
+      "appId": app_service.app_id,
+    }])
+  );
+
+  let res = test::call_service(&app, orphans(TestRequest::delete())).await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  assert!(memory
+    .object::<Deployment>(SHARED_NAMESPACE, &name)
+    .is_none());
+  assert!(memory.object::<Deployment>(&namespace, &name).is_some());
+}
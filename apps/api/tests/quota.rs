@@ -0,0 +1,170 @@
+use actix_web::{
+  http::StatusCode,
+  test::{self, TestRequest},
+};
+use api::{schemas::User, API_KEY};
+use fake::{uuid::UUIDv4, Fake};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+mod utils;
+
+use utils::{create_app, database};
+
+async fn set_quota(pool: &sqlx::PgPool, project_id: &Uuid, apps: i32, replicas: i32, storage: i32) {
+  sqlx::query!(
+    "UPDATE projects SET quota_apps = $1, quota_replicas = $2, quota_storage = $3 WHERE project_id = $4",
+    apps,
+    replicas,
+    storage,
+    project_id
+  )
+  .execute(pool)
+  .await
+  .unwrap();
+}
+
+fn new_app(name: &str) -> Value {
+  json!({
+    "name": name,
+    "replicas": 1,
+    "image": "nginx:latest",
+    "port": 80,
+    "publicDomain": {},
+    "privateDomain": {}
+  })
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn app_quota_is_enforced(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+  set_quota(&pool, &project.project_id, 1, 3, 1000).await;
+
+  let app = test::init_service(create_app(pool)).await;
+
+  let req = TestRequest::post()
+    .uri(&format!("/projects/{}/apps", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .set_json(new_app("second"))
+    .to_request();
+  let res = test::call_service(&app, req).await;
+  assert_eq!(res.status(), StatusCode::FORBIDDEN);
+  let body: Value = test::read_body_json(res).await;
+  assert_eq!(body["kind"], "QuotaExceeded");
+
+  let update = |replicas: i32| {
+    TestRequest::patch()
+      .uri(&format!(
+        "/projects/{}/apps/{}",
+        project.project_id, app_service.app_id
+      ))
+      .append_header((API_KEY, session.token.clone()))
+      .set_json(json!({ "replicas": replicas }))
+      .to_request()
+  };
+
+  let res = test::call_service(&app, update(4)).await;
+  assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+  // the rejected change is rolled back
+  let req = TestRequest::get()
+    .uri(&format!(
+      "/projects/{}/apps/{}",
+      project.project_id, app_service.app_id
+    ))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let body: Value = test::call_and_read_body_json(&app, req).await;
+  assert_eq!(body["replicas"], 2);
+
+  let res = test::call_service(&app, update(3)).await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  // an autoscaled app counts with its maximum
+  let req = TestRequest::patch()
+    .uri(&format!(
+      "/projects/{}/apps/{}",
+      project.project_id, app_service.app_id
+    ))
+    .append_header((API_KEY, session.token.clone()))
+    .set_json(json!({ "replicas": 1, "autoscaling": { "minReplicas": 1, "maxReplicas": 4 } }))
+    .to_request();
+  let res = test::call_service(&app, req).await;
+  assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn storage_quota_is_enforced(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  // 100 MB
+  database::insert_volume(&pool, &project.project_id, None).await;
+  set_quota(&pool, &project.project_id, 10, 20, 500).await;
+
+  let app = test::init_service(create_app(pool)).await;
+
+  let create = |capacity: i32| {
+    TestRequest::post()
+      .uri(&format!("/projects/{}/volumes", project.project_id))
+      .append_header((API_KEY, session.token.clone()))
+      .set_json(json!({
+        "name": format!("data-{capacity}"),
+        "capacity": capacity,
+        "path": "/data"
+      }))
+      .to_request()
+  };
+
+  let res = test::call_service(&app, create(500)).await;
+  assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+  let res = test::call_service(&app, create(400)).await;
+  assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn quota_is_updated_by_admin(pool: sqlx::PgPool) {
+  let admin = User {
+    user_id: UUIDv4.fake(),
+    username: "admin".to_string(),
+    password: "admin".to_string(),
+    admin: true,
+  };
+  database::insert_user(&pool, &admin).await;
+  let admin_session = database::insert_valid_session(&pool, &admin.user_id).await;
+
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  set_quota(&pool, &project.project_id, 0, 20, 1000).await;
+
+  let app = test::init_service(create_app(pool)).await;
+
+  let update_quota = |token: String| {
+    TestRequest::patch()
+      .uri(&format!("/admin/projects/{}/quota", project.project_id))
+      .append_header((API_KEY, token))
+      .set_json(json!({ "apps": 1 }))
+      .to_request()
+  };
+
+  let res = test::try_call_service(&app, update_quota(session.token.clone())).await;
+  assert!(res.is_err());
+
+  let body: Value =
+    test::call_and_read_body_json(&app, update_quota(admin_session.token.clone())).await;
+  assert_eq!(body["quotaApps"], 1);
+  assert_eq!(body["quotaReplicas"], 20);
+
+  let req = TestRequest::post()
+    .uri(&format!("/projects/{}/apps", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .set_json(new_app("web"))
+    .to_request();
+  let res = test::call_service(&app, req).await;
+  assert_eq!(res.status(), StatusCode::OK);
+}
@@ -0,0 +1,139 @@
+use actix_web::{
+  http::StatusCode,
+  test::{self, TestRequest},
+};
+use api::{create_app, Cluster, ImageRegistry, MemoryBackend, MemoryRegistry, API_KEY};
+use k8s_openapi::api::{apps::v1::Deployment, core::v1::Secret};
+use serde_json::{json, Value};
+
+mod utils;
+
+use utils::{database, release::release_project};
+
+const NAMESPACE: &str = "gws";
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn registry_credentials_are_pulled_with(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let private_service = database::insert_app(&pool, &project.project_id).await;
+  let public_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool.clone(),
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let registries = |req: TestRequest, path: &str| {
+    req
+      .uri(&format!(
+        "/projects/{}/registries/{path}",
+        project.project_id
+      ))
+      .append_header((API_KEY, session.token.clone()))
+  };
+
+  let credentials = json!({ "host": "ghcr.io", "username": "acme", "token": "ghp_secret" });
+
+  let registry: Value = test::call_and_read_body_json(
+    &app,
+    registries(TestRequest::post(), "")
+      .set_json(&credentials)
+      .to_request(),
+  )
+  .await;
+  assert_eq!(registry["host"], "ghcr.io");
+  assert_eq!(registry["username"], "acme");
+  // the token is write only
+  assert!(registry.get("token").is_none());
+
+  let res = test::call_service(
+    &app,
+    registries(TestRequest::post(), "")
+      .set_json(&credentials)
+      .to_request(),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::CONFLICT);
+
+  let res = test::call_service(
+    &app,
+    registries(TestRequest::post(), "")
+      .set_json(json!({ "host": "https://ghcr.io", "username": "acme", "token": "ghp_secret" }))
+      .to_request(),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  let stored: String =
+    sqlx::query_scalar!("SELECT token FROM registries WHERE registry_host = 'ghcr.io'")
+      .fetch_one(&pool)
+      .await
+      .unwrap();
+  assert!(!stored.contains("ghp_secret"));
+
+  let req = TestRequest::patch()
+    .uri(&format!(
+      "/projects/{}/apps/{}",
+      project.project_id, private_service.app_id
+    ))
+    .append_header((API_KEY, session.token.clone()))
+    .set_json(json!({ "image": "ghcr.io/acme/api:1.0" }))
+    .to_request();
+  let res = test::call_service(&app, req).await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let secret_name = format!("project-{}-registries", project.project_id);
+  let secret = memory
+    .object::<Secret>(NAMESPACE, &secret_name)
+    .expect("Registries secret not applied");
+  assert_eq!(
+    secret.type_.as_deref(),
+    Some("kubernetes.io/dockerconfigjson")
+  );
+  let config: Value = serde_json::from_slice(&secret.data.unwrap()[".dockerconfigjson"].0).unwrap();
+  assert_eq!(
+    config,
+    json!({ "auths": { "ghcr.io": { "username": "acme", "password": "ghp_secret" } } })
+  );
+
+  let pull_secrets = |app_id| {
+    memory
+      .object::<Deployment>(NAMESPACE, &format!("app-{app_id}"))
+      .unwrap()
+      .spec
+      .unwrap()
+      .template
+      .spec
+      .unwrap()
+      .image_pull_secrets
+  };
+
+  // only the image pulled from the registry gets its credentials
+  assert_eq!(
+    serde_json::to_value(pull_secrets(private_service.app_id)).unwrap(),
+    json!([{ "name": secret_name }])
+  );
+  assert_eq!(pull_secrets(public_service.app_id), None);
+
+  let res = test::call_service(
+    &app,
+    registries(
+      TestRequest::delete(),
+      &format!("{}/", registry["id"].as_str().unwrap()),
+    )
+    .to_request(),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  assert!(memory.object::<Secret>(NAMESPACE, &secret_name).is_none());
+}
@@ -0,0 +1,1543 @@
+use std::time::Duration;
+
+use actix_web::{
+  http::StatusCode,
+  test::{self, TestRequest},
+};
+use api::{
+  check_drift, create_app, Cluster, ClusterBackend, ImageRegistry, MemoryBackend, MemoryRegistry,
+  API_KEY,
+};
+use k8s_openapi::api::{
+  apps::v1::Deployment,
+  batch::v1::Job,
+  core::v1::{ConfigMap, PersistentVolumeClaim, Pod, Secret, Service},
+  networking::v1::{Ingress, NetworkPolicy},
+};
+use kube::discovery::ApiResource;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+mod utils;
+
+use utils::{
+  database,
+  release::{app_statuses, release_events, release_project},
+};
+
+const NAMESPACE: &str = "gws";
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn release_applies_project_resources(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+  database::insert_env(&pool, &app_service.app_id, "KEY", "value").await;
+  let volume = database::insert_volume(&pool, &project.project_id, Some(&app_service.app_id)).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let events = release_project(&app, &project.project_id, &session.token).await;
+
+  assert_eq!(events.last().unwrap()["state"], "Succeeded");
+
+  let name = format!("app-{}", app_service.app_id);
+
+  let deploy = memory
+    .object::<Deployment>(NAMESPACE, &name)
+    .expect("Deployment not applied");
+  let deploy_spec = deploy.spec.unwrap();
+  // an app with a volume is forced to one replica
+  assert_eq!(deploy_spec.replicas, Some(1));
+  let container = &deploy_spec.template.spec.unwrap().containers[0];
+  assert_eq!(container.image, Some(pinned("nginx:latest")));
+
+  let secrets = memory.objects::<Secret>();
+  assert_eq!(secrets.len(), 1);
+  let secret_ref = container.env_from.as_ref().unwrap()[0]
+    .secret_ref
+    .as_ref()
+    .unwrap();
+  assert_eq!(secrets[0].metadata.name, secret_ref.name);
+  assert_eq!(
+    secrets[0].string_data.as_ref().unwrap().get("KEY"),
+    Some(&"value".to_string())
+  );
+
+  assert!(memory.object::<Service>(NAMESPACE, &name).is_some());
+
+  let ingress = memory
+    .object::<Ingress>(NAMESPACE, &name)
+    .expect("Ingress not applied");
+  let host = ingress.spec.unwrap().rules.unwrap()[0].host.clone();
+  assert_eq!(
+    host,
+    Some(format!(
+      "{}.localhost",
+      app_service.public_domain.as_deref().unwrap()
+    ))
+  );
+
+  assert!(memory
+    .object::<PersistentVolumeClaim>(NAMESPACE, &format!("volume-{}", volume.volume_id))
+    .is_some());
+
+  let project_name = format!("project-{}", project.project_id);
+
+  assert!(memory
+    .object::<NetworkPolicy>(NAMESPACE, &project_name)
+    .is_some());
+
+  let coredns_custom = memory
+    .object::<ConfigMap>("kube-system", "coredns-custom")
+    .unwrap();
+  let project_override = coredns_custom
+    .data
+    .unwrap()
+    .remove(&format!("{project_name}.override"))
+    .expect("Private domains not applied");
+  assert!(project_override.contains(app_service.private_domain.as_deref().unwrap()));
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn release_removes_deleted_app(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool.clone(),
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let name = format!("app-{}", app_service.app_id);
+  assert!(memory.object::<Deployment>(NAMESPACE, &name).is_some());
+
+  let req = TestRequest::delete()
+    .uri(&format!(
+      "/projects/{}/apps/{}",
+      project.project_id, app_service.app_id
+    ))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let res = test::call_service(&app, req).await;
+  assert!(res.status().is_success());
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  assert!(memory.object::<Deployment>(NAMESPACE, &name).is_none());
+  assert!(memory.object::<Service>(NAMESPACE, &name).is_none());
+  assert!(memory.object::<Ingress>(NAMESPACE, &name).is_none());
+  assert!(memory.objects::<Secret>().is_empty());
+
+  let row = sqlx::query!(
+    "SELECT 1 as ok FROM app_services WHERE app_id = $1",
+    app_service.app_id
+  )
+  .fetch_optional(&pool)
+  .await
+  .unwrap();
+
+  assert!(row.is_none());
+}
+
+fn plan_actions(plan: &Value) -> Vec<(&str, &str)> {
+  plan
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|change| {
+      (
+        change["kind"].as_str().unwrap(),
+        change["action"].as_str().unwrap(),
+      )
+    })
+    .collect()
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn release_plan_reports_changes(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+  let env = database::insert_env(&pool, &app_service.app_id, "KEY", "value").await;
+  database::insert_volume(&pool, &project.project_id, Some(&app_service.app_id)).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool.clone(),
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let plan = || {
+    TestRequest::get()
+      .uri(&format!("/projects/{}/release/plan", project.project_id))
+      .append_header((API_KEY, session.token.clone()))
+      .to_request()
+  };
+
+  let body: Value = test::call_and_read_body_json(&app, plan()).await;
+
+  assert_eq!(
+    plan_actions(&body),
+    vec![
+      ("NetworkPolicy", "Create"),
+      ("CorednsOverride", "Create"),
+      ("PersistentVolumeClaim", "Create"),
+      ("Secret", "Create"),
+      ("Deployment", "Create"),
+      ("Service", "Create"),
+      ("Ingress", "Create"),
+    ]
+  );
+
+  // planning never touches the cluster
+  assert!(memory.objects::<Deployment>().is_empty());
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let body: Value = test::call_and_read_body_json(&app, plan()).await;
+
+  assert!(plan_actions(&body)
+    .iter()
+    .all(|(_, action)| *action == "Unchanged"));
+
+  // releasing again keeps the secret and leaves the deployment as it is, as planned
+  let deploy_name = format!("app-{}", app_service.app_id);
+  let deploy_spec = || {
+    memory
+      .object::<Deployment>(NAMESPACE, &deploy_name)
+      .unwrap()
+      .spec
+  };
+  let released_spec = deploy_spec();
+  release_project(&app, &project.project_id, &session.token).await;
+  assert_eq!(deploy_spec(), released_spec);
+  assert_eq!(memory.objects::<Secret>().len(), 1);
+
+  sqlx::query!(
+    "UPDATE envs SET env_value = 'changed' WHERE env_id = $1",
+    env.env_id
+  )
+  .execute(&pool)
+  .await
+  .unwrap();
+
+  let body: Value = test::call_and_read_body_json(&app, plan()).await;
+  let actions = plan_actions(&body);
+
+  assert!(actions.contains(&("Secret", "Patch")));
+  assert!(actions.contains(&("Deployment", "Patch")));
+  assert!(actions.contains(&("Service", "Unchanged")));
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn release_is_recorded_in_history(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+  database::insert_env(&pool, &app_service.app_id, "KEY", "value").await;
+
+  let app = test::init_service(utils::create_app(pool)).await;
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let req = TestRequest::get()
+    .uri(&format!("/projects/{}/releases", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let releases: Value = test::call_and_read_body_json(&app, req).await;
+  let releases = releases.as_array().unwrap();
+
+  assert_eq!(releases.len(), 1);
+  assert_eq!(releases[0]["userId"], user.user_id.to_string());
+
+  let req = TestRequest::get()
+    .uri(&format!(
+      "/projects/{}/releases/{}",
+      project.project_id,
+      releases[0]["id"].as_str().unwrap()
+    ))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let release: Value = test::call_and_read_body_json(&app, req).await;
+
+  assert_eq!(release["apps"][0]["id"], app_service.app_id.to_string());
+  assert_eq!(release["envs"][0]["value"], "value");
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn rollback_restores_release(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool.clone(),
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let req = TestRequest::get()
+    .uri(&format!("/projects/{}/releases", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let releases: Value = test::call_and_read_body_json(&app, req).await;
+  let first_release = releases[0]["id"].as_str().unwrap().to_string();
+
+  sqlx::query!(
+    "UPDATE app_services SET image = 'nginx:unstable' WHERE app_id = $1",
+    app_service.app_id
+  )
+  .execute(&pool)
+  .await
+  .unwrap();
+  let new_app = database::insert_app(&pool, &project.project_id).await;
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let new_name = format!("app-{}", new_app.app_id);
+  assert!(memory.object::<Deployment>(NAMESPACE, &new_name).is_some());
+
+  let req = TestRequest::post()
+    .uri(&format!(
+      "/projects/{}/releases/{first_release}/rollback",
+      project.project_id
+    ))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let res = test::call_service(&app, req).await;
+  assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+  let rollback: Value = test::read_body_json(res).await;
+  let events = release_events(
+    &app,
+    &project.project_id,
+    rollback["id"].as_str().unwrap(),
+    &session.token,
+  )
+  .await;
+  assert_eq!(events.last().unwrap()["state"], "Succeeded");
+
+  let deploy = memory
+    .object::<Deployment>(NAMESPACE, &format!("app-{}", app_service.app_id))
+    .unwrap();
+  let container = &deploy.spec.unwrap().template.spec.unwrap().containers[0];
+  assert_eq!(container.image, Some(pinned("nginx:latest")));
+  assert!(memory.object::<Deployment>(NAMESPACE, &new_name).is_none());
+
+  let images: Vec<String> = sqlx::query_scalar!(
+    "SELECT image FROM app_services WHERE project_id = $1",
+    project.project_id
+  )
+  .fetch_all(&pool)
+  .await
+  .unwrap();
+  assert_eq!(images, vec!["nginx:latest".to_string()]);
+
+  let releases = sqlx::query_scalar!(
+    "SELECT COUNT(*) FROM releases WHERE project_id = $1",
+    project.project_id
+  )
+  .fetch_one(&pool)
+  .await
+  .unwrap();
+  assert_eq!(releases, Some(3));
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn release_streams_reconciled_resources(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let app = test::init_service(utils::create_app(pool)).await;
+
+  let events = release_project(&app, &project.project_id, &session.token).await;
+
+  let steps: Vec<(&str, &str, &str)> = events
+    .iter()
+    .filter(|event| event["state"] == "Running")
+    .map(|event| {
+      (
+        event["kind"].as_str().unwrap(),
+        event["name"].as_str().unwrap(),
+        event["action"].as_str().unwrap(),
+      )
+    })
+    .collect();
+
+  let name = format!("app-{}", app_service.app_id);
+  assert!(steps.contains(&("Deployment", name.as_str(), "Create")));
+  assert!(steps.contains(&("Service", name.as_str(), "Create")));
+  assert!(steps.contains(&("Ingress", name.as_str(), "Create")));
+  assert_eq!(events.last().unwrap()["state"], "Succeeded");
+
+  let req = TestRequest::get()
+    .uri(&format!("/projects/{}/releases", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let releases: Value = test::call_and_read_body_json(&app, req).await;
+  assert_eq!(releases[0]["state"], "Succeeded");
+
+  // a finished release only reports its outcome
+  let events = release_events(
+    &app,
+    &project.project_id,
+    releases[0]["id"].as_str().unwrap(),
+    &session.token,
+  )
+  .await;
+  assert_eq!(events.len(), 1);
+  assert_eq!(events[0]["state"], "Succeeded");
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn failed_release_reports_resource(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  sqlx::query!(
+    "UPDATE app_services SET deleted = true WHERE app_id = $1",
+    app_service.app_id
+  )
+  .execute(&pool)
+  .await
+  .unwrap();
+
+  let memory = MemoryBackend::new();
+  memory
+    .delete(
+      &ApiResource::erase::<ConfigMap>(&()),
+      "kube-system",
+      "coredns-custom",
+    )
+    .await
+    .unwrap();
+
+  let app = test::init_service(create_app(
+    pool.clone(),
+    Cluster::new(memory),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let events = release_project(&app, &project.project_id, &session.token).await;
+
+  let outcome = events.last().unwrap();
+  assert_eq!(outcome["state"], "Failed");
+  assert_eq!(outcome["kind"], "CorednsOverride");
+  assert_eq!(
+    outcome["name"],
+    format!("project-{}.override", project.project_id)
+  );
+
+  let state = sqlx::query_scalar!(
+    r#"SELECT state::TEXT as "state!" FROM releases WHERE project_id = $1"#,
+    project.project_id
+  )
+  .fetch_one(&pool)
+  .await
+  .unwrap();
+  assert_eq!(state, "Failed");
+
+  // deleted rows are kept until a release removes them from the cluster
+  let row = sqlx::query!(
+    "SELECT 1 as ok FROM app_services WHERE app_id = $1",
+    app_service.app_id
+  )
+  .fetch_optional(&pool)
+  .await
+  .unwrap();
+  assert!(row.is_some());
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn release_conflicts_with_running_release(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+
+  sqlx::query!(
+    "INSERT INTO releases(apps, envs, volumes, released_at, user_id, project_id) VALUES ('[]', '[]', '[]', NOW(), $1, $2)",
+    user.user_id,
+    project.project_id
+  )
+  .execute(&pool)
+  .await
+  .unwrap();
+
+  let app = test::init_service(utils::create_app(pool)).await;
+
+  let req = TestRequest::post()
+    .uri(&format!("/projects/{}", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let res = test::call_service(&app, req).await;
+
+  assert_eq!(res.status(), StatusCode::CONFLICT);
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn drift_is_reported(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let cluster = Cluster::new(memory.clone());
+  let app = test::init_service(create_app(
+    pool.clone(),
+    cluster.clone(),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let drift = || {
+    TestRequest::get()
+      .uri(&format!("/projects/{}/drift", project.project_id))
+      .append_header((API_KEY, session.token.clone()))
+      .to_request()
+  };
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let body: Value = test::call_and_read_body_json(&app, drift()).await;
+  assert_eq!(body, Value::Array(vec![]));
+
+  let name = format!("app-{}", app_service.app_id);
+
+  let mut deploy = memory.object::<Deployment>(NAMESPACE, &name).unwrap();
+  deploy
+    .spec
+    .as_mut()
+    .unwrap()
+    .template
+    .spec
+    .as_mut()
+    .unwrap()
+    .containers[0]
+    .image = Some("nginx:edited".to_string());
+  memory
+    .apply(
+      &ApiResource::erase::<Deployment>(&()),
+      NAMESPACE,
+      &name,
+      serde_json::from_value(serde_json::to_value(deploy).unwrap()).unwrap(),
+    )
+    .await
+    .unwrap();
+  memory
+    .delete(&ApiResource::erase::<Service>(&()), NAMESPACE, &name)
+    .await
+    .unwrap();
+
+  let body: Value = test::call_and_read_body_json(&app, drift()).await;
+  let actions = plan_actions(&body);
+  assert_eq!(
+    actions,
+    vec![("Deployment", "Patch"), ("Service", "Create")]
+  );
+
+  // drift is only reported unless the project opted in to re-apply it
+  check_drift(&pool, &cluster, project.project_id)
+    .await
+    .unwrap();
+  assert!(memory.object::<Service>(NAMESPACE, &name).is_none());
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn drift_is_reapplied(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+  let volume = database::insert_volume(&pool, &project.project_id, Some(&app_service.app_id)).await;
+
+  let memory = MemoryBackend::new();
+  let cluster = Cluster::new(memory.clone());
+  let app = test::init_service(create_app(
+    pool.clone(),
+    cluster.clone(),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let req = TestRequest::patch()
+    .uri(&format!("/projects/{}", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .set_json(serde_json::json!({ "reapplyDrift": true }))
+    .to_request();
+  let body: Value = test::call_and_read_body_json(&app, req).await;
+  assert_eq!(body["reapplyDrift"], true);
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let name = format!("app-{}", app_service.app_id);
+  let volume_name = format!("volume-{}", volume.volume_id);
+  let deploy_spec = || memory.object::<Deployment>(NAMESPACE, &name).unwrap().spec;
+  let released_spec = deploy_spec();
+
+  memory
+    .delete(&ApiResource::erase::<Service>(&()), NAMESPACE, &name)
+    .await
+    .unwrap();
+  memory
+    .delete(
+      &ApiResource::erase::<PersistentVolumeClaim>(&()),
+      NAMESPACE,
+      &volume_name,
+    )
+    .await
+    .unwrap();
+
+  check_drift(&pool, &cluster, project.project_id)
+    .await
+    .unwrap();
+
+  assert!(memory.object::<Service>(NAMESPACE, &name).is_some());
+  assert!(memory
+    .object::<PersistentVolumeClaim>(NAMESPACE, &volume_name)
+    .is_some());
+  // only the drifted objects are applied again
+  assert_eq!(deploy_spec(), released_spec);
+  assert_eq!(memory.objects::<Secret>().len(), 1);
+
+  // the re-apply is recorded as a release of the same rows
+  let req = TestRequest::get()
+    .uri(&format!("/projects/{}/releases", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let releases: Value = test::call_and_read_body_json(&app, req).await;
+  assert_eq!(releases.as_array().unwrap().len(), 2);
+  assert_eq!(releases[0]["state"], "Succeeded");
+
+  let req = TestRequest::get()
+    .uri(&format!("/projects/{}/drift", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let body: Value = test::call_and_read_body_json(&app, req).await;
+  assert_eq!(body, Value::Array(vec![]));
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn project_deletion_tears_down_resources(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+  database::insert_env(&pool, &app_service.app_id, "KEY", "value").await;
+  database::insert_volume(&pool, &project.project_id, Some(&app_service.app_id)).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool.clone(),
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  release_project(&app, &project.project_id, &session.token).await;
+  assert!(!memory.objects::<Deployment>().is_empty());
+
+  let req = TestRequest::delete()
+    .uri(&format!("/projects/{}", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let res = test::call_service(&app, req).await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  assert!(memory.objects::<Deployment>().is_empty());
+  assert!(memory.objects::<Service>().is_empty());
+  assert!(memory.objects::<Ingress>().is_empty());
+  assert!(memory.objects::<Secret>().is_empty());
+  assert!(memory.objects::<PersistentVolumeClaim>().is_empty());
+  assert!(memory.objects::<NetworkPolicy>().is_empty());
+
+  let coredns_custom = memory
+    .object::<ConfigMap>("kube-system", "coredns-custom")
+    .unwrap();
+  assert!(coredns_custom.data.unwrap().is_empty());
+
+  let row = sqlx::query!(
+    "SELECT 1 as ok FROM projects WHERE project_id = $1",
+    project.project_id
+  )
+  .fetch_optional(&pool)
+  .await
+  .unwrap();
+  assert!(row.is_none());
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn running_release_blocks_project_deletion(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool.clone(),
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let name = format!("app-{}", app_service.app_id);
+  let deploy_spec = || memory.object::<Deployment>(NAMESPACE, &name).unwrap().spec;
+
+  release_project(&app, &project.project_id, &session.token).await;
+  let released_spec = deploy_spec();
+
+  let req = TestRequest::patch()
+    .uri(&format!(
+      "/projects/{}/apps/{}",
+      project.project_id, app_service.app_id
+    ))
+    .append_header((API_KEY, session.token.clone()))
+    .set_json(json!({ "image": "nginx:next", "preDeploy": ["./migrate"] }))
+    .to_request();
+  test::call_service(&app, req).await;
+
+  // the release stays running until its pre-deploy command finishes
+  let req = TestRequest::post()
+    .uri(&format!("/projects/{}", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let release: Value = test::call_and_read_body_json(&app, req).await;
+  while memory.objects::<Job>().is_empty() {
+    tokio::time::sleep(Duration::from_millis(10)).await;
+  }
+
+  let delete = || {
+    TestRequest::delete()
+      .uri(&format!("/projects/{}", project.project_id))
+      .append_header((API_KEY, session.token.clone()))
+      .to_request()
+  };
+  let res = test::call_service(&app, delete()).await;
+  assert_eq!(res.status(), StatusCode::CONFLICT);
+
+  // a deletion marked meanwhile stops the release before its next step
+  sqlx::query!(
+    "UPDATE projects SET deleting = true WHERE project_id = $1",
+    project.project_id
+  )
+  .execute(&pool)
+  .await
+  .unwrap();
+
+  tokio::spawn(finish_pre_deploy(
+    memory.clone(),
+    app_service.app_id,
+    0,
+    "applied 0042_users\n",
+  ));
+  let events = release_events(
+    &app,
+    &project.project_id,
+    release["id"].as_str().unwrap(),
+    &session.token,
+  )
+  .await;
+
+  let outcome = events.last().unwrap();
+  assert_eq!(outcome["state"], "Failed");
+  assert!(outcome["error"]
+    .as_str()
+    .unwrap()
+    .ends_with("the project is being deleted"));
+  assert_eq!(deploy_spec(), released_spec);
+
+  let res = test::call_service(&app, delete()).await;
+  assert_eq!(res.status(), StatusCode::OK);
+  assert!(memory.objects::<Deployment>().is_empty());
+  assert!(memory.objects::<Secret>().is_empty());
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn failed_teardown_keeps_project_deleting(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool.clone(),
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let coredns_custom = ApiResource::erase::<ConfigMap>(&());
+  let config_map = memory
+    .get(&coredns_custom, "kube-system", "coredns-custom")
+    .await
+    .unwrap()
+    .unwrap();
+  memory
+    .delete(&coredns_custom, "kube-system", "coredns-custom")
+    .await
+    .unwrap();
+
+  let delete = || {
+    TestRequest::delete()
+      .uri(&format!("/projects/{}", project.project_id))
+      .append_header((API_KEY, session.token.clone()))
+      .to_request()
+  };
+
+  let res = test::call_service(&app, delete()).await;
+  assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+  let deleting = sqlx::query_scalar!(
+    "SELECT deleting FROM projects WHERE project_id = $1",
+    project.project_id
+  )
+  .fetch_one(&pool)
+  .await
+  .unwrap();
+  assert!(deleting);
+
+  // a project being deleted can not be released again
+  let req = TestRequest::post()
+    .uri(&format!("/projects/{}", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let res = test::call_service(&app, req).await;
+  assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+  memory
+    .apply(&coredns_custom, "kube-system", "coredns-custom", config_map)
+    .await
+    .unwrap();
+
+  let res = test::call_service(&app, delete()).await;
+  assert_eq!(res.status(), StatusCode::OK);
+  assert!(memory.objects::<Deployment>().is_empty());
+}
+
+/// Image the deployments of `image` run, pinned to the digest the memory registry resolves its tag to
+fn pinned(image: &str) -> String {
+  format!("{image}@{}", MemoryRegistry::new().digest(image))
+}
+
+/// Stand in for the deployment controller: report the rollout of `image` with the Progressing `reason`
+async fn finish_rollout(memory: &MemoryBackend, name: &str, image: &str, reason: &str) {
+  loop {
+    let deploy = memory.object::<Deployment>(NAMESPACE, name);
+    let rolling_out = deploy.as_ref().is_some_and(|deploy| {
+      deploy
+        .spec
+        .as_ref()
+        .unwrap()
+        .template
+        .spec
+        .as_ref()
+        .unwrap()
+        .containers[0]
+        .image
+        .as_deref()
+        == Some(pinned(image).as_str())
+    });
+
+    if let (true, Some(mut deploy)) = (rolling_out, deploy) {
+      deploy.status = serde_json::from_value(serde_json::json!({
+        "conditions": [{ "type": "Progressing", "status": "True", "reason": reason }]
+      }))
+      .unwrap();
+
+      memory
+        .apply(
+          &ApiResource::erase::<Deployment>(&()),
+          NAMESPACE,
+          name,
+          serde_json::from_value(serde_json::to_value(deploy).unwrap()).unwrap(),
+        )
+        .await
+        .unwrap();
+      return;
+    }
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+  }
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn waiting_release_succeeds_once_available(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let req = TestRequest::post()
+    .uri(&format!(
+      "/projects/{}?waitForHealthy=true",
+      project.project_id
+    ))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let release: Value = test::call_and_read_body_json(&app, req).await;
+
+  let name = format!("app-{}", app_service.app_id);
+  finish_rollout(&memory, &name, "nginx:latest", "NewReplicaSetAvailable").await;
+
+  let events = release_events(
+    &app,
+    &project.project_id,
+    release["id"].as_str().unwrap(),
+    &session.token,
+  )
+  .await;
+  assert_eq!(events.last().unwrap()["state"], "Succeeded");
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn failed_rollout_is_rolled_back(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let req = TestRequest::patch()
+    .uri(&format!(
+      "/projects/{}/apps/{}",
+      project.project_id, app_service.app_id
+    ))
+    .append_header((API_KEY, session.token.clone()))
+    .set_json(serde_json::json!({ "image": "nginx:broken" }))
+    .to_request();
+  let res = test::call_service(&app, req).await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  let req = TestRequest::post()
+    .uri(&format!(
+      "/projects/{}?waitForHealthy=true",
+      project.project_id
+    ))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let release: Value = test::call_and_read_body_json(&app, req).await;
+
+  let name = format!("app-{}", app_service.app_id);
+  finish_rollout(&memory, &name, "nginx:broken", "ProgressDeadlineExceeded").await;
+
+  let events = release_events(
+    &app,
+    &project.project_id,
+    release["id"].as_str().unwrap(),
+    &session.token,
+  )
+  .await;
+  let outcome = events.last().unwrap();
+  assert_eq!(outcome["state"], "Failed");
+  assert_eq!(outcome["kind"], "Deployment");
+  assert_eq!(outcome["name"], name);
+  assert!(outcome["error"]
+    .as_str()
+    .unwrap()
+    .contains("rolled back to the last successful release"));
+
+  let deploy = memory.object::<Deployment>(NAMESPACE, &name).unwrap();
+  assert_eq!(
+    deploy.spec.unwrap().template.spec.unwrap().containers[0].image,
+    Some(pinned("nginx:latest"))
+  );
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn blue_green_switches_once_available(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let update = |body: Value| {
+    TestRequest::patch()
+      .uri(&format!(
+        "/projects/{}/apps/{}",
+        project.project_id, app_service.app_id
+      ))
+      .append_header((API_KEY, session.token.clone()))
+      .set_json(body)
+      .to_request()
+  };
+  let selected_track = || {
+    memory
+      .object::<Service>(NAMESPACE, &format!("app-{}", app_service.app_id))
+      .unwrap()
+      .spec
+      .unwrap()
+      .selector
+      .unwrap()
+      .get("track")
+      .cloned()
+  };
+
+  let res = test::call_service(
+    &app,
+    update(serde_json::json!({ "strategy": { "type": "BlueGreen" } })),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let name = format!("app-{}", app_service.app_id);
+  assert!(memory
+    .object::<Deployment>(NAMESPACE, &format!("{name}-blue"))
+    .is_some());
+  assert_eq!(selected_track(), Some("blue".to_string()));
+
+  let statuses = app_statuses(
+    &app,
+    &project.project_id,
+    &app_service.app_id,
+    &session.token,
+    1,
+  )
+  .await;
+  assert_eq!(statuses[0]["track"], "Blue");
+
+  let res = test::call_service(&app, update(serde_json::json!({ "image": "nginx:next" }))).await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  let req = TestRequest::post()
+    .uri(&format!("/projects/{}", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let release: Value = test::call_and_read_body_json(&app, req).await;
+
+  // the blue replicas keep the traffic until the green ones are available
+  finish_rollout(
+    &memory,
+    &format!("{name}-green"),
+    "nginx:next",
+    "NewReplicaSetAvailable",
+  )
+  .await;
+
+  let events = release_events(
+    &app,
+    &project.project_id,
+    release["id"].as_str().unwrap(),
+    &session.token,
+  )
+  .await;
+  assert_eq!(events.last().unwrap()["state"], "Succeeded");
+
+  assert_eq!(selected_track(), Some("green".to_string()));
+  assert!(memory
+    .object::<Deployment>(NAMESPACE, &format!("{name}-blue"))
+    .is_none());
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn canary_takes_its_share_of_replicas(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let update = |body: Value| {
+    TestRequest::patch()
+      .uri(&format!(
+        "/projects/{}/apps/{}",
+        project.project_id, app_service.app_id
+      ))
+      .append_header((API_KEY, session.token.clone()))
+      .set_json(body)
+      .to_request()
+  };
+  let deploy = |name: &str| {
+    let spec = memory
+      .object::<Deployment>(NAMESPACE, name)
+      .map(|deploy| deploy.spec.unwrap())?;
+    let image = spec.template.spec.unwrap().containers[0].image.clone();
+    Some((spec.replicas.unwrap(), image.unwrap()))
+  };
+
+  let res = test::call_service(
+    &app,
+    update(serde_json::json!({
+      "replicas": 4,
+      "strategy": { "type": "Canary", "weight": 25 }
+    })),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  // without stable replicas the first release has nothing to compare with
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let name = format!("app-{}", app_service.app_id);
+  let canary = format!("{name}-canary");
+  assert_eq!(deploy(&name), Some((4, pinned("nginx:latest"))));
+  assert_eq!(deploy(&canary), None);
+
+  let res = test::call_service(&app, update(serde_json::json!({ "image": "nginx:next" }))).await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  let events = release_project(&app, &project.project_id, &session.token).await;
+  assert_eq!(events.last().unwrap()["state"], "Succeeded");
+
+  assert_eq!(deploy(&name), Some((3, pinned("nginx:latest"))));
+  assert_eq!(deploy(&canary), Some((1, pinned("nginx:next"))));
+  assert!(!memory
+    .object::<Service>(NAMESPACE, &name)
+    .unwrap()
+    .spec
+    .unwrap()
+    .selector
+    .unwrap()
+    .contains_key("track"));
+
+  // the stable replicas keep the secret they read their envs from
+  assert_eq!(memory.objects::<Secret>().len(), 2);
+
+  // a full weight promotes the canary
+  let res = test::call_service(
+    &app,
+    update(serde_json::json!({ "strategy": { "type": "Canary", "weight": 100 } })),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  assert_eq!(deploy(&name), Some((4, pinned("nginx:next"))));
+  assert_eq!(deploy(&canary), None);
+  assert_eq!(memory.objects::<Secret>().len(), 1);
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn image_is_pinned_to_digest(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let registry = MemoryRegistry::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(registry.clone()),
+  ))
+  .await;
+
+  let name = format!("app-{}", app_service.app_id);
+  let image = || {
+    memory
+      .object::<Deployment>(NAMESPACE, &name)
+      .unwrap()
+      .spec
+      .unwrap()
+      .template
+      .spec
+      .unwrap()
+      .containers[0]
+      .image
+      .clone()
+      .unwrap()
+  };
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let first_digest = registry.digest("nginx:latest");
+  assert_eq!(image(), format!("nginx:latest@{first_digest}"));
+
+  let req = TestRequest::get()
+    .uri(&format!(
+      "/projects/{}/apps/{}",
+      project.project_id, app_service.app_id
+    ))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let app_json: Value = test::call_and_read_body_json(&app, req).await;
+  assert_eq!(app_json["imageDigest"], first_digest.as_str());
+
+  let req = TestRequest::get()
+    .uri(&format!("/projects/{}/releases", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let releases: Value = test::call_and_read_body_json(&app, req).await;
+  let first_release = releases[0]["id"].as_str().unwrap().to_string();
+
+  // a new build pushed under the same tag is only picked up by the next release
+  registry.push("nginx:latest", "sha256:next");
+  assert_eq!(image(), format!("nginx:latest@{first_digest}"));
+
+  release_project(&app, &project.project_id, &session.token).await;
+  assert_eq!(image(), "nginx:latest@sha256:next");
+
+  // a rollback runs the build of the release it goes back to
+  let req = TestRequest::post()
+    .uri(&format!(
+      "/projects/{}/releases/{first_release}/rollback",
+      project.project_id
+    ))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let rollback: Value = test::call_and_read_body_json(&app, req).await;
+  let events = release_events(
+    &app,
+    &project.project_id,
+    rollback["id"].as_str().unwrap(),
+    &session.token,
+  )
+  .await;
+  assert_eq!(events.last().unwrap()["state"], "Succeeded");
+  assert_eq!(image(), format!("nginx:latest@{first_digest}"));
+}
+
+/// Stand in for the job controller, ending the next pre-deploy job of the app with `exit_code`
+async fn finish_pre_deploy(memory: MemoryBackend, app_id: Uuid, exit_code: i32, logs: &str) {
+  let prefix = format!("app-{app_id}-pre-deploy-");
+  let cluster = Cluster::new(memory.clone());
+
+  let mut job = loop {
+    let running = memory
+      .objects::<Job>()
+      .into_iter()
+      .find(|job| job.metadata.name.as_ref().unwrap().starts_with(&prefix) && job.status.is_none());
+
+    if let Some(job) = running {
+      break job;
+    }
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+  };
+  let name = job.metadata.name.clone().unwrap();
+  let pod_name = format!("{name}-x7k2p");
+
+  let pod: Pod = serde_json::from_value(json!({
+    "apiVersion": "v1",
+    "kind": "Pod",
+    "metadata": { "name": pod_name, "labels": { "run": name } },
+    "status": {
+      "containerStatuses": [{
+        "name": "hook",
+        "image": "nginx",
+        "imageID": "",
+        "ready": false,
+        "restartCount": 0,
+        "state": { "terminated": { "exitCode": exit_code } }
+      }]
+    }
+  }))
+  .unwrap();
+  cluster
+    .api::<Pod>(NAMESPACE)
+    .apply(&pod_name, &pod)
+    .await
+    .unwrap();
+  memory.set_logs(NAMESPACE, &pod_name, logs);
+
+  let condition = match exit_code {
+    0 => "Complete",
+    _ => "Failed",
+  };
+  job.status = serde_json::from_value(json!({
+    "conditions": [{ "type": condition, "status": "True" }]
+  }))
+  .unwrap();
+  cluster
+    .api::<Job>(NAMESPACE)
+    .apply(&name, &job)
+    .await
+    .unwrap();
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn failed_pre_deploy_keeps_deployment(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+  let other = database::insert_app(&pool, &project.project_id).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool.clone(),
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let name = format!("app-{}", app_service.app_id);
+  let container = |name: &str| {
+    memory
+      .object::<Deployment>(NAMESPACE, name)
+      .unwrap()
+      .spec
+      .unwrap()
+      .template
+      .spec
+      .unwrap()
+      .containers[0]
+      .clone()
+  };
+  let image = || container(&name).image.unwrap();
+  let secret = || {
+    container(&name).env_from.unwrap()[0]
+      .secret_ref
+      .clone()
+      .unwrap()
+      .name
+      .unwrap()
+  };
+  let secrets = || {
+    memory
+      .objects::<Secret>()
+      .into_iter()
+      .filter(|secret| secret.metadata.labels.as_ref().unwrap()["app-name"] == name)
+      .count()
+  };
+
+  release_project(&app, &project.project_id, &session.token).await;
+  assert_eq!(image(), pinned("nginx:latest"));
+  let released_secret = secret();
+
+  let req = TestRequest::patch()
+    .uri(&format!(
+      "/projects/{}/apps/{}",
+      project.project_id, app_service.app_id
+    ))
+    .append_header((API_KEY, session.token.clone()))
+    .set_json(json!({ "image": "nginx:next", "preDeploy": ["./migrate", "up"] }))
+    .to_request();
+  let app_json: Value = test::call_and_read_body_json(&app, req).await;
+  assert_eq!(app_json["preDeploy"], json!(["./migrate", "up"]));
+  // the new version reads other envs
+  database::insert_env(&pool, &app_service.app_id, "KEY", "value").await;
+
+  let req = TestRequest::patch()
+    .uri(&format!(
+      "/projects/{}/apps/{}",
+      project.project_id, other.app_id
+    ))
+    .append_header((API_KEY, session.token.clone()))
+    .set_json(json!({ "image": "nginx:next" }))
+    .to_request();
+  test::call_service(&app, req).await;
+
+  tokio::spawn(finish_pre_deploy(
+    memory.clone(),
+    app_service.app_id,
+    1,
+    "relation \"users\" already exists\n",
+  ));
+  let events = release_project(&app, &project.project_id, &session.token).await;
+
+  let outcome = events.last().unwrap();
+  assert_eq!(outcome["state"], "Failed");
+  assert_eq!(outcome["kind"], "Job");
+  // the replicas keep running the previous version, with the envs they were started with
+  assert_eq!(image(), pinned("nginx:latest"));
+  assert_eq!(secret(), released_secret);
+  assert!(memory
+    .object::<Secret>(NAMESPACE, &released_secret)
+    .is_some());
+  assert_eq!(secrets(), 1);
+  // the other apps are still released
+  assert_eq!(
+    container(&format!("app-{}", other.app_id)).image,
+    Some(pinned("nginx:next"))
+  );
+
+  // the hook ran with the new image
+  let hook = memory
+    .objects::<Job>()
+    .into_iter()
+    .find(|job| job.metadata.name.as_deref() == outcome["name"].as_str())
+    .unwrap();
+  let container = &hook.spec.unwrap().template.spec.unwrap().containers[0];
+  assert_eq!(container.image, Some(pinned("nginx:next")));
+  assert_eq!(
+    container.command,
+    Some(vec!["./migrate".to_string(), "up".to_string()])
+  );
+
+  let req = TestRequest::get()
+    .uri(&format!("/projects/{}/releases", project.project_id))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let releases: Value = test::call_and_read_body_json(&app, req).await;
+
+  let req = TestRequest::get()
+    .uri(&format!(
+      "/projects/{}/releases/{}",
+      project.project_id,
+      releases[0]["id"].as_str().unwrap()
+    ))
+    .append_header((API_KEY, session.token.clone()))
+    .to_request();
+  let release: Value = test::call_and_read_body_json(&app, req).await;
+  assert_eq!(release["hooks"][0]["succeeded"], false);
+  assert_eq!(release["hooks"][0]["exitCode"], 1);
+  assert_eq!(
+    release["hooks"][0]["logs"],
+    "relation \"users\" already exists\n"
+  );
+
+  tokio::spawn(finish_pre_deploy(
+    memory.clone(),
+    app_service.app_id,
+    0,
+    "applied 0042_users\n",
+  ));
+  let events = release_project(&app, &project.project_id, &session.token).await;
+
+  assert_eq!(events.last().unwrap()["state"], "Succeeded");
+  assert_eq!(image(), pinned("nginx:next"));
+  assert!(memory
+    .object::<Secret>(NAMESPACE, &released_secret)
+    .is_none());
+  assert_eq!(secrets(), 1);
+}
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn containers_share_app_envs_and_volume(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+  database::insert_env(&pool, &app_service.app_id, "KEY", "value").await;
+  let volume = database::insert_volume(&pool, &project.project_id, Some(&app_service.app_id)).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let update = |body: Value| {
+    TestRequest::patch()
+      .uri(&format!(
+        "/projects/{}/apps/{}",
+        project.project_id, app_service.app_id
+      ))
+      .append_header((API_KEY, session.token.clone()))
+      .set_json(body)
+      .to_request()
+  };
+  let shipper = json!({
+    "name": "shipper",
+    "image": "fluent-bit:3",
+    "env": [{ "name": "OUTPUT", "value": "loki" }],
+    "volumes": [{ "id": volume.volume_id, "path": "/logs" }]
+  });
+
+  let res = test::call_service(&app, update(json!({ "containers": [shipper, shipper] }))).await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  let res = test::call_service(
+    &app,
+    update(json!({
+      "containers": [
+        shipper,
+        { "name": "wait-db", "kind": "Init", "image": "busybox", "command": ["./wait-db"] }
+      ]
+    })),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  let events = release_project(&app, &project.project_id, &session.token).await;
+  assert_eq!(events.last().unwrap()["state"], "Succeeded");
+
+  let pod = memory
+    .object::<Deployment>(NAMESPACE, &format!("app-{}", app_service.app_id))
+    .unwrap()
+    .spec
+    .unwrap()
+    .template
+    .spec
+    .unwrap();
+  let main = &pod.containers[0];
+
+  let init = pod.init_containers.as_ref().unwrap();
+  assert_eq!(init.len(), 1);
+  assert_eq!(init[0].name, "wait-db");
+  assert_eq!(init[0].command, Some(vec!["./wait-db".to_string()]));
+  assert_eq!(init[0].env_from, main.env_from);
+  // only the containers listing the volume mount it
+  assert_eq!(init[0].volume_mounts, None);
+
+  assert_eq!(pod.containers.len(), 2);
+  let sidecar = &pod.containers[1];
+  assert_eq!(sidecar.name, "shipper");
+  assert_eq!(sidecar.image.as_deref(), Some("fluent-bit:3"));
+  assert_eq!(sidecar.env_from, main.env_from);
+  assert_eq!(sidecar.env.as_ref().unwrap()[0].name, "OUTPUT");
+  let mounts = sidecar.volume_mounts.as_ref().unwrap();
+  assert_eq!(mounts[0].name, format!("volume-{}", volume.volume_id));
+  assert_eq!(mounts[0].mount_path, "/logs");
+}
@@ -0,0 +1,194 @@
+use std::time::Duration;
+
+use actix_web::{
+  http::StatusCode,
+  test::{self, TestRequest},
+};
+use api::{create_app, Cluster, ImageRegistry, MemoryBackend, MemoryRegistry, API_KEY};
+use k8s_openapi::api::{apps::v1::Deployment, batch::v1::Job, core::v1::Pod};
+use serde_json::{json, Value};
+
+mod utils;
+
+use utils::{database, release::release_project};
+
+const NAMESPACE: &str = "gws";
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn run_uses_the_released_app(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let app_service = database::insert_app(&pool, &project.project_id).await;
+  database::insert_env(&pool, &app_service.app_id, "DATABASE_URL", "postgres://db").await;
+
+  let memory = MemoryBackend::new();
+  let cluster = Cluster::new(memory.clone());
+  let app = test::init_service(create_app(
+    pool.clone(),
+    cluster.clone(),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let request = |req: TestRequest, path: &str| {
+    req
+      .uri(&format!(
+        "/projects/{}/apps/{}/{path}",
+        project.project_id, app_service.app_id
+      ))
+      .append_header((API_KEY, session.token.clone()))
+  };
+  let command = json!({ "command": ["./migrate", "--up"] });
+
+  // the run needs the image and envs of a release
+  let res = test::call_service(
+    &app,
+    request(TestRequest::post(), "runs/")
+      .set_json(&command)
+      .to_request(),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  let res = test::call_service(
+    &app,
+    request(TestRequest::post(), "runs/")
+      .set_json(json!({ "command": [] }))
+      .to_request(),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  release_project(&app, &project.project_id, &session.token).await;
+
+  let run: Value = test::call_and_read_body_json(
+    &app,
+    request(TestRequest::post(), "runs/")
+      .set_json(&command)
+      .to_request(),
+  )
+  .await;
+  assert_eq!(run["state"], "Running");
+  let run_id = run["id"].as_str().unwrap();
+  let name = format!("run-{run_id}");
+
+  let deploy = memory
+    .object::<Deployment>(NAMESPACE, &format!("app-{}", app_service.app_id))
+    .unwrap();
+  let app_container = deploy.spec.unwrap().template.spec.unwrap().containers[0].clone();
+
+  let job = memory
+    .object::<Job>(NAMESPACE, &name)
+    .expect("Job not created");
+  let spec = job.spec.unwrap();
+  assert_eq!(spec.backoff_limit, Some(0));
+
+  let template = spec.template;
+  let labels = template.metadata.unwrap().labels.unwrap();
+  assert_eq!(labels["project"], project.project_id.to_string());
+  // the service of the app does not send traffic to the run
+  assert_eq!(labels.get("app"), None);
+
+  let pod = template.spec.unwrap();
+  assert_eq!(pod.restart_policy.as_deref(), Some("Never"));
+
+  let container = &pod.containers[0];
+  assert_eq!(container.image, app_container.image);
+  assert_eq!(container.env_from, app_container.env_from);
+  assert_eq!(
+    container.command,
+    Some(vec!["./migrate".to_string(), "--up".to_string()])
+  );
+  assert_eq!(container.args, None);
+  assert_eq!(container.ports, None);
+
+  // the pod the job controller starts, stopped by the command failing
+  let pod_name = format!("{name}-x7k2p");
+  let pod: Pod = serde_json::from_value(json!({
+    "apiVersion": "v1",
+    "kind": "Pod",
+    "metadata": { "name": pod_name, "labels": { "run": name } },
+    "status": {
+      "containerStatuses": [{
+        "name": "run",
+        "image": "nginx",
+        "imageID": "",
+        "ready": false,
+        "restartCount": 0,
+        "state": { "terminated": { "exitCode": 3 } }
+      }]
+    }
+  }))
+  .unwrap();
+  cluster
+    .api::<Pod>(NAMESPACE)
+    .apply(&pod_name, &pod)
+    .await
+    .unwrap();
+  memory.set_logs(
+    NAMESPACE,
+    &pod_name,
+    "applying 0042_users\nconnection refused\n",
+  );
+
+  let logs: Value = test::call_and_read_body_json(
+    &app,
+    request(TestRequest::get(), &format!("runs/{run_id}/logs/")).to_request(),
+  )
+  .await;
+  assert_eq!(logs["logs"], "applying 0042_users\nconnection refused\n");
+
+  let mut job = memory.object::<Job>(NAMESPACE, &name).unwrap();
+  job.status = serde_json::from_value(json!({
+    "failed": 1,
+    "conditions": [{
+      "type": "Failed",
+      "status": "True",
+      "reason": "BackoffLimitExceeded",
+      "message": "Job has reached the specified backoff limit"
+    }]
+  }))
+  .unwrap();
+  cluster
+    .api::<Job>(NAMESPACE)
+    .apply(&name, &job)
+    .await
+    .unwrap();
+
+  let run = loop {
+    let run: Value = test::call_and_read_body_json(
+      &app,
+      request(TestRequest::get(), &format!("runs/{run_id}/")).to_request(),
+    )
+    .await;
+
+    if run["state"] != "Running" {
+      break run;
+    }
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+  };
+  assert_eq!(run["state"], "Failed");
+  assert_eq!(run["exitCode"], 3);
+  assert!(run["finishedAt"].is_i64());
+
+  // the logs outlive the job
+  cluster.api::<Job>(NAMESPACE).delete(&name).await.unwrap();
+  cluster
+    .api::<Pod>(NAMESPACE)
+    .delete(&pod_name)
+    .await
+    .unwrap();
+
+  let logs: Value = test::call_and_read_body_json(
+    &app,
+    request(TestRequest::get(), &format!("runs/{run_id}/logs/")).to_request(),
+  )
+  .await;
+  assert_eq!(logs["logs"], "applying 0042_users\nconnection refused\n");
+
+  let runs: Value =
+    test::call_and_read_body_json(&app, request(TestRequest::get(), "runs/").to_request()).await;
+  assert_eq!(runs.as_array().unwrap().len(), 1);
+}
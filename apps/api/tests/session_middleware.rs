@@ -2,12 +2,12 @@ use actix_web::{
   http::StatusCode,
   test::{self, TestRequest},
 };
-use api::{create_app, Token, API_KEY};
+use api::{Token, API_KEY};
 use chrono::{Duration, Utc};
 
 mod utils;
 
-use utils::database;
+use utils::{create_app, database};
 
 #[sqlx::test(migrator = "api::MIGRATOR")]
 async fn missing_api_key(pool: sqlx::PgPool) {
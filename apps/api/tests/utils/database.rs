@@ -1,4 +1,4 @@
-use api::schemas::{Project, Session, User};
+use api::schemas::{AppService, EnvVar, Project, Session, User, Volume, VolumeApp, VolumeApps};
 use api::{AuthSecurity, Token};
 use chrono::{Duration, NaiveDateTime, Utc};
 use fake::faker::internet::en::{Password, Username};
@@ -13,13 +13,15 @@ pub async fn insert_user(conn: &PgPool, user: &User) {
     user_id,
     username,
     password,
+    admin,
   } = user;
 
   sqlx::query!(
-    "INSERT INTO users(user_id, username, password) VALUES ($1, $2, $3)",
+    "INSERT INTO users(user_id, username, password, admin) VALUES ($1, $2, $3, $4)",
     user_id,
     username,
     api::Password::from(password.as_str()).hash().unwrap(),
+    admin,
   )
   .execute(conn)
   .await
@@ -31,6 +33,7 @@ pub async fn insert_random_user(conn: &PgPool) -> User {
     user_id: UUIDv4.fake(),
     username: Username().fake(),
     password: Password(6..12).fake(),
+    admin: false,
   };
 
   insert_user(conn, &user).await;
@@ -89,3 +92,74 @@ pub async fn insert_project(conn: &PgPool, user_id: &Uuid) -> Project {
 
   project
 }
+
+pub async fn insert_app(conn: &PgPool, project_id: &Uuid) -> AppService {
+  let app_name: String = Name().fake();
+  let domain = Uuid::new_v4().simple().to_string();
+
+  sqlx::query_as!(
+    AppService,
+    "INSERT INTO app_services(app_name, replicas, image, port, public_domain, private_domain, project_id) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *",
+    app_name,
+    2,
+    "nginx:latest",
+    8080,
+    format!("public-{domain}"),
+    format!("private-{domain}"),
+    project_id
+  )
+  .fetch_one(conn)
+  .await
+  .unwrap()
+}
+
+pub async fn insert_env(conn: &PgPool, app_id: &Uuid, name: &str, value: &str) -> EnvVar {
+  sqlx::query_as!(
+    EnvVar,
+    "INSERT INTO envs(env_name, env_value, app_id) VALUES ($1, $2, $3) RETURNING *",
+    name,
+    value,
+    app_id
+  )
+  .fetch_one(conn)
+  .await
+  .unwrap()
+}
+
+pub async fn insert_volume(conn: &PgPool, project_id: &Uuid, app_id: Option<&Uuid>) -> Volume {
+  let volume_name: String = Name().fake();
+
+  let volume = sqlx::query_as!(
+    Volume,
+    r#"INSERT INTO volumes(volume_name, capacity, path, project_id) VALUES ($1, $2, $3, $4) RETURNING *, '[]'::JSONB AS "apps!""#,
+    volume_name,
+    100,
+    "/data",
+    project_id
+  )
+  .fetch_one(conn)
+  .await
+  .unwrap();
+
+  let Some(app_id) = app_id else {
+    return volume;
+  };
+
+  sqlx::query!(
+    "INSERT INTO volume_apps(volume_id, app_id, path) VALUES ($1, $2, $3)",
+    volume.volume_id,
+    app_id,
+    volume.path
+  )
+  .execute(conn)
+  .await
+  .unwrap();
+
+  Volume {
+    apps: VolumeApps(vec![VolumeApp {
+      id: *app_id,
+      path: volume.path.clone(),
+    }]),
+    ..volume
+  }
+}
@@ -1 +1,31 @@
+#![allow(dead_code)]
+
+use actix_web::{
+  body::MessageBody,
+  dev::{ServiceFactory, ServiceRequest, ServiceResponse},
+  App,
+};
+use api::{Cluster, ImageRegistry, MemoryBackend, MemoryRegistry};
+
 pub mod database;
+pub mod release;
+
+/// Builds the app against an empty in-memory cluster and registry, for tests
+/// that don't look at either.
+pub fn create_app(
+  pool: sqlx::PgPool,
+) -> App<
+  impl ServiceFactory<
+    ServiceRequest,
+    Config = (),
+    Response = ServiceResponse<impl MessageBody>,
+    Error = actix_web::Error,
+    InitError = (),
+  >,
+> {
+  api::create_app(
+    pool,
+    Cluster::new(MemoryBackend::new()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  )
+}
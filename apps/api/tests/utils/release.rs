@@ -0,0 +1,107 @@
+use actix_http::Request;
+use actix_web::{
+  body::MessageBody,
+  dev::{Service, ServiceResponse},
+  http::StatusCode,
+  test::{self, TestRequest},
+};
+use api::API_KEY;
+use serde_json::Value;
+use uuid::Uuid;
+
+/// Events sent on the release stream until it closes
+pub async fn release_events<S, B>(
+  app: &S,
+  project_id: &Uuid,
+  release_id: &str,
+  token: &str,
+) -> Vec<Value>
+where
+  S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+  B: MessageBody,
+{
+  let req = TestRequest::get()
+    .uri(&format!(
+      "/projects/{project_id}/releases/{release_id}/events"
+    ))
+    .append_header((API_KEY, token.to_string()))
+    .to_request();
+  let res = test::call_service(app, req).await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  let body = test::read_body(res).await;
+
+  String::from_utf8(body.to_vec())
+    .unwrap()
+    .lines()
+    .filter_map(|line| line.strip_prefix("data: "))
+    .map(|data| serde_json::from_str(data).unwrap())
+    .collect()
+}
+
+/// First statuses sent on the status stream of an app, the stream itself never closes
+pub async fn app_statuses<S, B>(
+  app: &S,
+  project_id: &Uuid,
+  app_id: &Uuid,
+  token: &str,
+  count: usize,
+) -> Vec<Value>
+where
+  S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+  B: MessageBody,
+{
+  let req = TestRequest::get()
+    .uri(&format!("/projects/{project_id}/apps/{app_id}/status"))
+    .append_header((API_KEY, token.to_string()))
+    .to_request();
+  let res = test::call_service(app, req).await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  let mut body = Box::pin(res.into_body());
+  let mut statuses = Vec::new();
+  let mut event = None;
+  let mut lines = String::new();
+  while statuses.len() < count {
+    let chunk = tokio::time::timeout(
+      std::time::Duration::from_secs(5),
+      std::future::poll_fn(|cx| body.as_mut().poll_next(cx)),
+    )
+    .await
+    .expect("No status sent in time")
+    .expect("Status stream closed")
+    .unwrap_or_else(|_| panic!("Status stream failed"));
+    lines.push_str(std::str::from_utf8(&chunk).unwrap());
+
+    while let Some((line, rest)) = lines.split_once('\n') {
+      if let Some(name) = line.strip_prefix("event: ") {
+        event = Some(name.to_string());
+      } else if let Some(data) = line.strip_prefix("data: ") {
+        if event.as_deref() == Some("message") {
+          statuses.push(serde_json::from_str(data).unwrap());
+        }
+      }
+      lines = rest.to_string();
+    }
+  }
+
+  statuses
+}
+
+/// Start a release and follow its events until the outcome
+pub async fn release_project<S, B>(app: &S, project_id: &Uuid, token: &str) -> Vec<Value>
+where
+  S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+  B: MessageBody,
+{
+  let req = TestRequest::post()
+    .uri(&format!("/projects/{project_id}"))
+    .append_header((API_KEY, token.to_string()))
+    .to_request();
+  let res = test::call_service(app, req).await;
+  assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+  let release: Value = test::read_body_json(res).await;
+
+  release_events(app, project_id, release["id"].as_str().unwrap(), token).await
+}
@@ -0,0 +1,176 @@
+use actix_web::{
+  http::StatusCode,
+  test::{self, TestRequest},
+};
+use api::{create_app, Cluster, ImageRegistry, MemoryBackend, MemoryRegistry, API_KEY};
+use k8s_openapi::api::{
+  apps::v1::{Deployment, DeploymentSpec},
+  core::v1::PersistentVolumeClaim,
+};
+use serde_json::{json, Value};
+
+mod utils;
+
+use utils::{database, release::release_project};
+
+const NAMESPACE: &str = "gws";
+
+#[sqlx::test(migrator = "api::MIGRATOR")]
+async fn shared_volume_is_mounted_by_several_apps(pool: sqlx::PgPool) {
+  let user = database::insert_random_user(&pool).await;
+  let session = database::insert_valid_session(&pool, &user.user_id).await;
+  let project = database::insert_project(&pool, &user.user_id).await;
+  let web = database::insert_app(&pool, &project.project_id).await;
+  let worker = database::insert_app(&pool, &project.project_id).await;
+  // mounted at /data by the web app
+  let data = database::insert_volume(&pool, &project.project_id, Some(&web.app_id)).await;
+
+  let memory = MemoryBackend::new();
+  let app = test::init_service(create_app(
+    pool,
+    Cluster::new(memory.clone()),
+    ImageRegistry::new(MemoryRegistry::new()),
+  ))
+  .await;
+
+  let request = |req: TestRequest, path: &str| {
+    req
+      .uri(&format!("/projects/{}/{path}", project.project_id))
+      .append_header((API_KEY, session.token.clone()))
+  };
+  let apps = json!([{ "id": web.app_id }, { "id": worker.app_id, "path": "/shared" }]);
+
+  // a ReadWriteOnce volume is mounted by a single app
+  let res = test::call_service(
+    &app,
+    request(TestRequest::post(), "volumes/")
+      .set_json(json!({ "name": "uploads", "capacity": 100, "path": "/uploads", "apps": apps }))
+      .to_request(),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  // an app can't mount two volumes at the same path
+  let res = test::call_service(
+    &app,
+    request(TestRequest::post(), "volumes/")
+      .set_json(json!({
+        "name": "uploads",
+        "capacity": 100,
+        "path": "/data",
+        "accessMode": "ReadWriteMany",
+        "apps": apps
+      }))
+      .to_request(),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  // nor at the path of its own mount
+  let res = test::call_service(
+    &app,
+    request(TestRequest::post(), "volumes/")
+      .set_json(json!({
+        "name": "uploads",
+        "capacity": 100,
+        "path": "/uploads",
+        "accessMode": "ReadWriteMany",
+        "apps": [{ "id": web.app_id, "path": "/data" }]
+      }))
+      .to_request(),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  let uploads: Value = test::call_and_read_body_json(
+    &app,
+    request(TestRequest::post(), "volumes/")
+      .set_json(json!({
+        "name": "uploads",
+        "capacity": 100,
+        "path": "/uploads",
+        "accessMode": "ReadWriteMany",
+        "apps": apps
+      }))
+      .to_request(),
+  )
+  .await;
+  assert_eq!(uploads["accessMode"], "ReadWriteMany");
+  let mut paths: Vec<&str> = uploads["apps"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|app| app["path"].as_str().unwrap())
+    .collect();
+  paths.sort();
+  assert_eq!(paths, vec!["/shared", "/uploads"]);
+  let uploads_id = uploads["id"].as_str().unwrap();
+
+  let res = test::call_service(
+    &app,
+    request(TestRequest::patch(), &format!("volumes/{uploads_id}/"))
+      .set_json(json!({ "accessMode": "ReadWriteOnce" }))
+      .to_request(),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+  // the apps mounting the volume at its path follow it, the others keep their own
+  let res = test::call_service(
+    &app,
+    request(TestRequest::patch(), &format!("volumes/{uploads_id}/"))
+      .set_json(json!({ "path": "/files" }))
+      .to_request(),
+  )
+  .await;
+  assert_eq!(res.status(), StatusCode::OK);
+
+  let events = release_project(&app, &project.project_id, &session.token).await;
+  assert_eq!(events.last().unwrap()["state"], "Succeeded");
+
+  let claim = memory
+    .object::<PersistentVolumeClaim>(NAMESPACE, &format!("volume-{uploads_id}"))
+    .unwrap()
+    .spec
+    .unwrap();
+  assert_eq!(claim.access_modes, Some(vec!["ReadWriteMany".to_string()]));
+  assert_eq!(claim.storage_class_name.as_deref(), Some("nfs-client"));
+
+  let deploy = |app_id| {
+    memory
+      .object::<Deployment>(NAMESPACE, &format!("app-{app_id}"))
+      .unwrap()
+      .spec
+      .unwrap()
+  };
+  let mount_paths = |spec: &DeploymentSpec| {
+    let mut paths: Vec<String> = spec.template.spec.as_ref().unwrap().containers[0]
+      .volume_mounts
+      .iter()
+      .flatten()
+      .map(|mount| mount.mount_path.clone())
+      .collect();
+    paths.sort();
+    paths
+  };
+
+  // the ReadWriteOnce volume keeps the web app to one replica
+  let web_deploy = deploy(web.app_id);
+  assert_eq!(web_deploy.replicas, Some(1));
+  assert_eq!(mount_paths(&web_deploy), vec!["/data", "/files"]);
+  let claims: Vec<String> = web_deploy
+    .template
+    .spec
+    .unwrap()
+    .volumes
+    .unwrap()
+    .into_iter()
+    .map(|volume| volume.persistent_volume_claim.unwrap().claim_name)
+    .collect();
+  assert!(claims.contains(&format!("volume-{}", data.volume_id)));
+  assert!(claims.contains(&format!("volume-{uploads_id}")));
+
+  let worker_deploy = deploy(worker.app_id);
+  assert_eq!(worker_deploy.replicas, Some(worker.replicas));
+  assert_eq!(mount_paths(&worker_deploy), vec!["/shared"]);
+}
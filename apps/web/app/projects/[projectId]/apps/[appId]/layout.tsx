@@ -8,7 +8,7 @@ import {
   ResourceUpdateButtonContainer,
 } from "@/components/resource";
 import { Container, Trash2, Undo2 } from "lucide-react";
-import { cn, unwrap } from "@/lib/utils";
+import { cn, mountsVolume, unwrap } from "@/lib/utils";
 import { deleteApp, getApp, recoverApp } from "@/server-actions/app";
 import { AppStatusLight } from "@/components/app-status-light";
 import { TokenCookie } from "@/lib/cookies";
@@ -33,7 +33,13 @@ export default async function AppLayout({
     <Resource
       currentResource={{
         app,
-        hasVolume: volumes.some((volume) => volume.appId === app.id),
+        // a ReadWriteOnce volume keeps the app to a single replica
+        hasVolume: volumes.some(
+          (volume) =>
+            !volume.deleted &&
+            volume.accessMode !== "ReadWriteMany" &&
+            mountsVolume(volume, app.id)
+        ),
       }}
     >
       <ResourceHeader>
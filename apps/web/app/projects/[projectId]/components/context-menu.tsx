@@ -149,7 +149,7 @@ export function DisconnectVolumeItem({
               null,
               projectId,
               volumeId
-            )({ app: { id: undefined } })
+            )({ apps: [], cronJob: {} })
             .then(unwrap),
           {
             loading: "Disconnecting volume...",
@@ -260,7 +260,7 @@ function NewVolumeForm({ project, closeDialog }: NewVolumeFormProps) {
           name: "",
           capacity: 500,
           path: "/data",
-          app: {},
+          apps: [],
         },
       },
       actionProps: {
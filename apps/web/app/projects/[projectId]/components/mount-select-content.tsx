@@ -1,15 +1,15 @@
 import { listApps } from "@/server-actions/app";
 import { ComponentPropsWithoutRef, ReactNode } from "react";
 import { SelectContent, SelectItem } from "@/components/ui/select";
-import { unwrap } from "@/lib/utils";
-import { listVolumes } from "@/server-actions/volume";
-import { TAppService } from "@gws/api-client";
+import { mountsVolume, unwrap } from "@/lib/utils";
+import { TAppService, TVolume } from "@gws/api-client";
 
 type MountSelectContentProps = ComponentPropsWithoutRef<
   typeof SelectContent
 > & {
   renderItem?: (app: TAppService) => ReactNode;
   projectId: string;
+  volume: TVolume;
 };
 
 function defaultItemRender(app: TAppService) {
@@ -22,15 +22,13 @@ function defaultItemRender(app: TAppService) {
 
 export async function MountSelectContent({
   projectId,
+  volume,
   renderItem = defaultItemRender,
   ...props
 }: MountSelectContentProps) {
   const apps = await listApps(projectId).then(unwrap);
-  const volumes = await listVolumes(projectId).then(unwrap);
 
-  const appsWithoutVolume = apps.filter(
-    (app) => !volumes.find((volume) => app.id === volume.appId)
-  );
+  const appsWithoutVolume = apps.filter((app) => !mountsVolume(volume, app.id));
 
   return (
     <SelectContent {...props}>
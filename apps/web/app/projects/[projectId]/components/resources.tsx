@@ -1,5 +1,5 @@
 import { listApps } from "@/server-actions/app";
-import { getPublicUrl, unwrap } from "@/lib/utils";
+import { getPublicUrl, mountsVolume, unwrap } from "@/lib/utils";
 import { ScrollArea } from "@/components/ui/scroll-area";
 import Link from "next/link";
 import { cn } from "@/lib/utils";
@@ -43,7 +43,7 @@ export default async function Resources({ projectId }: ResourcesProps) {
     >
       <div className="flex size-full flex-col gap-4 sm:flex-row">
         <ul className="flex flex-1 flex-wrap justify-center gap-x-4 gap-y-3 px-4 py-2">
-          {apps.map(({ app, volume }) => (
+          {apps.map(({ app, volumes: appVolumes, singleReplica }) => (
             <li
               key={app.id}
               className="h-fit w-[min(20rem,100%)] font-semibold"
@@ -67,7 +67,7 @@ export default async function Resources({ projectId }: ResourcesProps) {
                     <span className="flex-1 overflow-hidden text-ellipsis text-nowrap">
                       {app.name}
                     </span>
-                    <span>x {volume ? 1 : app.replicas}</span>
+                    <span>x {singleReplica ? 1 : app.replicas}</span>
                   </Link>
                 </ContextMenuTrigger>
                 <ContextMenuContent className="min-w-60">
@@ -118,11 +118,20 @@ export default async function Resources({ projectId }: ResourcesProps) {
                   )}
                 </ContextMenuContent>
               </ContextMenu>
-              {volume ? (
-                <div className="size-full px-4">
-                  <Volume volume={volume} className="rounded-b-md border-t-0" />
+              {appVolumes.map((volume) => (
+                <div key={volume.id} className="size-full px-4">
+                  <Volume
+                    volume={volume}
+                    className="rounded-b-md border-t-0"
+                    mountSelectContent={
+                      <MountSelectContent
+                        projectId={projectId}
+                        volume={volume}
+                      />
+                    }
+                  />
                 </div>
-              ) : null}
+              ))}
             </li>
           ))}
         </ul>
@@ -137,7 +146,7 @@ export default async function Resources({ projectId }: ResourcesProps) {
                   volume={volume}
                   className="rounded-md"
                   mountSelectContent={
-                    <MountSelectContent projectId={projectId} />
+                    <MountSelectContent projectId={projectId} volume={volume} />
                   }
                 />
               </li>
@@ -150,33 +159,26 @@ export default async function Resources({ projectId }: ResourcesProps) {
 }
 
 interface AppsWithVolumes {
-  apps: { app: TAppService; volume?: TVolume }[];
+  apps: { app: TAppService; volumes: TVolume[]; singleReplica: boolean }[];
   volumes: TVolume[];
 }
 
+// a volume shows under every app mounting it, the others stand on their own
 function connectAppsAndVolumes(
   apps: TAppService[],
   volumes: TVolume[]
 ): AppsWithVolumes {
-  const connectedApps = [] as AppsWithVolumes["apps"];
-  const orphanVolumes = [] as AppsWithVolumes["volumes"];
-  for (const volume of volumes) {
-    const appIdx = volume.appId
-      ? apps.findIndex((app) => app.id === volume.appId)
-      : undefined;
-    if (appIdx != undefined) {
-      const app = apps.splice(appIdx, 1)[0];
-      if (!app) {
-        throw new Error("Missing app");
-      }
-      connectedApps.push({ app, volume });
-    } else {
-      orphanVolumes.push(volume);
-    }
-  }
-
   return {
-    apps: connectedApps.concat(apps.map((app) => ({ app }))),
-    volumes: orphanVolumes,
+    apps: apps.map((app) => {
+      const appVolumes = volumes.filter((volume) =>
+        mountsVolume(volume, app.id)
+      );
+      const singleReplica = appVolumes.some(
+        (volume) => !volume.deleted && volume.accessMode !== "ReadWriteMany"
+      );
+
+      return { app, volumes: appVolumes, singleReplica };
+    }),
+    volumes: volumes.filter((volume) => !volume.apps?.length),
   };
 }
@@ -6,7 +6,7 @@ import {
   ResponsiveDialogTrigger,
 } from "@/components/responsive-dialog";
 import { Button } from "@/components/ui/button";
-import { cn } from "@/lib/utils";
+import { canMountVolume, cn } from "@/lib/utils";
 import { TVolume } from "@gws/api-client";
 import {
   HardDrive,
@@ -45,6 +45,7 @@ export function Volume({
   disableMount = false,
 }: VolumeProps) {
   const [open, setOpen] = useState(false);
+  const mounted = !!volume.apps?.length || !!volume.cronJobId;
   return (
     <ResponsiveDialog open={open} onOpenChange={setOpen}>
       <ContextMenu>
@@ -62,7 +63,7 @@ export function Volume({
             <span className="flex-1 overflow-hidden text-ellipsis text-nowrap">
               {volume.name}
             </span>
-            {!volume.appId && !volume.deleted ? (
+            {canMountVolume(volume) ? (
               <ResponsiveDialogTrigger asChild>
                 <Button
                   variant="ghost"
@@ -81,7 +82,15 @@ export function Volume({
           </Link>
         </ContextMenuTrigger>
         <ContextMenuContent className="min-w-60">
-          {volume.appId ? (
+          {canMountVolume(volume) ? (
+            <ResponsiveDialogTrigger asChild>
+              <ContextMenuItem className="flex items-center gap-2">
+                <PlugZap className="size-4" />
+                <span>Mount volume</span>
+              </ContextMenuItem>
+            </ResponsiveDialogTrigger>
+          ) : null}
+          {mounted ? (
             <DisconnectVolumeItem
               projectId={volume.projectId}
               volumeId={volume.id}
@@ -90,14 +99,7 @@ export function Volume({
               <Unplug className="size-4" />
               <span>Disconnect volume</span>
             </DisconnectVolumeItem>
-          ) : (
-            <ResponsiveDialogTrigger asChild>
-              <ContextMenuItem className="flex items-center gap-2">
-                <PlugZap className="size-4" />
-                <span>Mount volume</span>
-              </ContextMenuItem>
-            </ResponsiveDialogTrigger>
-          )}
+          ) : null}
           <ContextMenuItem className="flex items-center gap-2" asChild>
             <Link href={`/projects/${volume.projectId}/volumes/${volume.id}`}>
               <Settings className="size-4" />
@@ -7,7 +7,7 @@ import {
   ResourceUpdateButtonContainer,
 } from "@/components/resource";
 import { HardDrive, PlugZap, Trash2, Undo2, Unplug } from "lucide-react";
-import { cn, unwrap } from "@/lib/utils";
+import { canMountVolume, cn, unwrap } from "@/lib/utils";
 import {
   deleteVolume,
   getVolume,
@@ -58,28 +58,29 @@ export default async function AppLayout({
             />
           ) : (
             <>
-              {volume.appId ? (
+              {canMountVolume(volume) ? (
+                <Mount
+                  volume={volume}
+                  mountSelectContent={
+                    <MountSelectContent projectId={projectId} volume={volume} />
+                  }
+                >
+                  <Button size="icon" icon={<PlugZap />} />
+                </Mount>
+              ) : null}
+              {volume.apps?.length || volume.cronJobId ? (
                 <ResourceActionButton
                   size="icon"
                   icon={<Unplug />}
                   action={updateVolume.bind(null, projectId, volumeId)}
-                  actionInputs={{ app: { id: undefined } }}
+                  actionInputs={{ apps: [], cronJob: {} }}
                   toastProps={{
                     loading: `Disconnecting Volume ${volume.name}...`,
                     success: `Volume ${volume.name} successfully disconnected`,
                     error: `Unable to disconnect Volume ${volume.name}`,
                   }}
                 />
-              ) : (
-                <Mount
-                  volume={volume}
-                  mountSelectContent={
-                    <MountSelectContent projectId={projectId} />
-                  }
-                >
-                  <Button size="icon" icon={<PlugZap />} />
-                </Mount>
-              )}
+              ) : null}
               <ResourceActionButton
                 variant="destructive"
                 size="icon"
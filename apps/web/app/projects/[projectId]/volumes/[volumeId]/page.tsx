@@ -20,6 +20,9 @@ import { useEffect, useId } from "react";
 import { Input } from "@/components/ui/input";
 import { Page } from "@/lib/types";
 
+// the apps and cron job mounting the volume are changed from its card
+const Schema = VolumeSchema.pick({ name: true, capacity: true, path: true });
+
 export default function VolumePage({
   params: { projectId, volumeId },
 }: Page<{
@@ -36,28 +39,18 @@ export default function VolumePage({
     action: { reset: resetAction },
   } = useHookFormAction(
     updateVolume.bind(null, projectId, volumeId),
-    zodResolver(VolumeSchema),
+    zodResolver(Schema),
     {
       formProps: {
         reValidateMode: "onBlur",
-        defaultValues: {
-          ...volume,
-          app: {
-            id: volume.appId,
-          },
-        },
+        defaultValues: volume,
       },
     }
   );
 
   useEffect(() => {
     resetAction();
-    form.reset({
-      ...volume,
-      app: {
-        id: volume.appId,
-      },
-    });
+    form.reset(volume);
   }, [volume, form, resetAction]);
 
   return (
@@ -1,6 +1,6 @@
 "use client";
 
-import { TVolume, VolumeSchema } from "@gws/api-client";
+import { TVolume, VolumeAppSchema } from "@gws/api-client";
 import {
   Form,
   FormControl,
@@ -11,7 +11,7 @@ import {
 } from "./ui/form";
 import { ReactNode } from "react";
 import { useHookFormAction } from "@next-safe-action/adapter-react-hook-form/hooks";
-import { updateVolume } from "@/server-actions/volume";
+import { mountVolume } from "@/server-actions/volume";
 import { zodResolver } from "@hookform/resolvers/zod";
 import { ScrollArea } from "./ui/scroll-area";
 import {
@@ -24,14 +24,11 @@ import {
 import { Select, SelectTrigger, SelectValue } from "./ui/select";
 import { Button } from "./ui/button";
 import { Input } from "./ui/input";
+import { z } from "zod";
 
-const Schema = VolumeSchema.pick({ app: true, path: true }).refine(
-  ({ app }) => app.id != undefined,
-  {
-    message: "App required",
-    path: ["app.id"],
-  }
-);
+const Schema = VolumeAppSchema.extend({
+  id: z.string({ required_error: "App required" }).uuid(),
+});
 
 interface VolumeMountFormProps {
   volume: TVolume;
@@ -45,12 +42,11 @@ export function VolumeMountForm({
   onSuccess,
 }: VolumeMountFormProps) {
   const { form, handleSubmitWithAction } = useHookFormAction(
-    updateVolume.bind(null, volume.projectId, volume.id),
+    mountVolume.bind(null, volume.projectId, volume.id),
     zodResolver(Schema),
     {
       formProps: {
         defaultValues: {
-          app: {},
           path: volume.path,
         },
       },
@@ -74,7 +70,7 @@ export function VolumeMountForm({
             </ResponsiveDialogHeader>
             <FormField
               control={form.control}
-              name="app.id"
+              name="id"
               render={({ field }) => (
                 <FormItem>
                   <FormLabel>App</FormLabel>
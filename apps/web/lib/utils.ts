@@ -1,4 +1,4 @@
-import { TErrorMessage } from "@gws/api-client";
+import { TErrorMessage, TVolume } from "@gws/api-client";
 import { clsx, type ClassValue } from "clsx";
 import { env } from "next-runtime-env";
 import {
@@ -66,3 +66,19 @@ export const HOST_PUBLIC_PORT = env("NEXT_PUBLIC_HOST_PUBLIC_PORT") ?? "8443";
 export function getPublicUrl(subdomain: string): string {
   return `https://${subdomain}.${HOST_PUBLIC_DOMAIN}:${HOST_PUBLIC_PORT}`;
 }
+
+export function mountsVolume(volume: TVolume, appId: string): boolean {
+  return volume.apps?.some((app) => app.id === appId) ?? false;
+}
+
+// a ReadWriteOnce volume is mounted by a single app or cron job
+export function canMountVolume(volume: TVolume): boolean {
+  if (volume.deleted) {
+    return false;
+  }
+
+  return (
+    volume.accessMode === "ReadWriteMany" ||
+    (!volume.apps?.length && !volume.cronJobId)
+  );
+}
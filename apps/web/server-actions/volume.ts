@@ -1,7 +1,11 @@
 "use server";
 
 import { apiActionClient } from "@/lib/safe-action";
-import { PartialVolumeSchema, VolumeSchema } from "@gws/api-client";
+import {
+  PartialVolumeSchema,
+  VolumeAppSchema,
+  VolumeSchema,
+} from "@gws/api-client";
 import { revalidateTag } from "next/cache";
 import { z } from "zod";
 import { IdentifyVolume } from "./common";
@@ -77,6 +81,35 @@ export const updateVolume = apiActionClient
     }
   );
 
+export const mountVolume = apiActionClient
+  .metadata({
+    name: "mountVolume",
+  })
+  .bindArgsSchemas<[projectId: z.ZodString, volumeId: z.ZodString]>([
+    z.string().uuid(),
+    z.string().uuid(),
+  ])
+  .schema(VolumeAppSchema)
+  .action(
+    async ({
+      parsedInput: app,
+      bindArgsParsedInputs: [projectId, volumeId],
+      ctx: { apiClient },
+    }) => {
+      const params = { project_id: projectId, volume_id: volumeId };
+      const volume = await apiClient.getVolume({ params });
+
+      // the apps already mounting the volume keep their paths
+      const apps = (volume.apps ?? [])
+        .filter(({ id }) => id !== app.id)
+        .concat(app);
+      await apiClient.updateVolume({ apps }, { params });
+
+      revalidateTag("volume");
+      revalidateTag("volume-list");
+    }
+  );
+
 export const deleteVolume = apiActionClient
   .metadata({
     name: "deleteVolume",
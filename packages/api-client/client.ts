@@ -73,11 +73,24 @@ export const PartialEnvSchema = z
   .object({ name: z.string().min(1), value: z.string().min(1) })
   .partial();
 export type TPartialEnvSchema = z.infer<typeof PartialEnvSchema>;
-export const VolumeAppId = z.object({ id: z.string().uuid() }).partial();
-export type TVolumeAppId = z.infer<typeof VolumeAppId>;
+export const AccessMode = z.enum(["ReadWriteOnce", "ReadWriteMany"]);
+export type TAccessMode = z.infer<typeof AccessMode>;
+export const VolumeAppSchema = z.object({
+  id: z.string().uuid(),
+  path: z
+    .string()
+    .min(1)
+    .regex(/^\/([a-zA-Z0-9.\-_\/])*/)
+    .optional(),
+});
+export type TVolumeAppSchema = z.infer<typeof VolumeAppSchema>;
+export const VolumeCronJobId = z.object({ id: z.string().uuid() }).partial();
+export type TVolumeCronJobId = z.infer<typeof VolumeCronJobId>;
 export const VolumeSchema = z.object({
-  app: VolumeAppId,
+  accessMode: AccessMode.optional(),
+  apps: z.array(VolumeAppSchema).max(64).optional(),
   capacity: z.number().int().gte(1).lte(5000),
+  cronJob: VolumeCronJobId.optional(),
   name: z.string().min(1),
   path: z
     .string()
@@ -87,8 +100,10 @@ export const VolumeSchema = z.object({
 export type TVolumeSchema = z.infer<typeof VolumeSchema>;
 export const PartialVolumeSchema = z
   .object({
-    app: VolumeAppId,
+    accessMode: AccessMode,
+    apps: z.array(VolumeAppSchema).max(64),
     capacity: z.number().int().gte(1).lte(5000),
+    cronJob: VolumeCronJobId,
     name: z.string().min(1),
     path: z
       .string()
@@ -128,9 +143,16 @@ export const EnvVar = z.object({
   value: z.string(),
 });
 export type TEnvVar = z.infer<typeof EnvVar>;
+export const VolumeApp = z.object({
+  id: z.string().uuid(),
+  path: z.string(),
+});
+export type TVolumeApp = z.infer<typeof VolumeApp>;
 export const Volume = z.object({
-  appId: z.string().uuid().optional(),
+  accessMode: AccessMode.optional(),
+  apps: z.array(VolumeApp).optional(),
   capacity: z.number().int(),
+  cronJobId: z.string().uuid().optional(),
   deleted: z.boolean(),
   id: z.string().uuid(),
   name: z.string(),